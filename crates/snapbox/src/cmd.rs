@@ -8,7 +8,10 @@ use anstream::panic;
 pub struct Command {
     cmd: std::process::Command,
     stdin: Option<crate::Data>,
+    null_stdin: bool,
+    stdin_from: Option<std::process::Stdio>,
     timeout: Option<std::time::Duration>,
+    max_output_bytes: Option<usize>,
     _stderr_to_stdout: bool,
     config: crate::Assert,
 }
@@ -19,7 +22,10 @@ impl Command {
         Self {
             cmd: std::process::Command::new(program),
             stdin: None,
+            null_stdin: false,
+            stdin_from: None,
             timeout: None,
+            max_output_bytes: None,
             _stderr_to_stdout: false,
             config: crate::Assert::new().action_env(crate::DEFAULT_ACTION_ENV),
         }
@@ -30,7 +36,10 @@ impl Command {
         Self {
             cmd,
             stdin: None,
+            null_stdin: false,
+            stdin_from: None,
             timeout: None,
+            max_output_bytes: None,
             _stderr_to_stdout: false,
             config: crate::Assert::new().action_env(crate::DEFAULT_ACTION_ENV),
         }
@@ -248,6 +257,23 @@ impl Command {
         self
     }
 
+    /// Give the command a null `stdin`, rather than a pipe
+    ///
+    /// For commands that act differently when `stdin` is a pipe.  Any [`Command::stdin`] is
+    /// ignored.
+    pub fn null_stdin(mut self) -> Self {
+        self.null_stdin = true;
+        self
+    }
+
+    /// Read `stdin` from another process, like the `stdout` of [`Command::spawn_piped`]
+    ///
+    /// Any [`Command::stdin`] is ignored.
+    pub fn stdin_from(mut self, stdin: impl Into<std::process::Stdio>) -> Self {
+        self.stdin_from = Some(stdin.into());
+        self
+    }
+
     /// Error out if a timeout is reached
     ///
     /// ```rust,no_run
@@ -266,12 +292,60 @@ impl Command {
         self
     }
 
+    /// Kill the command and fail with an error once `stdout` or `stderr` is over `limit` bytes
+    ///
+    /// This guards against a runaway command filling up memory.  The limit applies to each
+    /// stream on its own, or to both together with [`Command::stderr_to_stdout`].
+    ///
+    /// ```rust,no_run
+    /// use snapbox::cmd::Command;
+    /// use snapbox::cmd::cargo_bin;
+    ///
+    /// let err = Command::new(cargo_bin("snap-fixture"))
+    ///     .env("echo_large", "1")
+    ///     .max_output_bytes(1024)
+    ///     .output()
+    ///     .unwrap_err();
+    /// assert_eq!(err.to_string(), "stdout exceeded the limit of 1024 bytes");
+    /// ```
+    pub fn max_output_bytes(mut self, limit: usize) -> Self {
+        self.max_output_bytes = Some(limit);
+        self
+    }
+
+    /// Set the file mode creation mask of the command, like `0o022`
+    ///
+    /// Otherwise, files the command creates get permissions depending on the test process's
+    /// umask.
+    #[cfg(all(unix, feature = "cmd"))]
+    pub fn umask(mut self, mask: u32) -> Self {
+        use std::os::unix::process::CommandExt as _;
+
+        let mask = mask as libc::mode_t;
+        // SAFETY: `umask` is async-signal-safe and can't fail
+        unsafe {
+            self.cmd.pre_exec(move || {
+                libc::umask(mask);
+                Ok(())
+            });
+        }
+        self
+    }
+
     /// Merge `stderr` into `stdout`
     #[cfg(feature = "cmd")]
     pub fn stderr_to_stdout(mut self) -> Self {
         self._stderr_to_stdout = true;
         self
     }
+
+    /// The underlying `std` `Command`, for what this doesn't cover, like
+    /// `std::os::unix::process::CommandExt::pre_exec`
+    ///
+    /// `stdin`, `stdout`, and `stderr` are replaced when the command is run.
+    pub fn as_std_mut(&mut self) -> &mut std::process::Command {
+        &mut self.cmd
+    }
 }
 
 /// # Run Command
@@ -298,9 +372,62 @@ impl Command {
         }
     }
 
+    fn take_stdin(&mut self) -> std::process::Stdio {
+        if let Some(stdin) = self.stdin_from.take() {
+            stdin
+        } else if self.null_stdin {
+            std::process::Stdio::null()
+        } else {
+            std::process::Stdio::piped()
+        }
+    }
+
+    /// Spawn the command with `stdout` piped, to feed into another command with
+    /// [`Command::stdin_from`]
+    ///
+    /// `stderr` is piped too and the timeout doesn't apply; the caller is responsible for reading
+    /// `stderr` and waiting on the child.
+    pub fn spawn_piped(mut self) -> Result<std::process::Child, std::io::Error> {
+        use std::io::Write;
+
+        let stdin = self.take_stdin();
+        self.cmd.stdin(stdin);
+        self.cmd.stdout(std::process::Stdio::piped());
+        self.cmd.stderr(std::process::Stdio::piped());
+        let mut child = self.cmd.spawn()?;
+        // Close `stdin` once written so the command sees the end of it
+        if let Some(mut stdin) = child.stdin.take() {
+            let data = self
+                .stdin
+                .as_ref()
+                .map(|d| d.to_bytes())
+                .unwrap_or_default();
+            std::thread::spawn(move || stdin.write_all(&data));
+        }
+        Ok(child)
+    }
+
     /// Run the command and capture the `Output`
-    #[cfg(feature = "cmd")]
     pub fn output(self) -> Result<std::process::Output, std::io::Error> {
+        self.timed_output().map(|output| output.output)
+    }
+
+    /// Run the command and capture the `Output`, along with whether it was killed for reaching
+    /// its [`Command::timeout`]
+    ///
+    /// ```rust,no_run
+    /// use snapbox::cmd::Command;
+    /// use snapbox::cmd::cargo_bin;
+    ///
+    /// let output = Command::new(cargo_bin("snap-fixture"))
+    ///     .timeout(std::time::Duration::from_secs(1))
+    ///     .env("sleep", "100")
+    ///     .timed_output()
+    ///     .unwrap();
+    /// assert!(output.timed_out);
+    /// ```
+    #[cfg(feature = "cmd")]
+    pub fn timed_output(self) -> Result<TimedOutput, std::io::Error> {
         if self._stderr_to_stdout {
             self.single_output()
         } else {
@@ -308,14 +435,17 @@ impl Command {
         }
     }
 
+    /// Run the command and capture the `Output`, along with whether it was killed for reaching
+    /// its timeout
     #[cfg(not(feature = "cmd"))]
-    pub fn output(self) -> Result<std::process::Output, std::io::Error> {
+    pub fn timed_output(self) -> Result<TimedOutput, std::io::Error> {
         self.split_output()
     }
 
     #[cfg(feature = "cmd")]
-    fn single_output(mut self) -> Result<std::process::Output, std::io::Error> {
-        self.cmd.stdin(std::process::Stdio::piped());
+    fn single_output(mut self) -> Result<TimedOutput, std::io::Error> {
+        let stdin = self.take_stdin();
+        self.cmd.stdin(stdin);
         let (reader, writer) = os_pipe::pipe()?;
         let writer_clone = writer.try_clone()?;
         self.cmd.stdout(writer);
@@ -326,50 +456,152 @@ impl Command {
         // before we read. Here we do this by dropping the Command object.
         drop(self.cmd);
 
+        let limit = OutputLimit::new(self.max_output_bytes);
         let stdout = process_single_io(
             &mut child,
             reader,
             self.stdin.as_ref().map(|d| d.to_bytes()),
+            &limit,
         )?;
 
-        let status = wait(child, self.timeout)?;
+        let (status, timed_out) = wait_limited(child, self.timeout, &limit)?;
         let stdout = stdout.join().unwrap().ok().unwrap_or_default();
-
-        Ok(std::process::Output {
-            status,
-            stdout,
-            stderr: Default::default(),
+        limit.check()?;
+
+        Ok(TimedOutput {
+            output: std::process::Output {
+                status,
+                stdout,
+                stderr: Default::default(),
+            },
+            timed_out,
         })
     }
 
-    fn split_output(mut self) -> Result<std::process::Output, std::io::Error> {
-        self.cmd.stdin(std::process::Stdio::piped());
+    fn split_output(mut self) -> Result<TimedOutput, std::io::Error> {
+        let stdin = self.take_stdin();
+        self.cmd.stdin(stdin);
         self.cmd.stdout(std::process::Stdio::piped());
         self.cmd.stderr(std::process::Stdio::piped());
         let mut child = self.cmd.spawn()?;
 
-        let (stdout, stderr) =
-            process_split_io(&mut child, self.stdin.as_ref().map(|d| d.to_bytes()))?;
+        let limit = OutputLimit::new(self.max_output_bytes);
+        let (stdout, stderr) = process_split_io(
+            &mut child,
+            self.stdin.as_ref().map(|d| d.to_bytes()),
+            &limit,
+        )?;
 
-        let status = wait(child, self.timeout)?;
+        let (status, timed_out) = wait_limited(child, self.timeout, &limit)?;
         let stdout = stdout
             .and_then(|t| t.join().unwrap().ok())
             .unwrap_or_default();
         let stderr = stderr
             .and_then(|t| t.join().unwrap().ok())
             .unwrap_or_default();
+        limit.check()?;
+
+        Ok(TimedOutput {
+            output: std::process::Output {
+                status,
+                stdout,
+                stderr,
+            },
+            timed_out,
+        })
+    }
+
+    /// Run the command, capturing `stdout` and `stderr` line-by-line in the order they arrive
+    ///
+    /// Each stream is read on its own thread, so the order is best-effort: lines written at
+    /// nearly the same time may be captured in either order.  Output the command buffers is
+    /// captured when it is flushed.
+    ///
+    /// ```rust,no_run
+    /// use snapbox::cmd::Command;
+    /// use snapbox::cmd::OutputStream;
+    /// use snapbox::cmd::cargo_bin;
+    ///
+    /// let output = Command::new(cargo_bin("snap-fixture"))
+    ///     .env("stdout", "42")
+    ///     .interleaved_output()
+    ///     .unwrap();
+    /// assert_eq!(output.lines, vec![(OutputStream::Stdout, b"42\n".to_vec())]);
+    /// ```
+    pub fn interleaved_output(mut self) -> Result<InterleavedOutput, std::io::Error> {
+        use std::io::Write;
+
+        let stdin = self.take_stdin();
+        self.cmd.stdin(stdin);
+        self.cmd.stdout(std::process::Stdio::piped());
+        self.cmd.stderr(std::process::Stdio::piped());
+        let mut child = self.cmd.spawn()?;
+
+        let limit = OutputLimit::new(self.max_output_bytes);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let stdin = self.stdin.as_ref().map(|d| d.to_bytes()).and_then(|i| {
+            child
+                .stdin
+                .take()
+                .map(|mut stdin| std::thread::spawn(move || stdin.write_all(&i)))
+        });
+        let stdout = child
+            .stdout
+            .take()
+            .map(|o| threaded_read_lines(o, OutputStream::Stdout, sender.clone(), &limit));
+        let stderr = child
+            .stderr
+            .take()
+            .map(|e| threaded_read_lines(e, OutputStream::Stderr, sender.clone(), &limit));
+        drop(sender);
+
+        // Finish writing stdin before waiting, because waiting drops stdin.
+        stdin.and_then(|t| t.join().unwrap().ok());
+
+        let (status, timed_out) = wait_limited(child, self.timeout, &limit)?;
+        for reader in [stdout, stderr].into_iter().flatten() {
+            let _ = reader.join().unwrap();
+        }
+        limit.check()?;
+        let lines = receiver.into_iter().collect();
 
-        Ok(std::process::Output {
+        Ok(InterleavedOutput {
             status,
-            stdout,
-            stderr,
+            lines,
+            timed_out,
         })
     }
 }
 
+/// Output of [`Command::timed_output`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimedOutput {
+    pub output: std::process::Output,
+    /// Whether the command was killed for reaching its timeout
+    pub timed_out: bool,
+}
+
+/// Stream a line was written to, see [`Command::interleaved_output`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Output of [`Command::interleaved_output`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterleavedOutput {
+    pub status: std::process::ExitStatus,
+    /// Each line, including its terminator, in the order it was read
+    pub lines: Vec<(OutputStream, Vec<u8>)>,
+    /// Whether the command was killed for reaching its timeout
+    pub timed_out: bool,
+}
+
 fn process_split_io(
     child: &mut std::process::Child,
     input: Option<Vec<u8>>,
+    limit: &OutputLimit,
 ) -> std::io::Result<(Option<Stream>, Option<Stream>)> {
     use std::io::Write;
 
@@ -379,8 +611,14 @@ fn process_split_io(
             .take()
             .map(|mut stdin| std::thread::spawn(move || stdin.write_all(&i)))
     });
-    let stdout = child.stdout.take().map(threaded_read);
-    let stderr = child.stderr.take().map(threaded_read);
+    let stdout = child
+        .stdout
+        .take()
+        .map(|o| threaded_read(o, OutputStream::Stdout, limit));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|e| threaded_read(e, OutputStream::Stderr, limit));
 
     // Finish writing stdin before waiting, because waiting drops stdin.
     stdin.and_then(|t| t.join().unwrap().ok());
@@ -393,6 +631,7 @@ fn process_single_io(
     child: &mut std::process::Child,
     stdout: os_pipe::PipeReader,
     input: Option<Vec<u8>>,
+    limit: &OutputLimit,
 ) -> std::io::Result<Stream> {
     use std::io::Write;
 
@@ -402,7 +641,7 @@ fn process_single_io(
             .take()
             .map(|mut stdin| std::thread::spawn(move || stdin.write_all(&i)))
     });
-    let stdout = threaded_read(stdout);
+    let stdout = threaded_read(stdout, OutputStream::Stdout, limit);
     debug_assert!(child.stdout.is_none());
     debug_assert!(child.stderr.is_none());
 
@@ -414,16 +653,114 @@ fn process_single_io(
 
 type Stream = std::thread::JoinHandle<Result<Vec<u8>, std::io::Error>>;
 
-fn threaded_read<R>(mut input: R) -> Stream
+fn threaded_read_lines<R>(
+    input: R,
+    stream: OutputStream,
+    sender: std::sync::mpsc::Sender<(OutputStream, Vec<u8>)>,
+    limit: &OutputLimit,
+) -> std::thread::JoinHandle<Result<(), std::io::Error>>
+where
+    R: std::io::Read + Send + 'static,
+{
+    let limit = limit.clone();
+    std::thread::spawn(move || {
+        let mut input = std::io::BufReader::new(input);
+        let mut read = 0;
+        loop {
+            let mut line = Vec::new();
+            let len = match limit.max {
+                // One byte over to tell reaching the limit from going over it, without buffering
+                // an unbounded line
+                Some(max) => {
+                    let mut input = std::io::Read::take(&mut input, (max + 1 - read) as u64);
+                    std::io::BufRead::read_until(&mut input, b'\n', &mut line)?
+                }
+                None => std::io::BufRead::read_until(&mut input, b'\n', &mut line)?,
+            };
+            if len == 0 {
+                return Ok(());
+            }
+            read += line.len();
+            if limit.exceeds(stream, read) {
+                return Ok(());
+            }
+            let _ = sender.send((stream, line));
+        }
+    })
+}
+
+fn threaded_read<R>(input: R, stream: OutputStream, limit: &OutputLimit) -> Stream
 where
     R: std::io::Read + Send + 'static,
 {
+    use std::io::Read;
+
+    let limit = limit.clone();
     std::thread::spawn(move || {
         let mut ret = Vec::new();
-        input.read_to_end(&mut ret).map(|_| ret)
+        match limit.max {
+            Some(max) => {
+                // One byte over to tell reaching the limit from going over it
+                input.take(max as u64 + 1).read_to_end(&mut ret)?;
+                limit.exceeds(stream, ret.len());
+            }
+            None => {
+                let mut input = input;
+                input.read_to_end(&mut ret)?;
+            }
+        }
+        Ok(ret)
     })
 }
 
+/// See [`Command::max_output_bytes`]
+///
+/// Shared with the threads reading the output, which stop reading once their stream is over the
+/// limit so the command can be killed.
+#[derive(Clone, Debug)]
+struct OutputLimit {
+    max: Option<usize>,
+    exceeded: std::sync::Arc<std::sync::Mutex<Option<OutputStream>>>,
+}
+
+impl OutputLimit {
+    fn new(max: Option<usize>) -> Self {
+        Self {
+            max,
+            exceeded: Default::default(),
+        }
+    }
+
+    /// Record if `read` bytes of `stream` is over the limit
+    fn exceeds(&self, stream: OutputStream, read: usize) -> bool {
+        let exceeds = self.max.map_or(false, |max| max < read);
+        if exceeds {
+            self.exceeded.lock().unwrap().get_or_insert(stream);
+        }
+        exceeds
+    }
+
+    fn is_exceeded(&self) -> bool {
+        self.exceeded.lock().unwrap().is_some()
+    }
+
+    fn check(&self) -> std::io::Result<()> {
+        match (*self.exceeded.lock().unwrap(), self.max) {
+            (Some(stream), Some(max)) => {
+                let stream = match stream {
+                    OutputStream::Stdout => "stdout",
+                    OutputStream::Stderr => "stderr",
+                };
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!("{} exceeded the limit of {} bytes", stream, max),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
 impl From<std::process::Command> for Command {
     fn from(cmd: std::process::Command) -> Self {
         Self::from_std(cmd)
@@ -980,20 +1317,24 @@ fn basic_exit_status(status: std::process::ExitStatus) -> String {
     }
 }
 
+/// Wait on `child`, killing it once `timeout` is reached
+///
+/// Returns whether it was killed for reaching the timeout.
 #[cfg(feature = "cmd")]
 fn wait(
     mut child: std::process::Child,
     timeout: Option<std::time::Duration>,
-) -> std::io::Result<std::process::ExitStatus> {
+) -> std::io::Result<(std::process::ExitStatus, bool)> {
     if let Some(timeout) = timeout {
-        wait_timeout::ChildExt::wait_timeout(&mut child, timeout)
-            .transpose()
-            .unwrap_or_else(|| {
+        match wait_timeout::ChildExt::wait_timeout(&mut child, timeout)? {
+            Some(status) => Ok((status, false)),
+            None => {
                 let _ = child.kill();
-                child.wait()
-            })
+                child.wait().map(|status| (status, true))
+            }
+        }
     } else {
-        child.wait()
+        child.wait().map(|status| (status, false))
     }
 }
 
@@ -1001,8 +1342,32 @@ fn wait(
 fn wait(
     mut child: std::process::Child,
     _timeout: Option<std::time::Duration>,
-) -> std::io::Result<std::process::ExitStatus> {
-    child.wait()
+) -> std::io::Result<(std::process::ExitStatus, bool)> {
+    child.wait().map(|status| (status, false))
+}
+
+/// Like [`wait`] but killing `child` once its output is over the `limit`
+fn wait_limited(
+    mut child: std::process::Child,
+    timeout: Option<std::time::Duration>,
+    limit: &OutputLimit,
+) -> std::io::Result<(std::process::ExitStatus, bool)> {
+    if limit.max.is_none() {
+        return wait(child, timeout);
+    }
+
+    let start = std::time::Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, false));
+        }
+        let timed_out = timeout.map_or(false, |timeout| timeout <= start.elapsed());
+        if timed_out || limit.is_exceeded() {
+            let _ = child.kill();
+            return child.wait().map(|status| (status, timed_out));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
 }
 
 pub use snapbox_macros::cargo_bin;
@@ -1032,7 +1397,7 @@ fn target_dir() -> std::path::PathBuf {
 }
 
 #[cfg(feature = "examples")]
-pub use examples::{compile_example, compile_examples};
+pub use examples::{compile_bins, compile_example, compile_examples};
 
 #[cfg(feature = "examples")]
 pub(crate) mod examples {
@@ -1066,7 +1431,7 @@ pub(crate) mod examples {
                 .decode()
                 .map_err(|e| crate::Error::new(e.to_string()))?;
             crate::debug!("Message: {:?}", message);
-            if let Some(bin) = decode_example_message(&message) {
+            if let Some(bin) = decode_message(&message, "example") {
                 let (name, bin) = bin?;
                 assert_eq!(target_name, name);
                 return bin;
@@ -1113,7 +1478,7 @@ pub(crate) mod examples {
                 .decode()
                 .map_err(|e| crate::Error::new(e.to_string()))?;
             crate::debug!("Message: {:?}", message);
-            if let Some(bin) = decode_example_message(&message) {
+            if let Some(bin) = decode_message(&message, "example") {
                 let (name, bin) = bin?;
                 examples.insert(name.to_owned(), bin);
             }
@@ -1122,9 +1487,53 @@ pub(crate) mod examples {
         Ok(examples.into_iter())
     }
 
+    /// Prepare all bins for testing
+    ///
+    /// Unlike `cargo_bin!`, this does not inherit all of the current compiler settings.  It
+    /// will match the current target and profile but will not get feature flags.  Pass those arguments
+    /// to the compiler via `args`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// let bins = snapbox::cmd::compile_bins(["--features", "foo"]).unwrap().collect::<Vec<_>>();
+    /// ```
+    #[cfg(feature = "examples")]
+    pub fn compile_bins<'a>(
+        args: impl IntoIterator<Item = &'a str>,
+    ) -> Result<
+        impl Iterator<Item = (String, Result<std::path::PathBuf, crate::Error>)>,
+        crate::Error,
+    > {
+        crate::debug!("Compiling bins");
+        let mut bins = std::collections::BTreeMap::new();
+
+        let messages = escargot::CargoBuild::new()
+            .current_target()
+            .current_release()
+            .arg("--bins")
+            .args(args)
+            .exec()
+            .map_err(|e| crate::Error::new(e.to_string()))?;
+        for message in messages {
+            let message = message.map_err(|e| crate::Error::new(e.to_string()))?;
+            let message = message
+                .decode()
+                .map_err(|e| crate::Error::new(e.to_string()))?;
+            crate::debug!("Message: {:?}", message);
+            if let Some(bin) = decode_message(&message, "bin") {
+                let (name, bin) = bin?;
+                bins.insert(name.to_owned(), bin);
+            }
+        }
+
+        Ok(bins.into_iter())
+    }
+
     #[allow(clippy::type_complexity)]
-    fn decode_example_message<'m>(
+    fn decode_message<'m>(
         message: &'m escargot::format::Message,
+        kind: &str,
     ) -> Option<Result<(&'m str, Result<std::path::PathBuf, crate::Error>), crate::Error>> {
         match message {
             escargot::format::Message::CompilerMessage(msg) => {
@@ -1138,7 +1547,7 @@ pub(crate) mod examples {
                         .as_deref()
                         .unwrap_or_else(|| msg.message.message.as_ref())
                         .to_owned();
-                    if is_example_target(&msg.target) {
+                    if is_target(&msg.target, kind) {
                         let bin = Err(crate::Error::new(output));
                         Some(Ok((msg.target.name.as_ref(), bin)))
                     } else {
@@ -1149,7 +1558,7 @@ pub(crate) mod examples {
                 }
             }
             escargot::format::Message::CompilerArtifact(artifact) => {
-                if !artifact.profile.test && is_example_target(&artifact.target) {
+                if !artifact.profile.test && is_target(&artifact.target, kind) {
                     let path = artifact
                         .executable
                         .clone()
@@ -1164,7 +1573,7 @@ pub(crate) mod examples {
         }
     }
 
-    fn is_example_target(target: &escargot::format::Target) -> bool {
-        target.crate_types == ["bin"] && target.kind == ["example"]
+    fn is_target(target: &escargot::format::Target, kind: &str) -> bool {
+        target.crate_types == ["bin"] && target.kind == [kind]
     }
 }
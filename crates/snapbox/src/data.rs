@@ -291,6 +291,25 @@ impl Normalize for NormalizePaths {
     }
 }
 
+pub struct NormalizeTrailingWhitespace;
+impl Normalize for NormalizeTrailingWhitespace {
+    fn normalize(&self, data: Data) -> Data {
+        match data.inner {
+            DataInner::Binary(bin) => Data::binary(bin),
+            DataInner::Text(text) => {
+                let lines = crate::utils::strip_trailing_whitespace(&text);
+                Data::text(lines)
+            }
+            #[cfg(feature = "json")]
+            DataInner::Json(value) => {
+                let mut value = value;
+                normalize_value(&mut value, crate::utils::strip_trailing_whitespace);
+                Data::json(value)
+            }
+        }
+    }
+}
+
 pub struct NormalizeMatches<'a> {
     substitutions: &'a crate::Substitutions,
     pattern: &'a Data,
@@ -453,6 +472,13 @@ mod test {
         assert_eq!(bytes, rendered);
     }
 
+    #[test]
+    fn strip_trailing_whitespace() {
+        let d = Data::text("Hello \t\nWorld  \r\n\n  Trailing ");
+        let d = d.normalize(NormalizeTrailingWhitespace);
+        assert_eq!(d, Data::text("Hello\nWorld\r\n\n  Trailing"));
+    }
+
     // Tests for checking all types are coercible to each other and
     // for when the coercion should fail
     #[test]
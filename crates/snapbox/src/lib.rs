@@ -114,7 +114,9 @@ pub use action::DEFAULT_ACTION_ENV;
 pub use assert::Assert;
 pub use data::Data;
 pub use data::DataFormat;
-pub use data::{Normalize, NormalizeMatches, NormalizeNewlines, NormalizePaths};
+pub use data::{
+    Normalize, NormalizeMatches, NormalizeNewlines, NormalizePaths, NormalizeTrailingWhitespace,
+};
 pub use error::Error;
 pub use snapbox_macros::debug;
 pub use substitutions::Substitutions;
@@ -9,6 +9,9 @@ use std::borrow::Cow;
 pub struct Substitutions {
     vars: std::collections::BTreeMap<&'static str, Cow<'static, str>>,
     unused: std::collections::BTreeSet<&'static str>,
+    #[cfg(feature = "regex")]
+    redactions: Vec<Redaction>,
+    escapes: bool,
 }
 
 impl Substitutions {
@@ -61,6 +64,81 @@ impl Substitutions {
         Ok(())
     }
 
+    /// Replace matches of `regex` in each line with `replacement`
+    ///
+    /// `replacement` may refer to capture groups, like `$1` or `${name}`.  This is applied to
+    /// both the actual and expected content, so either can be redacted.
+    ///
+    /// ```rust
+    /// let mut subst = snapbox::Substitutions::new();
+    /// subst.redact(r"took \d+ms", "took [DURATION]ms").unwrap();
+    /// let output = subst.normalize("took 1234ms", "took [DURATION]ms");
+    /// assert_eq!(output, "took [DURATION]ms");
+    /// ```
+    #[cfg(feature = "regex")]
+    pub fn redact(
+        &mut self,
+        regex: &str,
+        replacement: impl Into<Cow<'static, str>>,
+    ) -> Result<(), crate::Error> {
+        let regex = regex::Regex::new(regex)
+            .map_err(|e| format!("Invalid redaction `{}`: {}", regex, e))?;
+        self.redactions.push(Redaction {
+            regex,
+            replacement: replacement.into(),
+        });
+        Ok(())
+    }
+
+    /// Remove the match pattern for `key`, including redactions that replace with it
+    ///
+    /// Returns whether there was anything to remove.
+    ///
+    /// ```rust
+    /// let mut subst = snapbox::Substitutions::new();
+    /// subst.insert("[EXE]", std::env::consts::EXE_SUFFIX).unwrap();
+    /// assert!(subst.remove("[EXE]"));
+    /// assert!(!subst.remove("[EXE]"));
+    /// ```
+    pub fn remove(&mut self, key: &str) -> bool {
+        let mut removed = self.vars.remove(key).is_some();
+        removed |= self.unused.remove(key);
+        #[cfg(feature = "regex")]
+        {
+            let before = self.redactions.len();
+            self.redactions
+                .retain(|redaction| !redaction.replacement.contains(key));
+            removed |= self.redactions.len() != before;
+        }
+        removed
+    }
+
+    /// Match `[[..]]` as a literal `[..]` and a `[[...]]` line as a literal `...` line
+    ///
+    /// When `pattern` doesn't match, `[..]` and `...` lines in what [`Substitutions::normalize`]
+    /// returns are escaped, so it only matches `input`.
+    ///
+    /// ```rust
+    /// let mut subst = snapbox::Substitutions::new();
+    /// subst.escapes(true);
+    /// let output = subst.normalize("Usage: cmd [..]", "Usage: [..] [[..]]");
+    /// assert_eq!(output, "Usage: [..] [[..]]");
+    /// let output = subst.normalize("Usage: cmd [..]", "Usage: other");
+    /// assert_eq!(output, "Usage: cmd [[..]]");
+    /// ```
+    pub fn escapes(&mut self, yes: bool) {
+        self.escapes = yes;
+    }
+
+    /// Insert all match patterns from `other`, replacing those with the same key
+    pub fn update(&mut self, other: &Self) {
+        self.vars
+            .extend(other.vars.iter().map(|(k, v)| (*k, v.clone())));
+        self.unused.extend(other.unused.iter().copied());
+        #[cfg(feature = "regex")]
+        self.redactions.extend(other.redactions.iter().cloned());
+    }
+
     /// Apply match pattern to `input`
     ///
     /// If `pattern` matches `input`, then `pattern` is returned.
@@ -77,7 +155,7 @@ impl Substitutions {
     }
 
     fn substitute<'v>(&self, value: &'v str) -> Cow<'v, str> {
-        let mut value = Cow::Borrowed(value);
+        let mut value = self.redact_all(value);
         for (var, replace) in self.vars.iter() {
             debug_assert!(!replace.is_empty());
             value = Cow::Owned(value.replace(replace.as_ref(), var));
@@ -85,19 +163,81 @@ impl Substitutions {
         value
     }
 
+    /// Escape and substitute `value` so it only matches itself
+    fn literal<'v>(&self, value: &'v str) -> Cow<'v, str> {
+        if !self.escapes {
+            return self.substitute(value);
+        }
+        match escape(value) {
+            Cow::Borrowed(value) => self.substitute(value),
+            Cow::Owned(value) => Cow::Owned(self.substitute(&value).into_owned()),
+        }
+    }
+
+    /// Split `pattern` on its `[..]`, see [`Substitutions::escapes`]
+    fn split_wildcards<'p>(&self, pattern: &'p str) -> Vec<&'p str> {
+        if self.escapes {
+            split_wildcards(pattern)
+        } else {
+            pattern.split(WILDCARD).collect()
+        }
+    }
+
+    fn unescape<'s>(&self, section: &'s str) -> Cow<'s, str> {
+        if self.escapes {
+            unescape(section)
+        } else {
+            Cow::Borrowed(section)
+        }
+    }
+
     fn clear<'v>(&self, pattern: &'v str) -> Cow<'v, str> {
+        let mut pattern = self.redact_all(pattern);
         if pattern.contains('[') {
-            let mut pattern = Cow::Borrowed(pattern);
             for var in self.unused.iter() {
                 pattern = Cow::Owned(pattern.replace(var, ""));
             }
-            pattern
-        } else {
-            Cow::Borrowed(pattern)
         }
+        pattern
     }
+
+    #[cfg(feature = "regex")]
+    fn redact_all<'v>(&self, value: &'v str) -> Cow<'v, str> {
+        let mut value = Cow::Borrowed(value);
+        for redaction in &self.redactions {
+            if let Cow::Owned(redacted) = redaction
+                .regex
+                .replace_all(value.as_ref(), redaction.replacement.as_ref())
+            {
+                value = Cow::Owned(redacted);
+            }
+        }
+        value
+    }
+
+    #[cfg(not(feature = "regex"))]
+    fn redact_all<'v>(&self, value: &'v str) -> Cow<'v, str> {
+        Cow::Borrowed(value)
+    }
+}
+
+#[cfg(feature = "regex")]
+#[derive(Clone, Debug)]
+struct Redaction {
+    regex: regex::Regex,
+    replacement: Cow<'static, str>,
 }
 
+#[cfg(feature = "regex")]
+impl PartialEq for Redaction {
+    fn eq(&self, other: &Self) -> bool {
+        self.regex.as_str() == other.regex.as_str() && self.replacement == other.replacement
+    }
+}
+
+#[cfg(feature = "regex")]
+impl Eq for Redaction {}
+
 fn validate_key(key: &'static str) -> Result<&'static str, crate::Error> {
     if !key.starts_with('[') || !key.ends_with(']') {
         return Err(format!("Key `{}` is not enclosed in []", key).into());
@@ -132,7 +272,7 @@ fn normalize(input: &str, pattern: &str, substitutions: &Substitutions) -> Strin
                 input_lines[input_index..]
                     .iter()
                     .copied()
-                    .map(|s| substitutions.substitute(s)),
+                    .map(|s| substitutions.literal(s)),
             );
             break 'outer;
         };
@@ -173,7 +313,7 @@ fn normalize(input: &str, pattern: &str, substitutions: &Substitutions) -> Strin
                     input_lines[input_index..]
                         .iter()
                         .copied()
-                        .map(|s| substitutions.substitute(s)),
+                        .map(|s| substitutions.literal(s)),
                 );
                 break 'outer;
             }
@@ -187,24 +327,22 @@ fn normalize(input: &str, pattern: &str, substitutions: &Substitutions) -> Strin
                     .find(|(_, l)| **l == future_input_line || is_line_elide(**l))
                     .map(|(i, _)| next_pattern_index + i)
                 {
-                    normalized.extend(
-                        input_lines[input_index..future_input_index]
-                            .iter()
-                            .copied()
-                            .map(|s| substitutions.substitute(s)),
-                    );
+                    normalized.extend(reapply_lines(
+                        &input_lines[input_index..future_input_index],
+                        &pattern_lines[pattern_index..future_pattern_index],
+                        substitutions,
+                    ));
                     pattern_index = future_pattern_index;
                     input_index = future_input_index;
                     continue 'outer;
                 }
             }
 
-            normalized.extend(
-                input_lines[input_index..]
-                    .iter()
-                    .copied()
-                    .map(|s| substitutions.substitute(s)),
-            );
+            normalized.extend(reapply_lines(
+                &input_lines[input_index..],
+                &pattern_lines[pattern_index..],
+                substitutions,
+            ));
             break 'outer;
         }
     }
@@ -212,12 +350,206 @@ fn normalize(input: &str, pattern: &str, substitutions: &Substitutions) -> Strin
     normalized.join("")
 }
 
+/// Normalize `input` lines that replaced `pattern` lines
+///
+/// When each changed line lines up with a pattern line, the parts of the pattern that still match
+/// are kept, rather than discarding all of the pattern's `[..]`.
+fn reapply_lines<'i>(
+    input: &[&'i str],
+    pattern: &[&str],
+    substitutions: &Substitutions,
+) -> Vec<Cow<'i, str>> {
+    if input.len() == pattern.len() {
+        input
+            .iter()
+            .zip(pattern)
+            .map(|(input, pattern)| reapply_line(input, pattern, substitutions))
+            .collect()
+    } else {
+        input
+            .iter()
+            .map(|input| substitutions.literal(input))
+            .collect()
+    }
+}
+
+/// Keep the start and end of `pattern` that still match `input`, with the rest of `input` literal
+fn reapply_line<'i>(input: &'i str, pattern: &str, substitutions: &Substitutions) -> Cow<'i, str> {
+    let literal = substitutions.literal(input);
+    let cleared = substitutions.clear(pattern);
+    let (pattern_line, _) = split_terminator(cleared.as_ref());
+    let sections = substitutions.split_wildcards(pattern_line);
+    if sections.len() == 1 {
+        return literal;
+    }
+
+    let subbed = substitutions.substitute(input);
+    let (line, terminator) = split_terminator(subbed.as_ref());
+
+    // Match sections from the start, with the first being anchored
+    let mut head = String::new();
+    let mut start = 0;
+    let mut head_count = 0;
+    for (i, section) in sections.iter().enumerate() {
+        let unescaped = substitutions.unescape(section);
+        let found = if i == 0 {
+            line.starts_with(unescaped.as_ref()).then_some(0)
+        } else {
+            line[start..]
+                .find(unescaped.as_ref())
+                .map(|offset| start + offset)
+        };
+        match found {
+            Some(found) => {
+                if i != 0 {
+                    head.push_str("[..]");
+                }
+                head.push_str(section);
+                start = found + unescaped.len();
+                head_count += 1;
+            }
+            None => break,
+        }
+    }
+    if head_count == sections.len() {
+        // Already matches
+        return Cow::Owned(format!("{}{}", pattern_line, terminator));
+    }
+
+    // Match the remaining sections from the end, with the last being anchored
+    let mut tail = Vec::new();
+    let mut end = line.len();
+    let last = sections.len() - 1;
+    for (i, section) in sections.iter().enumerate().skip(head_count).rev() {
+        let unescaped = substitutions.unescape(section);
+        let found = if i == last {
+            line[start..end]
+                .ends_with(unescaped.as_ref())
+                .then(|| end - unescaped.len())
+        } else {
+            line[start..end]
+                .rfind(unescaped.as_ref())
+                .map(|offset| start + offset)
+        };
+        match found {
+            Some(found) => {
+                tail.push(*section);
+                end = found;
+            }
+            None => break,
+        }
+    }
+    tail.reverse();
+
+    let mut reapplied = head;
+    if substitutions.escapes {
+        reapplied.push_str(&escape_inline(&line[start..end]));
+    } else {
+        reapplied.push_str(&line[start..end]);
+    }
+    reapplied.push_str(&tail.join("[..]"));
+    if line_matches(line, &reapplied, substitutions) {
+        reapplied.push_str(terminator);
+        Cow::Owned(reapplied)
+    } else {
+        literal
+    }
+}
+
+fn split_terminator(line: &str) -> (&str, &str) {
+    match line.strip_suffix('\n') {
+        Some(line) => match line.strip_suffix('\r') {
+            Some(line) => (line, "\r\n"),
+            None => (line, "\n"),
+        },
+        None => (line, ""),
+    }
+}
+
+const WILDCARD: &str = "[..]";
+const ESCAPED_WILDCARD: &str = "[[..]]";
+const ELIDE: &str = "...";
+
+/// Escape `[..]` and `...` lines so `text` matches itself
+///
+/// - `[..]` is escaped as `[[..]]`
+/// - A `...` line is escaped as `[[...]]`
+pub(crate) fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(WILDCARD) && !text.contains(ELIDE) {
+        return Cow::Borrowed(text);
+    }
+
+    let escaped = crate::utils::LinesWithTerminator::new(text)
+        .map(|line| {
+            let (content, terminator) = split_terminator(line);
+            if is_elide_literal(content) {
+                format!("[[{}]]{}", content, terminator)
+            } else {
+                format!("{}{}", escape_inline(content), terminator)
+            }
+        })
+        .collect();
+    Cow::Owned(escaped)
+}
+
+fn escape_inline(text: &str) -> Cow<'_, str> {
+    if text.contains(WILDCARD) {
+        Cow::Owned(text.replace(WILDCARD, ESCAPED_WILDCARD))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+fn unescape(section: &str) -> Cow<'_, str> {
+    if section.contains(ESCAPED_WILDCARD) {
+        Cow::Owned(section.replace(ESCAPED_WILDCARD, WILDCARD))
+    } else {
+        Cow::Borrowed(section)
+    }
+}
+
+/// `...`, possibly already escaped as `[[...]]`, `[[[[...]]]]`, etc
+fn is_elide_literal(content: &str) -> bool {
+    let mut content = content;
+    while let Some(inner) = content
+        .strip_prefix("[[")
+        .and_then(|c| c.strip_suffix("]]"))
+    {
+        content = inner;
+    }
+    content == ELIDE
+}
+
+/// Split `pattern` on its `[..]`, leaving any escaped `[[..]]` in the sections
+fn split_wildcards(pattern: &str) -> Vec<&str> {
+    let mut sections = Vec::new();
+    let mut section_start = 0;
+    let mut i = 0;
+    while i < pattern.len() {
+        if pattern.as_bytes()[i] == b'[' {
+            let remainder = &pattern[i..];
+            if remainder.starts_with(ESCAPED_WILDCARD) {
+                i += ESCAPED_WILDCARD.len();
+                continue;
+            } else if remainder.starts_with(WILDCARD) {
+                sections.push(&pattern[section_start..i]);
+                i += WILDCARD.len();
+                section_start = i;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    sections.push(&pattern[section_start..]);
+    sections
+}
+
 fn is_line_elide(line: &str) -> bool {
     line == "...\n" || line == "..."
 }
 
 fn line_matches(line: &str, pattern: &str, substitutions: &Substitutions) -> bool {
-    if line == pattern {
+    if line == pattern && !(substitutions.escapes && pattern.contains(ESCAPED_WILDCARD)) {
         return true;
     }
 
@@ -226,13 +558,24 @@ fn line_matches(line: &str, pattern: &str, substitutions: &Substitutions) -> boo
 
     let pattern = substitutions.clear(pattern);
 
-    let mut sections = pattern.split("[..]").peekable();
+    let (pattern_content, pattern_terminator) = split_terminator(pattern.as_ref());
+    if substitutions.escapes && is_elide_literal(pattern_content) && pattern_content != ELIDE {
+        let elide = &pattern_content[2..pattern_content.len() - 2];
+        return split_terminator(line) == (elide, pattern_terminator);
+    }
+
+    let sections: Vec<_> = substitutions
+        .split_wildcards(pattern.as_ref())
+        .into_iter()
+        .map(|section| substitutions.unescape(section))
+        .collect();
+    let mut sections = sections.iter().peekable();
     while let Some(section) = sections.next() {
-        if let Some(remainder) = line.strip_prefix(section) {
+        if let Some(remainder) = line.strip_prefix(section.as_ref()) {
             if let Some(next_section) = sections.peek() {
                 if next_section.is_empty() {
                     line = "";
-                } else if let Some(restart_index) = remainder.find(next_section) {
+                } else if let Some(restart_index) = remainder.find(next_section.as_ref()) {
                     line = &remainder[restart_index..];
                 }
             } else {
@@ -358,6 +701,108 @@ mod test {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn inline_elide_diverge() {
+        let input = "Hello\nCompiled foo v0.2.0 (/home/foo)\nGoodbye";
+        let pattern = "Hello\nCompiling foo v[..] ([..])\nGoodbye";
+        let expected = "Hello\nCompiled foo v0.2.0 ([..])\nGoodbye";
+        let actual = normalize(input, pattern, &Substitutions::new());
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn inline_elide_diverge_middle() {
+        let input = "Hello\nsrc/lib.rs:10: warning\nGoodbye";
+        let pattern = "Hello\n[..].rs:[..]: error\nGoodbye";
+        let expected = "Hello\n[..].rs:10: warning\nGoodbye";
+        let actual = normalize(input, pattern, &Substitutions::new());
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn inline_elide_diverge_line_count() {
+        let input = "Hello\nWorld\nMoon\nGoodbye";
+        let pattern = "Hello\nW[..]x\nGoodbye";
+        let expected = "Hello\nWorld\nMoon\nGoodbye";
+        let actual = normalize(input, pattern, &Substitutions::new());
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn redact_captures() {
+        let mut sub = Substitutions::new();
+        sub.redact(r"(?P<name>\w+)@\d+", "${name}@[ID]").unwrap();
+        let input = "Hello\nworker@1234 started\nGoodbye";
+        let pattern = "Hello\nworker@[ID] started\nGoodbye";
+        let expected = pattern;
+        let actual = normalize(input, pattern, &sub);
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn redact_both_sides() {
+        let mut sub = Substitutions::new();
+        sub.redact(r"took \d+ms", "took [DURATION]ms").unwrap();
+        let input = "Hello\ntook 1234ms\nGoodbye";
+
+        let pattern = "Hello\ntook 10ms\nGoodbye";
+        let expected = pattern;
+        let actual = normalize(input, pattern, &sub);
+        assert_eq!(expected, actual);
+
+        let pattern = "Hello\nGoodbye";
+        let expected = "Hello\ntook [DURATION]ms\nGoodbye";
+        let actual = normalize(input, pattern, &sub);
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn escaped_wildcard() {
+        let input = "Hello\nUsage: cmd [..]\n...\nGoodbye";
+        let pattern = "Hello\nUsage: [..] [[..]]\n[[...]]\nGoodbye";
+        let expected = pattern;
+        let actual = normalize(input, pattern, &escaping());
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn escaped_wildcard_is_literal() {
+        let input = "Hello\nUsage: cmd <args>\nabc\nGoodbye";
+        let pattern = "Hello\nUsage: [..] [[..]]\n[[...]]\nGoodbye";
+        let expected = "Hello\nUsage: cmd <args>\nabc\nGoodbye";
+        let actual = normalize(input, pattern, &escaping());
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn escape_literal_output() {
+        let input = "Hello\nUsage: cmd [..] [[..]]\n...\n[[...]]\nGoodbye";
+        let pattern = "Hello\nMoon\nGoodbye";
+        let expected = "Hello\nUsage: cmd [[..]] [[[..]]]\n[[...]]\n[[[[...]]]]\nGoodbye";
+        let actual = normalize(input, pattern, &escaping());
+        assert_eq!(expected, actual);
+
+        let roundtrip = normalize(input, &actual, &escaping());
+        assert_eq!(actual, roundtrip);
+    }
+
+    #[test]
+    fn escapes_disabled() {
+        let input = "Usage: cmd [..]\n...\n";
+        let pattern = "Usage: [..]\nabc\n";
+        let expected = "Usage: [..]\n...\n";
+        let actual = normalize(input, pattern, &Substitutions::new());
+        assert_eq!(expected, actual);
+    }
+
+    fn escaping() -> Substitutions {
+        let mut substitutions = Substitutions::new();
+        substitutions.escapes(true);
+        substitutions
+    }
+
     #[test]
     fn line_matches_cases() {
         let cases = [
@@ -403,6 +848,17 @@ mod test {
         }
     }
 
+    #[test]
+    #[cfg(feature = "regex")]
+    fn remove_redaction() {
+        let mut sub = Substitutions::new();
+        sub.redact(r"took \d+ms", "took [DURATION]ms").unwrap();
+        assert!(sub.remove("[DURATION]"));
+        let input = "took 1234ms";
+        let actual = normalize(input, "took [DURATION]ms", &sub);
+        assert_eq!(input, actual);
+    }
+
     #[test]
     fn test_validate_key() {
         let cases = [
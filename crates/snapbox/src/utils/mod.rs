@@ -20,6 +20,31 @@ fn normalize_paths_chars(data: impl Iterator<Item = char>) -> impl Iterator<Item
     data.map(|c| if c == '\\' { '/' } else { c })
 }
 
+/// Escape match patterns, like `[..]` and `...` lines, so `data` only matches itself
+///
+/// See [`Substitutions`][crate::Substitutions]
+pub fn escape_patterns(data: &str) -> String {
+    crate::substitutions::escape(data).into_owned()
+}
+
+/// Remove trailing whitespace from each line, preserving line endings
+pub fn strip_trailing_whitespace(data: &str) -> String {
+    LinesWithTerminator::new(data)
+        .map(|line| {
+            let terminator = if line.ends_with("\r\n") {
+                "\r\n"
+            } else if line.ends_with('\n') {
+                "\n"
+            } else {
+                ""
+            };
+            let content = &line[..line.len() - terminator.len()];
+            let content = content.trim_end_matches(|c: char| c.is_ascii_whitespace());
+            format!("{}{}", content, terminator)
+        })
+        .collect()
+}
+
 /// "Smart" text normalization
 ///
 /// This includes
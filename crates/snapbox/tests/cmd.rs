@@ -33,3 +33,93 @@ fn large_stdout_single() {
         .assert()
         .success();
 }
+
+#[test]
+fn interleaved_stdout_stderr() {
+    let output = snapbox::cmd::Command::new(snapbox::cmd::cargo_bin("snap-fixture"))
+        .env("stdout", "Hello")
+        .env("stderr", "World")
+        .interleaved_output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // Reading each stream on its own thread means their relative order isn't guaranteed
+    let mut lines = output.lines;
+    lines.sort_by_key(|(stream, _)| *stream == snapbox::cmd::OutputStream::Stderr);
+    assert_eq!(
+        lines,
+        vec![
+            (snapbox::cmd::OutputStream::Stdout, b"Hello\n".to_vec()),
+            (snapbox::cmd::OutputStream::Stderr, b"World\n".to_vec()),
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "cmd")]
+fn timed_out() {
+    let output = snapbox::cmd::Command::new(snapbox::cmd::cargo_bin("snap-fixture"))
+        .env("sleep", "30")
+        .timeout(std::time::Duration::from_millis(100))
+        .timed_output()
+        .unwrap();
+    assert!(output.timed_out);
+
+    let output = snapbox::cmd::Command::new(snapbox::cmd::cargo_bin("snap-fixture"))
+        .timeout(std::time::Duration::from_secs(30))
+        .timed_output()
+        .unwrap();
+    assert!(!output.timed_out);
+}
+
+#[test]
+#[cfg(all(feature = "cmd", unix))]
+fn killed_without_timing_out() {
+    // Like the OOM killer, rather than the timeout
+    let output = snapbox::cmd::Command::new("sh")
+        .args(["-c", "kill -9 $$"])
+        .timeout(std::time::Duration::from_secs(30))
+        .timed_output()
+        .unwrap();
+    assert_eq!(output.output.status.code(), None);
+    assert!(!output.timed_out);
+}
+
+#[test]
+fn max_output_bytes() {
+    let err = snapbox::cmd::Command::new(snapbox::cmd::cargo_bin("snap-fixture"))
+        .env("echo_large", "1")
+        .max_output_bytes(1024)
+        .output()
+        .unwrap_err();
+    assert_eq!(err.to_string(), "stdout exceeded the limit of 1024 bytes");
+
+    let output = snapbox::cmd::Command::new(snapbox::cmd::cargo_bin("snap-fixture"))
+        .env("stdout", "Hello")
+        .max_output_bytes(6)
+        .output()
+        .unwrap();
+    assert_eq!(output.stdout, b"Hello\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn max_output_bytes_interleaved_long_line() {
+    let err = snapbox::cmd::Command::new("sh")
+        .args(["-c", "head -c 10000000 /dev/zero"])
+        .max_output_bytes(1024)
+        .interleaved_output()
+        .unwrap_err();
+    assert_eq!(err.to_string(), "stdout exceeded the limit of 1024 bytes");
+}
+
+#[test]
+#[cfg(unix)]
+fn umask() {
+    let output = snapbox::cmd::Command::new("sh")
+        .args(["-c", "umask"])
+        .umask(0o027)
+        .output()
+        .unwrap();
+    assert_eq!(output.stdout, b"0027\n");
+}
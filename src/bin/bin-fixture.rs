@@ -12,6 +12,30 @@ fn run() -> Result<(), Box<dyn Error>> {
         eprintln!("{}", text);
     }
 
+    if let Ok(raw) = env::var("interleave") {
+        for entry in raw.split(',') {
+            match entry.split_once(':') {
+                Some(("err", text)) => eprintln!("{}", text),
+                Some((_, text)) => println!("{}", text),
+                None => println!("{}", entry),
+            }
+        }
+    }
+
+    if let Ok(names) = env::var("print_env") {
+        for name in names.split(',') {
+            if let Ok(value) = env::var(name) {
+                println!("{}={}", name, value);
+            }
+        }
+    }
+
+    if env::var("echo_stdin").as_deref() == Ok("1") {
+        let mut data = Vec::new();
+        io::Read::read_to_end(&mut io::stdin(), &mut data)?;
+        io::stdout().write_all(&data)?;
+    }
+
     if env::var("echo_large").as_deref() == Ok("1") {
         for i in 0..(128 * 1024) {
             println!("{}", i);
@@ -24,12 +48,22 @@ fn run() -> Result<(), Box<dyn Error>> {
         }
     }
 
+    if env::var("print_arg0").as_deref() == Ok("1") {
+        if let Some(arg0) = env::args().next() {
+            println!("{}", arg0);
+        }
+    }
+
     if let Ok(raw) = env::var("write") {
         let (path, text) = raw.split_once('=').unwrap_or((raw.as_str(), ""));
         std::fs::write(path.trim(), text.trim()).unwrap();
     }
 
     if let Ok(path) = env::var("cat") {
+        let path = match path.strip_prefix("~/") {
+            Some(rel) => std::path::Path::new(&env::var("HOME").unwrap()).join(rel),
+            None => path.into(),
+        };
         let text = std::fs::read_to_string(path).unwrap();
         eprintln!("{}", text);
     }
@@ -49,3 +49,158 @@ pub fn compile_examples<'a>(
 ) -> Result<impl Iterator<Item = (String, crate::schema::Bin)>, crate::Error> {
     snapbox::cmd::compile_examples(args).map(|i| i.map(|(name, path)| (name, path.into())))
 }
+
+/// Build a [`Bin::CargoWorkspace`][crate::schema::Bin::CargoWorkspace], returning the built path
+///
+/// Other bins are returned as-is.
+pub(crate) fn build_workspace_bin(bin: &crate::schema::Bin) -> crate::schema::Bin {
+    match bin {
+        crate::schema::Bin::CargoWorkspace {
+            package,
+            bin,
+            features,
+            profile,
+        } => {
+            let bin = bin.as_deref().unwrap_or(package);
+            match build_workspace_package(package, bin, features, profile) {
+                Ok(path) => crate::schema::Bin::Path(path),
+                Err(err) => crate::schema::Bin::Error(err),
+            }
+        }
+        _ => bin.clone(),
+    }
+}
+
+fn build_workspace_package(
+    package: &str,
+    bin: &str,
+    features: &[String],
+    profile: &str,
+) -> Result<std::path::PathBuf, crate::Error> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+
+    let metadata = std::process::Command::new(&cargo)
+        .args(["metadata", "--format-version", "1", "--no-deps"])
+        .output()
+        .map_err(|e| format!("Failed to run `cargo metadata`: {}", e))?;
+    if !metadata.status.success() {
+        return Err(format!(
+            "`cargo metadata` failed:\n{}",
+            String::from_utf8_lossy(&metadata.stderr)
+        )
+        .into());
+    }
+    let (workspace_root, target_dir) = parse_metadata(&metadata.stdout)?;
+    let target_dir = std::path::Path::new(&target_dir);
+    let target = current_target(target_dir);
+
+    let mut cmd = std::process::Command::new(&cargo);
+    cmd.current_dir(workspace_root).args([
+        "build",
+        "--package",
+        package,
+        "--bin",
+        bin,
+        "--profile",
+        profile,
+    ]);
+    if !features.is_empty() {
+        cmd.arg("--features").arg(features.join(","));
+    }
+    if let Some(target) = &target {
+        cmd.arg("--target").arg(target);
+    }
+    let build = cmd
+        .output()
+        .map_err(|e| format!("Failed to run `cargo build`: {}", e))?;
+    if !build.status.success() {
+        return Err(format!(
+            "Failed to build `{}` from `{}`:\n{}",
+            bin,
+            package,
+            String::from_utf8_lossy(&build.stderr)
+        )
+        .into());
+    }
+
+    let mut path = target_dir.to_owned();
+    if let Some(target) = &target {
+        path.push(target);
+    }
+    path.push(profile_dir(profile));
+    path.push(format!("{}{}", bin, std::env::consts::EXE_SUFFIX));
+    if !path.exists() {
+        return Err(format!("Built bin is missing: {}", path.display()).into());
+    }
+    Ok(path)
+}
+
+/// The workspace root and target directory from `cargo metadata`
+#[cfg(feature = "json")]
+fn parse_metadata(raw: &[u8]) -> Result<(String, String), crate::Error> {
+    let metadata: serde_json::Value = serde_json::from_slice(raw)
+        .map_err(|e| format!("Failed to parse `cargo metadata`: {}", e))?;
+    let workspace_root = metadata["workspace_root"]
+        .as_str()
+        .ok_or("`cargo metadata` is missing `workspace_root`")?;
+    let target_dir = metadata["target_directory"]
+        .as_str()
+        .ok_or("`cargo metadata` is missing `target_directory`")?;
+    Ok((workspace_root.to_owned(), target_dir.to_owned()))
+}
+
+#[cfg(not(feature = "json"))]
+fn parse_metadata(_raw: &[u8]) -> Result<(String, String), crate::Error> {
+    Err("`TestCases::default_bin_workspace` requires the `json` feature".into())
+}
+
+/// The `--target` the running tests were built with, if any
+///
+/// Cargo only puts builds in a directory named for the target triple when it is passed
+/// explicitly, like `target/x86_64-unknown-linux-gnu/debug`.
+fn current_target(target_dir: &std::path::Path) -> Option<String> {
+    let mut dir = std::env::current_exe().ok()?;
+    dir.pop();
+    if dir.ends_with("deps") {
+        dir.pop();
+    }
+    // The profile's directory
+    dir.pop();
+    let target_dir = std::fs::canonicalize(target_dir).ok()?;
+    if std::fs::canonicalize(dir.parent()?).ok()? != target_dir {
+        return None;
+    }
+    dir.file_name()?.to_str().map(ToOwned::to_owned)
+}
+
+/// Directory within the target directory that `profile` builds into
+fn profile_dir(profile: &str) -> &str {
+    match profile {
+        "dev" | "test" => "debug",
+        "bench" => "release",
+        _ => profile,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn host_build_has_no_target() {
+        let mut target_dir = std::env::current_exe().unwrap();
+        target_dir.pop();
+        if target_dir.ends_with("deps") {
+            target_dir.pop();
+        }
+        // Tests aren't built with `--target`, so the profile's directory is in the target
+        // directory
+        target_dir.pop();
+        assert_eq!(current_target(&target_dir), None);
+        // As if the target directory were the target triple's directory
+        assert_eq!(
+            current_target(target_dir.parent().unwrap()).as_deref(),
+            target_dir.file_name().unwrap().to_str()
+        );
+    }
+}
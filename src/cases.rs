@@ -6,6 +6,7 @@ pub struct TestCases {
     runner: std::cell::RefCell<crate::RunnerSpec>,
     bins: std::cell::RefCell<crate::BinRegistry>,
     substitutions: std::cell::RefCell<snapbox::Substitutions>,
+    modes: std::cell::RefCell<crate::mode::ModeHandlers>,
     has_run: std::cell::Cell<bool>,
 }
 
@@ -15,10 +16,26 @@ impl TestCases {
         s.runner
             .borrow_mut()
             .include(parse_include(std::env::args_os()));
+        s.runner.borrow_mut().include_tags(merge_tags(
+            parse_tag_args(std::env::args_os()),
+            parse_tags(std::env::var("TRYCMD_TAGS").ok().as_deref()),
+        ));
+        s.runner
+            .borrow_mut()
+            .verbosity(parse_verbose(std::env::var_os("TRYCMD_VERBOSE").as_deref()));
+        s.runner.borrow_mut().strict_artifacts(parse_flag(
+            std::env::var_os("TRYCMD_STRICT_ARTIFACTS").as_deref(),
+        ));
+        s.runner.borrow_mut().snapshot_dir(parse_dir(
+            std::env::var_os("TRYCMD_SNAPSHOT_DIR").as_deref(),
+        ));
         s
     }
 
     /// Load tests from `glob`
+    ///
+    /// Cases run and are reported in byte-wise order of their paths, with `/` separators, so the
+    /// order is the same on every platform.
     pub fn case(&self, glob: impl AsRef<std::path::Path>) -> &Self {
         self.runner.borrow_mut().case(glob.as_ref(), None);
         self
@@ -49,6 +66,88 @@ impl TestCases {
         self
     }
 
+    /// Load cases from a manifest, rather than registering each glob in code
+    ///
+    /// Each `[[case]]` in the manifest is like calling [`TestCases::case`] with its `glob`,
+    /// relative to the manifest, with optional settings for its cases:
+    /// - `status`: expected status, like [`TestCases::pass`] / [`TestCases::fail`] /
+    ///   [`TestCases::skip`], using the same values as `status` in `*.toml`
+    /// - `timeout`: for commands without one, like `timeout` in `*.toml`
+    /// - `env.add` / `env.remove` / `env.inherit`: added to [`TestCases::env`], like `env` in
+    ///   `*.toml`
+    /// - `tags`: like [`TestCases::case_tag`]
+    ///
+    /// Other settings of `*.toml` case files, like `bin` or `args`, aren't supported here and
+    /// belong in the case files themselves.
+    ///
+    /// ```toml
+    /// [[case]]
+    /// glob = "cmd/*.trycmd"
+    ///
+    /// [[case]]
+    /// glob = "cmd/slow/*.toml"
+    /// timeout = "30s"
+    /// tags = ["slow"]
+    /// env.add = { RUST_LOG = "debug" }
+    /// ```
+    ///
+    /// A manifest that can't be read or parsed is reported as a failed case.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new().load_cases_from_manifest("tests/trycmd-manifest.toml");
+    /// ```
+    pub fn load_cases_from_manifest(&self, path: impl AsRef<std::path::Path>) -> &Self {
+        self.runner.borrow_mut().manifest(path.as_ref());
+        self
+    }
+
+    /// Run tests from `glob` in a fresh sandbox copied from `fixture_dir`
+    ///
+    /// Each case gets its own copy of `fixture_dir` as its working directory, so cases can't
+    /// affect each other.  This takes precedence over a case's `*.in/` and `fs.base`.
+    pub fn case_with_fixtures(
+        &self,
+        glob: impl AsRef<std::path::Path>,
+        fixture_dir: impl AsRef<std::path::Path>,
+    ) -> &Self {
+        let mut runner = self.runner.borrow_mut();
+        runner.case(glob.as_ref(), None);
+        runner.case_fixtures(glob.as_ref(), fixture_dir.as_ref());
+        self
+    }
+
+    /// Finish running all cases matching `before` before starting any matching `after`
+    ///
+    /// Cases otherwise run in parallel, so use this when one case sets up state another relies
+    /// on.  This only affects when cases start; `before` failing doesn't skip `after`.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .case("tests/cmd/*.toml")
+    ///     .case_ordered_before("tests/cmd/migrate-*.toml", "tests/cmd/query-*.toml");
+    /// ```
+    pub fn case_ordered_before(
+        &self,
+        before: impl AsRef<std::path::Path>,
+        after: impl AsRef<std::path::Path>,
+    ) -> &Self {
+        self.runner
+            .borrow_mut()
+            .case_ordered_before(before.as_ref(), after.as_ref());
+        self
+    }
+
+    /// Start cases matching `after` only once all cases matching `before` have finished
+    ///
+    /// See [`TestCases::case_ordered_before`]
+    pub fn case_ordered_after(
+        &self,
+        after: impl AsRef<std::path::Path>,
+        before: impl AsRef<std::path::Path>,
+    ) -> &Self {
+        self.case_ordered_before(before, after)
+    }
+
     /// Overwrite expected status for a test
     pub fn skip(&self, glob: impl AsRef<std::path::Path>) -> &Self {
         self.runner
@@ -57,32 +156,881 @@ impl TestCases {
         self
     }
 
+    /// Expected status for commands that don't set one (default: success)
+    ///
+    /// [`TestCases::pass`], [`TestCases::fail`], etc take precedence, as does `status` in
+    /// `*.toml` or `? <status>` in `*.trycmd`.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .default_status(trycmd::schema::CommandStatus::Failed)
+    ///     .case("tests/cmd/violations/*.toml");
+    /// ```
+    pub fn default_status(&self, status: crate::schema::CommandStatus) -> &Self {
+        self.runner.borrow_mut().default_status(Some(status));
+        self
+    }
+
+    /// Fail when a glob passed to [`TestCases::case`] does not match any files
+    ///
+    /// This catches test directories being renamed or moved without updating the harness.
+    /// Filtering with `trycmd=` or by tags does not count as a glob not matching.
+    ///
+    /// See also [`TestCases::allow_empty`]
+    pub fn require_matches(&self, yes: bool) -> &Self {
+        self.runner.borrow_mut().require_matches(yes);
+        self
+    }
+
+    /// Allow `glob` to match no files, despite [`TestCases::require_matches`]
+    ///
+    /// `glob` must be the same as what was passed to [`TestCases::case`].  This is useful for
+    /// globs that are only expected to match on some platforms.
+    pub fn allow_empty(&self, glob: impl AsRef<std::path::Path>) -> &Self {
+        self.runner.borrow_mut().allow_empty(glob.as_ref());
+        self
+    }
+
+    /// Fail unless exactly `count` cases are discovered
+    ///
+    /// This catches case files being deleted or no longer matched, which would otherwise pass
+    /// silently with fewer cases.  Every file matched by [`TestCases::case`] counts, before
+    /// filtering with `trycmd=` or by tags.  The failure lists the discovered cases.
+    pub fn assert_case_count(&self, count: usize) -> &Self {
+        self.runner.borrow_mut().case_count(count);
+        self
+    }
+
+    /// Warn about `*.stdout`, `*.stderr`, `*.in/`, and `*.out/` that no case uses (default: `true`)
+    ///
+    /// These are left behind when a case is deleted or renamed and would otherwise mislead
+    /// readers.  Only the directories matched by [`TestCases::case`] globs are checked, and the
+    /// artifacts of cases filtered out with `trycmd=` or by tags are still in use.
+    pub fn check_orphaned_artifacts(&self, yes: bool) -> &Self {
+        self.runner.borrow_mut().check_artifacts(yes);
+        self
+    }
+
+    /// Fail, rather than warn, about artifacts no case uses
+    ///
+    /// See [`TestCases::check_orphaned_artifacts`].  This can also be enabled with
+    /// `TRYCMD_STRICT_ARTIFACTS=1`.
+    pub fn strict_artifacts(&self, yes: bool) -> &Self {
+        self.runner.borrow_mut().strict_artifacts(yes);
+        self
+    }
+
+    /// Label tests from `glob` with `tag`
+    ///
+    /// Tags can also be set within a `.toml` file with `tags = ["slow", "network"]`.
+    ///
+    /// Tagged tests can be selected with:
+    /// - [`TestCases::only_tags`] / [`TestCases::skip_tags`]
+    /// - `TRYCMD_TAGS=slow,network cargo test`
+    /// - `cargo test -- cli_tests trycmd=network`
+    pub fn case_tag(&self, glob: impl AsRef<std::path::Path>, tag: impl Into<String>) -> &Self {
+        self.runner.borrow_mut().case_tag(glob.as_ref(), tag.into());
+        self
+    }
+
+    /// Only run tests that have at least one of `tags`
+    ///
+    /// See [`TestCases::case_tag`]
+    pub fn only_tags(&self, tags: &[&str]) -> &Self {
+        let tags = tags.iter().map(|t| (*t).to_owned()).collect();
+        self.runner.borrow_mut().only_tags(Some(tags));
+        self
+    }
+
+    /// Do not run tests that have any of `tags`
+    ///
+    /// See [`TestCases::case_tag`]
+    pub fn skip_tags(&self, tags: &[&str]) -> &Self {
+        let tags = tags.iter().map(|t| (*t).to_owned()).collect();
+        self.runner.borrow_mut().skip_tags(tags);
+        self
+    }
+
+    /// Only run cases for which `filter` returns `true`
+    ///
+    /// `filter` is passed the canonical path of each case, so it can look at more than the name,
+    /// like the content of the case file.  Calling this again adds another filter that cases must
+    /// also pass.  Cases that are filtered out aren't reported, like with `trycmd=`.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .case("tests/cmd/*.toml")
+    ///     .run_cases_matching(|path| {
+    ///         std::fs::read_to_string(path).map_or(false, |content| content.contains("--json"))
+    ///     });
+    /// ```
+    pub fn run_cases_matching(
+        &self,
+        filter: impl Fn(&std::path::Path) -> bool + Send + Sync + 'static,
+    ) -> &Self {
+        self.runner
+            .borrow_mut()
+            .filter(crate::spec::CaseFilter::new(filter));
+        self
+    }
+
+    /// Fail commands that panic, rather than comparing their output
+    ///
+    /// A Rust panic otherwise shows up as a diff against a backtrace, which buries what went
+    /// wrong.  With this, output containing a `thread '...' panicked at` line fails the command
+    /// as having panicked, showing the panic message and anything after it in full.  This
+    /// applies even to commands expected to fail.
+    pub fn abort_on_panic(&self) -> &Self {
+        self.runner.borrow_mut().abort_on_panic(true);
+        self
+    }
+
+    /// Run commands in `locale`, like `C`, so sorting and messages don't depend on the machine
+    ///
+    /// This sets `LC_ALL`, `LANG`, and `LANGUAGE`, even when the environment is inherited, unless
+    /// the case sets them in `env.add`.  A case's own `locale` takes precedence.
+    pub fn locale(&self, locale: impl Into<String>) -> &Self {
+        self.runner.borrow_mut().locale(locale.into());
+        self
+    }
+
+    /// Run commands with the file mode creation mask `mask`, like `0o022`, so the permissions of
+    /// files they create don't depend on the machine
+    ///
+    /// A case's own `umask` takes precedence.  This does nothing on Windows.
+    pub fn umask(&self, mask: u32) -> &Self {
+        self.runner.borrow_mut().umask(mask);
+        self
+    }
+
+    /// Run commands in an environment that doesn't depend on the machine running the tests
+    ///
+    /// This is shorthand for:
+    /// - Only inheriting `PATH` (and `SYSTEMROOT` on Windows), like with
+    ///   [`TestCases::env_inherit_list`], unless inheritance is otherwise configured
+    /// - [`TestCases::locale`] of `C`
+    /// - [`TestCases::umask`] of `0o022`
+    /// - A terminal size of 80 by 24 through `COLUMNS` and `LINES`
+    ///
+    /// Explicit settings, whether here or in a case, take precedence.
+    pub fn hermetic(&self, yes: bool) -> &Self {
+        self.runner.borrow_mut().hermetic(yes);
+        self
+    }
+
+    /// Fail `*.trycmd` and `*.md` cases with a command whose program doesn't exist (default:
+    /// `false`)
+    ///
+    /// This catches documentation that has drifted from the program, like a README showing a
+    /// removed subcommand of a tool that has since been renamed.  Every `$` line in `trycmd` and
+    /// `console` blocks is checked, including in blocks with `ignore` whose output isn't.  A
+    /// program exists if it is a registered bin (see [`TestCases::register_bin`]), the default
+    /// bin, or on `PATH`.  Problems are reported with their file and line.
+    ///
+    /// See also [`TestCases::allowed_commands`].
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .default_bin_name("my-cmd")
+    ///     .verify_commands(true)
+    ///     .case("README.md");
+    /// ```
+    pub fn verify_commands(&self, yes: bool) -> &Self {
+        self.runner.borrow_mut().verify_commands(yes);
+        self
+    }
+
+    /// With [`TestCases::verify_commands`], also fail commands running a program not in `names`
+    pub fn allowed_commands(&self, names: &[&str]) -> &Self {
+        let names = names.iter().map(|n| (*n).to_owned()).collect();
+        self.runner.borrow_mut().allowed_commands(Some(names));
+        self
+    }
+
     /// Set default bin, by path, for commands
+    ///
+    /// This is tried before the other default bins, whichever was set first, see
+    /// [`TestCases::bin_fallback`].
     pub fn default_bin_path(&self, path: impl AsRef<std::path::Path>) -> &Self {
-        let bin = Some(crate::schema::Bin::Path(path.as_ref().into()));
-        self.runner.borrow_mut().default_bin(bin);
+        let bin = crate::schema::Bin::Path(path.as_ref().into());
+        self.runner
+            .borrow_mut()
+            .default_bin("default_bin_path", bin);
+        self
+    }
+
+    /// Fail before running any cases if the default bin or a bin from [`TestCases::case_bin`] or
+    /// [`TestCases::matrix_bins`] is a path that doesn't exist
+    ///
+    /// Otherwise, a missing binary shows up as an OS error from each case that runs it.  A
+    /// missing default bin is fine when another default bin or [`TestCases::bin_fallback`] might
+    /// be found instead.
+    pub fn require_binary_exists(&self) -> &Self {
+        self.runner.borrow_mut().require_binary_exists(true);
         self
     }
 
     /// Set default bin, by name, for commands
+    ///
+    /// This is tried after the other default bins, see [`TestCases::bin_fallback`].
     pub fn default_bin_name(&self, name: impl AsRef<str>) -> &Self {
-        let bin = Some(crate::schema::Bin::Name(name.as_ref().into()));
-        self.runner.borrow_mut().default_bin(bin);
+        let bin = crate::schema::Bin::Name(name.as_ref().into());
+        self.runner
+            .borrow_mut()
+            .default_bin("default_bin_name", bin);
+        self
+    }
+
+    /// Bins to try, in order, for commands when none of the default bins are found
+    ///
+    /// A command runs the first bin found from:
+    /// 1. `bin` in the case file, or the program in a `.trycmd` command.  This is always used,
+    ///    even if it doesn't exist.
+    /// 2. The last [`TestCases::case_bin`] matching the case
+    /// 3. The default bins, in this order no matter which was set first:
+    ///    [`TestCases::default_bin_path`], [`TestCases::default_bin_workspace`],
+    ///    [`TestCases::default_bin_script`], then [`TestCases::default_bin_name`]
+    /// 4. `bins`
+    ///
+    /// Names are looked up in the bins from [`TestCases::register_bin`], then the bins cargo
+    /// built.  Only names in `bins` are also looked up on `PATH`.  When none of the bins are
+    /// found, the failure lists each one tried and why it was skipped.
+    /// [`TestCases::matrix_bins`] replace all of these.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .default_bin_path("target/release/my-cmd")
+    ///     .bin_fallback([trycmd::schema::Bin::Name("my-cmd".into())])
+    ///     .case("tests/cmd/*.toml");
+    /// ```
+    pub fn bin_fallback(&self, bins: impl IntoIterator<Item = crate::schema::Bin>) -> &Self {
+        self.runner
+            .borrow_mut()
+            .bin_fallback(bins.into_iter().collect());
+        self
+    }
+
+    /// Set default bin, as a script run by `shell -c`, for commands
+    ///
+    /// Useful for piping commands together or testing shell completions.  Any `args` are passed
+    /// to the script as `$1`, `$2`, etc.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .default_bin_script("sh", "echo hello | tr a-z A-Z")
+    ///     .case("tests/cmd/*.toml");
+    /// ```
+    pub fn default_bin_script(&self, shell: impl AsRef<str>, script: impl AsRef<str>) -> &Self {
+        let bin = crate::schema::Bin::Script {
+            shell: shell.as_ref().into(),
+            script: script.as_ref().into(),
+        };
+        self.runner
+            .borrow_mut()
+            .default_bin("default_bin_script", bin);
+        self
+    }
+
+    /// Set default bin, by workspace package, for commands
+    ///
+    /// The package's bin of the same name is built with `cargo build` before running the tests.
+    /// For control over the bin, features, or profile, see [`Bin::CargoWorkspace`][crate::schema::Bin::CargoWorkspace].
+    pub fn default_bin_workspace(&self, package: impl AsRef<str>) -> &Self {
+        let bin = crate::schema::Bin::CargoWorkspace {
+            package: package.as_ref().into(),
+            bin: None,
+            features: Vec::new(),
+            profile: "dev".into(),
+        };
+        self.runner
+            .borrow_mut()
+            .default_bin("default_bin_workspace", bin);
+        self
+    }
+
+    /// Run `template` once for each non-blank line of `input`, with the line as `stdin`
+    ///
+    /// For data-driven tests, like replaying fuzzer seeds, without a case file per input.  Each
+    /// line, with a trailing newline, is the `stdin` of every command in `template` without
+    /// `stdin` of its own.  Cases are named after the template and the line's number in `input`,
+    /// so with `template` `tests/fuzz/parse.trycmd`, line 3 of `input` is reported as
+    /// `tests/fuzz/parse_3.trycmd`.  That name is also what `trycmd=`, [`TestCases::case_tag`]
+    /// globs, and `TRYCMD=dump` go by, so one line can be run on its own.
+    ///
+    /// All lines share `template`'s expected output, so `TRYCMD=overwrite` only checks them, with
+    /// a warning.  Keep `template` out of the globs passed to [`TestCases::case`] unless it should
+    /// also run on its own.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .default_bin_name("my-cmd")
+    ///     .case_per_line("tests/fuzz/seeds.txt", "tests/fuzz/parse.trycmd");
+    /// ```
+    pub fn case_per_line(
+        &self,
+        input: impl AsRef<std::path::Path>,
+        template: impl AsRef<std::path::Path>,
+    ) -> &Self {
+        self.runner
+            .borrow_mut()
+            .case_per_line(input.as_ref(), template.as_ref());
+        self
+    }
+
+    /// Override the default bin for cases from `glob`
+    ///
+    /// For cases that run against a different bin than the rest, without naming it in each case
+    /// file.  A `bin` in the case file still takes precedence, then the last matching `case_bin`,
+    /// then the default bin.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .default_bin_name("my-cmd")
+    ///     .case_bin("tests/cmd/helper/*.toml", trycmd::schema::Bin::Name("my-helper".into()))
+    ///     .case("tests/cmd/**/*.toml");
+    /// ```
+    pub fn case_bin(&self, glob: impl AsRef<std::path::Path>, bin: crate::schema::Bin) -> &Self {
+        self.runner.borrow_mut().case_bin(glob.as_ref(), bin);
+        self
+    }
+
+    /// Hint how expensive cases from `glob` are to run, relative to the default of `1`
+    ///
+    /// Heavier cases are started first so a slow case doesn't hold up the end of the run.  When
+    /// multiple globs match, the last one wins.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .case("tests/cmd/*.toml")
+    ///     .case_weight("tests/cmd/build-*.toml", 10);
+    /// ```
+    pub fn case_weight(&self, glob: impl AsRef<std::path::Path>, weight: u32) -> &Self {
+        self.runner.borrow_mut().case_weight(glob.as_ref(), weight);
+        self
+    }
+
+    /// Run each case against every bin, by label
+    ///
+    /// Each case runs once per bin, with the label included in its name (`help.trycmd[compat]:2`),
+    /// and the bin replacing any `bin.name` or default bin.  Cases can opt out with `matrix = false`
+    /// in their `.toml` file.
+    ///
+    /// As the bins share the same snapshots, only the first bin is run with `TRYCMD=overwrite`
+    /// or `TRYCMD=dump`.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .matrix_bins([
+    ///         ("default", trycmd::schema::Bin::Name("mytool".into())),
+    ///         ("compat", trycmd::schema::Bin::Name("mytool-compat".into())),
+    ///     ])
+    ///     .case("tests/cmd/*.trycmd");
+    /// ```
+    pub fn matrix_bins<L: Into<String>, B: Into<crate::schema::Bin>>(
+        &self,
+        bins: impl IntoIterator<Item = (L, B)>,
+    ) -> &Self {
+        let bins = bins
+            .into_iter()
+            .map(|(l, b)| (l.into(), b.into()))
+            .collect();
+        self.runner.borrow_mut().matrix_bins(bins);
+        self
+    }
+
+    /// With `TRYCMD=overwrite`, move expected output longer than `lines` to `*.stdout` /
+    /// `*.stderr` and shorter output inline in the `*.toml`
+    ///
+    /// Cases can override this with `externalize-over = N` in their `*.toml`.  Without either,
+    /// expectations are updated wherever they already are.
+    pub fn externalize_over(&self, lines: usize) -> &Self {
+        self.runner.borrow_mut().externalize_over(Some(lines));
         self
     }
 
     /// Set default timeout for commands
+    ///
+    /// To only affect the cases in one directory, set `timeout-ms` in its `trycmd.toml`.
     pub fn timeout(&self, time: std::time::Duration) -> &Self {
         self.runner.borrow_mut().timeout(Some(time));
         self
     }
 
+    /// Fail commands whose `stdout` or `stderr` goes over `limit` bytes (default: no limit)
+    ///
+    /// The command is killed once a stream is over the limit, rather than buffering all of it, to
+    /// protect against a runaway command running the tests out of memory.  The limit applies to
+    /// each stream on its own, or to both together for `*.trycmd` files and other cases that
+    /// capture them together.
+    pub fn max_output_bytes(&self, limit: usize) -> &Self {
+        self.runner.borrow_mut().max_output_bytes(Some(limit));
+        self
+    }
+
+    /// Set arguments to pass before each case's `args` when running the default bin
+    ///
+    /// A case can replace these with `args-override`.
+    ///
+    /// ```rust,no_run
+    /// #[test]
+    /// fn cli_tests() {
+    ///     trycmd::TestCases::new()
+    ///         .default_bin_name("my-cmd")
+    ///         .default_args(["--color=never"])
+    ///         .case("tests/cmd/*.toml");
+    /// }
+    /// ```
+    pub fn default_args(&self, args: impl IntoIterator<Item = impl Into<String>>) -> &Self {
+        let args = Some(args.into_iter().map(Into::into).collect());
+        self.runner.borrow_mut().default_args(args);
+        self
+    }
+
+    /// Set default `stdin` for commands
+    ///
+    /// Cases that specify their own `stdin`, even if empty, will use that instead.
+    pub fn default_stdin(&self, stdin: impl Into<Vec<u8>>) -> &Self {
+        let stdin = Some(crate::Data::binary(stdin));
+        self.runner.borrow_mut().default_stdin(stdin);
+        self
+    }
+
+    /// Give commands this process's `stdin`, rather than an empty one
+    ///
+    /// This is a debugging aid, like for stepping through an interactive command while developing
+    /// a case.  Commands share the one `stdin`, so with cases running in parallel which command
+    /// reads what is unpredictable; run a single case, like with `trycmd=<name>`.  Cases with
+    /// their own `stdin`, `*.stdin`, or `stdin-pipe = false`, and
+    /// [`TestCases::default_stdin`], take precedence.
+    pub fn inherit_stdin(&self) -> &Self {
+        self.runner.borrow_mut().inherit_stdin(true);
+        self
+    }
+
+    /// Set default working directory for commands that are not sandboxed
+    ///
+    /// `path` is relative to `CARGO_MANIFEST_DIR` and must exist.  Cases that set `fs.cwd`, have
+    /// a `*.in/`, or have a sandbox (`fs.sandbox = true` or `*.out/`) are unaffected.
+    ///
+    /// **WARNING:** Commands run outside of a sandbox see, and can modify, the real directory.
+    /// They can be affected by other tests or local changes in your repository and any
+    /// changes they make will persist.
+    pub fn default_cwd(&self, path: impl AsRef<std::path::Path>) -> &Self {
+        let cwd = Some(path.as_ref().into());
+        self.runner.borrow_mut().default_cwd(cwd);
+        self
+    }
+
+    /// Ignore trailing whitespace on each line of `stdout` and `stderr`
+    ///
+    /// This applies to both the expected and actual output and snapshots written with
+    /// `TRYCMD=overwrite` / `TRYCMD=dump` will have it stripped.
+    pub fn strip_trailing_whitespace(&self) -> &Self {
+        self.runner.borrow_mut().strip_trailing_whitespace(true);
+        self
+    }
+
+    /// How the final newline of `stdout` and `stderr` is compared
+    ///
+    /// By default, it has to match exactly.  With [`TrailingNewline::Ignore`][crate::TrailingNewline::Ignore],
+    /// `TRYCMD=overwrite` keeps whether each snapshot ends with a newline, so editors adding or
+    /// removing one don't cause failures.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .trailing_newline(trycmd::TrailingNewline::Ignore)
+    ///     .case("tests/cmd/*.toml");
+    /// ```
+    pub fn trailing_newline(&self, policy: crate::TrailingNewline) -> &Self {
+        self.runner.borrow_mut().trailing_newline(policy);
+        self
+    }
+
+    /// Only report the first `bytes` of `stdout` and `stderr` for failures
+    ///
+    /// For commands whose output is too large to read in a failure report.  Reports end with a
+    /// `[... truncated N bytes]` note in place of the rest, while the full output is still
+    /// compared and written with `TRYCMD=overwrite` / `TRYCMD=dump`.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .truncate_output(4096)
+    ///     .case("tests/cmd/*.toml");
+    /// ```
+    pub fn truncate_output(&self, bytes: usize) -> &Self {
+        self.runner.borrow_mut().truncate_output(bytes);
+        self
+    }
+
+    /// Collapse runs of more than `threshold` identical lines in `stdout` and `stderr`
+    ///
+    /// A run is written as its first line followed by `[.. repeated N times]`, including by
+    /// `TRYCMD=overwrite` / `TRYCMD=dump`, so a command printing the same progress line hundreds
+    /// of times doesn't bloat its snapshot.  Lines are compared after substitutions.
+    ///
+    /// In the expected output:
+    /// - `[.. repeated N times]` matches a run of exactly `N` lines, even when `N` isn't more
+    ///   than `threshold`
+    /// - `[.. repeated]` matches a run of two or more lines, of any length
+    /// - `...`, `[..]`, and variables like `[ROOT]` work as usual, including in the repeated line
+    ///
+    /// Runs are left alone with `stderr-unordered`, `sort-output-lines`, or `stdout-lines`, where
+    /// the markers are compared as plain lines.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .collapse_repeated_lines(5)
+    ///     .case("tests/cmd/*.toml");
+    /// ```
+    pub fn collapse_repeated_lines(&self, threshold: usize) -> &Self {
+        self.runner.borrow_mut().collapse_repeated(threshold);
+        self
+    }
+
+    /// Sort the lines of `stdout` and `stderr` for cases from `glob` before comparing them
+    ///
+    /// For commands that write lines in a nondeterministic order, like from parallel workers or
+    /// hash map iteration.  Both the expected and actual lines are sorted by their bytes, with
+    /// `stdout` and `stderr` sorted independently, so only which lines were written is compared.
+    ///
+    /// This can also be set within a `.toml` file or `.trycmd` front matter with
+    /// `sort-output-lines = true`.
+    pub fn normalize_sort_lines(&self, glob: impl AsRef<std::path::Path>) -> &Self {
+        self.runner.borrow_mut().sort_lines(glob.as_ref());
+        self
+    }
+
+    /// Report each command as it starts and finishes, along with how long it took, like `(3.2s)`
+    ///
+    /// This can also be enabled with `TRYCMD_VERBOSE=1`.  `TRYCMD_VERBOSE=2` also reports the
+    /// arguments each command is run with, like `["build", "--name", "a b"]`, to make quoting
+    /// mistakes visible.  Failures always report them.
+    ///
+    /// When `stderr` is a terminal, a status line at the bottom also shows how far along the run
+    /// is, staying below these and the case reports.  It is turned off when `NO_COLOR` or `CI` is
+    /// set.
+    pub fn verbose(&self, yes: bool) -> &Self {
+        self.runner.borrow_mut().verbose(yes);
+        self
+    }
+
+    /// Only report failures, for a log that stays empty while everything passes
+    ///
+    /// Passing and ignored cases aren't listed and there is no status line, while failures are
+    /// reported in full, followed by the usual summary of which cases failed.
+    pub fn quiet(&self, yes: bool) -> &Self {
+        self.runner.borrow_mut().quiet(yes);
+        self
+    }
+
+    /// Run every case in a sandbox, so stray writes can't modify your repository
+    ///
+    /// Cases with a `*.in/` or `fs.base` run in a copy of it, like with `fs.sandbox = true`.
+    /// Everything else runs in a fresh, empty temp directory rather than the real directory.
+    /// Cases that set `cwd` still run there.
+    pub fn working_copy(&self, yes: bool) -> &Self {
+        self.runner.borrow_mut().working_copy(yes);
+        self
+    }
+
+    /// Give every case its own empty home directory, so real config on the machine running the
+    /// tests can't change the results
+    ///
+    /// Each case runs with `HOME`, `XDG_CONFIG_HOME`, `XDG_CACHE_HOME`, and `XDG_DATA_HOME` (and
+    /// `USERPROFILE`, `APPDATA`, and `LOCALAPPDATA` on Windows) pointing at fresh directories in
+    /// a temp directory of its own, whatever the case's `env` settings.  `[HOME]` matches the fake
+    /// home in the output.
+    ///
+    /// A case can opt out with `isolate-home = false` or fill the fake home in from a fixture
+    /// directory with `isolate-home.seed = "home.in"`.
+    pub fn isolate_home(&self, yes: bool) -> &Self {
+        self.runner.borrow_mut().isolate_home(yes);
+        self
+    }
+
+    /// Fail after `TRYCMD=overwrite` creates or modifies any snapshots
+    ///
+    /// The snapshots are still written but the run fails with a list of them, so they get
+    /// reviewed rather than silently accepted, like in CI.  This has no effect without
+    /// `TRYCMD=overwrite` as nothing else writes snapshots.
+    pub fn assert_no_new_snapshots(&self) -> &Self {
+        self.runner.borrow_mut().no_new_snapshots(true);
+        self
+    }
+
+    /// Stop starting cases once `failures` cases have failed
+    ///
+    /// Unlike stopping at the first failure, this leaves room for a few unrelated failures while
+    /// sparing you from hundreds of the same one when something fundamental broke.  Cases already
+    /// running are finished, so slightly more than `failures` may be reported.  The results so
+    /// far are reported as usual, with the cases that were not run reported as skipped, and the
+    /// run fails.
+    ///
+    /// # Panics
+    ///
+    /// If `failures` is 0, as no case could ever run
+    pub fn abort_after(&self, failures: usize) -> &Self {
+        assert!(0 < failures, "`abort_after` needs at least 1 failure");
+        self.runner.borrow_mut().abort_after(failures);
+        self
+    }
+
+    /// Write the actual output of failing cases to `dir`, for reviewing what went wrong
+    ///
+    /// Unlike `TRYCMD=dump`, output from passing cases is not written.  When both are used,
+    /// everything is dumped to the `TRYCMD=dump` directory and failures are also written to
+    /// `dir`.
+    pub fn dump_on_failure(&self, dir: impl AsRef<std::path::Path>) -> &Self {
+        self.runner
+            .borrow_mut()
+            .dump_on_failure(Some(dir.as_ref().to_owned()));
+        self
+    }
+
+    /// Show the last `lines` lines of `path` with each failure, for logs the command writes
+    ///
+    /// A relative `path` is resolved against the command's working directory, which is the
+    /// sandbox for sandboxed cases.  A missing file is noted in the report rather than being an
+    /// error.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .fail_context_file("app.log", 20)
+    ///     .case("tests/cmd/*.toml");
+    /// ```
+    pub fn fail_context_file(&self, path: impl AsRef<std::path::Path>, lines: usize) -> &Self {
+        self.runner
+            .borrow_mut()
+            .fail_context(Some((path.as_ref().to_owned(), lines)));
+        self
+    }
+
+    /// Allow cases with the same name, like `init.toml` and `init.trycmd`, to share fixtures
+    ///
+    /// By default, it is an error for cases to share `*.stdout`, `*.stderr`, `*.stdin`, `*.in/`,
+    /// or `*.out/` as one case updating them would clobber the other's expectations.  Even with
+    /// this, these cases can't be run with `TRYCMD=overwrite`.
+    pub fn share_fixtures(&self, yes: bool) -> &Self {
+        self.runner.borrow_mut().share_fixtures(yes);
+        self
+    }
+
+    /// Also report results as JSON, JUnit XML, or TAP
+    ///
+    /// The human-readable report is still written to `stderr`.  How long each command took is
+    /// included as `duration`, in seconds, in JSON and as `time` in JUnit XML.
+    ///
+    /// Cases are grouped into suites by directory, relative to the shallowest glob they were found
+    /// with, as `suite` in JSON and as a `<testsuite>` each in JUnit XML.  Cases directly in that
+    /// directory are in the `trycmd` suite, and a `_suite.toml` with a `name` renames the suite
+    /// for its directory.  With more than one suite, per-suite counts are reported after the run,
+    /// and `trycmd=<suite>` runs only that suite's cases.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .output_format(trycmd::OutputFormat::Junit("target/trycmd.xml".into()))
+    ///     .case("tests/cmd/*.toml");
+    /// ```
+    pub fn output_format(&self, format: crate::OutputFormat) -> &Self {
+        self.runner.borrow_mut().output_format(format);
+        self
+    }
+
+    /// Handle `TRYCMD=<name>` with `mode`, rather than as `overwrite`, `dump`, etc
+    ///
+    /// ```rust,no_run
+    /// struct Upload;
+    ///
+    /// impl trycmd::ModeHandler for Upload {
+    ///     fn run_case(
+    ///         &self,
+    ///         path: &std::path::Path,
+    ///         run: &mut dyn FnMut() -> Vec<trycmd::report::CaseResult>,
+    ///     ) -> Result<(), trycmd::Error> {
+    ///         for result in run() {
+    ///             println!("{}: {:?}", path.display(), result.status());
+    ///         }
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// trycmd::TestCases::new()
+    ///     .register_mode("upload", Box::new(Upload))
+    ///     .case("tests/cmd/*.toml");
+    /// ```
+    pub fn register_mode(&self, name: &str, mode: Box<dyn crate::ModeHandler>) -> &Self {
+        self.modes.borrow_mut().register(name.to_owned(), mode);
+        self
+    }
+
+    /// Compare `*.out/` files ending in `.extension` with `comparator` rather than by content
+    ///
+    /// `comparator` is passed the expected and then the actual file content and returns whether
+    /// they match, like for images compared with a perceptual hash.  Mismatches are reported as
+    /// rejected by the custom comparator, along with the file paths.  `TRYCMD=overwrite`
+    /// replaces rejected files with the actual ones.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .comparator("png", |expected, actual| expected.len() == actual.len())
+    ///     .case("tests/cmd/*.toml");
+    /// ```
+    pub fn comparator(
+        &self,
+        extension: &str,
+        comparator: impl Fn(&[u8], &[u8]) -> bool + Send + Sync + 'static,
+    ) -> &Self {
+        let extension = extension.trim_start_matches('.').to_owned();
+        self.runner
+            .borrow_mut()
+            .comparator(extension, std::sync::Arc::new(comparator));
+        self
+    }
+
+    /// Adjust each command right before it is spawned, for what trycmd doesn't cover, like a
+    /// `umask` or `pre_exec`
+    ///
+    /// `hook` is passed the case's path and the command, after the program, args, environment,
+    /// and working directory are set.  `stdin`, `stdout`, and `stderr` are replaced when the
+    /// command is spawned.  It is called for each command in a pipeline.  A panic in `hook` fails
+    /// that case, with the panic's message, rather than the whole run.
+    ///
+    /// Nothing checks what `hook` does: changing the program or args isn't reflected in reports
+    /// and is likely to confuse whoever reads them.  Calling this again replaces the hook.
+    ///
+    /// ```rust,no_run
+    /// trycmd::TestCases::new()
+    ///     .configure_command(|path, cmd| {
+    ///         if path.ends_with("locale.toml") {
+    ///             cmd.env("LANG", "de_DE.UTF-8");
+    ///         }
+    ///     })
+    ///     .case("tests/cmd/*.toml");
+    /// ```
+    pub fn configure_command(
+        &self,
+        hook: impl Fn(&std::path::Path, &mut std::process::Command) + Send + Sync + 'static,
+    ) -> &Self {
+        self.runner
+            .borrow_mut()
+            .configure_command(crate::runner::CommandHook::new(hook));
+        self
+    }
+
     /// Set default environment variable
     pub fn env(&self, key: impl Into<String>, value: impl Into<String>) -> &Self {
         self.runner.borrow_mut().env(key, value);
         self
     }
 
+    /// Set an environment variable to the output of `command`, like a token from a secrets
+    /// manager's CLI
+    ///
+    /// `command` runs once, before the first case, and the variable is set to its `stdout` with
+    /// surrounding whitespace trimmed.  If it can't be run, fails, or prints nothing, no cases
+    /// run and the run fails with why.
+    ///
+    /// ```rust,no_run
+    /// #[test]
+    /// fn cli_tests() {
+    ///     trycmd::TestCases::new()
+    ///         .case("tests/cmd/*.toml")
+    ///         .env_var_from_command("GIT_SHA", &["git", "rev-parse", "HEAD"]);
+    /// }
+    /// ```
+    pub fn env_var_from_command(&self, key: impl Into<String>, command: &[&str]) -> &Self {
+        let command = command.iter().map(|arg| (*arg).to_owned()).collect();
+        self.runner
+            .borrow_mut()
+            .env_from_command(key.into(), command);
+        self
+    }
+
+    /// Only inherit these environment variables from the test process
+    ///
+    /// Commands otherwise start from an empty environment, as with `env.inherit = false`, so they
+    /// only see what they need to run, like `PATH`.  Variables set with [`TestCases::env`] or
+    /// `env.add` take precedence.  Cases with `env.inherit = false` also get these while cases
+    /// with `env.inherit = true` inherit everything.
+    ///
+    /// ```rust,no_run
+    /// #[test]
+    /// fn cli_tests() {
+    ///     trycmd::TestCases::new()
+    ///         .case("tests/cmd/*.toml")
+    ///         .env_inherit_list(&["PATH", "HOME"]);
+    /// }
+    /// ```
+    pub fn env_inherit_list(&self, keys: &[&str]) -> &Self {
+        let keys = keys.iter().map(|key| (*key).to_owned()).collect();
+        self.runner.borrow_mut().env_inherit_list(keys);
+        self
+    }
+
+    /// Remove an environment variable inherited from the test process
+    ///
+    /// A case setting the variable in `env.add` still gets its value.
+    pub fn env_remove(&self, key: impl Into<String>) -> &Self {
+        self.runner.borrow_mut().env_remove(key);
+        self
+    }
+
+    /// Set an environment variable whose value must not show up in failures, like a password,
+    /// token, or API key
+    ///
+    /// This is like [`TestCases::env`] but the value is replaced with `[REDACTED]` in the
+    /// output, before it's compared, reported, or written with `TRYCMD=overwrite` or
+    /// `TRYCMD=dump`, and in how to reproduce a failure.  Expected output can refer to it as
+    /// `[REDACTED]`.
+    ///
+    /// ```rust,no_run
+    /// #[test]
+    /// fn cli_tests() {
+    ///     trycmd::TestCases::new()
+    ///         .case("tests/cmd/*.toml")
+    ///         .env_secret("API_TOKEN", std::env::var("API_TOKEN").unwrap_or_default());
+    /// }
+    /// ```
+    pub fn env_secret(&self, key: impl Into<String>, value: impl Into<String>) -> &Self {
+        let value = value.into();
+        if !value.is_empty() {
+            self.substitutions
+                .borrow_mut()
+                .redact(&regex::escape(&value), crate::runner::REDACTED)
+                .expect("escaped values are valid");
+        }
+        self.runner.borrow_mut().env_secret(key.into(), value);
+        self
+    }
+
+    /// Set an environment variable that makes the command deterministic, like a random seed
+    ///
+    /// This is like [`TestCases::env`] but makes the intent explicit and the seed is reported
+    /// with failures.  When the runs of a case with `repeat` differ, the failure also warns that
+    /// the seed may not be honored.
+    pub fn seed_env(&self, key: impl Into<String>, value: impl Into<String>) -> &Self {
+        self.runner.borrow_mut().seed_env(key.into(), value.into());
+        self
+    }
+
+    /// Build bins referenced by `bin.name` with these `cargo build` arguments
+    ///
+    /// Use this to test the bin with the same feature flags, like `&["--features", "foo"]`, as
+    /// your tests.  Otherwise, the bin from `cargo build` / `cargo test` is used and its behavior
+    /// depends on which features were last built.
+    ///
+    /// Bins are built within `trycmd/` in the target directory that the tests were built in
+    /// (respecting `CARGO_TARGET_DIR`), one directory per set of arguments, so they don't
+    /// overwrite or trigger rebuilds of your regular build.  Bins registered with
+    /// [`TestCases::register_bin`] are not affected.
+    ///
+    /// Requires the `examples` feature.
+    pub fn cargo_build_args(&self, args: &[&str]) -> &Self {
+        let args = args.iter().map(|a| (*a).to_owned()).collect();
+        self.bins.borrow_mut().cargo_build_args(args);
+        self
+    }
+
     /// Add a bin to the "PATH" for cases to use
     pub fn register_bin(
         &self,
@@ -120,6 +1068,7 @@ impl TestCases {
     /// - `[..]`
     /// - `[EXE]`
     /// - `[CWD]`
+    /// - `[HOME]`
     /// - `[ROOT]`
     ///
     /// ## Example
@@ -152,17 +1101,261 @@ impl TestCases {
         Ok(self)
     }
 
+    /// Replace matches of `regex` in each line of output with `replacement`
+    ///
+    /// `replacement` may refer to capture groups, like `$1` or `${name}`.  This is applied to
+    /// both the expected and actual output and `TRYCMD=overwrite` will write the redacted form.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// #[test]
+    /// fn cli_tests() {
+    ///     trycmd::TestCases::new()
+    ///         .case("tests/cmd/*.trycmd")
+    ///         .redact(r"took \d+ms", "took [DURATION]ms")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn redact(
+        &self,
+        regex: &str,
+        replacement: impl Into<Cow<'static, str>>,
+    ) -> Result<&Self, crate::Error> {
+        self.substitutions.borrow_mut().redact(regex, replacement)?;
+        Ok(self)
+    }
+
+    /// Replace thread identifiers in output with `[THREAD]`
+    ///
+    /// This covers:
+    /// - Tokio workers, like `tokio-runtime-worker-3`
+    /// - Thread names in panics, like `thread 'main'`
+    /// - `ThreadId(7)`
+    /// - OS thread ids after `tid` or `thread id`, like `tid=1234`
+    ///
+    /// See [`TestCases::redact`]
+    ///
+    /// ```rust,no_run
+    /// #[test]
+    /// fn cli_tests() {
+    ///     trycmd::TestCases::new()
+    ///         .case("tests/cmd/*.trycmd")
+    ///         .normalize_thread_ids()
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn normalize_thread_ids(&self) -> Result<&Self, crate::Error> {
+        const THREAD_IDS: &[(&str, &str)] = &[
+            (r"tokio-runtime-worker(-\d+)?", "[THREAD]"),
+            (r"ThreadId\(\d+\)", "[THREAD]"),
+            (r"thread '[^']+'", "thread '[THREAD]'"),
+            // ASCII-only case folding, as `regex` is built without `unicode-case`
+            (r"(?i-u)\b(tid|thread[ _]id)([=: ]+)\d+", "${1}${2}[THREAD]"),
+        ];
+        for (regex, replacement) in THREAD_IDS {
+            self.redact(regex, *replacement)?;
+        }
+        Ok(self)
+    }
+
+    /// Replace IP addresses in output with `[ADDR]` and the ports after them with `[PORT]`
+    ///
+    /// This covers:
+    /// - IPv4 addresses, like `127.0.0.1`
+    /// - IPv6 addresses in brackets, like `[::1]` or `[fe80::1%eth0]`
+    /// - Compressed IPv6 addresses on their own, like `::1` or `fe80::1`
+    /// - Ports from 1 to 65535 after an address or `localhost`, like `127.0.0.1:52341` becoming
+    ///   `[ADDR]:[PORT]`
+    ///
+    /// See [`TestCases::redact`]
+    pub fn normalize_socket_addrs(&self) -> &Self {
+        const SOCKET_ADDRS: &[(&str, &str)] = &[
+            (r"\[[0-9A-Fa-f]*:[0-9A-Fa-f:.]*(%[\w.]+)?\]", "[ADDR]"),
+            (
+                r"(^|[^\w:.])(?:[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4})*)?::[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4})*\b(%[\w.]+)?",
+                "${1}[ADDR]",
+            ),
+            (
+                r"\b(?:(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)\.){3}(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)\b",
+                "[ADDR]",
+            ),
+            (
+                r"(\[ADDR\]|\blocalhost):(?:6553[0-5]|655[0-2]\d|65[0-4]\d\d|6[0-4]\d{3}|[1-5]\d{4}|[1-9]\d{0,3})\b",
+                "${1}:[PORT]",
+            ),
+        ];
+        let mut substitutions = self.substitutions.borrow_mut();
+        for (regex, replacement) in SOCKET_ADDRS {
+            substitutions
+                .redact(regex, *replacement)
+                .expect("socket address patterns are valid");
+        }
+        self
+    }
+
+    /// Merge in the configuration from `other`
+    ///
+    /// This allows sharing setup, like from a helper crate, between test harnesses:
+    /// ```rust,no_run
+    /// fn shared() -> trycmd::TestCases {
+    ///     let t = trycmd::TestCases::new();
+    ///     t.env("NO_COLOR", "1").timeout(std::time::Duration::from_secs(10));
+    ///     t
+    /// }
+    ///
+    /// #[test]
+    /// fn cli_tests() {
+    ///     trycmd::TestCases::new()
+    ///         .extend(&shared())
+    ///         .case("tests/cmd/*.toml");
+    /// }
+    /// ```
+    ///
+    /// Conflicts are resolved in favor of `other`:
+    /// - Cases are added after the existing ones, so `other` wins when globs overlap
+    /// - Environment variables, variables (see [`TestCases::insert_var`]), and bins with the same
+    ///   name are replaced
+    /// - Redactions (see [`TestCases::redact`]) from `other` are applied after the existing ones
+    /// - Defaults, like [`TestCases::default_bin_name`] or [`TestCases::timeout`], are replaced
+    ///   if set in `other`
+    /// - Flags, like [`TestCases::verbose`], are enabled if enabled in either
+    ///
+    /// `other` is treated as configuration and will no longer run on `drop`.
+    pub fn extend(&self, other: &TestCases) -> &Self {
+        if std::ptr::eq(self, other) {
+            return self;
+        }
+        other.has_run.set(true);
+        self.runner.borrow_mut().update(&other.runner.borrow());
+        self.bins.borrow_mut().update(&other.bins.borrow());
+        self.substitutions
+            .borrow_mut()
+            .update(&other.substitutions.borrow());
+        self.modes.borrow_mut().update(&other.modes.borrow());
+        self
+    }
+
+    /// Parse all test case files without running any commands
+    ///
+    /// On failure, all problems found are reported and tests will no longer be run on `drop`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// #[test]
+    /// fn cli_tests() {
+    ///     let t = trycmd::TestCases::new();
+    ///     t.case("tests/cmd/*.trycmd");
+    ///     if let Err(errors) = t.validate() {
+    ///         for error in &errors {
+    ///             eprintln!("{}", error);
+    ///         }
+    ///         panic!("{} test case files failed to parse", errors.len());
+    ///     }
+    /// }
+    /// ```
+    pub fn validate(&self) -> Result<(), Vec<crate::ParseError>> {
+        let runner = self.runner.borrow_mut().prepare();
+        let result = runner.validate();
+        if result.is_err() {
+            self.has_run.set(true);
+        }
+        result
+    }
+
+    /// Compare the `TRYCMD=dump` directory `old` against `new`, see [`diff_dumps`][crate::diff_dumps]
+    ///
+    /// Variables and redactions are applied to both sides first, so output that only differs in
+    /// them isn't reported.
+    ///
+    /// ```rust,no_run
+    /// let diff = trycmd::TestCases::new()
+    ///     .redact(r"took \d+ms", "took [DURATION]ms")
+    ///     .unwrap()
+    ///     .diff_dumps("dump-main", "dump")
+    ///     .unwrap();
+    /// println!("{}", diff);
+    /// ```
+    pub fn diff_dumps(
+        &self,
+        old: impl AsRef<std::path::Path>,
+        new: impl AsRef<std::path::Path>,
+    ) -> Result<crate::DumpDiff, crate::Error> {
+        crate::dump::diff(old.as_ref(), new.as_ref(), &self.substitutions.borrow())
+    }
+
+    /// Run only the case file at `path` and return its results, one per step
+    ///
+    /// This is for iterating on a single case from a scratch test.  The case runs with this
+    /// `TestCases`'s settings under the current `TRYCMD` mode, even when no glob matches it or
+    /// filters, like `trycmd=name`, would exclude it.  Failures don't panic; they're left to the
+    /// caller, with their report in [`CaseResult::details`][crate::report::CaseResult::details].
+    ///
+    /// This counts as running the cases, so they will no longer run on `drop`.
+    ///
+    /// ```rust,no_run
+    /// let results = trycmd::TestCases::new()
+    ///     .default_bin_name("my-cmd")
+    ///     .run_one("tests/cmd/help.trycmd");
+    /// for result in &results {
+    ///     println!("{}: {:?}", result.name(), result.status());
+    /// }
+    /// ```
+    pub fn run_one(&self, path: impl AsRef<std::path::Path>) -> Vec<crate::report::CaseResult> {
+        self.has_run.set(true);
+
+        let (mode, handler) = self.mode();
+        let path = path.as_ref();
+        let runner = self.runner.borrow().prepare_one(path);
+        self.bins.borrow_mut().prepare();
+        runner.run_one(
+            path,
+            &mode,
+            handler.as_deref(),
+            &self.bins.borrow(),
+            &self.substitutions.borrow(),
+        )
+    }
+
     /// Run tests
     ///
     /// This will happen on `drop` if not done explicitly
     pub fn run(&self) {
         self.has_run.set(true);
 
-        let mode = parse_mode(std::env::var_os("TRYCMD").as_deref());
-        mode.initialize().unwrap();
-
+        let (mode, handler) = self.mode();
         let runner = self.runner.borrow_mut().prepare();
-        runner.run(&mode, &self.bins.borrow(), &self.substitutions.borrow());
+        self.bins.borrow_mut().prepare();
+        if parse_flag(std::env::var_os("TRYCMD_EXPLAIN").as_deref()) {
+            runner.explain(&self.bins.borrow());
+            return;
+        }
+        runner.run(
+            &mode,
+            handler.as_deref(),
+            &self.bins.borrow(),
+            &self.substitutions.borrow(),
+        );
+    }
+
+    /// Mode selected by `TRYCMD`, with its handler if registered with
+    /// [`TestCases::register_mode`]
+    fn mode(&self) -> (crate::Mode, Option<std::sync::Arc<dyn crate::ModeHandler>>) {
+        let var = std::env::var_os("TRYCMD");
+        let handler = self.modes.borrow().get(var.as_deref());
+        let mode = match &handler {
+            Some(handler) => {
+                handler.initialize().unwrap();
+                crate::Mode::Fail
+            }
+            None => {
+                let mode = parse_mode(var.as_deref());
+                mode.initialize().unwrap();
+                mode
+            }
+        };
+        (mode, handler)
     }
 }
 
@@ -188,12 +1381,22 @@ impl Drop for TestCases {
 // filter string will be run.
 #[allow(clippy::needless_collect)] // false positive https://github.com/rust-lang/rust-clippy/issues/5991
 fn parse_include(args: impl IntoIterator<Item = std::ffi::OsString>) -> Option<Vec<String>> {
+    parse_filter_args(args, "trycmd=")
+}
+
+fn parse_tag_args(args: impl IntoIterator<Item = std::ffi::OsString>) -> Option<Vec<String>> {
+    parse_filter_args(args, "trycmd-tag=")
+}
+
+fn parse_filter_args(
+    args: impl IntoIterator<Item = std::ffi::OsString>,
+    prefix: &str,
+) -> Option<Vec<String>> {
     let filters = args
         .into_iter()
         .flat_map(std::ffi::OsString::into_string)
         .filter_map(|arg| {
-            const PREFIX: &str = "trycmd=";
-            if let Some(remainder) = arg.strip_prefix(PREFIX) {
+            if let Some(remainder) = arg.strip_prefix(prefix) {
                 if remainder.is_empty() {
                     None
                 } else {
@@ -212,11 +1415,59 @@ fn parse_include(args: impl IntoIterator<Item = std::ffi::OsString>) -> Option<V
     }
 }
 
+fn parse_tags(var: Option<&str>) -> Option<Vec<String>> {
+    let tags = var?
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    if tags.is_empty() {
+        None
+    } else {
+        Some(tags)
+    }
+}
+
+fn merge_tags(args: Option<Vec<String>>, var: Option<Vec<String>>) -> Option<Vec<String>> {
+    match (args, var) {
+        (Some(mut args), Some(var)) => {
+            args.extend(var);
+            Some(args)
+        }
+        (args, var) => args.or(var),
+    }
+}
+
+/// Verbosity level, where `TRYCMD_VERBOSE=2` also reports how each command line was split
+fn parse_verbose(var: Option<&std::ffi::OsStr>) -> u8 {
+    match var {
+        Some(var) if var == "2" => 2,
+        Some(var) => (!var.is_empty() && var != "0").into(),
+        None => 0,
+    }
+}
+
+fn parse_flag(var: Option<&std::ffi::OsStr>) -> bool {
+    match var {
+        Some(var) => !var.is_empty() && var != "0",
+        None => false,
+    }
+}
+
+fn parse_dir(var: Option<&std::ffi::OsStr>) -> Option<std::path::PathBuf> {
+    var.filter(|var| !var.is_empty()).map(Into::into)
+}
+
 fn parse_mode(var: Option<&std::ffi::OsStr>) -> crate::Mode {
     if var == Some(std::ffi::OsStr::new("overwrite")) {
         crate::Mode::Overwrite
     } else if var == Some(std::ffi::OsStr::new("dump")) {
         crate::Mode::Dump("dump".into())
+    } else if var == Some(std::ffi::OsStr::new("record")) {
+        crate::Mode::Record(crate::record::DEFAULT_DIR.into())
+    } else if var == Some(std::ffi::OsStr::new("replay")) {
+        crate::Mode::Replay(crate::record::DEFAULT_DIR.into())
     } else {
         crate::Mode::Fail
     }
@@ -0,0 +1,37 @@
+//! Custom comparison of `*.out/` files
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Whether the actual file content matches the expected content, see
+/// [`TestCases::comparator`][crate::TestCases::comparator]
+pub(crate) type Comparator = Arc<dyn Fn(&[u8], &[u8]) -> bool + Send + Sync>;
+
+/// [`Comparator`]s by file extension
+#[derive(Clone, Default)]
+pub(crate) struct Comparators(BTreeMap<String, Comparator>);
+
+impl Comparators {
+    pub(crate) fn register(&mut self, extension: String, comparator: Comparator) {
+        self.0.insert(extension, comparator);
+    }
+
+    /// Merge in `other`, with its comparators taking precedence
+    pub(crate) fn update(&mut self, other: &Self) {
+        self.0
+            .extend(other.0.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+
+    /// Comparator for `path`'s extension, if one was registered
+    #[cfg(feature = "filesystem")]
+    pub(crate) fn get(&self, path: &std::path::Path) -> Option<&Comparator> {
+        let extension = path.extension()?.to_str()?;
+        self.0.get(extension)
+    }
+}
+
+impl std::fmt::Debug for Comparators {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
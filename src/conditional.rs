@@ -0,0 +1,112 @@
+//! `[if VAR]` / `[endif]` blocks in expected output
+
+use snapbox::utils::LinesWithTerminator;
+
+const END: &str = "[endif]";
+
+/// Whether `expected` has any conditional blocks
+pub(crate) fn has_conditionals(expected: &str) -> bool {
+    LinesWithTerminator::new(expected).any(|line| condition(line).is_some())
+}
+
+/// Check that every block is closed
+///
+/// Errors carry the 0-based line of the offending marker.
+pub(crate) fn check(expected: &str) -> Result<(), (usize, String)> {
+    let mut open = Vec::new();
+    for (i, line) in LinesWithTerminator::new(expected).enumerate() {
+        if let Some(var) = condition(line) {
+            open.push((i, var));
+        } else if is_end(line) && open.pop().is_none() {
+            return Err((i, format!("`{}` has no `[if ...]`", END)));
+        }
+    }
+    match open.pop() {
+        Some((i, var)) => Err((i, format!("`[if {}]` has no `{}`", var, END))),
+        None => Ok(()),
+    }
+}
+
+/// `expected` with the markers removed, along with the lines of blocks whose variable isn't set
+///
+/// Blocks can be nested, with the lines of an inner block only expected when both variables are
+/// set.
+pub(crate) fn resolve(expected: &str, is_set: impl Fn(&str) -> bool) -> Result<String, String> {
+    check(expected).map_err(|(i, message)| format!("line {}: {}", i + 1, message))?;
+
+    let mut resolved = String::new();
+    // Whether each open block is included, outermost first
+    let mut included = Vec::new();
+    for line in LinesWithTerminator::new(expected) {
+        if let Some(var) = condition(line) {
+            included.push(is_set(var));
+        } else if is_end(line) {
+            included.pop();
+        } else if included.iter().all(|i| *i) {
+            resolved.push_str(line);
+        }
+    }
+    Ok(resolved)
+}
+
+/// The variable of an `[if VAR]` line
+fn condition(line: &str) -> Option<&str> {
+    let var = line.trim_end().strip_prefix("[if ")?.strip_suffix(']')?;
+    (!var.is_empty() && !var.contains(char::is_whitespace)).then_some(var)
+}
+
+fn is_end(line: &str) -> bool {
+    line.trim_end() == END
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn includes_set_blocks() {
+        let expected = "Start
+[if VERBOSE]
+debug: loaded config
+[endif]
+Done
+";
+        assert_eq!(
+            resolve(expected, |var| var == "VERBOSE"),
+            Ok("Start\ndebug: loaded config\nDone\n".to_owned())
+        );
+        assert_eq!(resolve(expected, |_| false), Ok("Start\nDone\n".to_owned()));
+    }
+
+    #[test]
+    fn nested_blocks() {
+        let expected = "[if A]
+a
+[if B]
+b
+[endif]
+[endif]
+";
+        assert_eq!(resolve(expected, |var| var == "B"), Ok("".to_owned()));
+        assert_eq!(resolve(expected, |var| var == "A"), Ok("a\n".to_owned()));
+        assert_eq!(resolve(expected, |_| true), Ok("a\nb\n".to_owned()));
+    }
+
+    #[test]
+    fn not_a_marker() {
+        assert!(!has_conditionals("[if]\n[if two words]\n[ifVAR]\n"));
+        assert!(has_conditionals("[if VAR]\n[endif]\n"));
+    }
+
+    #[test]
+    fn unbalanced_blocks() {
+        assert_eq!(
+            check("Start\n[if VERBOSE]\ndebug\n"),
+            Err((1, "`[if VERBOSE]` has no `[endif]`".to_owned()))
+        );
+        assert_eq!(
+            check("[endif]\n"),
+            Err((0, "`[endif]` has no `[if ...]`".to_owned()))
+        );
+    }
+}
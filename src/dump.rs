@@ -0,0 +1,304 @@
+//! Compare two `TRYCMD=dump` directories
+
+/// Differences between two `TRYCMD=dump` directories, see [`diff_dumps`][crate::diff_dumps]
+///
+/// Files are paired by their path relative to the dump, so `help.stdout` in one dump is compared
+/// against `help.stdout` in the other and `help.out/` sandboxes file by file.
+///
+/// `Display` renders a unified diff of each changed file followed by a summary of the added and
+/// removed files.
+#[derive(Clone, Debug, Default)]
+pub struct DumpDiff {
+    changed: Vec<DumpChange>,
+    added: Vec<std::path::PathBuf>,
+    removed: Vec<std::path::PathBuf>,
+}
+
+impl DumpDiff {
+    /// Whether the dumps are the same, after substitutions
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.added.is_empty() && self.removed.is_empty()
+    }
+
+    /// Files in both dumps whose content differs
+    pub fn changed(&self) -> &[DumpChange] {
+        &self.changed
+    }
+
+    /// Files only in the new dump, relative to it
+    pub fn added(&self) -> &[std::path::PathBuf] {
+        &self.added
+    }
+
+    /// Files only in the old dump, relative to it
+    pub fn removed(&self) -> &[std::path::PathBuf] {
+        &self.removed
+    }
+}
+
+impl std::fmt::Display for DumpDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let palette = snapbox::report::Palette::color();
+        for change in &self.changed {
+            writeln!(f, "{}", change)?;
+        }
+        for path in &self.added {
+            writeln!(f, "{} {}", palette.info("added"), path.display())?;
+        }
+        for path in &self.removed {
+            writeln!(f, "{} {}", palette.error("removed"), path.display())?;
+        }
+        write!(
+            f,
+            "{} changed, {} added, {} removed",
+            self.changed.len(),
+            self.added.len(),
+            self.removed.len()
+        )
+    }
+}
+
+/// A file whose content differs between two dumps
+#[derive(Clone, Debug)]
+pub struct DumpChange {
+    path: std::path::PathBuf,
+    old: snapbox::Data,
+    new: snapbox::Data,
+}
+
+impl DumpChange {
+    /// Path of the file, relative to the dumps
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Content in the old dump, after substitutions
+    pub fn before(&self) -> &snapbox::Data {
+        &self.old
+    }
+
+    /// Content in the new dump, after substitutions
+    pub fn after(&self) -> &snapbox::Data {
+        &self.new
+    }
+}
+
+impl std::fmt::Display for DumpChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let palette = snapbox::report::Palette::color();
+        let path = self.path.display();
+        writeln!(f, "{} {}", palette.warn("changed"), path)?;
+        snapbox::report::write_diff(
+            f,
+            &self.old,
+            &self.new,
+            Some(&format_args!("old/{}", path)),
+            Some(&format_args!("new/{}", path)),
+            palette,
+        )
+    }
+}
+
+/// Compare the `TRYCMD=dump` directory `old` against `new`
+///
+/// This is for reviewing how a change affects output without any expectations involved: dump on
+/// `main`, dump on a branch, and diff the two.  To keep noise like paths and durations out of the
+/// report, use [`TestCases::diff_dumps`][crate::TestCases::diff_dumps] which applies its
+/// variables and redactions to both sides.
+///
+/// ```rust,no_run
+/// let diff = trycmd::diff_dumps("dump-main", "dump").unwrap();
+/// if !diff.is_empty() {
+///     println!("{}", diff);
+/// }
+/// ```
+pub fn diff_dumps(
+    old: impl AsRef<std::path::Path>,
+    new: impl AsRef<std::path::Path>,
+) -> Result<DumpDiff, crate::Error> {
+    diff(old.as_ref(), new.as_ref(), &snapbox::Substitutions::new())
+}
+
+pub(crate) fn diff(
+    old: &std::path::Path,
+    new: &std::path::Path,
+    substitutions: &snapbox::Substitutions,
+) -> Result<DumpDiff, crate::Error> {
+    let old_files = files(old)?;
+    let new_files = files(new)?;
+
+    let mut diff = DumpDiff::default();
+    for path in &old_files {
+        if new_files.binary_search(path).is_err() {
+            diff.removed.push(path.clone());
+        }
+    }
+    for path in &new_files {
+        if old_files.binary_search(path).is_err() {
+            diff.added.push(path.clone());
+            continue;
+        }
+        let old_content = read(&old.join(path), substitutions)?;
+        let new_content = read(&new.join(path), substitutions)?;
+        if old_content != new_content {
+            diff.changed.push(DumpChange {
+                path: path.clone(),
+                old: old_content,
+                new: new_content,
+            });
+        }
+    }
+    Ok(diff)
+}
+
+/// Files under `root`, relative to it and sorted
+///
+/// Symlinks are listed rather than followed, so a link to a parent can't loop.
+fn files(root: &std::path::Path) -> Result<Vec<std::path::PathBuf>, crate::Error> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_owned()];
+    while let Some(dir) = pending.pop() {
+        let entries = std::fs::read_dir(&dir)
+            .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry
+                .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+                .path();
+            let file_type = std::fs::symlink_metadata(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+                .file_type();
+            if file_type.is_dir() {
+                pending.push(path);
+            } else {
+                let rel = path.strip_prefix(root).unwrap_or(&path).to_owned();
+                // Written by `TRYCMD=dump` to keep the dump out of version control
+                if rel != std::path::Path::new(".gitignore") {
+                    files.push(rel);
+                }
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn read(
+    path: &std::path::Path,
+    substitutions: &snapbox::Substitutions,
+) -> Result<snapbox::Data, crate::Error> {
+    if path.is_symlink() {
+        // Compared by where they point, which may not even exist
+        let target = std::fs::read_link(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        return Ok(snapbox::Data::text(format!(
+            "symlink to {}",
+            target.display()
+        )));
+    }
+    let content =
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let data = match String::from_utf8(content) {
+        Ok(text) => snapbox::Data::text(substitutions.normalize(&text, "")),
+        Err(err) => snapbox::Data::binary(err.into_bytes()),
+    };
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write(root: &std::path::Path, files: &[(&str, &str)]) {
+        for (name, content) in files {
+            let path = root.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+    }
+
+    #[test]
+    fn pairs_files() {
+        let root = std::env::temp_dir().join(format!("trycmd-dump-diff-{}", std::process::id()));
+        let old = root.join("old");
+        let new = root.join("new");
+        write(
+            &old,
+            &[
+                (".gitignore", "*\n"),
+                ("same.stdout", "Hello\n"),
+                ("help.stdout", "Usage: cmd\n"),
+                ("gone.stdout", "Bye\n"),
+                ("sandbox.out/file.txt", "one\n"),
+            ],
+        );
+        write(
+            &new,
+            &[
+                (".gitignore", "*\n"),
+                ("same.stdout", "Hello\n"),
+                ("help.stdout", "Usage: cmd [OPTIONS]\n"),
+                ("new.stderr", "warning\n"),
+                ("sandbox.out/file.txt", "two\n"),
+            ],
+        );
+
+        let diff = diff(&old, &new, &snapbox::Substitutions::new()).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let changed: Vec<_> = diff.changed().iter().map(|c| c.path()).collect();
+        assert_eq!(
+            changed,
+            [
+                std::path::Path::new("help.stdout"),
+                std::path::Path::new("sandbox.out/file.txt")
+            ]
+        );
+        assert_eq!(diff.added(), [std::path::PathBuf::from("new.stderr")]);
+        assert_eq!(diff.removed(), [std::path::PathBuf::from("gone.stdout")]);
+        assert_eq!(
+            diff.changed()[0].after(),
+            &snapbox::Data::text("Usage: cmd [OPTIONS]\n")
+        );
+        assert!(diff.to_string().ends_with("2 changed, 1 added, 1 removed"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn lists_symlinks() {
+        let root = std::env::temp_dir().join(format!("trycmd-dump-links-{}", std::process::id()));
+        let old = root.join("old");
+        let new = root.join("new");
+        write(&old, &[("sandbox.out/file.txt", "one\n")]);
+        write(&new, &[("sandbox.out/file.txt", "one\n")]);
+        std::os::unix::fs::symlink("..", old.join("sandbox.out/parent")).unwrap();
+        std::os::unix::fs::symlink(".", new.join("sandbox.out/parent")).unwrap();
+
+        let diff = diff(&old, &new, &snapbox::Substitutions::new()).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let changed: Vec<_> = diff.changed().iter().map(|c| c.path()).collect();
+        assert_eq!(changed, [std::path::Path::new("sandbox.out/parent")]);
+        assert_eq!(
+            diff.changed()[0].after(),
+            &snapbox::Data::text("symlink to .")
+        );
+    }
+
+    #[test]
+    fn substitutes_both_sides() {
+        let root = std::env::temp_dir().join(format!("trycmd-dump-subst-{}", std::process::id()));
+        let old = root.join("old");
+        let new = root.join("new");
+        write(&old, &[("run.stdout", "Finished in 1.52s\n")]);
+        write(&new, &[("run.stdout", "Finished in 0.98s\n")]);
+
+        let mut substitutions = snapbox::Substitutions::new();
+        let unchanged = diff(&old, &new, &substitutions).unwrap();
+        substitutions.redact(r"\d+\.\d+s", "[DURATION]").unwrap();
+        let redacted = diff(&old, &new, &substitutions).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(unchanged.changed().len(), 1);
+        assert!(redacted.is_empty(), "{}", redacted);
+    }
+}
@@ -0,0 +1,74 @@
+/// Problem found while parsing a test case file
+///
+/// See [`TestCases::validate`][crate::TestCases::validate]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    path: std::path::PathBuf,
+    line: Option<usize>,
+    message: String,
+}
+
+impl ParseError {
+    pub(crate) fn new(line: Option<usize>, message: impl std::fmt::Display) -> Self {
+        Self {
+            path: Default::default(),
+            line,
+            message: message.to_string().trim_end().to_owned(),
+        }
+    }
+
+    pub(crate) fn with_path(mut self, path: &std::path::Path) -> Self {
+        self.path = path.to_owned();
+        self
+    }
+
+    /// Test case file the problem was found in
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// 1-based line number within [`ParseError::path`], when known
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+
+    /// Human-readable description of the problem
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<crate::Error> for ParseError {
+    fn from(other: crate::Error) -> Self {
+        Self::new(None, other)
+    }
+}
+
+impl From<String> for ParseError {
+    fn from(other: String) -> Self {
+        Self::new(None, other)
+    }
+}
+
+impl<'s> From<&'s str> for ParseError {
+    fn from(other: &'s str) -> Self {
+        Self::new(None, other)
+    }
+}
+
+impl From<ParseError> for crate::Error {
+    fn from(other: ParseError) -> Self {
+        crate::Error::new(other.message)
+    }
+}
@@ -0,0 +1,250 @@
+//! `# [IGNORE START]` / `# [IGNORE END]` blocks in expected output
+
+use snapbox::utils::LinesWithTerminator;
+
+const START: &str = "# [IGNORE START]";
+const END: &str = "# [IGNORE END]";
+const ELIDE: &str = "...";
+
+/// Whether `expected` has any ignored blocks
+pub(crate) fn has_ignored(expected: &str) -> bool {
+    LinesWithTerminator::new(expected).any(|line| is_marker(line, START))
+}
+
+/// Whether `line` starts or ends an ignored block
+pub(crate) fn is_marker_line(line: &str) -> bool {
+    is_marker(line, START) || is_marker(line, END)
+}
+
+/// Check that every block is closed and not nested
+///
+/// Errors carry the 0-based line of the offending marker.
+pub(crate) fn check(expected: &str) -> Result<(), (usize, String)> {
+    let mut open = None;
+    for (i, line) in LinesWithTerminator::new(expected).enumerate() {
+        if is_marker(line, START) {
+            if open.is_some() {
+                return Err((i, format!("`{}` is already in an ignored block", START)));
+            }
+            open = Some(i);
+        } else if is_marker(line, END) && open.take().is_none() {
+            return Err((i, format!("`{}` has no `{}`", END, START)));
+        }
+    }
+    match open {
+        Some(i) => Err((i, format!("`{}` has no `{}`", START, END))),
+        None => Ok(()),
+    }
+}
+
+/// Compare `actual` against `expected`, skipping the lines in ignored blocks
+///
+/// A block ends at the first actual line matching the line after `# [IGNORE END]`, like `...`.
+/// On a mismatch, returns `actual` normalized against `expected` with the markers placed around
+/// the lines each block skipped, ready for `TRYCMD=overwrite`.
+pub(crate) fn normalize(
+    actual: &str,
+    expected: &str,
+    substitutions: &snapbox::Substitutions,
+) -> Result<(), String> {
+    let sections = sections(expected);
+    let actual: Vec<_> = LinesWithTerminator::new(actual).collect();
+
+    let mut matched = true;
+    let mut normalized = String::new();
+    let mut pos = 0;
+    for (i, section) in sections.iter().enumerate() {
+        let pattern = section.compared.concat();
+        let covered = match section.ignored {
+            Some(_) => coverage(&actual[pos..], &section.compared, substitutions),
+            // Any extra lines are a mismatch
+            None => actual.len() - pos,
+        };
+        let chunk = substitutions.normalize(&actual[pos..pos + covered].concat(), &pattern);
+        matched &= chunk == pattern;
+        normalized.push_str(&chunk);
+        pos += covered;
+
+        if let Some(ignored) = &section.ignored {
+            let next = sections
+                .get(i + 1)
+                .and_then(|s| s.compared.first())
+                .copied();
+            let skipped = match next {
+                // Leave it to the `...` to decide
+                Some(next) if is_elide(next) => 0,
+                Some(next) => actual[pos..]
+                    .iter()
+                    .position(|line| line_matches(line, next, substitutions))
+                    .unwrap_or(actual.len() - pos),
+                None => actual.len() - pos,
+            };
+            push_line(&mut normalized, ignored.start);
+            for line in &actual[pos..pos + skipped] {
+                normalized.push_str(line);
+            }
+            pos += skipped;
+            if let Some(end) = ignored.end {
+                push_line(&mut normalized, end);
+            }
+        }
+    }
+
+    if matched {
+        Ok(())
+    } else {
+        Err(normalized)
+    }
+}
+
+/// Lines compared against the actual output, followed by an ignored block
+#[derive(Debug, Default)]
+struct Section<'e> {
+    compared: Vec<&'e str>,
+    ignored: Option<Ignored<'e>>,
+}
+
+/// Marker lines of an ignored block
+#[derive(Debug)]
+struct Ignored<'e> {
+    start: &'e str,
+    end: Option<&'e str>,
+}
+
+fn sections(expected: &str) -> Vec<Section<'_>> {
+    let mut sections = Vec::new();
+    let mut current = Section::default();
+    let mut in_block = false;
+    for line in LinesWithTerminator::new(expected) {
+        if in_block {
+            if is_marker(line, END) {
+                if let Some(ignored) = &mut current.ignored {
+                    ignored.end = Some(line);
+                }
+                sections.push(std::mem::take(&mut current));
+                in_block = false;
+            }
+        } else if is_marker(line, START) {
+            current.ignored = Some(Ignored {
+                start: line,
+                end: None,
+            });
+            in_block = true;
+        } else {
+            current.compared.push(line);
+        }
+    }
+    sections.push(current);
+    sections
+}
+
+/// How many `lines` the `pattern` lines before a block cover
+fn coverage(lines: &[&str], pattern: &[&str], substitutions: &snapbox::Substitutions) -> usize {
+    let mut pos = 0;
+    for (i, pattern_line) in pattern.iter().enumerate() {
+        if is_elide(pattern_line) {
+            // A trailing `...` is redundant with the block
+            if let Some(next) = pattern.get(i + 1) {
+                pos += lines[pos..]
+                    .iter()
+                    .position(|line| line_matches(line, next, substitutions))
+                    .unwrap_or(lines.len() - pos);
+            }
+        } else {
+            pos = (pos + 1).min(lines.len());
+        }
+    }
+    pos
+}
+
+pub(crate) fn line_matches(
+    line: &str,
+    pattern: &str,
+    substitutions: &snapbox::Substitutions,
+) -> bool {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let pattern = pattern.trim_end_matches(['\n', '\r']);
+    substitutions.normalize(line, pattern) == pattern
+}
+
+fn push_line(normalized: &mut String, line: &str) {
+    if !normalized.is_empty() && !normalized.ends_with('\n') {
+        normalized.push('\n');
+    }
+    normalized.push_str(line);
+}
+
+fn is_marker(line: &str, marker: &str) -> bool {
+    line.trim_end() == marker
+}
+
+fn is_elide(line: &str) -> bool {
+    line.trim_end() == ELIDE
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches_anything_in_block() {
+        let expected = "Start
+# [IGNORE START]
+stack trace
+# [IGNORE END]
+Done
+";
+        let actual = "Start\nframe 1\nframe 2\nframe 3\nDone\n";
+        assert_eq!(
+            normalize(actual, expected, &snapbox::Substitutions::new()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn mismatch_keeps_markers() {
+        let expected = "Start
+# [IGNORE START]
+stack trace
+# [IGNORE END]
+Done
+";
+        let actual = "Begin\nframe 1\nframe 2\nDone\n";
+        assert_eq!(
+            normalize(actual, expected, &snapbox::Substitutions::new()),
+            Err("Begin
+# [IGNORE START]
+frame 1
+frame 2
+# [IGNORE END]
+Done
+"
+            .to_owned())
+        );
+    }
+
+    #[test]
+    fn block_at_end() {
+        let expected = "Start
+# [IGNORE START]
+# [IGNORE END]
+";
+        let actual = "Start\nanything\n";
+        assert_eq!(
+            normalize(actual, expected, &snapbox::Substitutions::new()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn unclosed_block() {
+        assert_eq!(
+            check("Start\n# [IGNORE START]\nstack trace\n"),
+            Err((1, "`# [IGNORE START]` has no `# [IGNORE END]`".to_owned()))
+        );
+        assert_eq!(
+            check("# [IGNORE END]\n"),
+            Err((0, "`# [IGNORE END]` has no `# [IGNORE START]`".to_owned()))
+        );
+    }
+}
@@ -57,17 +57,65 @@
 //!
 //! You can then copy over to `tests/cmd` the cases you want to test
 //!
+//! To review how a change affects output, dump before and after it and compare the two with
+//! [`diff_dumps`] or [`TestCases::diff_dumps`].
+//!
 //! To update snapshots, run
 //! ```console
 //! $ TRYCMD=overwrite cargo test --test cli_tests
 //! ```
 //! This will overwrite any existing `.stdout` and `.stderr` file in `tests/cmd`
+//! and list which files were created or modified.
 //!
-//! To filter the tests to those with `name1`, `name2`, etc in their file names, you can run:
+//! To filter the tests to those with `name1`, `name2`, etc in their file names or tags, you can run:
 //! ```console
 //! cargo test --test cli_tests -- cli_tests trycmd=name1 trycmd=name2...
 //! ```
 //!
+//! To filter the tests to those tagged with `tag1`, `tag2`, etc (see [`TestCases::case_tag`]), you
+//! can run:
+//! ```console
+//! $ TRYCMD_TAGS=tag1,tag2 cargo test --test cli_tests
+//! ```
+//! or
+//! ```console
+//! $ cargo test --test cli_tests -- cli_tests trycmd-tag=tag1 trycmd-tag=tag2
+//! ```
+//!
+//! To save what each case's commands did (exit status, `stdout`, `stderr`, and sandbox
+//! contents) to `.recorded/`, run
+//! ```console
+//! $ TRYCMD=record cargo test --test cli_tests
+//! ```
+//! Then `TRYCMD=replay` checks the cases against those recordings, without running anything.
+//! This is quick for iterating on snapshots and doesn't need the commands to be available.
+//! Replaying fails for cases without a recording or whose commands, input, or environment,
+//! including what [`TestCases`] adds, have changed since.
+//!
+//! Other values of `TRYCMD` can be handled with [`TestCases::register_mode`].
+//!
+//! To check how each command line was split into arguments without running anything, run
+//! ```console
+//! $ TRYCMD_EXPLAIN=1 cargo test --test cli_tests
+//! ```
+//! Failures report these arguments too, as does `TRYCMD_VERBOSE=2` for every command.
+//!
+//! `*.stdout`, `*.stderr`, `*.in/`, and `*.out/` that no case uses, like after a case was
+//! renamed, are reported as a warning.  To fail on them instead, run
+//! ```console
+//! $ TRYCMD_STRICT_ARTIFACTS=1 cargo test --test cli_tests
+//! ```
+//! See [`TestCases::check_orphaned_artifacts`].
+//!
+//! To keep `*.stdout`, `*.stderr`, and `*.interleaved` in a directory of their own, rather than
+//! next to each case, run
+//! ```console
+//! $ TRYCMD_SNAPSHOT_DIR=tests/snapshots cargo test --test cli_tests
+//! ```
+//! They are read from and written to that directory under the case's path, like
+//! `tests/snapshots/tests/cmd/help.stdout` for `tests/cmd/help.toml`, with each `..` written as
+//! `_parent_`.
+//!
 //! To debug what `trycmd` is doing, run `cargo test -F trycmd/debug`.
 //!
 //! ## File Formats
@@ -100,13 +148,35 @@
 //! - "`> `" line prefix appends to the prior command
 //! - "`? <status>`" line indicates the exit code (like `echo "? $?"`) and `<status>` can be
 //!   - An exit code
-//!   - `success` *(default)*, `failed`, `interrupted`, `skipped`
+//!   - `success` *(default, see [`TestCases::default_status`])*, `failed`, `interrupted`,
+//!     `skipped`
 //!  - All following lines are treated as stdout + stderr
+//! - With `comment-prefix = "//"` (or another prefix) in the front matter, lines starting with it
+//!   are comments, before a command or right before the next one, rather than output
+//!   (`# [IGNORE START]` / `# [IGNORE END]` are never comments)
 //!
 //! The command is then split with [shlex](https://crates.io/crates/shlex), allowing quoted content
 //! to allow spaces.  The first argument is the program to run which maps to `bin.name` in the
 //! `.toml` file.
 //!
+//! `$ cd <dir>` is built in rather than run: later commands run in `<dir>`, relative to the
+//! current directory or absolute, and `[CWD]` follows along.  `<dir>` must exist and be within
+//! the sandbox (or the starting directory when not sandboxed).  It can't have output or a status.
+//!
+//! With `shell-syntax = true` in the front matter, or the `shell-syntax` attribute on a block
+//! (like ` ```console,shell-syntax `), `|` and `<` work like in a shell.  Otherwise, they are
+//! passed as arguments like any other word.
+//!
+//! Commands can be chained with an unquoted `|`, like `$ cmd | sort`: each command's `stdout` is
+//! piped into the next, and only the last command's output and status are checked.  An earlier
+//! command that exits with a non-zero code fails the step, showing its `stderr`.  Environment
+//! variables assigned before a command only apply to it.
+//!
+//! A trailing unquoted `< <file>`, like `$ cmd apply - < plan.json`, gives the command `<file>` as
+//! `stdin`.  `<file>` is relative to the working directory, like the sandbox, and is read when the
+//! command runs, so an earlier command can write it.  In `*.toml` files, this is
+//! `stdin = { file = "<file>" }`.
+//!
 //! Example:
 //!
 //! With a `[[bin]]` like:
@@ -125,6 +195,24 @@
 //! ```
 //! ~~~
 //!
+//! Settings for all of the commands in the file, like `timeout`, `env`, or `fs`, can be put in
+//! front matter at the very top of the file, fenced by `---` lines or a ` ```toml ` block.  This
+//! accepts the same keys as [`*.toml`](#toml) except for the command (`bin`, `args`, `status`),
+//! its input and output (`stdin`, `stdout`, `stderr`, etc), `platforms`, and `matrix`.  A `---`
+//! block that isn't valid settings, like YAML front matter in a `.md` file, is left alone, while
+//! errors in a ` ```toml ` block are reported:
+//! ~~~md
+//! ---
+//! timeout = "10s"
+//! env.add.NO_COLOR = "1"
+//! ---
+//! ```console
+//! $ my-cmd
+//! Hello world
+//!
+//! ```
+//! ~~~
+//!
 //! For a more complete example, see:
 //! <https://github.com/assert-rs/trycmd/tree/main/examples/demo_trycmd>.
 //!
@@ -138,19 +226,119 @@
 //! [See full schema](https://github.com/assert-rs/trycmd/blob/main/schema.json):
 //! Basic parameters:
 //! - `bin.name`: The name of the binary target from `Cargo.toml` to be used to find the file path
-//! - `args`: the arguments (including flags and option) passed to the binary
+//! - `bin.script`: Instead of `bin.name`, a `script` to run with `shell -c` (see
+//!   [`TestCases::default_bin_script`])
+//! - `args`: the arguments (including flags and option) passed to the binary, either as an array
+//!   or as a command line string like `"build --features 'foo bar'"`, quoted like in `*.trycmd`
+//! - `args-override`: arguments passed before `args` in place of [`TestCases::default_args`]
+//! - `arg0`: the name the command sees as `argv[0]`, in place of the bin's path, for multi-call
+//!   binaries.  Unix only.
+//! - `lock`: name of a lock shared with other cases, including in other test binaries, that
+//!   should not run at the same time, like `fs.sandbox = false` cases modifying the same directory
+//! - `tags`: labels for selecting which cases to run (see [`TestCases::case_tag`])
+//! - `platforms`: only run on these platforms, skipping the case elsewhere.  Platforms are
+//!   matched against [`std::env::consts::FAMILY`] (`unix`, `windows`) and
+//!   [`std::env::consts::OS`] (`linux`, `macos`, etc)
+//! - `matrix`: set to `false` to run only once, rather than against each bin from
+//!   [`TestCases::matrix_bins`]
+//! - `cwd`: the directory, relative to `CARGO_MANIFEST_DIR`, to run in when not sandboxed (see
+//!   also [`TestCases::default_cwd`])
+//!   - **WARNING:** the command runs against the real directory, so it can be affected by and can
+//!     modify the state of your repository
+//! - `env.add`: environment variables to set
+//! - `env.remove`: environment variables to unset, including those set with [`TestCases::env`]
+//!   or by `trycmd.toml`.  Additions win: a variable in both `env.add` and `env.remove` is set
+//!   to its `env.add` value
+//! - `env.inherit`: set to `false` to not inherit the test's environment
+//! - `locale`: set `LC_ALL`, `LANG`, and `LANGUAGE`, like `locale = "C"`, even when the
+//!   environment is inherited.  Any of them in `env.add` are left as-is.  See
+//!   [`TestCases::locale`]
+//! - `umask`: the file mode creation mask in octal, like `umask = "022"`, see
+//!   [`TestCases::umask`].  This does nothing on Windows
+//! - `isolate-home`: set to `true` to point `HOME` and the XDG directories at empty directories
+//!   in a temp directory, or to `false` to opt out of [`TestCases::isolate_home`].
+//!   `isolate-home.seed = "home.in"` fills the fake home in from a directory next to the case
+//! - `extract`: a list of numbers captured from the output that must be within bounds, each
+//!   with a `pattern` (a regex with one capture group, checked against its first match), `min`
+//!   and / or `max`, and `stream = "stderr"` to read `stderr` rather than `stdout`.  This is for
+//!   output that varies within limits, like `processed 42 files`, without snapshotting it
+//! - `expect-timeout`: set to `true` to expect the command to still be running when `timeout`
+//!   fires, like when it should block waiting for input.  Exiting before then is a failure.  This
+//!   needs a `timeout` and can't be combined with `status`
+//! - `repeat`: run the case this many times, each in a fresh sandbox, to catch output that isn't
+//!   stable.  Every run has to pass and their output has to be the same after substitutions; a
+//!   failure shows the first run that differs from run 1.  With `TRYCMD=overwrite`, the first
+//!   run's output is written.  `TRYCMD=dump` and recording run the case once
+//!
+//! **Case Variables**
+//!
+//! Every command is told which case it is running for with these environment variables, even
+//! with `env.inherit = false`:
+//! - `TRYCMD_CASE`: the case file, relative to `CARGO_MANIFEST_DIR` and with `/` separators
+//! - `TRYCMD_STEP`: the 0-based index of the command within the case file
+//! - `TRYCMD_SANDBOX`: the absolute path of the sandbox, for `fs.sandbox = true` cases
+//!
+//! Set `env.case-vars = false` for a case to leave them out.  Values in `env.add` take
+//! precedence.
 //!
 //! #### `*.stdin`
 //!
 //! Data to pass to `stdin`.
 //! - If not present, nothing will be written to `stdin`
+//! - If `stdin-pipe = false` in `*.toml` or `.trycmd` front matter, `stdin` is null rather
+//!   than a pipe, for commands that act differently when `stdin` isn't a pipe.  This can't be
+//!   combined with `*.stdin` or `stdin`.
 //! - If `binary = false` in `*.toml` (the default), newlines and path separators will be normalized.
+//! - If `encoding` is set in `*.toml`, it is passed along as-is
 //!
 //! #### `*.stdout` and `*.stderr`
 //!
 //! Expected results for `stdout` or `stderr`.
 //! - If not present, we'll not verify the output
 //! - If `binary = false` in `*.toml` (the default), newlines and path separators will be normalized before comparing
+//! - If `stdout = { paths = ["header.stdout", "body.stdout"] }` in `*.toml`, those files (relative to
+//!   `*.toml`) are concatenated, in order, to form the expected output.  `TRYCMD=overwrite` needs
+//!   `overwrite = "body.stdout"` to know which file takes the changes.  The same goes for `stderr`.
+//! - If `stdout-lines = N` in `*.toml`, `stdout` must have exactly `N` lines, with or without
+//!   `*.stdout`
+//! - If `stdout = { same-as = "format.toml" }` in `*.toml`, `stdout` must match that case's
+//!   `stdout` (relative to `*.toml`), like for idempotency or round-trip checks.  That case is run
+//!   first, by the same [`TestCases`], and its output is normalized but otherwise compared as-is.
+//!   `TRYCMD=overwrite` leaves these alone.  The same goes for `stderr`.
+//! - With `TRYCMD=overwrite` and `externalize-over = N` in `*.toml` (or
+//!   [`TestCases::externalize_over`]), output longer than `N` lines is written here and shorter
+//!   output is written inline in `*.toml`
+//! - If `stderr-unordered = true` in `*.toml`, each line of `stderr` must match a line in
+//!   `*.stderr`, but in any order
+//! - If `sort-output-lines = true` in `*.toml` or `.trycmd` front matter (or
+//!   [`TestCases::normalize_sort_lines`]), the lines of `stdout` and of `stderr` are each sorted
+//!   by their bytes, both expected and actual, before comparing
+//! - With [`TestCases::collapse_repeated_lines`], runs of identical lines are written as the
+//!   line followed by `[.. repeated N times]`, and `[.. repeated]` matches a run of any length
+//! - If `encoding = "iso-8859-1"` (or another [WHATWG encoding
+//!   label](https://encoding.spec.whatwg.org/#names-and-labels)) in `*.toml`, `stdout`, `stderr`,
+//!   `*.stdout`, and `*.stderr` are transcoded to UTF-8 before comparing and `TRYCMD=overwrite`
+//!   writes `*.stdout` and `*.stderr` back in that encoding
+//! - If `stdout-match-anchor = "prefix"` (or `"suffix"`) in `*.toml`, only as many lines from the
+//!   start (or end) of `stdout` as are expected are compared; `"full"` is the default.  The same
+//!   goes for `stderr-match-anchor`.  `TRYCMD=overwrite` only rewrites the compared lines.
+//!
+//! #### `*.interleaved`
+//!
+//! Expected results for `stdout` and `stderr` together, when `output = "interleaved"` in
+//! `*.toml`.
+//! - Lines are in the order they were written, with `stderr` lines prefixed with `err: `, like:
+//!   ```text
+//!   Compiling foo
+//!   err: warning: unused variable
+//!   Finished
+//!   ```
+//! - Ordering is best-effort: each stream is read separately, so lines written at nearly the
+//!   same time may be captured in either order, and output the command buffers shows up when it
+//!   is flushed
+//! - `*.stdout`, `*.stderr`, and the `stdout`, `stderr`, `stderr-to-stdout`, `stderr-unordered`,
+//!   and `stderr-match-anchor` settings can't be used alongside it
+//! - Otherwise, this is treated like `*.stdout`, including with `TRYCMD=overwrite`
 //!
 //! **Eliding Content**
 //!
@@ -165,11 +353,37 @@
 //! - `[EXE]` as part of the line: On Windows, matches `.exe`, ignored otherwise
 //! - `[ROOT]` as part of the line: The root directory for where the test is running
 //! - `[CWD]` as part of the line: The current working directory within the root
+//! - `[HOME]` as part of the line: The fake home, see [`TestCases::isolate_home`]
 //! - `[YOUR_NAME_HERE]` as part of the line: See [`TestCases::insert_var`]
+//! - Anything matching a regex passed to [`TestCases::redact`] is replaced, like
+//!   `took [DURATION]ms`
+//! - `[THREAD]` as part of the line: A thread name or id, see
+//!   [`TestCases::normalize_thread_ids`]
+//! - `[ADDR]` and `[PORT]` as part of the line: An IP address and the port after it, see
+//!   [`TestCases::normalize_socket_addrs`]
+//! - `# [IGNORE START]` and `# [IGNORE END]` as their own lines: skip the lines between them,
+//!   however many there are, up to the line after `# [IGNORE END]`.  What's written between the
+//!   markers is not compared; `TRYCMD=overwrite` replaces it with the actual output, keeping the
+//!   markers.
+//! - `[if VAR]` and `[endif]` as their own lines: the lines between them are only expected when
+//!   the environment variable `VAR` is set for the command, whether inherited, from `env.add`,
+//!   or from [`TestCases::env`].  Blocks can be nested.  `TRYCMD=overwrite` keeps the blocks of
+//!   passing commands but won't overwrite output with blocks that fails, which has to be updated
+//!   by hand.
+//!
+//! To match a literal `[..]`, write it as `[[..]]`; to match a literal `...` line, write it as
+//! `[[...]]`.  `TRYCMD=dump` and `TRYCMD=overwrite` will escape these when they appear in the
+//! output.
 //!
 //! We will preserve these with `TRYCMD=dump` and will make a best-effort at preserving them with
 //! `TRYCMD=overwrite`.
 //!
+//! When a case needs a variable's value to be compared literally, like a test of path printing
+//! that happens to run under `[CWD]`, list it in `substitutions.disable = ["[CWD]"]` in the
+//! `*.toml` or `.trycmd` front matter.  `substitutions.disable-all = true` turns off all of them,
+//! including `[EXE]`, `[ROOT]`, `[CWD]`, and redactions.  Naming a variable that isn't defined is
+//! an error.
+//!
 //! ### `*.in/`
 //!
 //! When present, this will automatically be picked as the CWD for the command.
@@ -183,12 +397,39 @@
 //!
 //! When present, each file in this directory will be compared to generated or modified files.
 //!
-//! See also "Eliding Content" for `.stdout`
+//! See also "Eliding Content" for `.stdout`, and [`TestCases::comparator`] for formats that
+//! can't be compared by content, like images.
 //!
 //! `.keep` files will be ignored.
 //!
 //! Note: This implies `fs.sandbox = true`.
 //!
+//! As `*.out/` can only list files that should exist, set `fs.absent = ["build/cache.bin",
+//! "tmp/**"]` in the `*.toml` for paths, relative to the sandbox, that must not exist after the
+//! command runs.  Globs are supported.  Failures say whether the path was copied from `*.in/` or
+//! created by the command.  `TRYCMD=overwrite` leaves `fs.absent` as-is.  This also implies
+//! `fs.sandbox = true`.  Paths can also be listed one per table, which is added to `fs.absent`:
+//! ```toml
+//! [[files-absent]]
+//! path = "report.txt"
+//! ```
+//!
+//! ### `trycmd.toml`
+//!
+//! Defaults for every case in its directory and below; it is never run as a case itself.  Only
+//! the nearest one, looking up to the package root, is used and the case's own settings take
+//! precedence.
+//! ```toml
+//! # Timeout for cases without a `timeout`
+//! timeout-ms = 5000
+//! # Command for cases without a `bin`
+//! default-bin.name = "my-bin"
+//!
+//! # Environment the case's `env` adds to
+//! [env.add]
+//! RUST_LOG = "debug"
+//! ```
+//!
 //! ## Examples
 //!
 //! - Simple cargo binary: [trycmd's integration tests](https://github.com/assert-rs/trycmd/blob/main/tests/cli_tests.rs)
@@ -232,18 +473,35 @@
 #![allow(clippy::collapsible_else_if)]
 
 pub mod cargo;
+pub mod report;
 pub mod schema;
 
 mod cases;
+mod comparator;
+mod conditional;
+mod dump;
+mod error;
+mod ignore;
+mod lock;
+mod mode;
+mod newline;
+mod progress;
+mod record;
 mod registry;
+mod repeat;
 mod runner;
 mod spec;
 
 pub use cases::TestCases;
+pub use dump::{diff_dumps, DumpChange, DumpDiff};
+pub use error::ParseError;
+pub use mode::ModeHandler;
+pub use newline::TrailingNewline;
+pub use report::OutputFormat;
 pub use snapbox::Error;
 
 pub(crate) use registry::BinRegistry;
-pub(crate) use runner::{Case, Mode, Runner};
+pub(crate) use runner::{CapturedOutputs, Case, MatrixLeg, Mode, Runner};
 pub(crate) use spec::RunnerSpec;
 
 pub(crate) use snapbox::Data;
@@ -0,0 +1,161 @@
+use std::io::Seek as _;
+use std::io::Write as _;
+
+/// How long to wait on another case holding the same lock
+const LOCK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+const LOCK_POLL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Serialize cases that share a `lock` name, including across test binaries
+///
+/// The lock is an OS advisory lock on a file in the system temp directory.  The OS releases it
+/// when the file is closed, on `drop` or when the process dies, so a crashed test run can't leave
+/// it held.
+#[derive(Debug)]
+pub(crate) struct CaseLock {
+    file: std::fs::File,
+}
+
+impl CaseLock {
+    pub(crate) fn acquire(name: &str, holder: &std::path::Path) -> Result<Self, crate::Error> {
+        let root = std::env::temp_dir().join("trycmd-locks");
+        std::fs::create_dir_all(&root)
+            .map_err(|e| format!("Failed to create {}: {}", root.display(), e))?;
+        let path = root.join(format!("{}.lock", sanitize(name)));
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            // Keeps the holder's description until the lock is taken
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+        let start = std::time::Instant::now();
+        loop {
+            match try_lock(&file) {
+                Ok(true) => {
+                    // Only for reporting who holds the lock
+                    let _ = file.set_len(0);
+                    let _ = file.rewind();
+                    let _ = writeln!(file, "{} (pid {})", holder.display(), std::process::id());
+                    return Ok(Self { file });
+                }
+                Ok(false) => {
+                    if LOCK_TIMEOUT <= start.elapsed() {
+                        let other = std::fs::read_to_string(&path)
+                            .ok()
+                            .map(|s| s.trim().to_owned())
+                            .filter(|s| !s.is_empty())
+                            .unwrap_or_else(|| "an unknown case".to_owned());
+                        return Err(format!(
+                            "Timed out after {}s waiting for lock `{}` held by {}",
+                            LOCK_TIMEOUT.as_secs(),
+                            name,
+                            other,
+                        )
+                        .into());
+                    }
+                    std::thread::sleep(LOCK_POLL);
+                }
+                Err(err) => {
+                    return Err(format!("Failed to lock {}: {}", path.display(), err).into());
+                }
+            }
+        }
+    }
+}
+
+impl Drop for CaseLock {
+    fn drop(&mut self) {
+        // Closing the file releases the lock.  The file stays, as another process may be waiting
+        // on it.
+        let _ = self.file.set_len(0);
+    }
+}
+
+/// Take an exclusive lock on `file` without blocking, returning whether it was taken
+#[cfg(unix)]
+fn try_lock(file: &std::fs::File) -> std::io::Result<bool> {
+    use std::os::unix::io::AsRawFd as _;
+
+    // SAFETY: `file` owns a valid descriptor for the duration of the call
+    let res = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    if res == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    if err.kind() == std::io::ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(err)
+    }
+}
+
+/// Take an exclusive lock on `file` without blocking, returning whether it was taken
+#[cfg(windows)]
+fn try_lock(file: &std::fs::File) -> std::io::Result<bool> {
+    use std::os::windows::io::AsRawHandle as _;
+    use windows_sys::Win32::Foundation::ERROR_LOCK_VIOLATION;
+    use windows_sys::Win32::Storage::FileSystem::{
+        LockFileEx, LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY,
+    };
+
+    // SAFETY: `file` owns a valid handle for the duration of the call and `overlapped` is zeroed,
+    // as `LockFileEx` expects for a synchronous call
+    let res = unsafe {
+        let mut overlapped = std::mem::zeroed();
+        LockFileEx(
+            file.as_raw_handle() as _,
+            LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY,
+            0,
+            u32::MAX,
+            u32::MAX,
+            &mut overlapped,
+        )
+    };
+    if res != 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    if err.raw_os_error() == Some(ERROR_LOCK_VIOLATION as i32) {
+        Ok(false)
+    } else {
+        Err(err)
+    }
+}
+
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn left_behind_file_is_not_held() {
+        let name = format!("trycmd-stale-{}", std::process::id());
+        let path = std::env::temp_dir()
+            .join("trycmd-locks")
+            .join(format!("{}.lock", name));
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        // Like a test run that was killed while holding the lock
+        std::fs::write(&path, "crashed.toml (pid 1)\n").unwrap();
+
+        let lock = CaseLock::acquire(&name, std::path::Path::new("first.toml")).unwrap();
+        let other = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        assert!(!try_lock(&other).unwrap());
+        drop(lock);
+        assert!(try_lock(&other).unwrap());
+        drop(other);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
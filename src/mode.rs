@@ -0,0 +1,55 @@
+//! Custom values for `TRYCMD`
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Custom mode, selected with `TRYCMD=<name>`
+///
+/// See [`TestCases::register_mode`][crate::TestCases::register_mode]
+pub trait ModeHandler: Send + Sync {
+    /// Prepare for a run, before any cases start
+    fn initialize(&self) -> Result<(), crate::Error> {
+        Ok(())
+    }
+
+    /// Run the case at `path`
+    ///
+    /// `run` runs the case as without `TRYCMD`, returning a result for each of its steps.  It is
+    /// reported as usual once this returns; if `run` isn't called, the case is reported as
+    /// ignored.  Returning an error fails the case.
+    ///
+    /// Cases run in parallel, so this may be called from several threads at once.
+    fn run_case(
+        &self,
+        path: &std::path::Path,
+        run: &mut dyn FnMut() -> Vec<crate::report::CaseResult>,
+    ) -> Result<(), crate::Error>;
+}
+
+/// [`ModeHandler`]s by name
+#[derive(Clone, Default)]
+pub(crate) struct ModeHandlers(BTreeMap<String, Arc<dyn ModeHandler>>);
+
+impl ModeHandlers {
+    pub(crate) fn register(&mut self, name: String, handler: Box<dyn ModeHandler>) {
+        self.0.insert(name, Arc::from(handler));
+    }
+
+    /// Merge in `other`, with its handlers taking precedence
+    pub(crate) fn update(&mut self, other: &Self) {
+        self.0
+            .extend(other.0.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+
+    /// Handler for the value of `TRYCMD`, if it names one
+    pub(crate) fn get(&self, var: Option<&std::ffi::OsStr>) -> Option<Arc<dyn ModeHandler>> {
+        let name = var?.to_str()?;
+        self.0.get(name).cloned()
+    }
+}
+
+impl std::fmt::Debug for ModeHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
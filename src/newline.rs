@@ -0,0 +1,75 @@
+//! How the final newline of `stdout` and `stderr` is compared
+
+/// How the final newline of `stdout` and `stderr` is compared, see
+/// [`TestCases::trailing_newline`][crate::TestCases::trailing_newline]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TrailingNewline {
+    /// The output must end with a newline, whether or not the expected output does
+    Require,
+    /// The output must not end with a newline, whether or not the expected output does
+    Forbid,
+    /// Whether either ends with a newline doesn't matter
+    Ignore,
+}
+
+impl TrailingNewline {
+    /// Expected output as this policy would have written it
+    pub(crate) fn expected(self, expected: &str) -> String {
+        match self {
+            Self::Require => with_newline(expected),
+            Self::Forbid => expected.strip_suffix('\n').unwrap_or(expected).to_owned(),
+            Self::Ignore => expected.to_owned(),
+        }
+    }
+
+    /// Actual output, written as `expected` is for [`TrailingNewline::Ignore`]
+    ///
+    /// This avoids churn in snapshots with `TRYCMD=overwrite` that would otherwise flip-flop
+    /// between having a final newline and not.
+    pub(crate) fn actual(self, actual: &str, expected: Option<&str>) -> String {
+        match (self, expected) {
+            (Self::Ignore, Some(expected)) if expected.ends_with('\n') => with_newline(actual),
+            (Self::Ignore, Some(_)) => actual.strip_suffix('\n').unwrap_or(actual).to_owned(),
+            _ => actual.to_owned(),
+        }
+    }
+}
+
+fn with_newline(text: &str) -> String {
+    let mut text = text.to_owned();
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn require() {
+        let policy = TrailingNewline::Require;
+        assert_eq!(policy.expected("Hello"), "Hello\n");
+        assert_eq!(policy.expected("Hello\n"), "Hello\n");
+        assert_eq!(policy.expected(""), "");
+        assert_eq!(policy.actual("Hello", Some("Hello\n")), "Hello");
+    }
+
+    #[test]
+    fn forbid() {
+        let policy = TrailingNewline::Forbid;
+        assert_eq!(policy.expected("Hello\n"), "Hello");
+        assert_eq!(policy.expected("Hello"), "Hello");
+        assert_eq!(policy.actual("Hello\n", Some("Hello")), "Hello\n");
+    }
+
+    #[test]
+    fn ignore() {
+        let policy = TrailingNewline::Ignore;
+        assert_eq!(policy.expected("Hello"), "Hello");
+        assert_eq!(policy.actual("Hello", Some("Hello\n")), "Hello\n");
+        assert_eq!(policy.actual("Hello\n", Some("Hello")), "Hello");
+        assert_eq!(policy.actual("Hello\n", None), "Hello\n");
+    }
+}
@@ -0,0 +1,181 @@
+use std::io::Write;
+
+/// Width to fit the status line in when `COLUMNS` isn't set
+const DEFAULT_WIDTH: usize = 80;
+
+/// Single status line, redrawn in place at the bottom of an interactive `stderr`
+///
+/// The line is erased while reports are written so they are never interleaved with it.
+#[derive(Debug)]
+pub(crate) struct Progress {
+    enabled: bool,
+    total: usize,
+    width: usize,
+    state: std::sync::Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    started: usize,
+    failed: usize,
+    /// Most recently started case
+    current: Option<std::path::PathBuf>,
+    drawn: bool,
+}
+
+impl Progress {
+    pub(crate) fn new(total: usize, enabled: bool) -> Self {
+        let width = std::env::var("COLUMNS")
+            .ok()
+            .and_then(|c| c.parse().ok())
+            .unwrap_or(DEFAULT_WIDTH);
+        Self {
+            enabled,
+            total,
+            width,
+            state: Default::default(),
+        }
+    }
+
+    /// Whether a status line can be drawn without corrupting anyone's output
+    pub(crate) fn is_supported() -> bool {
+        is_terminal::IsTerminal::is_terminal(&std::io::stderr())
+            && std::env::var_os("NO_COLOR").map_or(true, |v| v.is_empty())
+            && std::env::var_os("CI").is_none()
+    }
+
+    pub(crate) fn start(&self, path: &std::path::Path) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.started += 1;
+        state.current = Some(path.to_owned());
+        self.draw(&mut state);
+    }
+
+    /// Erase the status line until the returned guard is dropped
+    pub(crate) fn suspend(&self) -> Suspended<'_> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.erase(&mut state);
+        Suspended {
+            progress: self,
+            state,
+        }
+    }
+
+    /// Erase the status line for good
+    pub(crate) fn finish(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.erase(&mut state);
+    }
+
+    fn draw(&self, state: &mut State) {
+        if !self.enabled {
+            return;
+        }
+        let stderr = std::io::stderr();
+        let mut stderr = stderr.lock();
+        let _ = write!(stderr, "\r\x1b[2K{}", self.render(state));
+        let _ = stderr.flush();
+        state.drawn = true;
+    }
+
+    fn erase(&self, state: &mut State) {
+        if !state.drawn {
+            return;
+        }
+        let stderr = std::io::stderr();
+        let mut stderr = stderr.lock();
+        let _ = write!(stderr, "\r\x1b[2K");
+        let _ = stderr.flush();
+        state.drawn = false;
+    }
+
+    /// Render the status line, like `running 113/412 — tests/cmd/build/release.toml (3 failed)`
+    fn render(&self, state: &State) -> String {
+        let prefix = format!("running {}/{}", state.started, self.total);
+        let suffix = if state.failed == 0 {
+            String::new()
+        } else {
+            format!(" ({} failed)", state.failed)
+        };
+        let current = state
+            .current
+            .as_deref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default();
+
+        // Keep the line from wrapping, as only the last row would be erased
+        let separator = " — ";
+        let fixed = prefix.chars().count() + separator.chars().count() + suffix.chars().count();
+        let available = self.width.saturating_sub(fixed + 1);
+        let current_len = current.chars().count();
+        let current = if current_len <= available {
+            current
+        } else if available <= 3 {
+            return format!("{}{}", prefix, suffix);
+        } else {
+            let kept: String = current
+                .chars()
+                .skip(current_len - (available - 3))
+                .collect();
+            format!("...{}", kept)
+        };
+        format!("{}{}{}{}", prefix, separator, current, suffix)
+    }
+}
+
+/// Status line is erased while this is alive; it is redrawn on `drop`
+pub(crate) struct Suspended<'p> {
+    progress: &'p Progress,
+    state: std::sync::MutexGuard<'p, State>,
+}
+
+impl Suspended<'_> {
+    /// Count failures reported while suspended
+    pub(crate) fn failed(&mut self, count: usize) {
+        self.state.failed += count;
+    }
+}
+
+impl Drop for Suspended<'_> {
+    fn drop(&mut self) {
+        self.progress.draw(&mut self.state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn progress(width: usize) -> Progress {
+        Progress {
+            enabled: false,
+            total: 412,
+            width,
+            state: Default::default(),
+        }
+    }
+
+    #[test]
+    fn render_status() {
+        let progress = progress(80);
+        progress.start(std::path::Path::new("tests/cmd/build/release.toml"));
+        progress.suspend().failed(3);
+
+        let state = progress.state.lock().unwrap();
+        assert_eq!(
+            progress.render(&state),
+            "running 1/412 — tests/cmd/build/release.toml (3 failed)"
+        );
+    }
+
+    #[test]
+    fn render_truncates_path() {
+        let progress = progress(40);
+        progress.start(std::path::Path::new("tests/cmd/build/release.toml"));
+
+        let state = progress.state.lock().unwrap();
+        let actual = progress.render(&state);
+        assert_eq!(actual, "running 1/412 — ...d/build/release.toml");
+        assert!(actual.chars().count() < 40);
+    }
+}
@@ -0,0 +1,604 @@
+//! Recordings of what cases' commands did, for `TRYCMD=record` and `TRYCMD=replay`
+
+use std::collections::BTreeMap;
+
+/// Where recordings are kept, relative to the package root
+pub(crate) const DEFAULT_DIR: &str = ".recorded";
+
+/// Everything observable about a case's run, see [`Tape`]
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Recording {
+    /// [`fingerprint`] of the case when it was recorded
+    inputs: String,
+    /// Sandbox the case was recorded in
+    root: Option<String>,
+    /// Directory the case was recorded in
+    cwd: Option<String>,
+    /// Keyed by step id, with `""` for a `*.toml` case's only step
+    steps: BTreeMap<String, RecordedStep>,
+    /// [`Tape::step_inputs`] of each step when it was recorded, keyed like `steps`
+    step_inputs: BTreeMap<String, String>,
+    /// Directories in the sandbox, relative to its root and with `/` separators
+    dirs: Vec<String>,
+    /// Files in the sandbox, relative to its root and with `/` separators
+    files: BTreeMap<String, Bytes>,
+    /// Symlinks in the sandbox and what they point to, relative to its root and with `/`
+    /// separators
+    links: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum RecordedStep {
+    Exited {
+        code: Option<i32>,
+        signal: Option<i32>,
+        /// Killed for running past its `timeout`
+        timed_out: bool,
+        stdout: Bytes,
+        stderr: Bytes,
+    },
+    /// The command couldn't be run
+    Failed(String),
+}
+
+/// Stored as a string when possible, to keep recordings readable
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum Bytes {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        match String::from_utf8(bytes) {
+            Ok(text) => Self::Text(text),
+            Err(err) => Self::Binary(err.into_bytes()),
+        }
+    }
+}
+
+impl From<Bytes> for Vec<u8> {
+    fn from(bytes: Bytes) -> Self {
+        match bytes {
+            Bytes::Text(text) => text.into_bytes(),
+            Bytes::Binary(bytes) => bytes,
+        }
+    }
+}
+
+/// Records a case's run or plays a recording back in place of running it
+#[derive(Debug)]
+pub(crate) struct Tape {
+    recording: Recording,
+    replay: bool,
+    /// Where the recorded `root` and `cwd` are now, when replaying
+    replay_paths: Vec<(String, String)>,
+    /// Where the case is run, longest first, to be replaced by these placeholders
+    current_paths: Vec<(String, &'static str)>,
+    /// Kept out of [`Tape::step_inputs`], see [`TestCases::env_secret`][crate::TestCases::env_secret]
+    secrets: Vec<String>,
+}
+
+impl Tape {
+    pub(crate) fn record(inputs: String) -> Self {
+        Self {
+            recording: Recording {
+                inputs,
+                ..Default::default()
+            },
+            replay: false,
+            replay_paths: Vec::new(),
+            current_paths: Vec::new(),
+            secrets: Vec::new(),
+        }
+    }
+
+    /// Load the recording of `case`, failing if `inputs` changed since it was made
+    pub(crate) fn replay(
+        dir: &std::path::Path,
+        case: &std::path::Path,
+        inputs: String,
+    ) -> Result<Self, crate::Error> {
+        let path = recording_path(dir, case);
+        let raw = match std::fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(format!(
+                    "No recording at {}; create one with `TRYCMD=record`",
+                    path.display()
+                )
+                .into());
+            }
+            Err(err) => {
+                return Err(format!("Failed to read {}: {}", path.display(), err).into());
+            }
+        };
+        let recording = parse_recording(&raw)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+        if recording.inputs != inputs {
+            return Err(format!(
+                "Case changed since {} was recorded; re-record it with `TRYCMD=record`",
+                path.display()
+            )
+            .into());
+        }
+        Ok(Self {
+            recording,
+            replay: true,
+            replay_paths: Vec::new(),
+            current_paths: Vec::new(),
+            secrets: Vec::new(),
+        })
+    }
+
+    pub(crate) fn is_replay(&self) -> bool {
+        self.replay
+    }
+
+    /// Where the case is run
+    ///
+    /// When replaying, these replace the recorded paths in the output so it matches `[ROOT]` and
+    /// `[CWD]`.
+    pub(crate) fn paths(&mut self, root: Option<&std::path::Path>, cwd: Option<&std::path::Path>) {
+        let root = root.map(|p| p.display().to_string());
+        let cwd = cwd.map(|p| p.display().to_string());
+        let mut current: Vec<_> = [(cwd.clone(), "[CWD]"), (root.clone(), "[ROOT]")]
+            .into_iter()
+            .filter_map(|(path, placeholder)| Some((path?, placeholder)))
+            .collect();
+        current.sort_by_key(|(path, _)| std::cmp::Reverse(path.len()));
+        self.current_paths = current;
+        if self.replay {
+            // Longest first, as `cwd` is often within `root`
+            let mut paths: Vec<_> = [(&self.recording.cwd, cwd), (&self.recording.root, root)]
+                .into_iter()
+                .filter_map(|(recorded, current)| Some((recorded.clone()?, current?)))
+                .filter(|(recorded, current)| recorded != current)
+                .collect();
+            paths.sort_by_key(|(recorded, _)| std::cmp::Reverse(recorded.len()));
+            self.replay_paths = paths;
+        } else {
+            self.recording.root = root;
+            self.recording.cwd = cwd;
+        }
+    }
+
+    /// Values that must not be written to the recording
+    ///
+    /// Output is redacted before it is recorded; these are also left out of what a step is
+    /// checked against when replayed.
+    pub(crate) fn secrets(&mut self, secrets: &[String]) {
+        self.secrets = secrets.to_vec();
+    }
+
+    /// Whether step `id` was run when recorded
+    pub(crate) fn has_step(&self, id: Option<&str>) -> bool {
+        self.recording.steps.contains_key(id.unwrap_or_default())
+    }
+
+    /// Remember what running `step` did, along with whether it timed out
+    pub(crate) fn record_step(
+        &mut self,
+        step: &crate::schema::Step,
+        output: Result<(std::process::Output, bool), String>,
+    ) {
+        let id = step.id.clone().unwrap_or_default();
+        let recorded = match output {
+            Ok((output, timed_out)) => RecordedStep::Exited {
+                code: output.status.code(),
+                signal: signal(output.status),
+                timed_out,
+                stdout: output.stdout.into(),
+                stderr: output.stderr.into(),
+            },
+            Err(err) => RecordedStep::Failed(err),
+        };
+        self.recording
+            .step_inputs
+            .insert(id.clone(), self.step_inputs(step));
+        self.recording.steps.insert(id, recorded);
+    }
+
+    /// What running `step` did when recorded, along with whether it timed out
+    pub(crate) fn replay_step(
+        &self,
+        step: &crate::schema::Step,
+    ) -> Result<(std::process::Output, bool), crate::Error> {
+        let id = step.id.as_deref().unwrap_or_default();
+        let recorded = self
+            .recording
+            .steps
+            .get(id)
+            .ok_or("Step was not run when recorded; re-record it with `TRYCMD=record`")?;
+        if self.recording.step_inputs.get(id) != Some(&self.step_inputs(step)) {
+            return Err(
+                "Step changed since it was recorded; re-record it with `TRYCMD=record`".into(),
+            );
+        }
+        match recorded {
+            RecordedStep::Exited {
+                code,
+                signal,
+                timed_out,
+                stdout,
+                stderr,
+            } => Ok((
+                std::process::Output {
+                    status: exit_status(*code, *signal)?,
+                    stdout: self.replay_bytes(stdout),
+                    stderr: self.replay_bytes(stderr),
+                },
+                *timed_out,
+            )),
+            RecordedStep::Failed(err) => Err(err.as_str().into()),
+        }
+    }
+
+    /// Summarize what running `step` depends on, once the harness's settings are applied
+    ///
+    /// Bins are identified by name and the sandbox by `[ROOT]` and `[CWD]`, as they are somewhere
+    /// else on each machine or run.
+    fn step_inputs(&self, step: &crate::schema::Step) -> String {
+        let mut hasher = Fnv::new();
+        let mut write = |value: String| {
+            let mut value = value;
+            for (path, placeholder) in &self.current_paths {
+                value = value.replace(path.as_str(), placeholder);
+            }
+            value = crate::runner::redact_text(&value, &self.secrets);
+            hasher.write(value.as_bytes());
+        };
+        write(format!("{:?}", step.id));
+        write(bin_name(step.bin.as_ref()));
+        write(format!("{:?}", step.args));
+        write(format!("{:?}", step.args_override));
+        write(format!("{:?}", step.arg0));
+        for piped in &step.pipeline {
+            write(bin_name(Some(&piped.bin)));
+            write(format!("{:?}", piped.args));
+            write(format!("{:?}", piped.env));
+            write(format!("{:?}", piped.unset));
+        }
+        write(format!("{:?}", step.env));
+        write(format!("{:?}", step.stderr_to_stdout));
+        write(format!("{:?}", step.interleaved));
+        write(format!("{:?}", step.timeout));
+        write(format!("{:?}", step.umask));
+        write(format!("{:?}", step.null_stdin));
+        // Read from the sandbox when run, which replaying doesn't
+        match (&step.stdin_file, &step.stdin) {
+            (Some(file), _) => write(format!("{:?}", file)),
+            (None, Some(stdin)) => write(String::from_utf8_lossy(&stdin.to_bytes()).into_owned()),
+            (None, None) => write("no stdin".to_owned()),
+        }
+        format!("{:016x}", hasher.finish())
+    }
+
+    fn replay_bytes(&self, bytes: &Bytes) -> Vec<u8> {
+        match bytes {
+            Bytes::Text(text) => {
+                let mut text = text.clone();
+                for (recorded, current) in &self.replay_paths {
+                    text = text.replace(recorded.as_str(), current);
+                }
+                text.into_bytes()
+            }
+            Bytes::Binary(bytes) => bytes.clone(),
+        }
+    }
+
+    /// Remember the contents of the sandbox at `root`
+    pub(crate) fn record_fs(&mut self, root: &std::path::Path) -> Result<(), crate::Error> {
+        let mut pending = vec![root.to_owned()];
+        while let Some(dir) = pending.pop() {
+            let entries = std::fs::read_dir(&dir)
+                .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+            for entry in entries {
+                let path = entry
+                    .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+                    .path();
+                let rel = relative_name(&path, root);
+                // Not following links, which may loop back on a parent
+                let file_type = std::fs::symlink_metadata(&path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+                    .file_type();
+                if file_type.is_symlink() {
+                    let target = std::fs::read_link(&path)
+                        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                    let target = target
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    self.recording.links.insert(rel, target);
+                } else if file_type.is_dir() {
+                    self.recording.dirs.push(rel);
+                    pending.push(path);
+                } else {
+                    let content = std::fs::read(&path)
+                        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                    self.recording.files.insert(rel, content.into());
+                }
+            }
+        }
+        self.recording.dirs.sort();
+        Ok(())
+    }
+
+    /// Fill the sandbox at `root` with what was recorded
+    pub(crate) fn replay_fs(&self, root: &std::path::Path) -> Result<(), crate::Error> {
+        for dir in &self.recording.dirs {
+            let path = root.join(dir);
+            std::fs::create_dir_all(&path)
+                .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        }
+        for (file, content) in &self.recording.files {
+            let path = root.join(file);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+            }
+            std::fs::write(&path, Vec::from(content.clone()))
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+        for (link, target) in &self.recording.links {
+            let path = root.join(link);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+            }
+            symlink(&path, std::path::Path::new(target))
+                .map_err(|e| format!("Failed to link {}: {}", path.display(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Write the recording of `case` to `dir`
+    pub(crate) fn save(
+        &self,
+        dir: &std::path::Path,
+        case: &std::path::Path,
+    ) -> Result<(), crate::Error> {
+        let path = recording_path(dir, case);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let json = render_recording(&self.recording)
+            .map_err(|e| format!("Failed to serialize recording: {}", e))?;
+        std::fs::write(&path, json)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e).into())
+    }
+}
+
+/// Summarize where a case runs, so replaying can tell when a recording is stale
+///
+/// This covers the sandbox and the steps' `cd`s but not the expected output, so snapshots can be
+/// changed and checked against an existing recording.  What each command runs is checked as it
+/// is replayed, once the harness's settings are applied.
+pub(crate) fn fingerprint(sequence: &crate::schema::TryCmd) -> String {
+    let mut hasher = Fnv::new();
+    for step in &sequence.steps {
+        hasher.write(format!("{:?}", step.id).as_bytes());
+        hasher.write(format!("{:?}", step.cd).as_bytes());
+    }
+    hasher.write(format!("{:?}", sequence.cwd).as_bytes());
+    hasher.write(format!("{:?}", sequence.fs.sandbox()).as_bytes());
+    hasher.write(format!("{:?}", sequence.isolate_home).as_bytes());
+    if let Some(base) = sequence.fs.base.as_deref() {
+        let mut template = Tape::record(String::new());
+        // A missing template fails the run on its own
+        let _ = template.record_fs(base);
+        hasher.write(format!("{:?}", template.recording).as_bytes());
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(feature = "json")]
+fn parse_recording(raw: &str) -> Result<Recording, String> {
+    serde_json::from_str(raw).map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "json")]
+fn render_recording(recording: &Recording) -> Result<String, String> {
+    serde_json::to_string_pretty(recording).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "json"))]
+fn parse_recording(_raw: &str) -> Result<Recording, String> {
+    Err("recordings require the `json` feature".to_owned())
+}
+
+#[cfg(not(feature = "json"))]
+fn render_recording(_recording: &Recording) -> Result<String, String> {
+    Err("recordings require the `json` feature".to_owned())
+}
+
+fn recording_path(dir: &std::path::Path, case: &std::path::Path) -> std::path::PathBuf {
+    let case = std::env::current_dir()
+        .ok()
+        .and_then(|cwd| case.strip_prefix(cwd).ok())
+        .unwrap_or(case);
+    let mut name = case
+        .components()
+        .filter(|c| matches!(c, std::path::Component::Normal(_)))
+        .collect::<std::path::PathBuf>()
+        .into_os_string();
+    name.push(".json");
+    dir.join(name)
+}
+
+/// A bin's name, which is the same wherever it is built
+fn bin_name(bin: Option<&crate::schema::Bin>) -> String {
+    match bin {
+        Some(crate::schema::Bin::Path(path)) => path
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        Some(crate::schema::Bin::Name(name)) => name.clone(),
+        bin => format!("{:?}", bin),
+    }
+}
+
+fn relative_name(path: &std::path::Path, root: &std::path::Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(unix)]
+fn symlink(path: &std::path::Path, target: &std::path::Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(windows)]
+fn symlink(path: &std::path::Path, target: &std::path::Path) -> std::io::Result<()> {
+    let resolved = path.parent().unwrap_or(path).join(target);
+    if resolved.is_dir() {
+        std::os::windows::fs::symlink_dir(target, path)
+    } else {
+        std::os::windows::fs::symlink_file(target, path)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_path: &std::path::Path, _target: &std::path::Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "symlinks are not supported on this platform",
+    ))
+}
+
+#[cfg(unix)]
+fn signal(status: std::process::ExitStatus) -> Option<i32> {
+    std::os::unix::process::ExitStatusExt::signal(&status)
+}
+
+#[cfg(not(unix))]
+fn signal(_status: std::process::ExitStatus) -> Option<i32> {
+    None
+}
+
+#[cfg(unix)]
+fn exit_status(
+    code: Option<i32>,
+    signal: Option<i32>,
+) -> Result<std::process::ExitStatus, crate::Error> {
+    let raw = match (code, signal) {
+        (Some(code), _) => (code & 0xff) << 8,
+        (None, Some(signal)) => signal & 0x7f,
+        (None, None) => return Err("Recording has no exit status".into()),
+    };
+    Ok(std::os::unix::process::ExitStatusExt::from_raw(raw))
+}
+
+#[cfg(windows)]
+fn exit_status(
+    code: Option<i32>,
+    _signal: Option<i32>,
+) -> Result<std::process::ExitStatus, crate::Error> {
+    let code = code.ok_or("Recording was interrupted, which can't be replayed on Windows")?;
+    Ok(std::os::windows::process::ExitStatusExt::from_raw(
+        code as u32,
+    ))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn exit_status(
+    _code: Option<i32>,
+    _signal: Option<i32>,
+) -> Result<std::process::ExitStatus, crate::Error> {
+    Err("Replaying is not supported on this platform".into())
+}
+
+/// FNV-1a, as recordings need a hash that is stable across Rust versions and platforms
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+        // Keep `write(b"ab"); write(b"c")` distinct from `write(b"a"); write(b"bc")`
+        self.0 ^= 0xff;
+        self.0 = self.0.wrapping_mul(0x100000001b3);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn bytes_roundtrip() {
+        let text = Bytes::from(b"Hello\n".to_vec());
+        assert_eq!(text, Bytes::Text("Hello\n".into()));
+        let binary = Bytes::from(vec![0xff, 0x00]);
+        assert_eq!(binary, Bytes::Binary(vec![0xff, 0x00]));
+
+        let json = serde_json::to_string(&[&text, &binary]).unwrap();
+        assert_eq!(json, r#"["Hello\n",[255,0]]"#);
+        let parsed: Vec<Bytes> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, [text, binary]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exit_status_roundtrip() {
+        let status = exit_status(Some(3), None).unwrap();
+        assert_eq!(status.code(), Some(3));
+        let status = exit_status(None, Some(9)).unwrap();
+        assert_eq!(status.code(), None);
+        assert_eq!(signal(status), Some(9));
+    }
+
+    #[test]
+    fn recording_path_mirrors_case() {
+        assert_eq!(
+            recording_path(
+                std::path::Path::new(".recorded"),
+                std::path::Path::new("tests/cmd/help.toml")
+            ),
+            std::path::Path::new(".recorded/tests/cmd/help.toml.json")
+        );
+    }
+
+    #[test]
+    fn record_fs_keeps_symlinks() {
+        let root = std::env::temp_dir().join(format!("trycmd-record-fs-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("dir")).unwrap();
+        std::fs::write(root.join("dir/file.txt"), "content").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("..", root.join("dir/parent")).unwrap();
+
+        let mut tape = Tape::record(String::new());
+        tape.record_fs(&root).unwrap();
+        let replayed = root.join("replayed");
+        std::fs::create_dir_all(&replayed).unwrap();
+        tape.replay_fs(&replayed).unwrap();
+        let content = std::fs::read_to_string(replayed.join("dir/file.txt"));
+        #[cfg(unix)]
+        let link = std::fs::read_link(replayed.join("dir/parent"));
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(content.unwrap(), "content");
+        #[cfg(unix)]
+        assert_eq!(link.unwrap(), std::path::Path::new(".."));
+    }
+}
@@ -1,6 +1,8 @@
 #[derive(Clone, Debug)]
 pub(crate) struct BinRegistry {
     bins: std::collections::BTreeMap<String, crate::schema::Bin>,
+    cargo_build_args: Option<Vec<String>>,
+    built: Option<Result<std::collections::BTreeMap<String, crate::schema::Bin>, crate::Error>>,
     fallback: bool,
 }
 
@@ -8,10 +10,42 @@ impl BinRegistry {
     pub(crate) fn new() -> Self {
         Self {
             bins: Default::default(),
+            cargo_build_args: None,
+            built: None,
             fallback: true,
         }
     }
 
+    pub(crate) fn cargo_build_args(&mut self, args: Vec<String>) {
+        self.cargo_build_args = Some(args);
+        self.built = None;
+    }
+
+    /// Build bins with `cargo_build_args` and workspace bins, if needed
+    pub(crate) fn prepare(&mut self) {
+        for bin in self.bins.values_mut() {
+            if matches!(bin, crate::schema::Bin::CargoWorkspace { .. }) {
+                *bin = crate::cargo::build_workspace_bin(bin);
+            }
+        }
+
+        if self.built.is_some() {
+            return;
+        }
+        if let Some(args) = self.cargo_build_args.as_deref() {
+            self.built = Some(build_bins(args));
+        }
+    }
+
+    /// Merge in `other`, with its bins and build arguments taking precedence
+    pub(crate) fn update(&mut self, other: &Self) {
+        self.bins
+            .extend(other.bins.iter().map(|(k, v)| (k.clone(), v.clone())));
+        if let Some(args) = &other.cargo_build_args {
+            self.cargo_build_args(args.clone());
+        }
+    }
+
     pub(crate) fn register_bin(&mut self, name: String, bin: crate::schema::Bin) {
         self.bins.insert(name, bin);
     }
@@ -36,7 +70,13 @@ impl BinRegistry {
                 let bin = self.resolve_name(&name);
                 Ok(bin)
             }
+            crate::schema::Bin::Script { shell, script } => {
+                Ok(crate::schema::Bin::Script { shell, script })
+            }
             crate::schema::Bin::Ignore => Ok(crate::schema::Bin::Ignore),
+            crate::schema::Bin::CargoWorkspace { package, .. } => {
+                Err(format!("Bin for `{}` was not built", package).into())
+            }
             crate::schema::Bin::Error(err) => Err(err),
         }
     }
@@ -46,6 +86,18 @@ impl BinRegistry {
             return path.clone();
         }
 
+        match &self.built {
+            Some(Ok(built)) => {
+                if let Some(path) = built.get(name) {
+                    return path.clone();
+                }
+            }
+            Some(Err(err)) => {
+                return crate::schema::Bin::Error(err.clone());
+            }
+            None => {}
+        }
+
         if self.fallback {
             let path = crate::cargo::cargo_bin(name);
             if path.exists() {
@@ -57,8 +109,60 @@ impl BinRegistry {
     }
 }
 
+pub(crate) fn find_on_path(name: &str) -> Option<std::path::PathBuf> {
+    let file_name = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.is_file())
+}
+
 impl Default for BinRegistry {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(feature = "examples")]
+fn build_bins(
+    args: &[String],
+) -> Result<std::collections::BTreeMap<String, crate::schema::Bin>, crate::Error> {
+    // Keep these separate from `cargo build` and other argument sets so we don't thrash rebuilds
+    let target_dir = build_dir(args)?;
+    let target_dir = target_dir.to_string_lossy();
+    let args = ["--target-dir", target_dir.as_ref()]
+        .into_iter()
+        .chain(args.iter().map(String::as_str));
+    let bins = snapbox::cmd::compile_bins(args)?
+        .map(|(name, path)| (name, path.into()))
+        .collect();
+    Ok(bins)
+}
+
+#[cfg(not(feature = "examples"))]
+fn build_bins(
+    _args: &[String],
+) -> Result<std::collections::BTreeMap<String, crate::schema::Bin>, crate::Error> {
+    Err("`cargo_build_args` requires the `examples` feature".into())
+}
+
+#[cfg(feature = "examples")]
+fn build_dir(args: &[String]) -> Result<std::path::PathBuf, crate::Error> {
+    use std::hash::Hash as _;
+    use std::hash::Hasher as _;
+
+    // Same target directory the current test was built in, respecting `CARGO_TARGET_DIR`
+    let mut target_dir =
+        std::env::current_exe().map_err(|e| format!("Failed to find target dir: {}", e))?;
+    target_dir.pop();
+    if target_dir.ends_with("deps") {
+        target_dir.pop();
+    }
+    target_dir.pop();
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    args.hash(&mut hasher);
+    Ok(target_dir
+        .join("trycmd")
+        .join(format!("{:016x}", hasher.finish())))
+}
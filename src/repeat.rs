@@ -0,0 +1,172 @@
+//! `[.. repeated N times]` markers for runs of identical lines in expected output
+
+use snapbox::utils::LinesWithTerminator;
+
+/// Marker matching a run of any length
+const ANY: &str = "[.. repeated]";
+
+/// Collapse each run of identical lines in `actual` to its first line followed by a marker
+///
+/// Lines are compared after substitutions.  Runs longer than `threshold` lines are collapsed to
+/// `[.. repeated N times]`, which only matches a run of exactly that length.  Runs of a line
+/// followed by `[.. repeated]` in `expected` are collapsed to that instead, whatever their
+/// length, so it matches any repetition.  Runs of a line followed by `[.. repeated N times]` in
+/// `expected` are collapsed whatever `threshold` is, so the count is checked.  Other lines are
+/// left alone, so `...` and `[..]` in `expected` work as usual.
+pub(crate) fn collapse(
+    actual: &str,
+    expected: Option<&str>,
+    threshold: usize,
+    substitutions: &snapbox::Substitutions,
+) -> String {
+    let any_count = expected
+        .map(|expected| marked_lines(expected, |marker| marker == ANY))
+        .unwrap_or_default();
+    let exact_count = expected
+        .map(|expected| marked_lines(expected, |marker| exact_count(marker).is_some()))
+        .unwrap_or_default();
+    let lines: Vec<_> = LinesWithTerminator::new(actual).collect();
+    let keys: Vec<_> = lines
+        .iter()
+        .map(|line| substitutions.normalize(trim(line), ""))
+        .collect();
+
+    let mut collapsed = String::new();
+    let mut pos = 0;
+    while pos < lines.len() {
+        let run = keys[pos..].iter().take_while(|k| **k == keys[pos]).count();
+        let line = lines[pos];
+        let marked = |patterns: &[&str]| {
+            run > 1
+                && patterns
+                    .iter()
+                    .any(|pattern| crate::ignore::line_matches(line, pattern, substitutions))
+        };
+        let marker = if marked(&any_count) {
+            Some(ANY.to_owned())
+        } else if run > threshold.max(1) || marked(&exact_count) {
+            Some(format!("[.. repeated {} times]", run))
+        } else {
+            None
+        };
+        match marker {
+            Some(marker) => {
+                collapsed.push_str(trim(line));
+                collapsed.push('\n');
+                collapsed.push_str(&marker);
+                let last = lines[pos + run - 1];
+                collapsed.push_str(&last[trim(last).len()..]);
+            }
+            None => {
+                for line in &lines[pos..pos + run] {
+                    collapsed.push_str(line);
+                }
+            }
+        }
+        pos += run;
+    }
+    collapsed
+}
+
+/// Lines of `expected` followed by a marker that `is_marker` accepts
+fn marked_lines(expected: &str, is_marker: impl Fn(&str) -> bool) -> Vec<&str> {
+    let lines: Vec<_> = LinesWithTerminator::new(expected).collect();
+    lines
+        .windows(2)
+        .filter(|pair| is_marker(trim(pair[1])))
+        .map(|pair| pair[0])
+        .collect()
+}
+
+/// `N` of a `[.. repeated N times]` marker
+fn exact_count(marker: &str) -> Option<usize> {
+    marker
+        .strip_prefix("[.. repeated ")?
+        .strip_suffix(" times]")?
+        .parse()
+        .ok()
+}
+
+fn trim(line: &str) -> &str {
+    line.trim_end_matches(['\n', '\r'])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn collapses_over_threshold() {
+        let actual = "start\ntick\ntick\ntick\ndone\n";
+        let substitutions = snapbox::Substitutions::new();
+        assert_eq!(
+            collapse(actual, None, 2, &substitutions),
+            "start\ntick\n[.. repeated 3 times]\ndone\n"
+        );
+        assert_eq!(collapse(actual, None, 3, &substitutions), actual);
+    }
+
+    #[test]
+    fn any_count_marker() {
+        let expected = "start\ntick\n[.. repeated]\ndone\n";
+        let substitutions = snapbox::Substitutions::new();
+        for ticks in [2, 3, 10] {
+            let actual = format!("start\n{}done\n", "tick\n".repeat(ticks));
+            let collapsed = collapse(&actual, Some(expected), 100, &substitutions);
+            assert_eq!(substitutions.normalize(&collapsed, expected), expected);
+        }
+        // A single line isn't a repetition
+        let collapsed = collapse("start\ntick\ndone\n", Some(expected), 100, &substitutions);
+        assert_ne!(substitutions.normalize(&collapsed, expected), expected);
+    }
+
+    #[test]
+    fn exact_count_marker() {
+        let expected = "tick\n[.. repeated 3 times]\n";
+        let substitutions = snapbox::Substitutions::new();
+        let three = collapse("tick\ntick\ntick\n", Some(expected), 1, &substitutions);
+        let four = collapse(
+            "tick\ntick\ntick\ntick\n",
+            Some(expected),
+            1,
+            &substitutions,
+        );
+        assert_eq!(substitutions.normalize(&three, expected), expected);
+        assert_ne!(substitutions.normalize(&four, expected), expected);
+    }
+
+    #[test]
+    fn exact_count_marker_under_threshold() {
+        let expected = "start\ntick\n[.. repeated 2 times]\ndone\n";
+        let substitutions = snapbox::Substitutions::new();
+        let two = collapse(
+            "start\ntick\ntick\ndone\n",
+            Some(expected),
+            5,
+            &substitutions,
+        );
+        let three = collapse(
+            "start\ntick\ntick\ntick\ndone\n",
+            Some(expected),
+            5,
+            &substitutions,
+        );
+        assert_eq!(two, expected);
+        assert_eq!(three, "start\ntick\n[.. repeated 3 times]\ndone\n");
+        assert_ne!(substitutions.normalize(&three, expected), expected);
+    }
+
+    #[test]
+    fn with_elision_and_substitutions() {
+        let expected = "...\nstart\nfetching [ROOT]/[..]\n[.. repeated]\ndone\n";
+        let mut substitutions = snapbox::Substitutions::new();
+        substitutions.insert("[ROOT]", "/tmp/root").unwrap();
+        let actual = "setup\nstart\nfetching /tmp/root/a\nfetching /tmp/root/a\ndone\n";
+        let collapsed = collapse(actual, Some(expected), 100, &substitutions);
+        assert_eq!(
+            collapsed,
+            "setup\nstart\nfetching /tmp/root/a\n[.. repeated]\ndone\n"
+        );
+        assert_eq!(substitutions.normalize(&collapsed, expected), expected);
+    }
+}
@@ -0,0 +1,499 @@
+//! Machine-readable test results
+
+use std::io::Write as _;
+
+/// How to report results, in addition to the human-readable output on `stderr`
+///
+/// See [`TestCases::output_format`][crate::TestCases::output_format]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OutputFormat {
+    /// Only the human-readable output
+    #[default]
+    Text,
+    /// JSON array of [`CaseResult`]s, written to the path or `stdout`
+    ///
+    /// Requires the `json` feature, which is on by default.
+    Json(Option<std::path::PathBuf>),
+    /// JUnit XML, written to the path
+    Junit(std::path::PathBuf),
+    /// [Test Anything Protocol](https://testanything.org/), written to `stdout`
+    Tap,
+}
+
+impl OutputFormat {
+    pub(crate) fn write(&self, results: &[CaseResult]) -> Result<(), crate::Error> {
+        match self {
+            Self::Text => Ok(()),
+            Self::Json(path) => write_report(path.as_deref(), &render_json(results)?),
+            Self::Junit(path) => write_report(Some(path), &render_junit(results)),
+            Self::Tap => write_report(None, &render_tap(results)),
+        }
+    }
+}
+
+/// Result of running a single step of a test case
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct CaseResult {
+    name: String,
+    path: std::path::PathBuf,
+    /// See [`CaseResult::suite`]
+    suite: String,
+    step: Option<String>,
+    status: CaseStatus,
+    mismatches: Vec<Mismatch>,
+    details: Option<String>,
+    /// In seconds
+    #[serde(serialize_with = "serialize_secs")]
+    duration: std::time::Duration,
+}
+
+impl CaseResult {
+    pub(crate) fn new(
+        name: String,
+        path: std::path::PathBuf,
+        step: Option<String>,
+        status: CaseStatus,
+        mismatches: Vec<Mismatch>,
+        details: Option<String>,
+        duration: std::time::Duration,
+    ) -> Self {
+        Self {
+            name,
+            path,
+            suite: "trycmd".to_owned(),
+            step,
+            status,
+            mismatches,
+            details,
+            duration,
+        }
+    }
+
+    /// Name reported for this result, including the step
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Test case file
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Group the case is reported in, from its directory or a `_suite.toml` with a `name`
+    pub fn suite(&self) -> &str {
+        &self.suite
+    }
+
+    pub(crate) fn in_suite(mut self, suite: String) -> Self {
+        self.suite = suite;
+        self
+    }
+
+    /// Step within the test case file, if any
+    pub fn step(&self) -> Option<&str> {
+        self.step.as_deref()
+    }
+
+    /// Whether the step passed, failed, or was skipped
+    pub fn status(&self) -> CaseStatus {
+        self.status
+    }
+
+    /// What did not match the expectations, if anything
+    pub fn mismatches(&self) -> &[Mismatch] {
+        &self.mismatches
+    }
+
+    /// Description of what went wrong
+    pub fn details(&self) -> Option<&str> {
+        self.details.as_deref()
+    }
+
+    /// How long the command took to run, zero if it didn't
+    pub fn duration(&self) -> std::time::Duration {
+        self.duration
+    }
+}
+
+fn serialize_secs<S: serde::Serializer>(
+    duration: &std::time::Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// Render `duration` for people, like `3.2s`
+pub(crate) fn format_duration(duration: std::time::Duration) -> String {
+    format!("{:.1}s", duration.as_secs_f64())
+}
+
+/// Outcome of a [`CaseResult`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CaseStatus {
+    Passed,
+    Failed,
+    Ignored,
+}
+
+/// Part of a case that can independently fail to match what was expected
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mismatch {
+    /// Exit status
+    Status,
+    Stdout,
+    Stderr,
+    /// Files in the sandbox, see `*.out/`
+    Files,
+}
+
+impl Mismatch {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::Stdout => "stdout",
+            Self::Stderr => "stderr",
+            Self::Files => "files",
+        }
+    }
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Render `mismatches` as a list, like `status, stdout`
+pub(crate) fn join_mismatches(mismatches: &[Mismatch]) -> String {
+    mismatches
+        .iter()
+        .map(Mismatch::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn write_report(path: Option<&std::path::Path>, report: &str) -> Result<(), crate::Error> {
+    match path {
+        Some(path) => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+            }
+            std::fs::write(path, report)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+        None => {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            writeln!(stdout, "{}", report).map_err(|e| format!("Failed to write report: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Results grouped by [`CaseResult::suite`], in order of name
+fn by_suite(results: &[CaseResult]) -> std::collections::BTreeMap<&str, Vec<&CaseResult>> {
+    let mut suites = std::collections::BTreeMap::<_, Vec<_>>::new();
+    for result in results {
+        suites
+            .entry(result.suite.as_str())
+            .or_default()
+            .push(result);
+    }
+    suites
+}
+
+/// Pass/fail counts for each suite and which was slowest, when there is more than one suite
+pub(crate) fn suite_summary(results: &[CaseResult]) -> Option<String> {
+    let suites = by_suite(results);
+    if suites.len() < 2 {
+        return None;
+    }
+    let mut summary = String::from("Suites:");
+    let mut slowest = None;
+    for (name, results) in &suites {
+        let count = |status| results.iter().filter(|r| r.status == status).count();
+        let duration: std::time::Duration = results.iter().map(|r| r.duration).sum();
+        summary.push_str(&format!(
+            "\n  {}: {} passed, {} failed, {} ignored in {}",
+            name,
+            count(CaseStatus::Passed),
+            count(CaseStatus::Failed),
+            count(CaseStatus::Ignored),
+            format_duration(duration)
+        ));
+        if slowest.map_or(true, |(_, slowest)| slowest < duration) {
+            slowest = Some((name, duration));
+        }
+    }
+    if let Some((name, duration)) = slowest {
+        summary.push_str(&format!(
+            "\nSlowest suite: {} ({})",
+            name,
+            format_duration(duration)
+        ));
+    }
+    Some(summary)
+}
+
+#[cfg(feature = "json")]
+fn render_json(results: &[CaseResult]) -> Result<String, crate::Error> {
+    serde_json::to_string_pretty(results)
+        .map_err(|e| format!("Failed to serialize results: {}", e).into())
+}
+
+#[cfg(not(feature = "json"))]
+fn render_json(_results: &[CaseResult]) -> Result<String, crate::Error> {
+    Err("JSON reports require the `json` feature".into())
+}
+
+fn render_junit(results: &[CaseResult]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<testsuites>\n");
+    for (name, results) in by_suite(results) {
+        render_junit_suite(&mut xml, name, &results);
+    }
+    xml.push_str("</testsuites>");
+    xml
+}
+
+fn render_junit_suite(xml: &mut String, name: &str, results: &[&CaseResult]) {
+    let failures = results
+        .iter()
+        .filter(|r| r.status == CaseStatus::Failed)
+        .count();
+    let skipped = results
+        .iter()
+        .filter(|r| r.status == CaseStatus::Ignored)
+        .count();
+
+    xml.push_str(&format!(
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\">\n",
+        escape_xml(name),
+        results.len(),
+        failures,
+        skipped
+    ));
+    for result in results {
+        xml.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\">",
+            escape_xml(&result.name),
+            escape_xml(&result.path.display().to_string()),
+            result.duration.as_secs_f64()
+        ));
+        match result.status {
+            CaseStatus::Passed => {}
+            CaseStatus::Failed => {
+                let message = if result.mismatches.is_empty() {
+                    "failed".to_owned()
+                } else {
+                    format!("mismatched {}", join_mismatches(&result.mismatches))
+                };
+                xml.push_str(&format!(
+                    "<failure message=\"{}\">{}</failure>",
+                    escape_xml(&message),
+                    escape_xml(result.details.as_deref().unwrap_or_default())
+                ));
+            }
+            CaseStatus::Ignored => {
+                xml.push_str("<skipped/>");
+            }
+        }
+        xml.push_str("</testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n");
+}
+
+fn render_tap(results: &[CaseResult]) -> String {
+    let mut tap = String::new();
+    tap.push_str("TAP version 13\n");
+    tap.push_str(&format!("1..{}", results.len()));
+    for (i, result) in results.iter().enumerate() {
+        let number = i + 1;
+        match result.status {
+            CaseStatus::Passed => {
+                tap.push_str(&format!("\nok {} - {}", number, result.name));
+            }
+            CaseStatus::Ignored => {
+                tap.push_str(&format!("\nok {} - {} # SKIP", number, result.name));
+            }
+            CaseStatus::Failed => {
+                tap.push_str(&format!("\nnot ok {} - {}", number, result.name));
+                if let Some(details) = result.details.as_deref() {
+                    tap.push_str("\n  ---\n  message: |");
+                    for line in details.lines() {
+                        tap.push_str("\n    ");
+                        tap.push_str(line);
+                    }
+                    tap.push_str("\n  ...");
+                }
+            }
+        }
+    }
+    tap
+}
+
+/// Escape `s` for XML, replacing characters XML 1.0 doesn't allow, like terminal escapes, with
+/// `U+FFFD`
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {
+                escaped.push(char::REPLACEMENT_CHARACTER)
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Remove terminal styling from rendered output
+pub(crate) fn strip_ansi(s: &str) -> String {
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // Skip the CSI sequence, through its final byte
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) && c != '[' {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn results() -> Vec<CaseResult> {
+        vec![
+            CaseResult::new(
+                "a.toml".into(),
+                "a.toml".into(),
+                None,
+                CaseStatus::Passed,
+                vec![],
+                None,
+                std::time::Duration::from_millis(1500),
+            ),
+            CaseResult::new(
+                "b.trycmd:3".into(),
+                "b.trycmd".into(),
+                Some("3".into()),
+                CaseStatus::Failed,
+                vec![Mismatch::Status, Mismatch::Stdout],
+                Some("Expected success, was 1\n<stdout>".into()),
+                std::time::Duration::from_millis(20),
+            ),
+            CaseResult::new(
+                "c.toml".into(),
+                "c.toml".into(),
+                None,
+                CaseStatus::Ignored,
+                vec![],
+                None,
+                std::time::Duration::ZERO,
+            ),
+        ]
+    }
+
+    #[test]
+    fn tap() {
+        let expected = "TAP version 13
+1..3
+ok 1 - a.toml
+not ok 2 - b.trycmd:3
+  ---
+  message: |
+    Expected success, was 1
+    <stdout>
+  ...
+ok 3 - c.toml # SKIP";
+        assert_eq!(render_tap(&results()), expected);
+    }
+
+    #[test]
+    fn junit() {
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+  <testsuite name="trycmd" tests="3" failures="1" skipped="1">
+    <testcase name="a.toml" classname="a.toml" time="1.500"></testcase>
+    <testcase name="b.trycmd:3" classname="b.trycmd" time="0.020"><failure message="mismatched status, stdout">Expected success, was 1
+&lt;stdout&gt;</failure></testcase>
+    <testcase name="c.toml" classname="c.toml" time="0.000"><skipped/></testcase>
+  </testsuite>
+</testsuites>"#;
+        assert_eq!(render_junit(&results()), expected);
+    }
+
+    #[test]
+    fn junit_control_characters() {
+        let results = vec![CaseResult::new(
+            "a.toml".into(),
+            "a.toml".into(),
+            None,
+            CaseStatus::Failed,
+            vec![Mismatch::Stderr],
+            Some("stderr:\n\u{1b}[31merror\u{1b}[0m\0\tdone\r\n".into()),
+            std::time::Duration::ZERO,
+        )];
+        let expected = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<testsuites>
+  <testsuite name=\"trycmd\" tests=\"1\" failures=\"1\" skipped=\"0\">
+    <testcase name=\"a.toml\" classname=\"a.toml\" time=\"0.000\"><failure message=\"mismatched stderr\">stderr:
+\u{fffd}[31merror\u{fffd}[0m\u{fffd}\tdone\r
+</failure></testcase>
+  </testsuite>
+</testsuites>";
+        assert_eq!(render_junit(&results), expected);
+    }
+
+    #[test]
+    fn junit_suites() {
+        let suites: Vec<_> = results()
+            .into_iter()
+            .zip(["init", "build", "init"])
+            .map(|(result, suite)| result.in_suite(suite.to_owned()))
+            .collect();
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+  <testsuite name="build" tests="1" failures="1" skipped="0">
+    <testcase name="b.trycmd:3" classname="b.trycmd" time="0.020"><failure message="mismatched status, stdout">Expected success, was 1
+&lt;stdout&gt;</failure></testcase>
+  </testsuite>
+  <testsuite name="init" tests="2" failures="0" skipped="1">
+    <testcase name="a.toml" classname="a.toml" time="1.500"></testcase>
+    <testcase name="c.toml" classname="c.toml" time="0.000"><skipped/></testcase>
+  </testsuite>
+</testsuites>"#;
+        assert_eq!(render_junit(&suites), expected);
+
+        let expected = "Suites:
+  build: 0 passed, 1 failed, 0 ignored in 0.0s
+  init: 1 passed, 0 failed, 1 ignored in 1.5s
+Slowest suite: init (1.5s)";
+        assert_eq!(suite_summary(&suites).as_deref(), Some(expected));
+        assert_eq!(suite_summary(&results()), None);
+    }
+
+    #[test]
+    fn strip_ansi_styles() {
+        assert_eq!(strip_ansi("\u{1b}[32mok\u{1b}[0m done"), "ok done");
+    }
+}
@@ -13,17 +13,40 @@ use std::io::stderr;
 
 use rayon::prelude::*;
 use snapbox::path::FileType;
-use snapbox::{DataFormat, NormalizeNewlines, NormalizePaths};
+use snapbox::{DataFormat, NormalizeNewlines, NormalizePaths, NormalizeTrailingWhitespace};
+
+/// Replacement for [`TestCases::env_secret`][crate::TestCases::env_secret] values
+pub(crate) const REDACTED: &str = "[REDACTED]";
+
+/// Source of [`TestCases::bin_fallback`][crate::TestCases::bin_fallback] bins in
+/// [`Case::default_bins`]
+pub(crate) const FALLBACK_SOURCE: &str = "bin_fallback";
 
 #[derive(Debug)]
 pub(crate) struct Runner {
     cases: Vec<Case>,
+    /// Which batch each case runs in, with all of one batch finishing before the next starts
+    stages: Vec<usize>,
+    /// How expensive each case is expected to be, with heavier cases started first
+    weights: Vec<u32>,
+    format: crate::OutputFormat,
+    no_new_snapshots: bool,
+    /// Only report failures
+    quiet: bool,
+    /// Stop starting cases after this many have failed
+    abort_after: Option<usize>,
 }
 
 impl Runner {
     pub(crate) fn new() -> Self {
         Self {
             cases: Default::default(),
+            stages: Default::default(),
+            weights: Default::default(),
+            format: Default::default(),
+            no_new_snapshots: false,
+            quiet: false,
+            abort_after: None,
         }
     }
 
@@ -31,66 +54,244 @@ impl Runner {
         self.cases.push(case);
     }
 
+    pub(crate) fn output_format(&mut self, format: crate::OutputFormat) {
+        self.format = format;
+    }
+
+    pub(crate) fn no_new_snapshots(&mut self, yes: bool) {
+        self.no_new_snapshots = yes;
+    }
+
+    pub(crate) fn quiet(&mut self, yes: bool) {
+        self.quiet = yes;
+    }
+
+    /// Print the arguments each step would run with, see `TRYCMD_EXPLAIN`
+    pub(crate) fn explain(&self, bins: &crate::BinRegistry) {
+        let stderr = std::io::stderr();
+        let mut stderr = stderr.lock();
+        for case in &self.cases {
+            for line in case.explain(bins) {
+                let _ = writeln!(stderr, "{}", line);
+            }
+        }
+    }
+
+    pub(crate) fn abort_after(&mut self, failures: Option<usize>) {
+        self.abort_after = failures;
+    }
+
+    pub(crate) fn cases(&self) -> &[Case] {
+        &self.cases
+    }
+
+    pub(crate) fn stages(&mut self, stages: Vec<usize>) {
+        self.stages = stages;
+    }
+
+    pub(crate) fn weights(&mut self, weights: Vec<u32>) {
+        self.weights = weights;
+    }
+
+    /// Cases in `stage`, heaviest first and otherwise in order
+    pub(crate) fn schedule(&self, stage: usize) -> Vec<usize> {
+        let mut scheduled: Vec<_> = (0..self.cases.len())
+            .filter(|i| self.stages.get(*i).copied().unwrap_or_default() == stage)
+            .collect();
+        scheduled.sort_by_key(|i| std::cmp::Reverse(self.weights.get(*i).copied().unwrap_or(1)));
+        scheduled
+    }
+
     pub(crate) fn run(
         &self,
         mode: &Mode,
+        handler: Option<&dyn crate::ModeHandler>,
         bins: &crate::BinRegistry,
         substitutions: &snapbox::Substitutions,
     ) {
         let palette = snapbox::report::Palette::color();
+        let start = std::time::Instant::now();
 
         if self.cases.is_empty() {
             eprintln!("{}", palette.warn("There are no trycmd tests enabled yet"));
         } else {
-            let failures: Vec<_> = self
-                .cases
-                .par_iter()
-                .flat_map(|c| {
-                    let results = c.run(mode, bins, substitutions);
+            if *mode == Mode::Overwrite && self.cases.iter().any(|c| c.input_line.is_some()) {
+                eprintln!(
+                    "{}",
+                    palette.warn(
+                        "Cases from `case_per_line` share their template's snapshots, so they are checked rather than overwritten"
+                    )
+                );
+            }
+            let progress = crate::progress::Progress::new(
+                self.cases.len(),
+                !self.quiet && crate::progress::Progress::is_supported(),
+            );
+            let failed_cases = std::sync::atomic::AtomicUsize::new(0);
+            let run_case = |c: &Case| {
+                if let Some(abort_after) = self.abort_after {
+                    if abort_after <= failed_cases.load(std::sync::atomic::Ordering::SeqCst) {
+                        return None;
+                    }
+                }
+                progress.start(&c.path);
+                let snapshots = (*mode == Mode::Overwrite).then(|| Snapshots::capture(c));
+                let results = match handler {
+                    Some(handler) => c.run_with(handler, mode, bins, substitutions, &progress),
+                    None => c.run(mode, bins, substitutions, &progress),
+                };
+                let updates = snapshots.map(|s| s.updates(c)).unwrap_or_default();
 
-                    let stderr = stderr();
-                    let mut stderr = stderr.lock();
+                let mut progress = progress.suspend();
+                let stderr = stderr();
+                let mut stderr = stderr.lock();
 
-                    results
-                        .into_iter()
-                        .filter_map(|s| {
-                            snapbox::debug!("Case: {:#?}", s);
-                            match s {
-                                Ok(status) => {
-                                    let _ = writeln!(
-                                        stderr,
-                                        "{} {} ... {}",
-                                        palette.hint("Testing"),
-                                        status.name(),
-                                        status.spawn.status.summary()
-                                    );
-                                    if !status.is_ok() {
-                                        // Assuming `status` will print the newline
-                                        let _ = write!(stderr, "{}", &status);
-                                    }
-                                    None
+                let results = results
+                    .into_iter()
+                    .map(|s| {
+                        snapbox::debug!("Case: {:#?}", s);
+                        match s {
+                            Ok(status) if self.quiet && status.is_ok() => {
+                                (status.to_result(false), None)
+                            }
+                            Ok(status) => {
+                                let _ = write!(
+                                    stderr,
+                                    "{} {} ... {}",
+                                    palette.hint("Testing"),
+                                    status.name(),
+                                    status.spawn.status.summary()
+                                );
+                                if let Some(repeat) = &status.repeat {
+                                    let _ = write!(stderr, " (ran {} times)", repeat.runs);
                                 }
-                                Err(status) => {
-                                    let _ = writeln!(
+                                if let Some(reason) = &status.skip_reason {
+                                    let _ = write!(stderr, " ({})", palette.hint(reason));
+                                } else if c.verbose > 0 {
+                                    let _ = write!(
                                         stderr,
-                                        "{} {} ... {}",
-                                        palette.hint("Testing"),
-                                        status.name(),
-                                        palette.error("failed"),
+                                        " ({})",
+                                        palette
+                                            .hint(crate::report::format_duration(status.duration))
                                     );
+                                }
+                                let _ = writeln!(stderr);
+                                if !status.is_ok() {
                                     // Assuming `status` will print the newline
                                     let _ = write!(stderr, "{}", &status);
-                                    Some(status)
                                 }
+                                (status.to_result(false), None)
+                            }
+                            Err(status) => {
+                                let _ = write!(
+                                    stderr,
+                                    "{} {} ... {}",
+                                    palette.hint("Testing"),
+                                    status.name(),
+                                    palette.error("failed"),
+                                );
+                                if let Some(repeat) = &status.repeat {
+                                    let _ = write!(stderr, " (ran {} times)", repeat.runs);
+                                }
+                                if c.verbose > 0 {
+                                    let _ = write!(
+                                        stderr,
+                                        " ({})",
+                                        palette
+                                            .hint(crate::report::format_duration(status.duration))
+                                    );
+                                }
+                                let _ = writeln!(stderr);
+                                // Assuming `status` will print the newline
+                                let _ = write!(stderr, "{}", &status);
+                                (status.to_result(true), Some(status))
                             }
-                        })
-                        .collect::<Vec<_>>()
+                        }
+                    })
+                    .map(|(result, failure)| (result.in_suite(c.suite.clone()), failure))
+                    .collect::<Vec<_>>();
+                let failed = results.iter().filter(|(_, f)| f.is_some()).count();
+                progress.failed(failed);
+                if failed != 0 {
+                    failed_cases.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                }
+                Some((results, updates))
+            };
+            let stage_count = self.stages.iter().max().map_or(1, |max| max + 1);
+            let ran = (0..stage_count)
+                .flat_map(|stage| {
+                    // Bridged so idle threads take the next case in order, like a priority queue
+                    let mut ran = self
+                        .schedule(stage)
+                        .into_iter()
+                        .par_bridge()
+                        .map(|i| (i, run_case(&self.cases[i])))
+                        .collect::<Vec<_>>();
+                    ran.sort_by_key(|(i, _)| *i);
+                    ran
                 })
-                .collect();
+                .collect::<Vec<_>>();
+            progress.finish();
+            let not_run = ran.iter().filter(|(_, r)| r.is_none()).count();
+            let (results, updates): (Vec<_>, Vec<_>) = ran
+                .into_iter()
+                .map(|(i, r)| {
+                    r.unwrap_or_else(|| {
+                        let c = &self.cases[i];
+                        let result = crate::report::CaseResult::new(
+                            c.path.display().to_string(),
+                            c.path.clone(),
+                            None,
+                            crate::report::CaseStatus::Ignored,
+                            Vec::new(),
+                            Some("Not run, the run was aborted".to_owned()),
+                            std::time::Duration::ZERO,
+                        );
+                        (vec![(result.in_suite(c.suite.clone()), None)], Vec::new())
+                    })
+                })
+                .unzip();
+            let (results, failures): (Vec<_>, Vec<_>) = results.into_iter().flatten().unzip();
+            let failures: Vec<_> = failures.into_iter().flatten().collect();
+            let updates: Vec<_> = updates.into_iter().flatten().collect();
+
+            if !updates.is_empty() {
+                let stderr = stderr();
+                let mut stderr = stderr.lock();
+                let _ = writeln!(
+                    stderr,
+                    "{}",
+                    palette.info(format_args!("Updated {} snapshots:", updates.len()))
+                );
+                for update in &updates {
+                    let _ = writeln!(stderr, "  {}", update);
+                }
+            }
+
+            if !self.quiet {
+                if let Some(summary) = crate::report::suite_summary(&results) {
+                    let _ = writeln!(stderr(), "{}", palette.hint(summary));
+                }
+            }
+
+            if let Err(err) = self.format.write(&results) {
+                let _ = writeln!(stderr(), "{}", palette.error(err));
+            }
 
             if !failures.is_empty() {
                 let stderr = stderr();
                 let mut stderr = stderr.lock();
+                if 0 < not_run {
+                    let _ = writeln!(
+                        stderr,
+                        "{}",
+                        palette.warn(format_args!(
+                            "Aborted after {} failed cases; {} cases were not run",
+                            self.abort_after.unwrap_or_default(),
+                            not_run
+                        )),
+                    );
+                }
                 let _ = writeln!(
                     stderr,
                     "{}",
@@ -101,10 +302,186 @@ impl Runner {
                     "{}",
                     palette.hint("Debug output with `TRYCMD=dump`"),
                 );
-                panic!("{} of {} tests failed", failures.len(), self.cases.len());
+                let mut failed_paths: Vec<_> = failures.iter().map(|f| f.path.clone()).collect();
+                failed_paths.dedup();
+                panic!(
+                    "{}",
+                    failure_digest(
+                        failed_cases.load(std::sync::atomic::Ordering::SeqCst),
+                        self.cases.len(),
+                        &failed_paths,
+                        start.elapsed()
+                    )
+                );
+            }
+
+            if self.no_new_snapshots && !updates.is_empty() {
+                let mut message = format!(
+                    "{} snapshots were written and need to be reviewed:",
+                    updates.len()
+                );
+                for update in &updates {
+                    message.push_str(&format!("\n  {}", update));
+                }
+                panic!("{}", message);
+            }
+        }
+    }
+
+    /// Run only the cases for `path`, returning their results rather than reporting them
+    pub(crate) fn run_one(
+        &self,
+        path: &std::path::Path,
+        mode: &Mode,
+        handler: Option<&dyn crate::ModeHandler>,
+        bins: &crate::BinRegistry,
+        substitutions: &snapbox::Substitutions,
+    ) -> Vec<crate::report::CaseResult> {
+        let progress = crate::progress::Progress::new(0, false);
+        self.cases
+            .iter()
+            .filter(|c| c.path == path)
+            .flat_map(|c| {
+                let outputs = match handler {
+                    Some(handler) => c.run_with(handler, mode, bins, substitutions, &progress),
+                    None => c.run(mode, bins, substitutions, &progress),
+                };
+                outputs.into_iter().map(move |o| {
+                    let result = match o {
+                        Ok(output) => output.to_result(false),
+                        Err(output) => output.to_result(true),
+                    };
+                    result.in_suite(c.suite.clone())
+                })
+            })
+            .collect()
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), Vec<crate::ParseError>> {
+        let errors: Vec<_> = self
+            .cases
+            .par_iter()
+            .filter_map(|c| c.validate().err())
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Summarize failures without repeating their details
+///
+/// `failed` and `total` count each matrix leg and line of a `case_per_line` file, while `paths`
+/// lists each failing file once.
+fn failure_digest(
+    failed: usize,
+    total: usize,
+    paths: &[std::path::PathBuf],
+    elapsed: std::time::Duration,
+) -> String {
+    const MAX_LISTED: usize = 10;
+
+    let mut digest = format!(
+        "{} of {} cases failed in {}: ",
+        failed,
+        total,
+        crate::report::format_duration(elapsed)
+    );
+    let listed = paths
+        .iter()
+        .take(MAX_LISTED)
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    digest.push_str(&listed);
+    if MAX_LISTED < paths.len() {
+        digest.push_str(&format!(", and {} more", paths.len() - MAX_LISTED));
+    }
+    digest
+}
+
+/// Contents of a case's snapshot files, for reporting what `TRYCMD=overwrite` changed
+#[derive(Debug)]
+struct Snapshots {
+    files: std::collections::BTreeMap<std::path::PathBuf, Vec<u8>>,
+}
+
+impl Snapshots {
+    fn capture(case: &Case) -> Self {
+        let files = snapshot_paths(case.file(), case.snapshot_dir.as_deref())
+            .into_iter()
+            .filter_map(|path| std::fs::read(&path).ok().map(|content| (path, content)))
+            .collect();
+        Self { files }
+    }
+
+    fn updates(&self, case: &Case) -> Vec<SnapshotUpdate> {
+        snapshot_paths(case.file(), case.snapshot_dir.as_deref())
+            .into_iter()
+            .filter_map(|path| {
+                let content = std::fs::read(&path).ok()?;
+                match self.files.get(&path) {
+                    None => Some(SnapshotUpdate::Created(path)),
+                    Some(original) if *original != content => Some(SnapshotUpdate::Modified(path)),
+                    Some(_) => None,
+                }
+            })
+            .chain(
+                self.files
+                    .keys()
+                    .filter(|path| !path.exists())
+                    .map(|path| SnapshotUpdate::Removed(path.clone())),
+            )
+            .collect()
+    }
+}
+
+/// Files `TRYCMD=overwrite` may write for a case
+fn snapshot_paths(
+    path: &std::path::Path,
+    snapshot_dir: Option<&std::path::Path>,
+) -> Vec<std::path::PathBuf> {
+    let mut paths = vec![
+        path.to_owned(),
+        crate::schema::snapshot_path(snapshot_dir, path, "stdout"),
+        crate::schema::snapshot_path(snapshot_dir, path, "stderr"),
+        crate::schema::snapshot_path(snapshot_dir, path, "interleaved"),
+    ];
+    let mut dirs = vec![path.with_extension("out")];
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let entry_path = entry.path();
+            if entry_path.is_dir() {
+                dirs.push(entry_path);
+            } else {
+                paths.push(entry_path);
             }
         }
     }
+    paths
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SnapshotUpdate {
+    Created(std::path::PathBuf),
+    Modified(std::path::PathBuf),
+    Removed(std::path::PathBuf),
+}
+
+impl std::fmt::Display for SnapshotUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Created(path) => write!(f, "created {}", path.display()),
+            Self::Modified(path) => write!(f, "modified {}", path.display()),
+            Self::Removed(path) => write!(f, "removed {}", path.display()),
+        }
+    }
 }
 
 impl Default for Runner {
@@ -113,37 +490,367 @@ impl Default for Runner {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) struct Case {
     pub(crate) path: std::path::PathBuf,
+    /// Status set for this case's glob, like with [`TestCases::fail`][crate::TestCases::fail]
     pub(crate) expected: Option<crate::schema::CommandStatus>,
+    /// Status for steps that don't set one, see
+    /// [`TestCases::default_status`][crate::TestCases::default_status]
+    pub(crate) default_status: Option<crate::schema::CommandStatus>,
     pub(crate) timeout: Option<std::time::Duration>,
-    pub(crate) default_bin: Option<crate::schema::Bin>,
+    /// Bins for steps without their own, in the order they're tried, with the
+    /// [`TestCases`][crate::TestCases] method each came from
+    pub(crate) default_bins: Vec<(&'static str, crate::schema::Bin)>,
+    /// See [`TestCases::default_args`][crate::TestCases::default_args]
+    pub(crate) default_args: Option<Vec<String>>,
+    pub(crate) default_stdin: Option<crate::Data>,
+    /// See [`TestCases::inherit_stdin`][crate::TestCases::inherit_stdin]
+    pub(crate) inherit_stdin: bool,
+    /// See [`TestCases::max_output_bytes`][crate::TestCases::max_output_bytes]
+    pub(crate) max_output_bytes: Option<usize>,
+    /// See [`TestCases::verify_commands`][crate::TestCases::verify_commands]
+    pub(crate) verify_commands: bool,
+    /// See [`TestCases::allowed_commands`][crate::TestCases::allowed_commands]
+    pub(crate) allowed_commands: Option<Vec<String>>,
+    /// See [`TestCases::abort_on_panic`][crate::TestCases::abort_on_panic]
+    pub(crate) abort_on_panic: bool,
+    /// See [`TestCases::locale`][crate::TestCases::locale]
+    pub(crate) locale: Option<String>,
+    /// See [`TestCases::umask`][crate::TestCases::umask]
+    pub(crate) umask: Option<u32>,
+    /// See [`TestCases::hermetic`][crate::TestCases::hermetic]
+    pub(crate) hermetic: bool,
+    pub(crate) default_cwd: Option<std::path::PathBuf>,
     pub(crate) env: crate::schema::Env,
+    pub(crate) strip_trailing_whitespace: bool,
+    /// See [`TestCases::collapse_repeated_lines`][crate::TestCases::collapse_repeated_lines]
+    pub(crate) collapse_repeated: Option<usize>,
+    /// See [`TestCases::trailing_newline`][crate::TestCases::trailing_newline]
+    pub(crate) trailing_newline: Option<crate::TrailingNewline>,
+    /// See [`TestCases::truncate_output`][crate::TestCases::truncate_output]
+    pub(crate) truncate_output: Option<usize>,
+    /// See [`TestCases::normalize_sort_lines`][crate::TestCases::normalize_sort_lines]
+    pub(crate) sort_lines: bool,
+    /// Verbosity level, see [`TestCases::verbose`][crate::TestCases::verbose]
+    pub(crate) verbose: u8,
+    /// Sandbox cases that otherwise would run in the real directory
+    pub(crate) working_copy: bool,
+    /// See [`TestCases::isolate_home`][crate::TestCases::isolate_home]
+    pub(crate) isolate_home: bool,
+    pub(crate) matrix_leg: Option<MatrixLeg>,
+    /// Line of the input whose text is `stdin`, see
+    /// [`TestCases::case_per_line`][crate::TestCases::case_per_line]
+    pub(crate) input_line: Option<usize>,
+    /// Case file that an `input_line` case, named with [`input_line_path`], runs
+    pub(crate) template: Option<std::path::PathBuf>,
+    pub(crate) externalize_over: Option<usize>,
+    /// From `TRYCMD_SNAPSHOT_DIR`, see [`crate::schema::snapshot_path`]
+    pub(crate) snapshot_dir: Option<std::path::PathBuf>,
+    /// Environment variables set to make the command deterministic
+    pub(crate) seeds: std::collections::BTreeMap<String, String>,
+    /// Values to hide from output, see [`TestCases::env_secret`][crate::TestCases::env_secret]
+    pub(crate) secrets: Vec<String>,
+    /// Where to also write the actual output of failed steps
+    pub(crate) dump_on_failure: Option<std::path::PathBuf>,
+    /// File and how many of its last lines to report with failures
+    pub(crate) fail_context: Option<(std::path::PathBuf, usize)>,
+    /// Another case using the same fixtures, see
+    /// [`TestCases::share_fixtures`][crate::TestCases::share_fixtures]
+    pub(crate) shared_with: Option<std::path::PathBuf>,
+    /// Template for a fresh sandbox, see [`TestCases::case_with_fixtures`][crate::TestCases::case_with_fixtures]
+    pub(crate) fixture_dir: Option<std::path::PathBuf>,
+    /// Why the case is skipped, when not by request
+    pub(crate) skip_reason: Option<String>,
+    /// Other cases compare against this case's output, so it is kept in `captured`
+    pub(crate) capture_output: bool,
+    /// Output of cases that others compare against, shared by all cases in the run
+    pub(crate) captured: CapturedOutputs,
+    /// See [`TestCases::comparator`][crate::TestCases::comparator]
+    #[cfg(feature = "filesystem")]
+    pub(crate) comparators: crate::comparator::Comparators,
+    /// See [`TestCases::configure_command`][crate::TestCases::configure_command]
+    pub(crate) configure_command: Option<CommandHook>,
+    /// Which suite results are grouped under, see [`crate::schema::SuiteConfig`]
+    pub(crate) suite: String,
     pub(crate) error: Option<SpawnStatus>,
 }
 
+type CommandHookFn = dyn Fn(&std::path::Path, &mut std::process::Command) + Send + Sync;
+
+/// See [`TestCases::configure_command`][crate::TestCases::configure_command]
+#[derive(Clone)]
+pub(crate) struct CommandHook(std::sync::Arc<CommandHookFn>);
+
+impl CommandHook {
+    pub(crate) fn new(
+        hook: impl Fn(&std::path::Path, &mut std::process::Command) + Send + Sync + 'static,
+    ) -> Self {
+        Self(std::sync::Arc::new(hook))
+    }
+
+    /// Run the hook, failing rather than panicking if it panics
+    fn call(
+        &self,
+        path: &std::path::Path,
+        cmd: &mut std::process::Command,
+    ) -> Result<(), crate::Error> {
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| (self.0)(path, cmd))).map_err(
+            |payload| {
+                let msg = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("Box<dyn Any>");
+                format!("`configure_command` panicked: {}", msg).into()
+            },
+        )
+    }
+}
+
+impl std::fmt::Debug for CommandHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CommandHook")
+    }
+}
+
+/// Output of cases, for cases with `stdout = { same-as = "..." }` to compare against
+///
+/// Keyed by case file and matrix leg, so each leg compares against the same leg.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CapturedOutputs(std::sync::Arc<std::sync::Mutex<CapturedByCase>>);
+
+/// Captured output by case file and matrix leg label
+type CapturedByCase =
+    std::collections::BTreeMap<(std::path::PathBuf, Option<String>), CapturedOutput>;
+
+#[derive(Clone, Debug, Default)]
+struct CapturedOutput {
+    stdout: Option<crate::Data>,
+    stderr: Option<crate::Data>,
+}
+
+impl CapturedOutputs {
+    fn insert(&self, path: &std::path::Path, leg: Option<String>, output: CapturedOutput) {
+        let mut captured = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        captured.insert((path.to_owned(), leg), output);
+    }
+
+    fn get(
+        &self,
+        path: &std::path::Path,
+        leg: Option<String>,
+    ) -> Result<CapturedOutput, crate::Error> {
+        let captured = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        captured.get(&(path.to_owned(), leg)).cloned().ok_or_else(|| {
+            format!(
+                "No output from {} to compare against; it must be run by the same `TestCases` and not be skipped",
+                path.display()
+            )
+            .into()
+        })
+    }
+}
+
+/// `expected` with its `[if VAR]` blocks resolved against the step's environment, if it has any
+///
+/// Malformed blocks fail `stream`.
+fn resolve_conditionals(
+    stream: &mut Option<Stream>,
+    expected: &Option<crate::Data>,
+    step: &crate::schema::Step,
+) -> Option<crate::Data> {
+    let expected = expected
+        .as_ref()
+        .filter(|_| !step.binary)
+        .and_then(crate::Data::render)
+        .filter(|e| crate::conditional::has_conditionals(e))?;
+    if let Some(stream) = stream {
+        stream.conditional = true;
+    }
+    match crate::conditional::resolve(&expected, |var| step.env.is_set(var)) {
+        Ok(resolved) => Some(crate::Data::text(resolved)),
+        Err(e) => {
+            if let Some(stream) = stream {
+                stream.status = StreamStatus::Failure(e.into());
+            }
+            None
+        }
+    }
+}
+
+/// Name of the case for line `line` of a
+/// [`TestCases::case_per_line`][crate::TestCases::case_per_line] input, like `parse_3.trycmd`
+pub(crate) fn input_line_path(template: &std::path::Path, line: usize) -> std::path::PathBuf {
+    let mut name = format!(
+        "{}_{}",
+        template.file_stem().unwrap_or_default().to_string_lossy(),
+        line
+    );
+    if let Some(ext) = template.extension() {
+        name.push('.');
+        name.push_str(&ext.to_string_lossy());
+    }
+    template.with_file_name(name)
+}
+
+/// One of the bins from [`TestCases::matrix_bins`][crate::TestCases::matrix_bins] to run a case
+/// against
+#[derive(Clone, Debug)]
+pub(crate) struct MatrixLeg {
+    pub(crate) label: String,
+    pub(crate) bin: crate::schema::Bin,
+    /// Only the canonical leg may write snapshots
+    pub(crate) canonical: bool,
+}
+
 impl Case {
     pub(crate) fn with_error(path: std::path::PathBuf, error: crate::Error) -> Self {
         Self {
             path,
             expected: None,
+            default_status: None,
             timeout: None,
-            default_bin: None,
+            default_bins: Vec::new(),
+            default_args: None,
+            default_stdin: None,
+            inherit_stdin: false,
+            max_output_bytes: None,
+            verify_commands: false,
+            allowed_commands: None,
+            abort_on_panic: false,
+            locale: None,
+            umask: None,
+            hermetic: false,
+            default_cwd: None,
             env: Default::default(),
+            strip_trailing_whitespace: false,
+            collapse_repeated: None,
+            trailing_newline: None,
+            truncate_output: None,
+            sort_lines: false,
+            verbose: 0,
+            working_copy: false,
+            isolate_home: false,
+            matrix_leg: None,
+            input_line: None,
+            template: None,
+            externalize_over: None,
+            snapshot_dir: None,
+            seeds: Default::default(),
+            secrets: Default::default(),
+            dump_on_failure: None,
+            fail_context: None,
+            shared_with: None,
+            fixture_dir: None,
+            skip_reason: None,
+            capture_output: false,
+            captured: Default::default(),
+            #[cfg(feature = "filesystem")]
+            comparators: Default::default(),
+            configure_command: None,
+            suite: Default::default(),
             error: Some(SpawnStatus::Failure(error)),
         }
     }
 
+    pub(crate) fn validate(&self) -> Result<(), crate::ParseError> {
+        if self.expected == Some(crate::schema::CommandStatus::Skipped) {
+            return Ok(());
+        }
+
+        if let Some(SpawnStatus::Failure(err)) = self.error.clone() {
+            return Err(crate::ParseError::from(err).with_path(&self.path));
+        }
+
+        crate::schema::TryCmd::parse(self.file(), self.snapshot_dir.as_deref()).map(|_| ())
+    }
+
+    /// The file the case is loaded from, which is only different from `path` for a
+    /// [`TestCases::case_per_line`][crate::TestCases::case_per_line] case
+    pub(crate) fn file(&self) -> &std::path::Path {
+        self.template.as_deref().unwrap_or(&self.path)
+    }
+
     pub(crate) fn run(
         &self,
         mode: &Mode,
         bins: &crate::BinRegistry,
         substitutions: &snapbox::Substitutions,
+        progress: &crate::progress::Progress,
     ) -> Vec<Result<Output, Output>> {
-        if self.expected == Some(crate::schema::CommandStatus::Skipped) {
+        // Every line shares the template's snapshots
+        let fail = Mode::Fail;
+        let mode = match self.input_line {
+            Some(_) if *mode == Mode::Overwrite => &fail,
+            _ => mode,
+        };
+        let mut outputs = match &self.matrix_leg {
+            // Legs would fight over the snapshots
+            Some(leg) if !leg.canonical && *mode != Mode::Fail => {
+                let output = Output::sequence(self.path.clone());
+                assert_eq!(output.spawn.status, SpawnStatus::Skipped);
+                vec![Ok(output)]
+            }
+            _ => self.run_sequence(mode, bins, substitutions, progress),
+        };
+        for output in outputs.iter_mut().filter_map(|o| o.as_mut().err()) {
+            output.seeds = self.seeds.clone();
+        }
+        if let Some(leg) = &self.matrix_leg {
+            for output in &mut outputs {
+                let (Ok(output) | Err(output)) = output;
+                output.matrix_leg = Some(leg.label.clone());
+            }
+        }
+        outputs
+    }
+
+    /// Run with a custom [`ModeHandler`][crate::ModeHandler] in control
+    fn run_with(
+        &self,
+        handler: &dyn crate::ModeHandler,
+        mode: &Mode,
+        bins: &crate::BinRegistry,
+        substitutions: &snapbox::Substitutions,
+        progress: &crate::progress::Progress,
+    ) -> Vec<Result<Output, Output>> {
+        let mut outputs = None;
+        let handled = handler.run_case(&self.path, &mut || {
+            let ran = self.run(mode, bins, substitutions, progress);
+            let results = ran
+                .iter()
+                .map(|o| match o {
+                    Ok(output) => output.to_result(false),
+                    Err(output) => output.to_result(true),
+                })
+                .collect();
+            outputs = Some(ran);
+            results
+        });
+        let mut outputs = outputs.unwrap_or_else(|| {
             let output = Output::sequence(self.path.clone());
             assert_eq!(output.spawn.status, SpawnStatus::Skipped);
+            vec![Ok(output)]
+        });
+        if let Err(err) = handled {
+            let output = Output::step(self.path.clone(), "mode".into());
+            outputs.push(Err(output.error(err)));
+        }
+        outputs
+    }
+
+    fn run_sequence(
+        &self,
+        mode: &Mode,
+        bins: &crate::BinRegistry,
+        substitutions: &snapbox::Substitutions,
+        progress: &crate::progress::Progress,
+    ) -> Vec<Result<Output, Output>> {
+        if self.expected == Some(crate::schema::CommandStatus::Skipped) {
+            let mut output = Output::sequence(self.path.clone());
+            assert_eq!(output.spawn.status, SpawnStatus::Skipped);
+            output.skip_reason = self.skip_reason.clone();
             return vec![Ok(output)];
         }
 
@@ -153,13 +860,134 @@ impl Case {
             return vec![Err(output)];
         }
 
-        let mut sequence = match crate::schema::TryCmd::load(&self.path) {
+        if let (Some(shared_with), Mode::Overwrite) = (&self.shared_with, mode) {
+            let output = Output::step(self.path.clone(), "setup".into());
+            return vec![Err(output.error(
+                format!(
+                    "{} and {} share fixtures, so they can't be overwritten",
+                    self.path.display(),
+                    shared_with.display()
+                )
+                .into(),
+            ))];
+        }
+
+        if self.verify_commands {
+            if let Err(e) = self.verify_programs(bins) {
+                let output = Output::step(self.path.clone(), "setup".into());
+                return vec![Err(output.error(e))];
+            }
+        }
+
+        let sequence = match crate::schema::TryCmd::load(self.file(), self.snapshot_dir.as_deref())
+        {
             Ok(sequence) => sequence,
             Err(e) => {
                 let output = Output::step(self.path.clone(), "setup".into());
                 return vec![Err(output.error(e))];
             }
         };
+        match (sequence.repeat, mode) {
+            (Some(runs), Mode::Fail | Mode::Overwrite) if 1 < runs => {
+                self.run_repeated(sequence, runs, mode, bins, substitutions, progress)
+            }
+            _ => self.run_loaded(sequence, mode, bins, substitutions, progress),
+        }
+    }
+
+    /// Run `sequence` `runs` times, each in a fresh sandbox, failing if any run fails or their
+    /// outputs differ
+    ///
+    /// With `TRYCMD=overwrite`, only the first run's output is written but the others must still
+    /// agree with it.
+    fn run_repeated(
+        &self,
+        sequence: crate::schema::TryCmd,
+        runs: usize,
+        mode: &Mode,
+        bins: &crate::BinRegistry,
+        substitutions: &snapbox::Substitutions,
+        progress: &crate::progress::Progress,
+    ) -> Vec<Result<Output, Output>> {
+        let mut outputs = self.run_loaded(sequence.clone(), mode, bins, substitutions, progress);
+        for output in &mut outputs {
+            let (Ok(output) | Err(output)) = output;
+            output.repeat = Some(Repeat::new(runs));
+        }
+        for run in 2..=runs {
+            let later =
+                self.run_loaded(sequence.clone(), &Mode::Fail, bins, substitutions, progress);
+            for (status, other) in outputs.iter_mut().zip(later) {
+                let other_failed = other.is_err();
+                let (Ok(mut other) | Err(mut other)) = other;
+                let (Ok(output) | Err(output)) = &mut *status;
+                output.duration += other.duration;
+                let duration = output.duration;
+                if !output.repeat.as_ref().map_or(true, Repeat::is_ok) {
+                    // Only report the first problem
+                    continue;
+                }
+                let first = output.rendered.clone().unwrap_or_default();
+                let current = other.rendered.clone().unwrap_or_default();
+                if first != current {
+                    let repeat = output.repeat.as_mut().expect("set for every output");
+                    repeat.divergence = Some(Divergence {
+                        run,
+                        first,
+                        other: current,
+                    });
+                    if let Ok(output) = status {
+                        let output = output.clone();
+                        *status = Err(output);
+                    }
+                } else if other_failed && *mode == Mode::Fail && status.is_ok() {
+                    // Same output but, for example, different files left behind
+                    other.duration = duration;
+                    other.repeat = Some(Repeat {
+                        failed_run: Some(run),
+                        ..Repeat::new(runs)
+                    });
+                    *status = Err(other);
+                }
+            }
+        }
+        outputs
+    }
+
+    fn run_loaded(
+        &self,
+        mut sequence: crate::schema::TryCmd,
+        mode: &Mode,
+        bins: &crate::BinRegistry,
+        substitutions: &snapbox::Substitutions,
+        progress: &crate::progress::Progress,
+    ) -> Vec<Result<Output, Output>> {
+        if let Some(fixture_dir) = self.fixture_dir.as_deref() {
+            match snapbox::path::resolve_dir(fixture_dir) {
+                Ok(fixture_dir) => {
+                    sequence.fs.base = Some(fixture_dir.clone());
+                    sequence.fs.cwd = Some(fixture_dir);
+                    sequence.fs.sandbox = Some(true);
+                }
+                Err(e) => {
+                    let output = Output::step(self.path.clone(), "setup".into());
+                    return vec![Err(output.error(
+                        format!("Failed to find fixture ({}): {}", fixture_dir.display(), e).into(),
+                    ))];
+                }
+            }
+        }
+        if self.working_copy && !sequence.fs.sandbox() && sequence.cwd.is_none() {
+            sequence.fs.sandbox = Some(true);
+        }
+        let isolate_home = sequence
+            .isolate_home
+            .as_ref()
+            .map_or(self.isolate_home, crate::schema::IsolateHome::is_enabled);
+        if let Err(e) = self.resolve_same_as(&mut sequence) {
+            let output = Output::step(self.path.clone(), "setup".into());
+            return vec![Err(output.error(e))];
+        }
 
         if sequence.steps.is_empty() {
             let output = Output::sequence(self.path.clone());
@@ -167,6 +995,36 @@ impl Case {
             return vec![Ok(output)];
         }
 
+        let mut tape = match mode {
+            Mode::Record(_) => Some(crate::record::Tape::record(crate::record::fingerprint(
+                &sequence,
+            ))),
+            Mode::Replay(dir) => {
+                let inputs = crate::record::fingerprint(&sequence);
+                match crate::record::Tape::replay(dir, &self.path, inputs) {
+                    Ok(tape) => Some(tape),
+                    Err(e) => {
+                        let output = Output::step(self.path.clone(), "setup".into());
+                        return vec![Err(output.error(e))];
+                    }
+                }
+            }
+            Mode::Fail | Mode::Overwrite | Mode::Dump(_) => None,
+        };
+
+        let _lock = match sequence
+            .lock
+            .as_deref()
+            .map(|name| crate::lock::CaseLock::acquire(name, &self.path))
+            .transpose()
+        {
+            Ok(lock) => lock,
+            Err(e) => {
+                let output = Output::step(self.path.clone(), "setup".into());
+                return vec![Err(output.error(e))];
+            }
+        };
+
         let fs_context = match fs_context(
             &self.path,
             sequence.fs.base.as_deref(),
@@ -181,6 +1039,15 @@ impl Case {
                 )];
             }
         };
+        if let (Some(tape), Some(root)) = (
+            tape.as_ref().filter(|t| t.is_replay()),
+            fs_context.path().filter(|_| fs_context.is_mutable()),
+        ) {
+            if let Err(e) = tape.replay_fs(root) {
+                let output = Output::step(self.path.clone(), "setup".into());
+                return vec![Err(output.error(e))];
+            }
+        }
         let cwd = match fs_context
             .path()
             .map(|p| {
@@ -197,31 +1064,116 @@ impl Case {
                 return vec![Err(output.error(e))];
             }
         };
+        let unsandboxed_cwd = match self.unsandboxed_cwd(&sequence) {
+            Ok(unsandboxed_cwd) => unsandboxed_cwd,
+            Err(e) => {
+                let output = Output::step(self.path.clone(), "setup".into());
+                return vec![Err(output.error(e))];
+            }
+        };
+        let cwd = unsandboxed_cwd.clone().or(cwd);
+        if let Some(tape) = &mut tape {
+            tape.paths(fs_context.path(), cwd.as_deref());
+            tape.secrets(&self.secrets);
+        }
+        let home = if isolate_home {
+            let seed = sequence
+                .isolate_home
+                .as_ref()
+                .and_then(crate::schema::IsolateHome::seed);
+            match isolated_home(seed) {
+                Ok(home) => Some(home),
+                Err(e) => {
+                    let output = Output::step(self.path.clone(), "setup".into());
+                    return vec![Err(output.error(e))];
+                }
+            }
+        } else {
+            None
+        };
         let mut substitutions = substitutions.clone();
         if let Some(root) = fs_context.path() {
             substitutions
                 .insert("[ROOT]", root.display().to_string())
                 .unwrap();
         }
-        if let Some(cwd) = cwd.clone().or_else(|| std::env::current_dir().ok()) {
+        if let Some(home) = home.as_ref().and_then(|home| home.path()) {
             substitutions
-                .insert("[CWD]", cwd.display().to_string())
+                .insert("[HOME]", home.display().to_string())
+                .unwrap();
+        }
+        if let Some(cwd) = cwd.clone().or_else(|| std::env::current_dir().ok()) {
+            substitutions
+                .insert("[CWD]", cwd.display().to_string())
                 .unwrap();
         }
         substitutions
             .insert("[EXE]", std::env::consts::EXE_SUFFIX)
             .unwrap();
+        if let Err(e) = sequence.substitutions.apply(&mut substitutions) {
+            let output = Output::step(self.path.clone(), "setup".into());
+            return vec![Err(output.error(e))];
+        }
+        substitutions.escapes(true);
         snapbox::debug!("{:?}", substitutions);
 
         let mut outputs = Vec::with_capacity(sequence.steps.len());
         let mut prior_step_failed = false;
-        for step in &mut sequence.steps {
+        // Changed by `$ cd`
+        let mut step_cwd = cwd.clone();
+        for (i, step) in sequence.steps.iter_mut().enumerate() {
+            if let Some(dir) = &step.cd {
+                let mut output = match step.id.clone() {
+                    Some(id) => Output::step(self.path.clone(), id),
+                    None => Output::sequence(self.path.clone()),
+                };
+                if prior_step_failed {
+                    outputs.push(Ok(output));
+                    continue;
+                }
+                let root = fs_context.path().or(cwd.as_deref());
+                match change_dir(step_cwd.as_deref(), root, dir) {
+                    Ok(new_cwd) => {
+                        if !sequence.substitutions.disables("[CWD]") {
+                            substitutions
+                                .insert("[CWD]", new_cwd.display().to_string())
+                                .unwrap();
+                        }
+                        step_cwd = Some(new_cwd);
+                        output.spawn.status = SpawnStatus::Ok;
+                        outputs.push(Ok(output));
+                    }
+                    Err(e) => {
+                        prior_step_failed |= fs_context.is_mutable();
+                        outputs.push(Err(output.error(e)));
+                    }
+                }
+                continue;
+            }
             if prior_step_failed {
                 step.expected_status = Some(crate::schema::CommandStatus::Skipped);
             }
+            let sandbox = fs_context.path().filter(|_| fs_context.is_mutable());
+            step.env.add_case_vars(&self.path, i, sandbox);
+            if let Some(home) = home.as_ref().and_then(|home| home.path()) {
+                step.env.isolate_home(home);
+            }
 
-            let step_status = self.run_step(step, cwd.as_deref(), bins, &substitutions);
-            if fs_context.is_mutable() && step_status.is_err() && *mode == Mode::Fail {
+            let mut step_status = self.run_step(
+                step,
+                step_cwd.as_deref(),
+                bins,
+                &substitutions,
+                progress,
+                tape.as_mut(),
+            );
+            if let (Some(unsandboxed_cwd), Err(output)) = (&unsandboxed_cwd, &mut step_status) {
+                output.unsandboxed_cwd = Some(unsandboxed_cwd.clone());
+            }
+            if fs_context.is_mutable()
+                && step_status.is_err()
+                && matches!(mode, Mode::Fail | Mode::Record(_) | Mode::Replay(_))
+            {
                 prior_step_failed = true;
             }
             outputs.push(step_status);
@@ -249,29 +1201,95 @@ impl Case {
                 // `rev()` to ensure we don't mess up our line number info
                 for step_status in outputs.iter_mut().rev() {
                     if let Err(output) = step_status {
+                        let conditional = [&output.stdout, &output.stderr]
+                            .into_iter()
+                            .flatten()
+                            .find(|s| s.conditional && !s.is_ok());
+                        if let Some(stream) = conditional {
+                            output.spawn.status = SpawnStatus::Failure(
+                                format!(
+                                    "Failed to overwrite: expected {} has `[if ...]` blocks, update it by hand",
+                                    stream.stream
+                                )
+                                .into(),
+                            );
+                            continue;
+                        }
                         let res = sequence.overwrite(
-                            &self.path,
+                            self.file(),
                             output.id.as_deref(),
                             output.stdout.as_ref().map(|s| &s.content),
                             output.stderr.as_ref().map(|s| &s.content),
                             output.spawn.exit,
+                            self.externalize_over,
                         );
 
-                        if res.is_ok() {
-                            *step_status = Ok(output.clone());
+                        // Overwriting can't change another case's output
+                        let same_as_failed = sequence.steps.iter().any(|step| {
+                            let failed = |stream: &Option<Stream>| {
+                                stream.as_ref().map_or(false, |s| !s.is_ok())
+                            };
+                            (step.expected_stdout_same_as.is_some() && failed(&output.stdout))
+                                || (step.expected_stderr_same_as.is_some()
+                                    && failed(&output.stderr))
+                        });
+                        match res {
+                            Ok(()) if same_as_failed => {}
+                            Ok(()) => {
+                                *step_status = Ok(output.clone());
+                            }
+                            Err(err) => {
+                                output.spawn.status = SpawnStatus::Failure(
+                                    format!("Failed to overwrite: {}", err.to_string().trim_end())
+                                        .into(),
+                                );
+                            }
                         }
                     }
                 }
             }
-            Mode::Fail => {}
+            Mode::Fail | Mode::Record(_) | Mode::Replay(_) => {}
+        }
+        if let Some(root) = &self.dump_on_failure {
+            for output in outputs.iter_mut().filter_map(|o| o.as_mut().err()) {
+                if let Err(stream) =
+                    self.dump_stream(root, output.id.as_deref(), output.stdout.clone())
+                {
+                    output.stdout = stream;
+                }
+                if let Err(stream) =
+                    self.dump_stream(root, output.id.as_deref(), output.stderr.clone())
+                {
+                    output.stderr = stream;
+                }
+            }
+        }
+
+        if let (Mode::Record(dir), Some(tape)) = (mode, &mut tape) {
+            let recorded = match fs_context.path().filter(|_| fs_context.is_mutable()) {
+                Some(root) => tape.record_fs(root),
+                None => Ok(()),
+            }
+            .and_then(|()| tape.save(dir, &self.path));
+            if let Err(e) = recorded {
+                let output = Output::step(self.path.clone(), "record".into());
+                outputs.push(Err(output.error(e)));
+            }
         }
 
+        // Read before the sandbox is cleaned up
+        let context = self
+            .fail_context
+            .as_ref()
+            .map(|(path, lines)| FailContext::read(path, cwd.as_deref(), *lines, &self.secrets));
+
         if sequence.fs.sandbox() {
             let mut ok = true;
             let mut output = Output::step(self.path.clone(), "teardown".into());
 
             output.fs = match self.validate_fs(
                 fs_context.path().expect("sandbox must be filled"),
+                &sequence.fs,
                 output.fs,
                 mode,
                 &substitutions,
@@ -299,15 +1317,117 @@ impl Case {
             outputs.push(output);
         }
 
+        if let Some(context) = context {
+            for output in outputs.iter_mut().filter_map(|o| o.as_mut().err()) {
+                output.context = Some(context.clone());
+            }
+        }
+
         outputs
     }
 
+    /// Check that every command in a `*.trycmd` / `*.md` file runs a program that exists, see
+    /// [`TestCases::verify_commands`][crate::TestCases::verify_commands]
+    fn verify_programs(&self, bins: &crate::BinRegistry) -> Result<(), crate::Error> {
+        let is_literate = self
+            .path
+            .extension()
+            .map_or(false, |ext| ext == "trycmd" || ext == "md");
+        if !is_literate {
+            return Ok(());
+        }
+
+        let mut problems = Vec::new();
+        for (line, name) in crate::schema::TryCmd::load_programs(self.file())? {
+            let location = format!("{}:{}", self.file().display(), line);
+            if let Some(allowed) = &self.allowed_commands {
+                if !allowed.contains(&name) {
+                    problems.push(format!(
+                        "{}: `{}` is not one of the allowed commands",
+                        location, name
+                    ));
+                    continue;
+                }
+            }
+            if !self.is_known_program(&name, bins) {
+                problems.push(format!(
+                    "{}: `{}` is not a registered bin or on `PATH`",
+                    location, name
+                ));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("\n").into())
+        }
+    }
+
+    fn is_known_program(&self, name: &str, bins: &crate::BinRegistry) -> bool {
+        // Built in, see `crate::schema::Step::cd`
+        if name == "cd" {
+            return true;
+        }
+        let is_default = self.default_bins.iter().any(|(_, bin)| match bin {
+            crate::schema::Bin::Name(default) => default == name,
+            crate::schema::Bin::Path(path) => path.file_stem().map_or(false, |stem| stem == name),
+            _ => false,
+        });
+        is_default
+            || !matches!(bins.resolve_name(name), crate::schema::Bin::Name(_))
+            || crate::registry::find_on_path(name).is_some()
+    }
+
+    /// Run the [`TestCases::configure_command`][crate::TestCases::configure_command] hook on
+    /// `cmd`
+    fn configure_command(&self, cmd: &mut snapbox::cmd::Command) -> Result<(), crate::Error> {
+        match &self.configure_command {
+            Some(hook) => hook.call(&self.path, cmd.as_std_mut()),
+            None => Ok(()),
+        }
+    }
+
+    fn unsandboxed_cwd(
+        &self,
+        sequence: &crate::schema::TryCmd,
+    ) -> Result<Option<std::path::PathBuf>, crate::Error> {
+        let cwd = if sequence.fs.sandbox() {
+            if sequence.cwd.is_some() {
+                return Err("`cwd` cannot be used with `fs.sandbox = true`".into());
+            }
+            None
+        } else if let Some(cwd) = sequence.cwd.as_deref() {
+            Some(cwd)
+        } else if sequence.fs.cwd.is_none() {
+            self.default_cwd.as_deref()
+        } else {
+            None
+        };
+
+        cwd.map(|cwd| {
+            let root = std::env::var_os("CARGO_MANIFEST_DIR")
+                .map(std::path::PathBuf::from)
+                .or_else(|| std::env::current_dir().ok())
+                .unwrap_or_default();
+            let cwd = root.join(cwd);
+            let resolved = snapbox::path::resolve_dir(&cwd)
+                .map_err(|e| format!("Failed to find cwd ({}): {}", cwd.display(), e))?;
+            if !resolved.is_dir() {
+                return Err(format!("cwd ({}) is not a directory", cwd.display()).into());
+            }
+            Ok(resolved)
+        })
+        .transpose()
+    }
+
     pub(crate) fn run_step(
         &self,
         step: &mut crate::schema::Step,
         cwd: Option<&std::path::Path>,
         bins: &crate::BinRegistry,
         substitutions: &snapbox::Substitutions,
+        progress: &crate::progress::Progress,
+        tape: Option<&mut crate::record::Tape>,
     ) -> Result<Output, Output> {
         let output = if let Some(id) = step.id.clone() {
             Output::step(self.path.clone(), id)
@@ -315,37 +1435,85 @@ impl Case {
             Output::sequence(self.path.clone())
         };
 
-        let mut bin = step.bin.take();
-        if bin.is_none() {
-            bin = self.default_bin.clone()
-        }
-        bin = bin
-            .map(|name| bins.resolve_bin(name))
-            .transpose()
+        self.resolve_bin(step, bins)
             .map_err(|e| output.clone().error(e))?;
-        step.bin = bin;
         if step.timeout.is_none() {
             step.timeout = self.timeout;
         }
-        if self.expected.is_some() {
+        if step.stdin.is_none() && step.stdin_file.is_none() && !step.null_stdin {
+            step.stdin = self.default_stdin.clone();
+        }
+        step.inherit_stdin = self.inherit_stdin
+            && step.stdin.is_none()
+            && step.stdin_file.is_none()
+            && !step.null_stdin;
+        step.max_output_bytes = self.max_output_bytes;
+        let status_origin = if self.expected.is_some() {
             step.expected_status = self.expected;
+            StatusOrigin::Glob
+        } else if step.expected_status.is_some() {
+            StatusOrigin::Case
+        } else if self.default_status.is_some() {
+            step.expected_status = self.default_status;
+            StatusOrigin::Default
+        } else {
+            StatusOrigin::Implicit
+        };
+        if self.hermetic {
+            for (key, value) in crate::spec::HERMETIC_TERMINAL {
+                step.env
+                    .add
+                    .entry((*key).to_owned())
+                    .or_insert_with(|| (*value).to_owned());
+            }
         }
+        let removed = step.env.removed_only();
         step.env.update(&self.env);
+        step.env.add.retain(|k, _| !removed.contains(k));
+        if let Some(locale) = step.locale.as_ref().or(self.locale.as_ref()) {
+            for key in crate::schema::LOCALE_VARS {
+                step.env
+                    .add
+                    .entry((*key).to_owned())
+                    .or_insert_with(|| locale.clone());
+            }
+        }
+        if step.umask.is_none() {
+            step.umask = self.umask;
+        }
+        if self.strip_trailing_whitespace {
+            step.expected_stdout = step
+                .expected_stdout
+                .take()
+                .map(|d| d.normalize(NormalizeTrailingWhitespace));
+            step.expected_stderr = step
+                .expected_stderr
+                .take()
+                .map(|d| d.normalize(NormalizeTrailingWhitespace));
+        }
 
         if step.expected_status() == crate::schema::CommandStatus::Skipped {
             assert_eq!(output.spawn.status, SpawnStatus::Skipped);
             return Ok(output);
         }
 
+        let replaying = tape.as_ref().map_or(false, |t| t.is_replay());
         match &step.bin {
             Some(crate::schema::Bin::Path(_)) => {}
+            // Recorded with the bin built, which it doesn't need to be for replaying
+            Some(crate::schema::Bin::Name(_))
+                if tape
+                    .as_ref()
+                    .map_or(false, |t| t.is_replay() && t.has_step(output.id.as_deref())) => {}
             Some(crate::schema::Bin::Name(_name)) => {
                 // Unhandled by resolve
                 snapbox::debug!("bin={:?} not found", _name);
                 assert_eq!(output.spawn.status, SpawnStatus::Skipped);
                 return Ok(output);
             }
+            Some(crate::schema::Bin::Script { .. }) => {}
             Some(crate::schema::Bin::Error(_)) => {}
+            Some(crate::schema::Bin::CargoWorkspace { .. }) => {}
             // Unlike `Name`, this always represents a bug
             None => {}
             Some(crate::schema::Bin::Ignore) => {
@@ -355,45 +1523,259 @@ impl Case {
             }
         }
 
-        let cmd = step.to_command(cwd).map_err(|e| output.clone().error(e))?;
-        let cmd_output = cmd
-            .output()
-            .map_err(|e| output.clone().error(e.to_string().into()))?;
-        let output = output.output(cmd_output);
+        if step.expect_timeout && step.timeout.is_none() {
+            return Err(output.error("`expect-timeout` needs a `timeout`".into()));
+        }
+        // Read now, as an earlier step may have written it
+        if let Some(file) = step.stdin_file.as_ref().filter(|_| !replaying) {
+            let path = match cwd {
+                Some(cwd) => cwd.join(file),
+                None => file.clone(),
+            };
+            if !path.exists() {
+                return Err(output.error(
+                    format!(
+                        "Can't read `stdin` from {}, which doesn't exist (yet)",
+                        file.display()
+                    )
+                    .into(),
+                ));
+            }
+            let stdin = std::fs::read(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+                .map_err(|e| output.clone().error(e.into()))?;
+            step.stdin = Some(crate::Data::binary(stdin));
+        }
+
+        if self.verbose > 0 {
+            let palette = snapbox::report::Palette::color();
+            let _progress = progress.suspend();
+            eprintln!(
+                "{} {} -> {}",
+                palette.hint("Running:"),
+                output.name(),
+                redact_text(&step.to_command_line(), &self.secrets)
+            );
+            if self.verbose > 1 {
+                let argv: Vec<_> = step
+                    .argv()
+                    .iter()
+                    .map(|arg| redact_text(arg, &self.secrets))
+                    .collect();
+                eprintln!("{} {:?}", palette.hint("Argv:"), argv);
+            }
+        }
+        let mut output = output.argv(step.argv());
+        let start = std::time::Instant::now();
+        let cmd_output = match tape {
+            Some(tape) if replaying => tape.replay_step(step),
+            tape => {
+                let cmd_output = step_output(step, cwd, &|cmd| self.configure_command(cmd));
+                if let Some(tape) = tape {
+                    // Recordings are committed alongside the cases
+                    let recorded = match &cmd_output {
+                        Ok((cmd_output, timed_out)) => {
+                            Ok((redact_output(cmd_output, &self.secrets), *timed_out))
+                        }
+                        Err(e) => Err(redact_text(e.to_string().trim_end(), &self.secrets)),
+                    };
+                    tape.record_step(step, recorded);
+                }
+                cmd_output
+            }
+        };
+        output.duration = start.elapsed();
+        let cmd_output = cmd_output.map_err(|e| output.clone().error(e).reproduction(step, cwd));
+        let output = cmd_output.map(|(cmd_output, timed_out)| {
+            let timed_out = step.timeout.filter(|_| timed_out).map(|after| TimedOut {
+                after,
+                stdout_len: cmd_output.stdout.len(),
+                stderr_len: cmd_output.stderr.len(),
+            });
+            let mut output = output.output(cmd_output).redact(&self.secrets);
+            if step.interleaved {
+                output = output.interleaved();
+            }
+            output.rendered = Some(Repeat::render(&output, substitutions));
+            if self.abort_on_panic {
+                if let Some(panic) = output.panic() {
+                    let msg = format!("Child process panicked:\n{}", panic);
+                    return output.error(msg.into());
+                }
+            }
+
+            // For Mode::Dump's sake, allow running all
+            let mut output = if step.expect_timeout {
+                self.validate_timeout(output, timed_out.is_some())
+            } else {
+                self.validate_spawn(output, step.expected_status(), status_origin)
+            };
+            if let Some(timed_out) = timed_out {
+                if !output.spawn.is_ok() {
+                    output = output.reproduction(step, cwd);
+                    if let Some(reproduction) = &mut output.reproduction {
+                        reproduction.timed_out = Some(timed_out);
+                    }
+                }
+            }
+            self.validate_streams(output, step, substitutions)
+        });
+        let output = match output {
+            Ok(output) if output.is_ok() => Ok(output),
+            Ok(output) | Err(output) => Err(output.redact_reproduction(&self.secrets)),
+        };
+
+        if self.verbose > 0 {
+            let palette = snapbox::report::Palette::color();
+            let name = match &output {
+                Ok(output) | Err(output) => output.name(),
+            };
+            let summary = if output.is_ok() {
+                palette.info("ok")
+            } else {
+                palette.error("FAILED")
+            };
+            let _progress = progress.suspend();
+            eprintln!("{} {} ... {}", palette.hint("Finished:"), name, summary);
+        }
+
+        output
+    }
 
-        // For Mode::Dump's sake, allow running all
-        let output = self.validate_spawn(output, step.expected_status());
-        let output = self.validate_streams(output, step, substitutions);
+    /// Pick the bin for `step`, from the matrix, the case, or the default bins, in that order
+    ///
+    /// `args-override`, or the default args for steps without a bin of their own, go before
+    /// the step's args.
+    fn resolve_bin(
+        &self,
+        step: &mut crate::schema::Step,
+        bins: &crate::BinRegistry,
+    ) -> Result<(), crate::Error> {
+        let mut bin = step.bin.take();
+        let mut prefix = step.args_override.take();
+        if bin.is_none() {
+            prefix = prefix.or_else(|| self.default_args.clone());
+        }
+        if let Some(leg) = &self.matrix_leg {
+            bin = Some(leg.bin.clone());
+        }
+        if let Some(mut args) = prefix {
+            args.append(&mut step.args);
+            step.args = args;
+        }
+        step.bin = match bin {
+            Some(bin) => Some(bins.resolve_bin(bin)?),
+            None => self.resolve_default_bin(bins)?,
+        };
+        for piped in &mut step.pipeline {
+            piped.bin = bins.resolve_bin(piped.bin.clone())?;
+        }
+        Ok(())
+    }
 
-        if output.is_ok() {
-            Ok(output)
+    /// The first of the default bins that is found
+    ///
+    /// A lone default bin is used even if it isn't found, so running it reports why.
+    fn resolve_default_bin(
+        &self,
+        bins: &crate::BinRegistry,
+    ) -> Result<Option<crate::schema::Bin>, crate::Error> {
+        if let [(source, bin)] = self.default_bins.as_slice() {
+            return match resolve_candidate(bin.clone(), bins, *source == FALLBACK_SOURCE) {
+                Ok(bin) => Ok(Some(bin)),
+                Err(_) => bins.resolve_bin(bin.clone()).map(Some),
+            };
+        }
+        let mut rejected = Vec::new();
+        for (source, bin) in &self.default_bins {
+            match resolve_candidate(bin.clone(), bins, *source == FALLBACK_SOURCE) {
+                Ok(bin) => return Ok(Some(bin)),
+                Err(reason) => rejected.push(format!("  `{}`: {}", source, reason)),
+            }
+        }
+        if rejected.is_empty() {
+            Ok(None)
         } else {
-            Err(output)
+            Err(format!("No bin found, tried:\n{}", rejected.join("\n")).into())
+        }
+    }
+
+    /// Describe each step's arguments without running anything, see `TRYCMD_EXPLAIN`
+    fn explain(&self, bins: &crate::BinRegistry) -> Vec<String> {
+        let name = match &self.matrix_leg {
+            Some(leg) => format!("{}[{}]", self.path.display(), leg.label),
+            None => self.path.display().to_string(),
+        };
+        if let Some(error) = &self.error {
+            return vec![format!("{}: {:?}", name, error)];
+        }
+        let sequence = match crate::schema::TryCmd::load(self.file(), self.snapshot_dir.as_deref())
+        {
+            Ok(sequence) => sequence,
+            Err(e) => return vec![format!("{}: {}", name, e.to_string().trim_end())],
+        };
+        sequence
+            .steps
+            .into_iter()
+            .map(|mut step| {
+                let name = match &step.id {
+                    Some(id) => format!("{}:{}", name, id),
+                    None => name.clone(),
+                };
+                if let Some(dir) = &step.cd {
+                    return format!("{}: cd {}", name, dir.display());
+                }
+                match self.resolve_bin(&mut step, bins) {
+                    Ok(()) => format!("{}: {:?}", name, step.argv()),
+                    Err(e) => format!("{}: {}", name, e.to_string().trim_end()),
+                }
+            })
+            .collect()
+    }
+
+    /// Expect the output captured from the cases named by `same-as`
+    fn resolve_same_as(&self, sequence: &mut crate::schema::TryCmd) -> Result<(), crate::Error> {
+        let leg = self.matrix_leg.as_ref().map(|leg| leg.label.clone());
+        for step in &mut sequence.steps {
+            if let Some(path) = &step.expected_stdout_same_as {
+                let captured = self.captured.get(path, leg.clone())?;
+                step.expected_stdout = Some(captured.stdout.unwrap_or_default());
+            }
+            if let Some(path) = &step.expected_stderr_same_as {
+                let captured = self.captured.get(path, leg.clone())?;
+                step.expected_stderr = Some(captured.stderr.unwrap_or_default());
+            }
         }
+        Ok(())
     }
 
-    fn validate_spawn(&self, mut output: Output, expected: crate::schema::CommandStatus) -> Output {
+    fn validate_spawn(
+        &self,
+        mut output: Output,
+        expected: crate::schema::CommandStatus,
+        origin: StatusOrigin,
+    ) -> Output {
         let status = output.spawn.exit.expect("bale out before now");
         match expected {
             crate::schema::CommandStatus::Success => {
                 if !status.success() {
-                    output.spawn.status = SpawnStatus::Expected("success".into());
+                    output.spawn.status = SpawnStatus::Expected("success".into(), origin);
                 }
             }
             crate::schema::CommandStatus::Failed => {
                 if status.success() || status.code().is_none() {
-                    output.spawn.status = SpawnStatus::Expected("failure".into());
+                    output.spawn.status = SpawnStatus::Expected("failure".into(), origin);
                 }
             }
             crate::schema::CommandStatus::Interrupted => {
                 if status.code().is_some() {
-                    output.spawn.status = SpawnStatus::Expected("interrupted".into());
+                    output.spawn.status = SpawnStatus::Expected("interrupted".into(), origin);
                 }
             }
             crate::schema::CommandStatus::Skipped => unreachable!("handled earlier"),
             crate::schema::CommandStatus::Code(expected_code) => {
                 if Some(expected_code) != status.code() {
-                    output.spawn.status = SpawnStatus::Expected(expected_code.to_string());
+                    output.spawn.status = SpawnStatus::Expected(expected_code.to_string(), origin);
                 }
             }
         }
@@ -401,33 +1783,210 @@ impl Case {
         output
     }
 
+    /// With `expect-timeout`, the command passes only if it was killed for running too long
+    fn validate_timeout(&self, mut output: Output, timed_out: bool) -> Output {
+        if !timed_out {
+            output.spawn.status = SpawnStatus::Expected("timeout".into(), StatusOrigin::Case);
+        }
+        output
+    }
+
     fn validate_streams(
         &self,
         mut output: Output,
         step: &crate::schema::Step,
         substitutions: &snapbox::Substitutions,
     ) -> Output {
+        if let (Some(encoding), false) = (step.encoding, step.binary) {
+            output.stdout = output.stdout.map(|stream| stream.decode(encoding));
+            output.stderr = output.stderr.map(|stream| stream.decode(encoding));
+        }
+        if self.capture_output {
+            let normalize = |stream: &Stream| {
+                let mut stream = stream.clone();
+                if !step.binary {
+                    stream = stream.make_text();
+                }
+                if self.strip_trailing_whitespace {
+                    stream.content = stream.content.normalize(NormalizeTrailingWhitespace);
+                }
+                stream.content
+            };
+            self.captured.insert(
+                &self.path,
+                self.matrix_leg.as_ref().map(|leg| leg.label.clone()),
+                CapturedOutput {
+                    stdout: output.stdout.as_ref().map(normalize),
+                    stderr: output.stderr.as_ref().map(normalize),
+                },
+            );
+        }
+        let resolved_stdout = resolve_conditionals(&mut output.stdout, &step.expected_stdout, step);
+        let resolved_stderr = resolve_conditionals(&mut output.stderr, &step.expected_stderr, step);
+        let conditional = resolved_stdout.is_some() || resolved_stderr.is_some();
+        let resolved_stdout = resolved_stdout.as_ref().or(step.expected_stdout.as_ref());
+        let resolved_stderr = resolved_stderr.as_ref().or(step.expected_stderr.as_ref());
+
+        let sort_lines = self.sort_lines || step.sort_lines;
+        let (expected_stdout, expected_stderr) = if sort_lines {
+            // Substitute first, so lines sort like the expected lines they match
+            let sort = |mut stream: Stream, expected: Option<&crate::Data>| {
+                if let (Some(expected), false) = (expected, step.binary) {
+                    stream = stream.make_text();
+                    if stream.is_ok() {
+                        stream.content = stream
+                            .content
+                            .normalize(snapbox::NormalizeMatches::new(substitutions, expected));
+                    }
+                }
+                stream.sort_lines()
+            };
+            output.stdout = output.stdout.map(|s| sort(s, resolved_stdout));
+            output.stderr = output.stderr.map(|s| sort(s, resolved_stderr));
+            (
+                resolved_stdout.map(sorted_lines),
+                resolved_stderr.map(sorted_lines),
+            )
+        } else {
+            (None, None)
+        };
+        let expected_stdout = expected_stdout.as_ref().or(resolved_stdout);
+        let expected_stderr = expected_stderr.as_ref().or(resolved_stderr);
+
+        // Sorting scatters the markers and line counts are of the lines as written
+        let collapse = self.collapse_repeated.filter(|_| !sort_lines);
         output.stdout = self.validate_stream(
             output.stdout,
-            step.expected_stdout.as_ref(),
-            step.binary,
-            substitutions,
-        );
-        output.stderr = self.validate_stream(
-            output.stderr,
-            step.expected_stderr.as_ref(),
+            expected_stdout,
             step.binary,
+            step.stdout_match_anchor,
+            collapse.filter(|_| step.expected_stdout_lines.is_none()),
             substitutions,
         );
+        if let Some(expected_lines) = step.expected_stdout_lines {
+            output.stdout = output
+                .stdout
+                .map(|stream| self.validate_line_count(stream, expected_lines));
+        }
+        for extract in &step.extracts {
+            let stream = match extract.stream {
+                crate::schema::ExtractStream::Stdout => &mut output.stdout,
+                crate::schema::ExtractStream::Stderr => &mut output.stderr,
+            };
+            *stream = stream
+                .take()
+                .map(|stream| self.validate_extract(stream, extract));
+        }
+        output.stderr = if step.stderr_unordered {
+            self.validate_unordered_stream(
+                output.stderr,
+                expected_stderr,
+                step.binary,
+                substitutions,
+            )
+        } else {
+            self.validate_stream(
+                output.stderr,
+                expected_stderr,
+                step.binary,
+                step.stderr_match_anchor,
+                collapse,
+                substitutions,
+            )
+        };
+
+        if sort_lines || conditional {
+            // Avoid churn from reordering, or dropping conditional blocks, with
+            // `TRYCMD=overwrite` / `TRYCMD=dump`
+            for (stream, expected) in [
+                (&mut output.stdout, &step.expected_stdout),
+                (&mut output.stderr, &step.expected_stderr),
+            ] {
+                if let (Some(stream), Some(expected)) = (stream, expected) {
+                    if stream.is_ok() {
+                        stream.content = expected.clone();
+                    }
+                }
+            }
+        }
+        for stream in [&mut output.stdout, &mut output.stderr]
+            .into_iter()
+            .flatten()
+        {
+            stream.shown = self.truncate_output;
+        }
 
         output
     }
 
+    /// Like [`Case::validate_stream`] but lines may be in any order
+    fn validate_unordered_stream(
+        &self,
+        stream: Option<Stream>,
+        expected_content: Option<&crate::Data>,
+        binary: bool,
+        substitutions: &snapbox::Substitutions,
+    ) -> Option<Stream> {
+        let mut stream = stream?;
+
+        if !binary {
+            stream = stream.make_text();
+            if !stream.is_ok() {
+                return Some(stream);
+            }
+        }
+        if self.strip_trailing_whitespace {
+            stream.content = stream.content.normalize(NormalizeTrailingWhitespace);
+        }
+
+        if let Some(expected_content) = expected_content {
+            let (actual, expected) = match (stream.content.render(), expected_content.render()) {
+                (Some(actual), Some(expected)) => (actual, expected),
+                _ => {
+                    stream.status =
+                        StreamStatus::Failure("Unordered lines require text content".into());
+                    return Some(stream);
+                }
+            };
+
+            let mut unexpected: Vec<&str> = actual.lines().collect();
+            let mut missing = Vec::new();
+            for expected_line in expected.lines() {
+                let pattern = crate::Data::text(expected_line);
+                let position = unexpected.iter().position(|actual_line| {
+                    crate::Data::text(*actual_line)
+                        .normalize(snapbox::NormalizeMatches::new(substitutions, &pattern))
+                        == pattern
+                });
+                match position {
+                    Some(i) => {
+                        unexpected.remove(i);
+                    }
+                    None => missing.push(expected_line.to_owned()),
+                }
+            }
+
+            if missing.is_empty() && unexpected.is_empty() {
+                // Avoid churn from reordering with `TRYCMD=overwrite` / `TRYCMD=dump`
+                stream.content = expected_content.clone();
+            } else {
+                stream.status = StreamStatus::Unordered {
+                    missing,
+                    unexpected: unexpected.into_iter().map(ToOwned::to_owned).collect(),
+                };
+            }
+        }
+
+        Some(stream)
+    }
+
     fn validate_stream(
         &self,
         stream: Option<Stream>,
         expected_content: Option<&crate::Data>,
         binary: bool,
+        anchor: crate::schema::MatchAnchor,
+        collapse: Option<usize>,
         substitutions: &snapbox::Substitutions,
     ) -> Option<Stream> {
         let mut stream = stream?;
@@ -438,8 +1997,55 @@ impl Case {
                 return Some(stream);
             }
         }
+        if self.strip_trailing_whitespace {
+            stream.content = stream.content.normalize(NormalizeTrailingWhitespace);
+        }
+        let adjusted;
+        let mut expected_content = expected_content;
+        if let (Some(policy), false) = (self.trailing_newline, binary) {
+            let expected = expected_content.and_then(crate::Data::render);
+            if let Some(actual) = stream.content.render() {
+                stream.content = crate::Data::text(policy.actual(&actual, expected.as_deref()));
+            }
+            if let Some(expected) = expected {
+                adjusted = crate::Data::text(policy.expected(&expected));
+                expected_content = Some(&adjusted);
+            }
+        }
+        if let (Some(threshold), Some(actual), false) = (collapse, stream.content.render(), binary)
+        {
+            let expected = expected_content.and_then(crate::Data::render);
+            stream.content = crate::Data::text(crate::repeat::collapse(
+                &actual,
+                expected.as_deref(),
+                threshold,
+                substitutions,
+            ));
+        }
 
         if let Some(expected_content) = expected_content {
+            if let (Some(actual), Some(expected)) = (
+                stream.content.render().filter(|_| !binary),
+                expected_content
+                    .render()
+                    .filter(|e| crate::ignore::has_ignored(e)),
+            ) {
+                match crate::ignore::normalize(&actual, &expected, substitutions) {
+                    // Avoid churn with `TRYCMD=overwrite` / `TRYCMD=dump`
+                    Ok(()) => stream.content = expected_content.clone(),
+                    Err(normalized) => {
+                        stream.content = crate::Data::text(normalized);
+                        stream.status = StreamStatus::Expected(expected_content.clone());
+                    }
+                }
+                return Some(stream);
+            }
+            if anchor != crate::schema::MatchAnchor::Full {
+                stream = stream.anchor(anchor, expected_content);
+                if !stream.is_ok() {
+                    return Some(stream);
+                }
+            }
             stream.content = stream.content.normalize(snapbox::NormalizeMatches::new(
                 substitutions,
                 expected_content,
@@ -454,6 +2060,45 @@ impl Case {
         Some(stream)
     }
 
+    fn validate_line_count(&self, mut stream: Stream, expected: usize) -> Stream {
+        if !stream.is_ok() {
+            return stream;
+        }
+
+        match stream.content.render() {
+            Some(content) => {
+                let actual = content.lines().count();
+                if actual != expected {
+                    stream.status = StreamStatus::LineCount { expected, actual };
+                }
+            }
+            None => {
+                stream.status =
+                    StreamStatus::Failure("Unable to count lines of binary data".into());
+            }
+        }
+        stream
+    }
+
+    fn validate_extract(&self, mut stream: Stream, extract: &crate::schema::Extract) -> Stream {
+        if !stream.is_ok() {
+            return stream;
+        }
+
+        match stream.content.clone().try_coerce(DataFormat::Text).render() {
+            Some(content) => {
+                if let Err(e) = extract.validate(&content) {
+                    stream.status = StreamStatus::Failure(e);
+                }
+            }
+            None => {
+                stream.status =
+                    StreamStatus::Failure("Unable to extract numbers from binary data".into());
+            }
+        }
+        stream
+    }
+
     fn dump_stream(
         &self,
         root: &std::path::Path,
@@ -479,7 +2124,13 @@ impl Case {
                 }
             };
             let stream_path = root.join(file_name);
-            stream.content.write_to(&stream_path).map_err(|e| {
+            let content = match stream.content.render() {
+                Some(text) if stream.content.format() == DataFormat::Text => {
+                    snapbox::Data::text(snapbox::utils::escape_patterns(&text))
+                }
+                _ => stream.content.clone(),
+            };
+            content.write_to(&stream_path).map_err(|e| {
                 let mut stream = stream.clone();
                 if stream.is_ok() {
                     stream.status = StreamStatus::Failure(e);
@@ -492,9 +2143,11 @@ impl Case {
         }
     }
 
+    #[cfg_attr(not(feature = "filesystem"), allow(unused_variables))]
     fn validate_fs(
         &self,
         actual_root: &std::path::Path,
+        spec: &crate::schema::Filesystem,
         mut fs: Filesystem,
         mode: &Mode,
         substitutions: &snapbox::Substitutions,
@@ -505,21 +2158,35 @@ impl Case {
         if let Mode::Dump(_) = mode {
             // Handled as part of PathFixture
         } else {
-            let fixture_root = self.path.with_extension("out");
+            let fixture_root = self.file().with_extension("out");
             if fixture_root.exists() {
                 for status in snapbox::path::PathDiff::subset_matches_iter(
                     fixture_root,
                     actual_root,
                     substitutions,
                 ) {
-                    match status {
+                    match self.compare_custom(status) {
                         Ok((expected_path, actual_path)) => {
                             fs.context.push(FileStatus::Ok {
                                 actual_path,
                                 expected_path,
                             });
                         }
-                        Err(diff) => {
+                        Err(CustomDiff::Rejected {
+                            expected_path,
+                            actual_path,
+                        }) => {
+                            let overwritten = *mode == Mode::Overwrite
+                                && std::fs::copy(&actual_path, &expected_path).is_ok();
+                            fs.context.push(FileStatus::Rejected {
+                                expected_path,
+                                actual_path,
+                            });
+                            if !overwritten {
+                                ok = false;
+                            }
+                        }
+                        Err(CustomDiff::Diff(diff)) => {
                             let mut is_current_ok = false;
                             if *mode == Mode::Overwrite && diff.overwrite().is_ok() {
                                 is_current_ok = true;
@@ -532,6 +2199,21 @@ impl Case {
                     }
                 }
             }
+
+            for actual_path in absent_violations(actual_root, &spec.absent) {
+                let rel_path = actual_path
+                    .strip_prefix(actual_root)
+                    .unwrap_or(&actual_path);
+                let from_base = spec
+                    .base
+                    .as_deref()
+                    .map_or(false, |base| base.join(rel_path).exists());
+                fs.context.push(FileStatus::Present {
+                    path: rel_path.to_owned(),
+                    from_base,
+                });
+                ok = false;
+            }
         }
 
         if ok {
@@ -540,16 +2222,151 @@ impl Case {
             Err(fs)
         }
     }
-}
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub(crate) struct Output {
-    path: std::path::PathBuf,
-    id: Option<String>,
-    spawn: Spawn,
+    /// Let a [`TestCases::comparator`][crate::TestCases::comparator] decide whether a file
+    /// matches, in place of the usual comparison
+    #[cfg(feature = "filesystem")]
+    fn compare_custom(
+        &self,
+        status: Result<(std::path::PathBuf, std::path::PathBuf), snapbox::path::PathDiff>,
+    ) -> Result<(std::path::PathBuf, std::path::PathBuf), CustomDiff> {
+        let (expected_path, actual_path) = match status {
+            Ok(paths) => paths,
+            Err(snapbox::path::PathDiff::ContentMismatch {
+                expected_path,
+                actual_path,
+                ..
+            }) if self.comparators.get(&expected_path).is_some() => (expected_path, actual_path),
+            Err(diff) => return Err(CustomDiff::Diff(diff)),
+        };
+        let comparator = match self.comparators.get(&expected_path) {
+            Some(comparator) => comparator,
+            None => return Ok((expected_path, actual_path)),
+        };
+        let matches = match (std::fs::read(&expected_path), std::fs::read(&actual_path)) {
+            (Ok(expected), Ok(actual)) => comparator(&expected, &actual),
+            // Directories and the like are left to the usual comparison
+            _ => true,
+        };
+        if matches {
+            Ok((expected_path, actual_path))
+        } else {
+            Err(CustomDiff::Rejected {
+                expected_path,
+                actual_path,
+            })
+        }
+    }
+}
+
+/// Why a file in `*.out/` didn't match, see [`Case::compare_custom`]
+#[cfg(feature = "filesystem")]
+enum CustomDiff {
+    Diff(snapbox::path::PathDiff),
+    Rejected {
+        expected_path: std::path::PathBuf,
+        actual_path: std::path::PathBuf,
+    },
+}
+
+/// Resolve `$ cd <dir>` from `cwd`, not allowing it to leave `root`
+fn change_dir(
+    cwd: Option<&std::path::Path>,
+    root: Option<&std::path::Path>,
+    dir: &std::path::Path,
+) -> Result<std::path::PathBuf, crate::Error> {
+    let (cwd, root) = match (cwd, root) {
+        (Some(cwd), Some(root)) => (cwd, root),
+        _ => return Err(format!("`cd {}`: no working directory", dir.display()).into()),
+    };
+    let mut target = std::path::PathBuf::new();
+    for component in cwd.join(dir).components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                target.pop();
+            }
+            component => target.push(component),
+        }
+    }
+    if !target.starts_with(root) {
+        return Err(format!(
+            "`cd {}`: {} is outside of {}",
+            dir.display(),
+            target.display(),
+            root.display()
+        )
+        .into());
+    }
+    if !target.is_dir() {
+        return Err(format!("`cd {}`: {} doesn't exist", dir.display(), target.display()).into());
+    }
+    Ok(target)
+}
+
+/// Sort the lines of `data` by their bytes, to not depend on it being UTF-8
+fn sorted_lines(data: &crate::Data) -> crate::Data {
+    let bytes = data.to_bytes();
+    let (body, trailing_newline) = match bytes.strip_suffix(b"\n") {
+        Some(body) => (body, true),
+        None => (&bytes[..], false),
+    };
+    let mut lines: Vec<&[u8]> = body.split(|b| *b == b'\n').collect();
+    lines.sort_unstable();
+    let mut sorted = lines.join(&b'\n');
+    if trailing_newline {
+        sorted.push(b'\n');
+    }
+    match data.format() {
+        DataFormat::Text => match String::from_utf8(sorted) {
+            Ok(text) => crate::Data::text(text),
+            Err(e) => crate::Data::binary(e.into_bytes()),
+        },
+        _ => crate::Data::binary(sorted),
+    }
+}
+
+/// Paths under `root` matching the `fs.absent` patterns
+#[cfg(feature = "filesystem")]
+fn absent_violations(root: &std::path::Path, patterns: &[String]) -> Vec<std::path::PathBuf> {
+    let root_pattern = glob::Pattern::escape(&root.display().to_string());
+    let mut present = Vec::new();
+    for pattern in patterns {
+        let pattern = format!("{}/{}", root_pattern, pattern);
+        // Patterns were checked when loading the case
+        for path in glob::glob(&pattern).into_iter().flatten().flatten() {
+            if !present.contains(&path) {
+                present.push(path);
+            }
+        }
+    }
+    present
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Output {
+    path: std::path::PathBuf,
+    id: Option<String>,
+    spawn: Spawn,
     stdout: Option<Stream>,
     stderr: Option<Stream>,
     fs: Filesystem,
+    unsandboxed_cwd: Option<std::path::PathBuf>,
+    matrix_leg: Option<String>,
+    /// Arguments the command ran with, including the bin, see [`crate::schema::Step::argv`]
+    argv: Option<Vec<String>>,
+    /// How long the command took to run
+    duration: std::time::Duration,
+    reproduction: Option<Reproduction>,
+    skip_reason: Option<String>,
+    /// See [`TestCases::seed_env`][crate::TestCases::seed_env]
+    seeds: std::collections::BTreeMap<String, String>,
+    /// See [`TestCases::fail_context_file`][crate::TestCases::fail_context_file]
+    context: Option<FailContext>,
+    /// See [`crate::schema::OneShot::repeat`]
+    repeat: Option<Repeat>,
+    /// [`Repeat::render`] of the command's output, before it was checked
+    rendered: Option<String>,
 }
 
 impl Output {
@@ -564,6 +2381,16 @@ impl Output {
             stdout: None,
             stderr: None,
             fs: Default::default(),
+            unsandboxed_cwd: None,
+            matrix_leg: None,
+            argv: None,
+            duration: std::time::Duration::ZERO,
+            reproduction: None,
+            skip_reason: None,
+            seeds: Default::default(),
+            context: None,
+            repeat: None,
+            rendered: None,
         }
     }
 
@@ -575,6 +2402,16 @@ impl Output {
             stdout: None,
             stderr: None,
             fs: Default::default(),
+            unsandboxed_cwd: None,
+            matrix_leg: None,
+            argv: None,
+            duration: std::time::Duration::ZERO,
+            reproduction: None,
+            skip_reason: None,
+            seeds: Default::default(),
+            context: None,
+            repeat: None,
+            rendered: None,
         }
     }
 
@@ -582,16 +2419,49 @@ impl Output {
         self.spawn.exit = Some(output.status);
         assert_eq!(self.spawn.status, SpawnStatus::Skipped);
         self.spawn.status = SpawnStatus::Ok;
-        self.stdout = Some(Stream {
-            stream: Stdio::Stdout,
-            content: output.stdout.into(),
-            status: StreamStatus::Ok,
-        });
-        self.stderr = Some(Stream {
-            stream: Stdio::Stderr,
-            content: output.stderr.into(),
-            status: StreamStatus::Ok,
-        });
+        self.stdout = Some(Stream::new(Stdio::Stdout, output.stdout.into()));
+        self.stderr = Some(Stream::new(Stdio::Stderr, output.stderr.into()));
+        self
+    }
+
+    /// Replace each of `secrets` in the output with `[REDACTED]`
+    fn redact(mut self, secrets: &[String]) -> Self {
+        if secrets.is_empty() {
+            return self;
+        }
+        for stream in [&mut self.stdout, &mut self.stderr].into_iter().flatten() {
+            let mut content = stream.content.to_bytes();
+            for secret in secrets {
+                content = redact_bytes(&content, secret.as_bytes());
+            }
+            stream.content = content.into();
+        }
+        self
+    }
+
+    /// Replace each of `secrets` in how to reproduce the failure, including the argv, with
+    /// `[REDACTED]`
+    fn redact_reproduction(mut self, secrets: &[String]) -> Self {
+        if let Some(argv) = &mut self.argv {
+            for arg in argv {
+                *arg = redact_text(arg, secrets);
+            }
+        }
+        if let Some(reproduction) = &mut self.reproduction {
+            for value in reproduction.env.add.values_mut() {
+                *value = redact_text(value, secrets);
+            }
+            reproduction.command_line = redact_text(&reproduction.command_line, secrets);
+        }
+        self
+    }
+
+    /// Report `stdout` as the combined output of [`interleaved_output`]
+    fn interleaved(mut self) -> Self {
+        if let Some(stdout) = &mut self.stdout {
+            stdout.stream = Stdio::Interleaved;
+        }
+        self.stderr = None;
         self
     }
 
@@ -600,24 +2470,114 @@ impl Output {
         self
     }
 
+    /// A Rust panic in the output, from its `thread '...' panicked at` line to the end of the
+    /// stream
+    fn panic(&self) -> Option<String> {
+        [&self.stdout, &self.stderr]
+            .into_iter()
+            .flatten()
+            .find_map(|stream| {
+                let text = String::from_utf8_lossy(&stream.content.to_bytes()).into_owned();
+                let start = snapbox::utils::LinesWithTerminator::new(&text)
+                    .scan(0, |offset, line| {
+                        let start = *offset;
+                        *offset += line.len();
+                        Some((start, line))
+                    })
+                    .find(|(_, line)| line.starts_with("thread '") && line.contains(" panicked at"))
+                    .map(|(start, _)| start)?;
+                Some(text[start..].trim_end().to_owned())
+            })
+    }
+
+    fn argv(mut self, argv: Vec<String>) -> Self {
+        self.argv = Some(argv);
+        self
+    }
+
+    fn reproduction(mut self, step: &crate::schema::Step, cwd: Option<&std::path::Path>) -> Self {
+        self.reproduction = Some(Reproduction::new(step, cwd));
+        self
+    }
+
     fn is_ok(&self) -> bool {
         self.spawn.is_ok()
             && self.stdout.as_ref().map(|s| s.is_ok()).unwrap_or(true)
             && self.stderr.as_ref().map(|s| s.is_ok()).unwrap_or(true)
             && self.fs.is_ok()
+            && self.repeat.as_ref().map_or(true, Repeat::is_ok)
+    }
+
+    fn to_result(&self, failed: bool) -> crate::report::CaseResult {
+        let status = if failed {
+            crate::report::CaseStatus::Failed
+        } else if self.spawn.status == SpawnStatus::Skipped {
+            crate::report::CaseStatus::Ignored
+        } else {
+            crate::report::CaseStatus::Passed
+        };
+        let details = (!self.is_ok()).then(|| crate::report::strip_ansi(&self.to_string()));
+        crate::report::CaseResult::new(
+            self.name(),
+            self.path.clone(),
+            self.id.clone(),
+            status,
+            self.mismatches(),
+            details,
+            self.duration,
+        )
+    }
+
+    /// Each part of the output that did not match what was expected
+    fn mismatches(&self) -> Vec<crate::report::Mismatch> {
+        let mut mismatches = Vec::new();
+        if let SpawnStatus::Expected(..) = self.spawn.status {
+            mismatches.push(crate::report::Mismatch::Status);
+        }
+        if self.stdout.as_ref().map(|s| !s.is_ok()).unwrap_or(false) {
+            mismatches.push(crate::report::Mismatch::Stdout);
+        }
+        if self.stderr.as_ref().map(|s| !s.is_ok()).unwrap_or(false) {
+            mismatches.push(crate::report::Mismatch::Stderr);
+        }
+        if !self.fs.is_ok() {
+            mismatches.push(crate::report::Mismatch::Files);
+        }
+        mismatches
     }
 
     fn name(&self) -> String {
-        self.id
-            .as_deref()
-            .map(|id| format!("{}:{}", self.path.display(), id))
-            .unwrap_or_else(|| self.path.display().to_string())
+        let mut name = self.path.display().to_string();
+        if let Some(leg) = &self.matrix_leg {
+            name.push_str(&format!("[{}]", leg));
+        }
+        if let Some(id) = &self.id {
+            name.push_str(&format!(":{}", id));
+        }
+        name
     }
 }
 
 impl std::fmt::Display for Output {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mismatches = self.mismatches();
+        if !mismatches.is_empty() {
+            let palette = snapbox::report::Palette::color();
+            writeln!(
+                f,
+                "Mismatched: {}",
+                palette.error(crate::report::join_mismatches(&mismatches))
+            )?;
+        }
         self.spawn.fmt(f)?;
+        // Reproduction already covers it
+        if let (Some(argv), None) = (&self.argv, &self.reproduction) {
+            let palette = snapbox::report::Palette::color();
+            writeln!(f, "{} {:?}", palette.hint("Argv:"), argv)?;
+        }
+        if let Some(reproduction) = &self.reproduction {
+            reproduction.fmt(f)?;
+        }
         if let Some(stdout) = &self.stdout {
             stdout.fmt(f)?;
         }
@@ -625,11 +2585,353 @@ impl std::fmt::Display for Output {
             stderr.fmt(f)?;
         }
         self.fs.fmt(f)?;
+        if let Some(repeat) = &self.repeat {
+            repeat.fmt(f)?;
+            if repeat.divergence.is_some() && !self.seeds.is_empty() {
+                let palette = snapbox::report::Palette::color();
+                writeln!(
+                    f,
+                    "{}",
+                    palette.warn("Warning: runs differ despite the seed; it may not be honored")
+                )?;
+            }
+        }
+        if let Some(context) = &self.context {
+            context.fmt(f)?;
+        }
+        if !self.seeds.is_empty() {
+            let palette = snapbox::report::Palette::color();
+            let seeds: Vec<_> = self
+                .seeds
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect();
+            writeln!(f, "{} {}", palette.hint("Seeded with"), seeds.join(" "))?;
+        }
+        if let Some(cwd) = &self.unsandboxed_cwd {
+            let palette = snapbox::report::Palette::color();
+            writeln!(
+                f,
+                "{}",
+                palette.warn(format_args!(
+                    "Warning: ran without a sandbox in {}; results may be affected by, and may have modified, the state of that directory",
+                    cwd.display()
+                ))
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Results across the runs of a case with [`crate::schema::OneShot::repeat`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Repeat {
+    runs: usize,
+    /// A later run that failed its expectations when the first passed
+    failed_run: Option<usize>,
+    divergence: Option<Divergence>,
+}
+
+/// The first run whose output differed from the first run's
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Divergence {
+    run: usize,
+    first: String,
+    other: String,
+}
+
+impl Repeat {
+    fn new(runs: usize) -> Self {
+        Self {
+            runs,
+            failed_run: None,
+            divergence: None,
+        }
+    }
+
+    fn is_ok(&self) -> bool {
+        self.failed_run.is_none() && self.divergence.is_none()
+    }
+
+    /// The parts of `output` compared between runs
+    ///
+    /// This is the actual output with only substitutions and redactions applied, so wildcards in
+    /// what's expected can't hide differences between runs.
+    fn render(output: &Output, substitutions: &snapbox::Substitutions) -> String {
+        let mut rendered = String::new();
+        if let Some(exit) = output.spawn.exit {
+            match exit.code() {
+                Some(code) => rendered.push_str(&format!("Exit: {}\n", code)),
+                None => rendered.push_str("Exit: interrupted\n"),
+            }
+        }
+        for stream in [&output.stdout, &output.stderr].into_iter().flatten() {
+            let content = match stream.content.clone().try_coerce(DataFormat::Text).render() {
+                // No pattern to match, so this only substitutes
+                Some(text) => substitutions.normalize(&text, ""),
+                None => stream.content.to_string(),
+            };
+            rendered.push_str(&format!("{}:\n{}\n", stream.stream, content));
+        }
+        rendered
+    }
+}
+
+impl std::fmt::Display for Repeat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let palette = snapbox::report::Palette::color();
+        if let Some(run) = self.failed_run {
+            writeln!(
+                f,
+                "{}",
+                palette.error(format_args!("Failed on run {} of {}", run, self.runs))
+            )?;
+        }
+        if let Some(divergence) = &self.divergence {
+            writeln!(
+                f,
+                "{}",
+                palette.error(format_args!(
+                    "Run {} of {} differs from run 1:",
+                    divergence.run, self.runs
+                ))
+            )?;
+            snapbox::report::write_diff(
+                f,
+                &crate::Data::text(divergence.first.clone()),
+                &crate::Data::text(divergence.other.clone()),
+                Some(&"run 1"),
+                Some(&format_args!("run {}", divergence.run)),
+                palette,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// End of a file the command writes, to help explain a failure
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct FailContext {
+    /// As given, rather than resolved, to keep sandbox paths out of the report
+    path: std::path::PathBuf,
+    lines: usize,
+    tail: ContextTail,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ContextTail {
+    Lines(String),
+    Missing,
+    Failure(crate::Error),
+}
+
+impl FailContext {
+    fn read(
+        path: &std::path::Path,
+        cwd: Option<&std::path::Path>,
+        lines: usize,
+        secrets: &[String],
+    ) -> Self {
+        let resolved = match cwd {
+            Some(cwd) => cwd.join(path),
+            None => path.to_owned(),
+        };
+        let tail = match std::fs::read(&resolved) {
+            Ok(content) => {
+                let content = String::from_utf8_lossy(&content);
+                let all: Vec<_> = content.lines().collect();
+                let tail: Vec<_> = all[all.len().saturating_sub(lines)..]
+                    .iter()
+                    .map(|line| redact_text(line, secrets))
+                    .collect();
+                ContextTail::Lines(tail.join("\n"))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ContextTail::Missing,
+            Err(e) => ContextTail::Failure(e.to_string().into()),
+        };
+        Self {
+            path: path.to_owned(),
+            lines,
+            tail,
+        }
+    }
+}
+
+impl std::fmt::Display for FailContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let palette = snapbox::report::Palette::color();
+        match &self.tail {
+            ContextTail::Lines(lines) => {
+                writeln!(
+                    f,
+                    "context (last {} lines of {}):",
+                    self.lines,
+                    self.path.display()
+                )?;
+                writeln!(f, "{}", palette.info(lines))?;
+            }
+            ContextTail::Missing => {
+                writeln!(
+                    f,
+                    "context: {}",
+                    palette.warn(format_args!("{} does not exist", self.path.display()))
+                )?;
+            }
+            ContextTail::Failure(err) => {
+                writeln!(
+                    f,
+                    "context: {}",
+                    palette.error(format_args!(
+                        "Failed to read {}: {}",
+                        self.path.display(),
+                        err.to_string().trim_end()
+                    ))
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Everything needed to run a failed command by hand
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Reproduction {
+    bin: String,
+    args: Vec<String>,
+    argv: Vec<String>,
+    cwd: Option<std::path::PathBuf>,
+    env: crate::schema::Env,
+    stdin: Stdin,
+    command_line: String,
+    timed_out: Option<TimedOut>,
+}
+
+impl Reproduction {
+    fn new(step: &crate::schema::Step, cwd: Option<&std::path::Path>) -> Self {
+        let bin = match &step.bin {
+            Some(crate::schema::Bin::Path(path)) => path.display().to_string(),
+            Some(crate::schema::Bin::Name(name)) => name.clone(),
+            Some(crate::schema::Bin::Script { shell, .. }) => shell.clone(),
+            Some(crate::schema::Bin::CargoWorkspace { package, bin, .. }) => {
+                bin.clone().unwrap_or_else(|| package.clone())
+            }
+            Some(crate::schema::Bin::Ignore) | Some(crate::schema::Bin::Error(_)) | None => {
+                "<none>".to_owned()
+            }
+        };
+        let stdin = match (&step.stdin_source, &step.stdin) {
+            (Some(path), _) => Stdin::File(path.clone()),
+            (None, Some(data)) => Stdin::Inline(data.to_bytes().len()),
+            (None, None) => Stdin::None,
+        };
+        Self {
+            bin,
+            args: step.args.clone(),
+            argv: step.argv(),
+            cwd: cwd.map(ToOwned::to_owned),
+            env: step.env.clone(),
+            stdin,
+            command_line: step.to_command_line(),
+            timed_out: None,
+        }
+    }
+}
+
+impl std::fmt::Display for Reproduction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let palette = snapbox::report::Palette::color();
+
+        if let Some(timed_out) = &self.timed_out {
+            writeln!(
+                f,
+                "{}",
+                palette.error(format_args!(
+                    "Timed out after {}, having written {} bytes to stdout and {} bytes to stderr",
+                    humantime::format_duration(timed_out.after),
+                    timed_out.stdout_len,
+                    timed_out.stderr_len
+                ))
+            )?;
+        }
+
+        writeln!(f, "{}", palette.hint("Reproduce with:"))?;
+        writeln!(f, "  bin:   {}", self.bin)?;
+        writeln!(
+            f,
+            "  args:  {}",
+            shlex::join(self.args.iter().map(String::as_str))
+        )?;
+        writeln!(f, "  argv:  {:?}", self.argv)?;
+        match &self.cwd {
+            Some(cwd) => writeln!(f, "  cwd:   {}", cwd.display())?,
+            None => writeln!(f, "  cwd:   <current>")?,
+        }
+        let mut env: Vec<_> = self
+            .env
+            .add
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        if !self.env.inherit() && !self.env.inherit_only.is_empty() {
+            env.push(format!(
+                "(only inherited: {})",
+                self.env.inherit_only.join(", ")
+            ));
+        } else if !self.env.inherit() {
+            env.push("(not inherited)".to_owned());
+        }
+        if !self.env.remove.is_empty() {
+            env.push(format!("(removed: {})", self.env.remove.join(", ")));
+        }
+        if env.is_empty() {
+            writeln!(f, "  env:   <inherited>")?;
+        } else {
+            writeln!(f, "  env:   {}", env.join(" "))?;
+        }
+        writeln!(f, "  stdin: {}", self.stdin)?;
+
+        let mut command_line = String::new();
+        if let Some(cwd) = &self.cwd {
+            command_line.push_str(&format!(
+                "cd {} && ",
+                shlex::quote(&cwd.display().to_string())
+            ));
+        }
+        command_line.push_str(&self.command_line);
+        if let Stdin::File(path) = &self.stdin {
+            command_line.push_str(&format!(" < {}", shlex::quote(&path.display().to_string())));
+        }
+        writeln!(f, "  $ {}", command_line)?;
 
         Ok(())
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Stdin {
+    None,
+    File(std::path::PathBuf),
+    Inline(usize),
+}
+
+impl std::fmt::Display for Stdin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "<none>"),
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Inline(len) => write!(f, "<inline, {} bytes>", len),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct TimedOut {
+    after: std::time::Duration,
+    stdout_len: usize,
+    stderr_len: usize,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct Spawn {
     exit: Option<std::process::ExitStatus>,
@@ -673,20 +2975,22 @@ impl std::fmt::Display for Spawn {
             SpawnStatus::Failure(msg) => {
                 writeln!(f, "Failed: {}", palette.error(msg))?;
             }
-            SpawnStatus::Expected(expected) => {
+            SpawnStatus::Expected(expected, origin) => {
                 if let Some(exit) = self.exit {
                     if exit.success() {
                         writeln!(
                             f,
-                            "Expected {}, was {}",
+                            "Expected {} ({}), was {}",
                             palette.info(expected),
+                            origin,
                             palette.error("success")
                         )?;
                     } else {
                         writeln!(
                             f,
-                            "Expected {}, was {}",
+                            "Expected {} ({}), was {}",
                             palette.info(expected),
+                            origin,
                             palette.error(snapbox::cmd::display_exit_status(exit))
                         )?;
                     }
@@ -703,14 +3007,38 @@ pub(crate) enum SpawnStatus {
     Ok,
     Skipped,
     Failure(crate::Error),
-    Expected(String),
+    Expected(String, StatusOrigin),
+}
+
+/// Where a step's expected status came from
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum StatusOrigin {
+    /// Nothing set it, so it is `success`
+    Implicit,
+    /// [`TestCases::default_status`][crate::TestCases::default_status]
+    Default,
+    /// [`TestCases::pass`][crate::TestCases::pass], etc for the case's glob
+    Glob,
+    /// `status` or `expect-timeout` in `*.toml` or `? <status>` in `*.trycmd`
+    Case,
+}
+
+impl std::fmt::Display for StatusOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Implicit => "default".fmt(f),
+            Self::Default => "from `TestCases::default_status`".fmt(f),
+            Self::Glob => "from glob override".fmt(f),
+            Self::Case => "from case file".fmt(f),
+        }
+    }
 }
 
 impl SpawnStatus {
     fn is_ok(&self) -> bool {
         match self {
             Self::Ok | Self::Skipped => true,
-            Self::Failure(_) | Self::Expected(_) => false,
+            Self::Failure(_) | Self::Expected(..) => false,
         }
     }
 
@@ -719,7 +3047,7 @@ impl SpawnStatus {
         match self {
             Self::Ok => palette.info("ok"),
             Self::Skipped => palette.warn("ignored"),
-            Self::Failure(_) | Self::Expected(_) => palette.error("failed"),
+            Self::Failure(_) | Self::Expected(..) => palette.error("failed"),
         }
     }
 }
@@ -729,9 +3057,104 @@ struct Stream {
     stream: Stdio,
     content: crate::Data,
     status: StreamStatus,
+    /// The region of the output that `content` was cut down to
+    anchor: Option<(crate::schema::MatchAnchor, usize)>,
+    /// Bytes of `content` to report, see
+    /// [`TestCases::truncate_output`][crate::TestCases::truncate_output]
+    shown: Option<usize>,
+    /// Whether the expected output has `[if VAR]` blocks, which `TRYCMD=overwrite` can't keep
+    conditional: bool,
 }
 
 impl Stream {
+    fn new(stream: Stdio, content: crate::Data) -> Self {
+        Self {
+            stream,
+            content,
+            status: StreamStatus::Ok,
+            anchor: None,
+            shown: None,
+            conditional: false,
+        }
+    }
+
+    /// The first [`Stream::shown`] bytes of `data` and how many bytes were cut
+    fn truncated(&self, data: &crate::Data) -> (crate::Data, usize) {
+        let limit = match self.shown {
+            Some(limit) => limit,
+            None => return (data.clone(), 0),
+        };
+        match data.render() {
+            Some(text) if limit < text.len() => {
+                let mut end = limit;
+                while !text.is_char_boundary(end) {
+                    end -= 1;
+                }
+                (crate::Data::text(&text[..end]), text.len() - end)
+            }
+            Some(_) => (data.clone(), 0),
+            None => {
+                let bytes = data.to_bytes();
+                if limit < bytes.len() {
+                    (crate::Data::binary(&bytes[..limit]), bytes.len() - limit)
+                } else {
+                    (data.clone(), 0)
+                }
+            }
+        }
+    }
+
+    /// Transcode the output to UTF-8
+    fn decode(mut self, encoding: crate::schema::Encoding) -> Self {
+        self.content = encoding.decode(&self.content);
+        self
+    }
+
+    fn sort_lines(mut self) -> Self {
+        self.content = sorted_lines(&self.content);
+        self
+    }
+
+    /// Keep only as many lines from the start or end of the output as `expected` has
+    fn anchor(mut self, anchor: crate::schema::MatchAnchor, expected: &crate::Data) -> Self {
+        let (actual, expected) = match (self.content.render(), expected.render()) {
+            (Some(actual), Some(expected)) => (actual, expected),
+            _ => {
+                self.status = StreamStatus::Failure("Anchored matches require text content".into());
+                return self;
+            }
+        };
+
+        let count = snapbox::utils::LinesWithTerminator::new(&expected).count();
+        let lines: Vec<_> = snapbox::utils::LinesWithTerminator::new(&actual).collect();
+        let lines = match anchor {
+            crate::schema::MatchAnchor::Full => &lines[..],
+            crate::schema::MatchAnchor::Prefix => &lines[..count.min(lines.len())],
+            crate::schema::MatchAnchor::Suffix => &lines[lines.len().saturating_sub(count)..],
+        };
+        let mut region = lines.concat();
+        if !expected.ends_with('\n') && region.ends_with('\n') {
+            region.pop();
+        }
+
+        self.content = crate::Data::text(region);
+        self.anchor = Some((anchor, count));
+        self
+    }
+
+    /// Name of the stream, including which region of it was compared
+    fn label(&self) -> String {
+        match self.anchor {
+            None | Some((crate::schema::MatchAnchor::Full, _)) => self.stream.to_string(),
+            Some((crate::schema::MatchAnchor::Prefix, count)) => {
+                format!("{} (first {} lines)", self.stream, count)
+            }
+            Some((crate::schema::MatchAnchor::Suffix, count)) => {
+                format!("{} (last {} lines)", self.stream, count)
+            }
+        }
+    }
+
     fn make_text(mut self) -> Self {
         let content = self.content.try_coerce(DataFormat::Text);
         if content.format() != DataFormat::Text {
@@ -751,57 +3174,263 @@ impl Stream {
 impl std::fmt::Display for Stream {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let palette = snapbox::report::Palette::color();
+        let label = self.label();
+        let (content, truncated) = self.truncated(&self.content);
 
         match &self.status {
             StreamStatus::Ok => {
-                writeln!(f, "{}:", self.stream)?;
-                writeln!(f, "{}", palette.info(&self.content))?;
+                writeln!(f, "{}:", label)?;
+                writeln!(f, "{}", palette.info(&content))?;
             }
             StreamStatus::Failure(msg) => {
-                writeln!(
-                    f,
-                    "{} {}:",
-                    self.stream,
-                    palette.error(format_args!("({})", msg))
-                )?;
-                writeln!(f, "{}", palette.info(&self.content))?;
+                writeln!(f, "{} {}:", label, palette.error(format_args!("({})", msg)))?;
+                writeln!(f, "{}", palette.info(&content))?;
             }
             StreamStatus::Expected(expected) => {
+                // Cut both to keep the diff about the part that is shown
+                let (expected, _) = self.truncated(expected);
                 snapbox::report::write_diff(
                     f,
-                    expected,
-                    &self.content,
-                    Some(&self.stream),
-                    Some(&self.stream),
+                    &expected,
+                    &content,
+                    Some(&label),
+                    Some(&label),
                     palette,
                 )?;
             }
-        }
-
-        Ok(())
-    }
-}
+            StreamStatus::LineCount { expected, actual } => {
+                writeln!(
+                    f,
+                    "{} {}:",
+                    label,
+                    palette.error(format_args!(
+                        "(expected {} lines, was {})",
+                        expected, actual
+                    ))
+                )?;
+                writeln!(f, "{}", palette.info(&content))?;
+            }
+            StreamStatus::Unordered {
+                missing,
+                unexpected,
+            } => {
+                writeln!(f, "{} (unordered):", label)?;
+                for line in missing {
+                    writeln!(f, "{}", palette.info(format_args!("missing: {}", line)))?;
+                }
+                for line in unexpected {
+                    writeln!(f, "{}", palette.error(format_args!("unexpected: {}", line)))?;
+                }
+            }
+        }
+        if 0 < truncated {
+            writeln!(
+                f,
+                "{}",
+                palette.hint(format_args!("[... truncated {} bytes]", truncated))
+            )?;
+        }
+
+        Ok(())
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 enum StreamStatus {
     Ok,
     Failure(crate::Error),
     Expected(crate::Data),
+    Unordered {
+        missing: Vec<String>,
+        unexpected: Vec<String>,
+    },
+    LineCount {
+        expected: usize,
+        actual: usize,
+    },
 }
 
 impl StreamStatus {
     fn is_ok(&self) -> bool {
         match self {
             Self::Ok => true,
-            Self::Failure(_) | Self::Expected(_) => false,
+            Self::Failure(_)
+            | Self::Expected(_)
+            | Self::Unordered { .. }
+            | Self::LineCount { .. } => false,
+        }
+    }
+}
+
+/// Resolve `bin`, failing with why it can't be run
+///
+/// Only [`TestCases::bin_fallback`][crate::TestCases::bin_fallback] names are looked up on
+/// `PATH`, so a typo doesn't silently run whatever is installed.
+fn resolve_candidate(
+    bin: crate::schema::Bin,
+    bins: &crate::BinRegistry,
+    search_path: bool,
+) -> Result<crate::schema::Bin, String> {
+    match bins.resolve_bin(bin) {
+        Ok(crate::schema::Bin::Path(path)) if !path.exists() => {
+            Err(format!("{} doesn't exist", path.display()))
+        }
+        Ok(crate::schema::Bin::Name(name)) if search_path => {
+            match crate::registry::find_on_path(&name) {
+                Some(path) => Ok(crate::schema::Bin::Path(path)),
+                None => Err(format!(
+                    "`{}` is not a registered bin, a cargo bin, or on `PATH`",
+                    name
+                )),
+            }
+        }
+        Ok(crate::schema::Bin::Name(name)) => {
+            Err(format!("`{}` is not a registered bin or a cargo bin", name))
+        }
+        Ok(crate::schema::Bin::Error(err)) | Err(err) => Err(err.to_string().trim_end().to_owned()),
+        Ok(bin) => Ok(bin),
+    }
+}
+
+/// Run `step`, piping `stdout` through each command in its pipeline
+///
+/// Only the last command's output is captured, along with whether it was killed for reaching its
+/// timeout.  The earlier commands are killed once the last one exits, and the step fails if one of
+/// them exited with a non-zero code, reporting its `stderr`.
+fn step_output(
+    step: &crate::schema::Step,
+    cwd: Option<&std::path::Path>,
+    configure: &dyn Fn(&mut snapbox::cmd::Command) -> Result<(), crate::Error>,
+) -> Result<(std::process::Output, bool), crate::Error> {
+    let mut children = Vec::new();
+    let output = (|| {
+        let mut cmd = step.to_command(cwd)?;
+        configure(&mut cmd)?;
+        for index in 0..step.pipeline.len() {
+            let mut child = cmd
+                .spawn_piped()
+                .map_err(|e| crate::Error::new(e.to_string()))?;
+            let stdout = child.stdout.take().expect("spawned with piped stdout");
+            let stderr = child.stderr.take().map(|mut stderr| {
+                std::thread::spawn(move || {
+                    let mut buffer = Vec::new();
+                    let _ = stderr.read_to_end(&mut buffer);
+                    buffer
+                })
+            });
+            children.push((child, stderr));
+            cmd = step.piped_command(index, cwd)?.stdin_from(stdout);
+            configure(&mut cmd)?;
+        }
+        if step.interleaved {
+            interleaved_output(cmd)
+        } else {
+            cmd.timed_output()
+        }
+        .map(|output| (output.output, output.timed_out))
+        .map_err(|e| crate::Error::new(e.to_string()))
+    })();
+    let mut failed = None;
+    for (index, (mut child, stderr)) in children.into_iter().enumerate() {
+        let status = match child.try_wait() {
+            Ok(Some(status)) => Some(status),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                None
+            }
+        };
+        let stderr = stderr
+            .and_then(|stderr| stderr.join().ok())
+            .unwrap_or_default();
+        if let Some(status) = status.filter(|s| s.code().map_or(false, |code| code != 0)) {
+            failed.get_or_insert_with(|| {
+                format!(
+                    "Command {} of the pipeline failed with {}:\n{}",
+                    index + 1,
+                    status,
+                    String::from_utf8_lossy(&stderr)
+                )
+            });
+        }
+    }
+    match failed {
+        Some(failed) if output.is_ok() => Err(failed.into()),
+        _ => output,
+    }
+}
+
+/// `text` with each of `secrets` replaced with [`REDACTED`]
+pub(crate) fn redact_text(text: &str, secrets: &[String]) -> String {
+    secrets.iter().fold(text.to_owned(), |text, secret| {
+        text.replace(secret, REDACTED)
+    })
+}
+
+/// `output` with each of `secrets` replaced with [`REDACTED`] in `stdout` and `stderr`
+fn redact_output(output: &std::process::Output, secrets: &[String]) -> std::process::Output {
+    let redact = |bytes: &[u8]| {
+        secrets.iter().fold(bytes.to_vec(), |bytes, secret| {
+            redact_bytes(&bytes, secret.as_bytes())
+        })
+    };
+    std::process::Output {
+        status: output.status,
+        stdout: redact(&output.stdout),
+        stderr: redact(&output.stderr),
+    }
+}
+
+/// `bytes` with each `secret` replaced with [`REDACTED`]
+fn redact_bytes(bytes: &[u8], secret: &[u8]) -> Vec<u8> {
+    let mut redacted = Vec::with_capacity(bytes.len());
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        if rest.starts_with(secret) {
+            redacted.extend_from_slice(REDACTED.as_bytes());
+            rest = &rest[secret.len()..];
+        } else {
+            redacted.push(rest[0]);
+            rest = &rest[1..];
         }
     }
+    redacted
+}
+
+/// Run `cmd`, with its `stdout` and `stderr` lines combined in the order they were read
+///
+/// `stderr` lines are prefixed with `err: ` so the two can be told apart.
+fn interleaved_output(
+    cmd: snapbox::cmd::Command,
+) -> Result<snapbox::cmd::TimedOutput, std::io::Error> {
+    let output = cmd.interleaved_output()?;
+    Ok(snapbox::cmd::TimedOutput {
+        output: std::process::Output {
+            status: output.status,
+            stdout: combine_lines(output.lines),
+            stderr: Vec::new(),
+        },
+        timed_out: output.timed_out,
+    })
+}
+
+fn combine_lines(lines: Vec<(snapbox::cmd::OutputStream, Vec<u8>)>) -> Vec<u8> {
+    let mut combined = Vec::new();
+    for (stream, line) in lines {
+        if stream == snapbox::cmd::OutputStream::Stderr {
+            combined.extend_from_slice(b"err: ");
+        }
+        combined.extend(line);
+    }
+    combined
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 enum Stdio {
     Stdout,
     Stderr,
+    /// `stdout` and `stderr` together, see [`interleaved_output`]
+    Interleaved,
 }
 
 impl Stdio {
@@ -809,6 +3438,7 @@ impl Stdio {
         match self {
             Self::Stdout => "stdout",
             Self::Stderr => "stderr",
+            Self::Interleaved => "interleaved",
         }
     }
 }
@@ -869,6 +3499,20 @@ enum FileStatus {
         expected_content: crate::Data,
         actual_content: crate::Data,
     },
+    /// See [`TestCases::comparator`][crate::TestCases::comparator]
+    #[cfg(feature = "filesystem")]
+    Rejected {
+        expected_path: std::path::PathBuf,
+        actual_path: std::path::PathBuf,
+    },
+    /// Matches `fs.absent`
+    #[cfg(feature = "filesystem")]
+    Present {
+        /// Relative to the sandbox
+        path: std::path::PathBuf,
+        /// Copied from `*.in/` rather than created by the command
+        from_base: bool,
+    },
 }
 
 impl FileStatus {
@@ -879,6 +3523,8 @@ impl FileStatus {
             | Self::TypeMismatch { .. }
             | Self::LinkMismatch { .. }
             | Self::ContentMismatch { .. } => false,
+            #[cfg(feature = "filesystem")]
+            Self::Rejected { .. } | Self::Present { .. } => false,
         }
     }
 }
@@ -986,6 +3632,34 @@ impl std::fmt::Display for FileStatus {
                     palette,
                 )?;
             }
+            #[cfg(feature = "filesystem")]
+            Self::Rejected {
+                expected_path,
+                actual_path,
+            } => {
+                writeln!(
+                    f,
+                    "{}: {} {}",
+                    expected_path.display(),
+                    palette.error("custom comparator rejected"),
+                    actual_path.display()
+                )?;
+            }
+            #[cfg(feature = "filesystem")]
+            Self::Present { path, from_base } => {
+                let origin = if *from_base {
+                    "copied from `*.in/`"
+                } else {
+                    "created by the command"
+                };
+                writeln!(
+                    f,
+                    "{}: {} ({})",
+                    path.display(),
+                    palette.error("expected path to be absent but it exists"),
+                    origin
+                )?;
+            }
         }
 
         Ok(())
@@ -997,6 +3671,10 @@ pub(crate) enum Mode {
     Fail,
     Overwrite,
     Dump(std::path::PathBuf),
+    /// Like `Fail`, also saving what each case did to the directory
+    Record(std::path::PathBuf),
+    /// Like `Fail`, but checks what was saved by `Record` rather than running anything
+    Replay(std::path::PathBuf),
 }
 
 impl Mode {
@@ -1004,6 +3682,10 @@ impl Mode {
         match self {
             Self::Fail => {}
             Self::Overwrite => {}
+            Self::Record(root) => {
+                std::fs::create_dir_all(root)?;
+            }
+            Self::Replay(_) => {}
             Self::Dump(root) => {
                 std::fs::create_dir_all(root)?;
                 let gitignore_path = root.join(".gitignore");
@@ -1015,6 +3697,32 @@ impl Mode {
     }
 }
 
+/// Create the fake home for `isolate-home` in its own temp directory
+///
+/// This is kept out of the sandbox so it isn't part of the files the case is checked against.
+#[cfg_attr(not(feature = "filesystem"), allow(unused_variables))]
+fn isolated_home(
+    seed: Option<&std::path::Path>,
+) -> Result<snapbox::path::PathFixture, crate::Error> {
+    #[cfg(feature = "filesystem")]
+    {
+        let mut home = snapbox::path::PathFixture::mutable_temp()?;
+        if let Some(seed) = seed {
+            home = home
+                .with_template(seed)
+                .map_err(|e| format!("Failed to seed home from {}: {}", seed.display(), e))?;
+        }
+        let path = home.path().expect("temp dirs have a path");
+        for (_, dir) in crate::schema::home_dirs(path) {
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        Ok(home)
+    }
+    #[cfg(not(feature = "filesystem"))]
+    Err("Sandboxing is disabled".into())
+}
+
 #[cfg_attr(not(feature = "filesystem"), allow(unused_variables))]
 fn fs_context(
     path: &std::path::Path,
@@ -1033,7 +3741,9 @@ fn fs_context(
                 }
                 Ok(context)
             }
-            crate::Mode::Fail | crate::Mode::Overwrite => {
+            // Filled in from the recording
+            crate::Mode::Replay(_) => snapbox::path::PathFixture::mutable_temp(),
+            crate::Mode::Fail | crate::Mode::Overwrite | crate::Mode::Record(_) => {
                 let mut context = snapbox::path::PathFixture::mutable_temp()?;
                 if let Some(cwd) = cwd {
                     context = context.with_template(cwd)?;
@@ -1049,3 +3759,1327 @@ fn fs_context(
             .unwrap_or_else(snapbox::path::PathFixture::none))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Directory for a test's files, removed when dropped so a failing test doesn't leave it
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("trycmd-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&root).unwrap();
+            Self(root)
+        }
+    }
+
+    impl std::ops::Deref for TempDir {
+        type Target = std::path::Path;
+
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// The case in the file at `path`, ready to run
+    fn case_at(path: &std::path::Path) -> Case {
+        let mut case = Case::with_error(path.to_owned(), "unused".into());
+        case.error = None;
+        case
+    }
+
+    /// Bins for `.trycmd` files, which run `bin-fixture` by name
+    fn bins() -> crate::BinRegistry {
+        let mut bins = crate::BinRegistry::new();
+        bins.register_bin(
+            "bin-fixture".to_owned(),
+            crate::schema::Bin::Path(crate::cargo::cargo_bin("bin-fixture")),
+        );
+        bins
+    }
+
+    /// What's reported for the steps that failed
+    fn failures(outputs: Vec<Result<Output, Output>>) -> String {
+        outputs
+            .into_iter()
+            .filter_map(Result::err)
+            .map(|o| crate::report::strip_ansi(&o.to_string()))
+            .collect()
+    }
+
+    /// Run `case`, returning what's reported for the steps that failed
+    fn run_case(case: &Case, mode: &Mode) -> String {
+        failures(case.run(
+            mode,
+            &bins(),
+            &snapbox::Substitutions::new(),
+            &crate::progress::Progress::new(0, false),
+        ))
+    }
+
+    #[test]
+    fn failure_digest_short() {
+        let failed = vec!["tests/cmd/a.trycmd".into(), "tests/cmd/b.toml".into()];
+        let actual = failure_digest(2, 412, &failed, std::time::Duration::from_millis(3200));
+        assert_eq!(
+            actual,
+            "2 of 412 cases failed in 3.2s: tests/cmd/a.trycmd, tests/cmd/b.toml"
+        );
+    }
+
+    #[test]
+    fn failure_digest_capped() {
+        let failed: Vec<std::path::PathBuf> =
+            (0..17).map(|i| format!("{}.toml", i).into()).collect();
+        let actual = failure_digest(17, 412, &failed, std::time::Duration::ZERO);
+        assert_eq!(
+            actual,
+            "17 of 412 cases failed in 0.0s: 0.toml, 1.toml, 2.toml, 3.toml, 4.toml, 5.toml, 6.toml, 7.toml, 8.toml, 9.toml, and 7 more"
+        );
+    }
+
+    #[test]
+    fn failure_digest_legs() {
+        let failed = vec!["tests/cmd/matrix.toml".into()];
+        let actual = failure_digest(3, 412, &failed, std::time::Duration::ZERO);
+        assert_eq!(
+            actual,
+            "3 of 412 cases failed in 0.0s: tests/cmd/matrix.toml"
+        );
+    }
+
+    #[test]
+    fn snapshot_updates() {
+        let root = TempDir::new("snapshots");
+        let path = root.join("case.toml");
+        std::fs::write(&path, "bin.name = \"cmd\"").unwrap();
+        std::fs::write(path.with_extension("stderr"), "unchanged").unwrap();
+        std::fs::create_dir_all(path.with_extension("out")).unwrap();
+        std::fs::write(path.with_extension("out").join("file"), "removed").unwrap();
+        let case = Case::with_error(path.clone(), "unused".into());
+
+        let snapshots = Snapshots::capture(&case);
+        std::fs::write(&path, "bin.name = \"cmd\"\nstatus.code = 1").unwrap();
+        std::fs::write(path.with_extension("stdout"), "new").unwrap();
+        std::fs::remove_file(path.with_extension("out").join("file")).unwrap();
+        let actual = snapshots.updates(&case);
+
+        assert_eq!(
+            actual,
+            vec![
+                SnapshotUpdate::Modified(path.clone()),
+                SnapshotUpdate::Created(path.with_extension("stdout")),
+                SnapshotUpdate::Removed(path.with_extension("out").join("file")),
+            ]
+        );
+    }
+
+    fn run_step(case: &Case, mut step: crate::schema::Step) -> String {
+        let output = case
+            .run_step(
+                &mut step,
+                None,
+                &crate::BinRegistry::new(),
+                &snapbox::Substitutions::new(),
+                &crate::progress::Progress::new(0, false),
+                None,
+            )
+            .unwrap_err();
+        crate::report::strip_ansi(&output.to_string())
+    }
+
+    #[test]
+    fn reproduce_missing_bin() {
+        let case = Case::with_error("case.toml".into(), "unused".into());
+        let mut env = crate::schema::Env::default();
+        env.add.insert("FOO".into(), "bar".into());
+        let step = crate::schema::Step {
+            bin: Some(crate::schema::Bin::Path("does-not-exist".into())),
+            args: vec!["hello world".into(), "--flag".into()],
+            env,
+            stdin: Some(crate::Data::text("Hello")),
+            ..Default::default()
+        };
+
+        let actual = run_step(&case, step);
+        assert!(
+            actual.starts_with("Failed: Bin doesn't exist: does-not-exist\n"),
+            "{}",
+            actual
+        );
+        let expected = "
+Reproduce with:
+  bin:   does-not-exist
+  args:  \"hello world\" --flag
+  argv:  [\"does-not-exist\", \"hello world\", \"--flag\"]
+  cwd:   <current>
+  env:   FOO=bar
+  stdin: <inline, 5 bytes>
+  $ FOO=bar does-not-exist \"hello world\" --flag
+";
+        assert!(actual.ends_with(expected), "{}", actual);
+    }
+
+    #[test]
+    fn stdin_file_missing() {
+        let case = Case::with_error("case.toml".into(), "unused".into());
+        let step = crate::schema::Step {
+            bin: Some(crate::schema::Bin::Path("does-not-exist".into())),
+            stdin_file: Some("plan.json".into()),
+            ..Default::default()
+        };
+
+        let actual = run_step(&case, step);
+        assert!(
+            actual.starts_with(
+                "Failed: Can't read `stdin` from plan.json, which doesn't exist (yet)"
+            ),
+            "{}",
+            actual
+        );
+    }
+
+    #[test]
+    fn truncate_output() {
+        let mut stream = Stream::new(Stdio::Stdout, crate::Data::text("Hello world\n"));
+        stream.shown = Some(5);
+        let actual = crate::report::strip_ansi(&stream.to_string());
+        assert_eq!(actual, "stdout:\nHello\n[... truncated 7 bytes]\n");
+
+        // Not on a character boundary
+        stream.content = crate::Data::text("Héllo");
+        stream.shown = Some(2);
+        let actual = crate::report::strip_ansi(&stream.to_string());
+        assert_eq!(actual, "stdout:\nH\n[... truncated 5 bytes]\n");
+
+        stream.shown = Some(100);
+        let actual = crate::report::strip_ansi(&stream.to_string());
+        assert_eq!(actual, "stdout:\nHéllo\n");
+    }
+
+    #[test]
+    fn mismatches_each_dimension() {
+        let mut output = Output::step("case.toml".into(), "1".into());
+        output.spawn.status = SpawnStatus::Expected("success".into(), StatusOrigin::Implicit);
+        output.stdout = Some(Stream::new(Stdio::Stdout, crate::Data::text("Hello")));
+        let mut stderr = Stream::new(Stdio::Stderr, crate::Data::text("Goodbye"));
+        stderr.status = StreamStatus::Expected(crate::Data::text("Hello"));
+        output.stderr = Some(stderr);
+
+        assert_eq!(
+            output.mismatches(),
+            vec![
+                crate::report::Mismatch::Status,
+                crate::report::Mismatch::Stderr
+            ]
+        );
+        let actual = crate::report::strip_ansi(&output.to_string());
+        assert!(
+            actual.starts_with("Mismatched: status, stderr\n"),
+            "{}",
+            actual
+        );
+    }
+
+    #[test]
+    fn match_anchor() {
+        use crate::schema::MatchAnchor;
+
+        let actual = crate::Data::text("Compiling\nBuilding\nFinished\n");
+        let prefix = Stream::new(Stdio::Stdout, actual.clone())
+            .anchor(MatchAnchor::Prefix, &crate::Data::text("Compiling\n"));
+        assert_eq!(prefix.content, crate::Data::text("Compiling\n"));
+        assert_eq!(prefix.label(), "stdout (first 1 lines)");
+
+        let suffix = Stream::new(Stdio::Stdout, actual.clone()).anchor(
+            MatchAnchor::Suffix,
+            &crate::Data::text("Building\nFinished"),
+        );
+        assert_eq!(suffix.content, crate::Data::text("Building\nFinished"));
+        assert_eq!(suffix.label(), "stdout (last 2 lines)");
+
+        let short = Stream::new(Stdio::Stdout, crate::Data::text("Finished\n"))
+            .anchor(MatchAnchor::Suffix, &actual);
+        assert_eq!(short.content, crate::Data::text("Finished\n"));
+    }
+
+    #[test]
+    fn abort_after() {
+        let mut runner = Runner::new();
+        for name in ["a.toml", "b.toml", "c.toml"] {
+            runner.case(Case::with_error(name.into(), "broken".into()));
+        }
+        // One at a time, so which cases run is deterministic
+        runner.stages(vec![0, 1, 2]);
+        runner.abort_after(Some(1));
+        let root = TempDir::new("abort-after");
+        let report = root.join("report.xml");
+        runner.output_format(crate::OutputFormat::Junit(report.clone()));
+
+        let message = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            runner.run(
+                &Mode::Fail,
+                None,
+                &crate::BinRegistry::new(),
+                &snapbox::Substitutions::new(),
+            )
+        }))
+        .unwrap_err()
+        .downcast::<String>()
+        .unwrap();
+        assert!(
+            message.starts_with("1 of 3 cases failed in ") && message.ends_with("s: a.toml"),
+            "{}",
+            message
+        );
+        let report = std::fs::read_to_string(&report).unwrap();
+        assert!(
+            report.contains(r#"tests="3" failures="1" skipped="2""#),
+            "{}",
+            report
+        );
+    }
+
+    #[test]
+    fn no_new_snapshots() {
+        let root = TempDir::new("no-new");
+        let path = root.join("case.toml");
+        std::fs::write(
+            &path,
+            format!(
+                "bin.path = '{}'\nstdout = \"\"\n[env.add]\nstdout = \"Hello\"\n",
+                crate::cargo::cargo_bin("bin-fixture").display()
+            ),
+        )
+        .unwrap();
+        let case = case_at(&path);
+        let mut runner = Runner::new();
+        runner.case(case);
+        runner.no_new_snapshots(true);
+
+        let fail = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            runner.run(
+                &Mode::Fail,
+                None,
+                &crate::BinRegistry::new(),
+                &snapbox::Substitutions::new(),
+            )
+        }));
+        let overwrite = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            runner.run(
+                &Mode::Overwrite,
+                None,
+                &crate::BinRegistry::new(),
+                &snapbox::Substitutions::new(),
+            )
+        }));
+        let rerun = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            runner.run(
+                &Mode::Overwrite,
+                None,
+                &crate::BinRegistry::new(),
+                &snapbox::Substitutions::new(),
+            )
+        }));
+
+        assert!(fail.is_err());
+        let message = overwrite.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(
+            *message,
+            format!(
+                "1 snapshots were written and need to be reviewed:\n  modified {}",
+                path.display()
+            )
+        );
+        assert!(rerun.is_ok());
+    }
+
+    #[test]
+    fn seeds_on_failure() {
+        let mut output = Output::step("case.toml".into(), "1".into());
+        output.spawn.status = SpawnStatus::Expected("success".into(), StatusOrigin::Implicit);
+        output.seeds.insert("SEED".into(), "42".into());
+
+        let actual = crate::report::strip_ansi(&output.to_string());
+        assert!(actual.ends_with("Seeded with SEED=42\n"), "{}", actual);
+    }
+
+    #[test]
+    fn seed_not_honored() {
+        let mut output = Output::step("case.toml".into(), "1".into());
+        output.repeat = Some(Repeat {
+            divergence: Some(Divergence {
+                run: 2,
+                first: "stdout:\n1\n".into(),
+                other: "stdout:\n2\n".into(),
+            }),
+            ..Repeat::new(2)
+        });
+        let unseeded = crate::report::strip_ansi(&output.to_string());
+        output.seeds.insert("SEED".into(), "42".into());
+        let seeded = crate::report::strip_ansi(&output.to_string());
+
+        let warning = "Warning: runs differ despite the seed; it may not be honored\n";
+        assert!(!unseeded.contains(warning), "{}", unseeded);
+        assert!(
+            seeded.ends_with(&format!("{}Seeded with SEED=42\n", warning)),
+            "{}",
+            seeded
+        );
+    }
+
+    #[test]
+    fn dump_stream() {
+        let root = TempDir::new("dump-stream");
+        let case = Case::with_error("tests/cmd/case.toml".into(), "unused".into());
+        let stream = Stream::new(Stdio::Stdout, crate::Data::text("Hello\n"));
+        case.dump_stream(&root, None, Some(stream.clone())).unwrap();
+        case.dump_stream(&root, Some("2"), Some(stream)).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(root.join("case.stdout")).unwrap(),
+            "Hello\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("case-2.stdout")).unwrap(),
+            "Hello\n"
+        );
+    }
+
+    #[test]
+    fn status_origin() {
+        use crate::schema::CommandStatus;
+
+        let mut env = crate::schema::Env::default();
+        env.add.insert("exit".into(), "1".into());
+        let step = crate::schema::Step {
+            bin: Some(crate::schema::Bin::Path(crate::cargo::cargo_bin(
+                "bin-fixture",
+            ))),
+            env,
+            expected_status: Some(CommandStatus::Success),
+            ..Default::default()
+        };
+        let mut case = Case::with_error("case.toml".into(), "unused".into());
+        case.default_status = Some(CommandStatus::Failed);
+        let from_case = run_step(&case, step.clone());
+        case.expected = Some(CommandStatus::Success);
+        let from_glob = run_step(
+            &case,
+            crate::schema::Step {
+                expected_status: None,
+                ..step
+            },
+        );
+
+        assert!(
+            from_case.contains("Expected success (from case file), was 1\n"),
+            "{}",
+            from_case
+        );
+        assert!(
+            from_glob.contains("Expected success (from glob override), was 1\n"),
+            "{}",
+            from_glob
+        );
+    }
+
+    #[test]
+    fn combine_lines() {
+        // The order across streams depends on thread timing, so it's only checked here
+        let lines = vec![
+            (snapbox::cmd::OutputStream::Stdout, b"one\n".to_vec()),
+            (snapbox::cmd::OutputStream::Stderr, b"two\n".to_vec()),
+            (snapbox::cmd::OutputStream::Stdout, b"three\n".to_vec()),
+        ];
+        assert_eq!(super::combine_lines(lines), b"one\nerr: two\nthree\n");
+    }
+
+    #[test]
+    fn resolve_same_as() {
+        let case = Case::with_error("b.toml".into(), "unused".into());
+        let mut sequence = crate::schema::TryCmd {
+            steps: vec![crate::schema::Step {
+                expected_stdout_same_as: Some("a.toml".into()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let missing = case.resolve_same_as(&mut sequence.clone()).unwrap_err();
+        case.captured.insert(
+            std::path::Path::new("a.toml"),
+            None,
+            CapturedOutput {
+                stdout: Some(crate::Data::text("Hello\n")),
+                stderr: None,
+            },
+        );
+        case.resolve_same_as(&mut sequence).unwrap();
+
+        assert!(
+            missing
+                .to_string()
+                .starts_with("No output from a.toml to compare against"),
+            "{}",
+            missing
+        );
+        assert_eq!(
+            sequence.steps[0].expected_stdout,
+            Some(crate::Data::text("Hello\n"))
+        );
+    }
+
+    #[test]
+    fn expect_timeout() {
+        let step = crate::schema::Step {
+            bin: Some(crate::schema::Bin::Path(crate::cargo::cargo_bin(
+                "bin-fixture",
+            ))),
+            expect_timeout: true,
+            ..Default::default()
+        };
+        let case = Case::with_error("case.toml".into(), "unused".into());
+        let exited = run_step(
+            &case,
+            crate::schema::Step {
+                timeout: Some(std::time::Duration::from_secs(10)),
+                ..step.clone()
+            },
+        );
+        let no_timeout = run_step(&case, step);
+
+        assert!(
+            exited.contains("Expected timeout (from case file), was success"),
+            "{}",
+            exited
+        );
+        assert!(
+            no_timeout.contains("`expect-timeout` needs a `timeout`"),
+            "{}",
+            no_timeout
+        );
+    }
+
+    #[test]
+    fn pipeline_stage_failure() {
+        let fixture = crate::cargo::cargo_bin("bin-fixture");
+        let env = |vars: &[(&str, &str)]| crate::schema::Env {
+            add: vars
+                .iter()
+                .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+                .collect(),
+            ..Default::default()
+        };
+        let mut step = crate::schema::Step {
+            bin: Some(crate::schema::Bin::Path(fixture.clone())),
+            env: env(&[("exit", "3"), ("stderr", "oops")]),
+            pipeline: vec![crate::schema::Piped {
+                bin: crate::schema::Bin::Path(fixture),
+                args: Vec::new(),
+                env: env(&[("echo_stdin", "1")]),
+                unset: vec!["exit".to_owned(), "stderr".to_owned()],
+            }],
+            ..Default::default()
+        };
+        let err = step_output(&step, None, &|_| Ok(())).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Command 1 of the pipeline failed with exit status: 3:\noops"),
+            "{}",
+            err
+        );
+
+        step.env = env(&[("stdout", "Hello")]);
+        step.pipeline[0].unset = vec!["stdout".to_owned()];
+        let (output, timed_out) = step_output(&step, None, &|_| Ok(())).unwrap();
+        assert_eq!(output.stdout, b"Hello\n");
+        assert!(!timed_out);
+    }
+
+    #[test]
+    fn env_secret_on_failure() {
+        let root = TempDir::new("secret");
+        let path = root.join("case.trycmd");
+        std::fs::write(&path, "```\n$ bin-fixture --token hunter2\n? 1\n```\n").unwrap();
+        let mut case = case_at(&path);
+        case.env
+            .add
+            .insert("stdout".to_owned(), "token hunter2".to_owned());
+        case.secrets = vec!["hunter2".to_owned()];
+        let errors = run_case(&case, &Mode::Fail);
+
+        assert!(!errors.contains("hunter2"), "{}", errors);
+        assert!(errors.contains("token [REDACTED]"), "{}", errors);
+        assert!(errors.contains(r#""--token", "[REDACTED]""#), "{}", errors);
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn env_secret_not_recorded() {
+        let root = TempDir::new("secret-recorded");
+        let recordings = root.join("recorded");
+        let path = root.join("case.toml");
+        std::fs::write(
+            &path,
+            "bin.name = 'bin-fixture'\nargs = ['--token', 'hunter2']\nstdout = \"token [REDACTED]\\n\"\nstderr = \"oops [REDACTED]\\n\"\n",
+        )
+        .unwrap();
+        let mut case = case_at(&path);
+        case.env
+            .add
+            .insert("stdout".to_owned(), "token hunter2".to_owned());
+        case.env
+            .add
+            .insert("stderr".to_owned(), "oops hunter2".to_owned());
+        case.secrets = vec!["hunter2".to_owned()];
+        let recorded = run_case(&case, &Mode::Record(recordings.clone()));
+        let replayed = run_case(&case, &Mode::Replay(recordings.clone()));
+
+        let mut pending = vec![recordings];
+        let mut saved = String::new();
+        while let Some(dir) = pending.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else {
+                    saved.push_str(&std::fs::read_to_string(path).unwrap());
+                }
+            }
+        }
+        assert_eq!(recorded, "");
+        assert_eq!(replayed, "");
+        assert!(saved.contains("[REDACTED]"), "{}", saved);
+        assert!(!saved.contains("hunter2"), "{}", saved);
+    }
+
+    #[test]
+    fn argv_on_failure() {
+        let root = TempDir::new("argv");
+        let path = root.join("case.trycmd");
+        std::fs::write(&path, "```\n$ bin-fixture build --name \"a b\"\n? 1\n```\n").unwrap();
+        let case = case_at(&path);
+        let errors = run_case(&case, &Mode::Fail);
+        let explained = case.explain(&bins());
+
+        let argv = format!(
+            "[{:?}, \"build\", \"--name\", \"a b\"]",
+            crate::cargo::cargo_bin("bin-fixture").display().to_string()
+        );
+        assert!(errors.contains(&format!("Argv: {}", argv)), "{}", errors);
+        assert_eq!(explained.len(), 1);
+        assert!(
+            explained[0].ends_with(&format!(": {}", argv)),
+            "{:?}",
+            explained
+        );
+    }
+
+    #[test]
+    fn configure_command_panics() {
+        let mut case = Case::with_error("case.toml".into(), "unused".into());
+        case.configure_command = Some(CommandHook::new(|_, _| std::panic!("no namespace")));
+        let step = crate::schema::Step {
+            bin: Some(crate::schema::Bin::Path(crate::cargo::cargo_bin(
+                "bin-fixture",
+            ))),
+            ..Default::default()
+        };
+
+        let actual = run_step(&case, step);
+        assert!(
+            actual.contains("`configure_command` panicked: no namespace"),
+            "{}",
+            actual
+        );
+    }
+
+    #[test]
+    fn verify_commands() {
+        let root = TempDir::new("verify");
+        let path = root.join("README.md");
+        std::fs::write(
+            &path,
+            "```console,ignore\n$ mytool deploy\n$ cd somewhere\n$ cargo build\n```\n",
+        )
+        .unwrap();
+        let mut case = case_at(&path);
+        case.verify_commands = true;
+        let unknown = run_case(&case, &Mode::Fail);
+        case.allowed_commands = Some(vec!["mytool".to_owned()]);
+        case.default_bins = vec![(
+            "default_bin_name",
+            crate::schema::Bin::Name("mytool".to_owned()),
+        )];
+        let disallowed = run_case(&case, &Mode::Fail);
+
+        assert!(
+            unknown.contains(&format!(
+                "Failed: {}:2: `mytool` is not a registered bin or on `PATH`",
+                path.display()
+            )),
+            "{}",
+            unknown
+        );
+        // `cd` is built in and `cargo` is on `PATH`
+        assert!(!unknown.contains("`cd`"), "{}", unknown);
+        assert!(!unknown.contains("`cargo`"), "{}", unknown);
+        assert!(
+            disallowed.contains(&format!(
+                "{}:4: `cargo` is not one of the allowed commands",
+                path.display()
+            )),
+            "{}",
+            disallowed
+        );
+    }
+
+    #[test]
+    fn bin_fallback() {
+        let mut case = Case::with_error("case.toml".into(), "unused".into());
+        case.default_bins = vec![
+            (
+                "default_bin_path",
+                crate::schema::Bin::Path("does-not-exist".into()),
+            ),
+            (
+                "default_bin_name",
+                crate::schema::Bin::Name("does-not-exist".into()),
+            ),
+            (
+                "bin_fallback",
+                crate::schema::Bin::Path(crate::cargo::cargo_bin("bin-fixture")),
+            ),
+        ];
+        let bins = crate::BinRegistry::new();
+
+        let mut step = crate::schema::Step::default();
+        case.resolve_bin(&mut step, &bins).unwrap();
+        assert_eq!(
+            step.bin,
+            Some(crate::schema::Bin::Path(crate::cargo::cargo_bin(
+                "bin-fixture"
+            )))
+        );
+
+        case.default_bins.pop();
+        let mut step = crate::schema::Step::default();
+        let err = case.resolve_bin(&mut step, &bins).unwrap_err();
+        assert_eq!(
+            err,
+            crate::Error::from(
+                "No bin found, tried:
+  `default_bin_path`: does-not-exist doesn't exist
+  `default_bin_name`: `does-not-exist` is not a registered bin or a cargo bin"
+            )
+        );
+
+        // The case's own bin isn't a fallback
+        let mut step = crate::schema::Step {
+            bin: Some(crate::schema::Bin::Path("mine".into())),
+            ..Default::default()
+        };
+        case.resolve_bin(&mut step, &bins).unwrap();
+        assert_eq!(step.bin, Some(crate::schema::Bin::Path("mine".into())));
+    }
+
+    #[test]
+    fn default_args() {
+        let root = TempDir::new("default-args");
+        let defaulted = root.join("defaulted.toml");
+        std::fs::write(&defaulted, "args = [\"build\"]\n").unwrap();
+        let overridden = root.join("overridden.toml");
+        std::fs::write(
+            &overridden,
+            "args-override = [\"--verbose\"]\nargs = [\"build\"]\n",
+        )
+        .unwrap();
+        let explain = |path: &std::path::Path| {
+            let mut case = case_at(path);
+            case.default_bins = vec![(
+                "default_bin_path",
+                crate::schema::Bin::Path("my-cmd".into()),
+            )];
+            case.default_args = Some(vec!["--color=never".into()]);
+            case.explain(&crate::BinRegistry::new())
+        };
+        let defaulted = explain(&defaulted);
+        let overridden = explain(&overridden);
+
+        assert!(
+            defaulted[0].ends_with(r#"["my-cmd", "--color=never", "build"]"#),
+            "{:?}",
+            defaulted
+        );
+        assert!(
+            overridden[0].ends_with(r#"["my-cmd", "--verbose", "build"]"#),
+            "{:?}",
+            overridden
+        );
+    }
+
+    #[test]
+    fn disable_substitutions() {
+        let root = TempDir::new("disable-substitutions");
+        let cwd = std::env::current_dir().unwrap();
+        let path = root.join("case.toml");
+        let expected_path = root.join("case.stderr");
+        let run = |disable: &str| {
+            std::fs::write(
+                &path,
+                format!(
+                    "bin.path = '{}'\nsubstitutions.disable = [{}]\n[env.add]\necho_cwd = \"1\"\n",
+                    crate::cargo::cargo_bin("bin-fixture").display(),
+                    disable
+                ),
+            )
+            .unwrap();
+            std::fs::write(&expected_path, "").unwrap();
+            let case = case_at(&path);
+            let mut substitutions = snapbox::Substitutions::new();
+            substitutions
+                .insert("[CWD]", cwd.display().to_string())
+                .unwrap();
+            let errors = failures(case.run(
+                &Mode::Overwrite,
+                &bins(),
+                &substitutions,
+                &crate::progress::Progress::new(0, false),
+            ));
+            (errors, std::fs::read_to_string(&expected_path).unwrap())
+        };
+        let substituted = run("");
+        let disabled = run("'[CWD]'");
+        let unknown = run("'[UNKNOWN]'");
+
+        assert_eq!(substituted.1, "[CWD]\n");
+        assert_eq!(disabled.1, format!("{}\n", cwd.display()));
+        assert!(
+            unknown
+                .0
+                .contains("`substitutions.disable` names unknown variable `[UNKNOWN]`"),
+            "{}",
+            unknown.0
+        );
+    }
+
+    #[test]
+    fn ignore_block_overwrite() {
+        let root = TempDir::new("ignore-block");
+        let path = root.join("case.trycmd");
+        std::fs::write(
+            &path,
+            "```
+$ interleave='out:Begin,out:frame 1,out:frame 2,out:Done' bin-fixture
+Start
+# [IGNORE START]
+old trace
+# [IGNORE END]
+Done
+
+```
+",
+        )
+        .unwrap();
+        let case = case_at(&path);
+        run_case(&case, &Mode::Overwrite);
+        let actual = std::fs::read_to_string(&path).unwrap();
+
+        let expected = "```
+$ interleave='out:Begin,out:frame 1,out:frame 2,out:Done' bin-fixture
+Begin
+# [IGNORE START]
+frame 1
+frame 2
+# [IGNORE END]
+Done
+
+```
+";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn conditional_overwrite() {
+        let root = TempDir::new("conditional");
+        let path = root.join("case.trycmd");
+        let original = "```
+$ stdout=changed bin-fixture
+Start
+[if TRYCMD_UNSET_VAR]
+debug
+[endif]
+
+```
+";
+        std::fs::write(&path, original).unwrap();
+        let case = case_at(&path);
+        let outputs = case.run(
+            &Mode::Overwrite,
+            &bins(),
+            &snapbox::Substitutions::new(),
+            &crate::progress::Progress::new(0, false),
+        );
+        let actual = std::fs::read_to_string(&path).unwrap();
+
+        assert_eq!(actual, original);
+        let output = outputs[0].as_ref().unwrap_err();
+        let message = crate::report::strip_ansi(&output.to_string());
+        assert!(
+            message.contains(
+                "Failed to overwrite: expected stdout has `[if ...]` blocks, update it by hand"
+            ),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn mode_handler() {
+        struct Handler {
+            run: bool,
+            fail: bool,
+            seen: std::sync::Mutex<Vec<crate::report::CaseStatus>>,
+        }
+
+        impl crate::ModeHandler for Handler {
+            fn run_case(
+                &self,
+                _path: &std::path::Path,
+                run: &mut dyn FnMut() -> Vec<crate::report::CaseResult>,
+            ) -> Result<(), crate::Error> {
+                if self.run {
+                    let results = run();
+                    self.seen
+                        .lock()
+                        .unwrap()
+                        .extend(results.iter().map(|r| r.status()));
+                }
+                if self.fail {
+                    Err("upload failed".into())
+                } else {
+                    Ok(())
+                }
+            }
+        }
+
+        let root = TempDir::new("mode-handler");
+        let path = root.join("case.toml");
+        std::fs::write(
+            &path,
+            format!(
+                "bin.path = '{}'\n",
+                crate::cargo::cargo_bin("bin-fixture").display()
+            ),
+        )
+        .unwrap();
+        let case = case_at(&path);
+        let run = |handler: &Handler| {
+            case.run_with(
+                handler,
+                &Mode::Fail,
+                &crate::BinRegistry::new(),
+                &snapbox::Substitutions::new(),
+                &crate::progress::Progress::new(0, false),
+            )
+        };
+
+        let observed = Handler {
+            run: true,
+            fail: false,
+            seen: Default::default(),
+        };
+        let outputs = run(&observed);
+        assert!(outputs.iter().all(Result::is_ok));
+        assert_eq!(
+            *observed.seen.lock().unwrap(),
+            [crate::report::CaseStatus::Passed]
+        );
+
+        let skipped = Handler {
+            run: false,
+            fail: false,
+            seen: Default::default(),
+        };
+        let outputs = run(&skipped);
+        assert!(matches!(
+            &outputs[..],
+            [Ok(output)] if output.spawn.status == SpawnStatus::Skipped
+        ));
+
+        let failed = Handler {
+            run: true,
+            fail: true,
+            seen: Default::default(),
+        };
+        let report = failures(run(&failed));
+        assert!(report.contains("Failed: upload failed"), "{}", report);
+    }
+
+    #[test]
+    #[cfg(feature = "filesystem")]
+    fn record_replay() {
+        let root = TempDir::new("record");
+        let recordings = root.join("recorded");
+        std::fs::create_dir_all(root.join("case.out")).unwrap();
+        std::fs::write(root.join("case.out/written.txt"), "content").unwrap();
+        let bin = crate::cargo::cargo_bin("bin-fixture");
+        let path = root.join("case.toml");
+        let write_case = |stdout: &str, args: &str| {
+            std::fs::write(
+                &path,
+                format!(
+                    "bin.path = '{}'\nargs = [{}]\nstdout = \"{}\"\nfs.sandbox = true\n[env.add]\nstdout = \"Hello\"\nwrite = \"written.txt=content\"\n",
+                    bin.display(),
+                    args,
+                    stdout
+                ),
+            )
+            .unwrap();
+        };
+        let run_with_env = |mode: Mode, env: crate::schema::Env| {
+            let mut case = case_at(&path);
+            case.env = env;
+            run_case(&case, &mode)
+        };
+        let run = |mode: Mode| run_with_env(mode, Default::default());
+
+        write_case("Hello\\n", "");
+        let missing = run(Mode::Replay(recordings.clone()));
+        let recorded = run(Mode::Record(recordings.clone()));
+        let replayed = run(Mode::Replay(recordings.clone()));
+        write_case("Goodbye\\n", "");
+        let mismatched = run(Mode::Replay(recordings.clone()));
+        write_case("Hello\\n", "'--flag'");
+        let changed = run(Mode::Replay(recordings.clone()));
+        write_case("Hello\\n", "");
+        let mut env = crate::schema::Env::default();
+        env.add.insert("HARNESS".into(), "1".into());
+        let harness_changed = run_with_env(Mode::Replay(recordings.clone()), env);
+        std::fs::remove_dir_all(root.join("case.out")).unwrap();
+        std::fs::write(
+            &path,
+            format!(
+                "bin.path = '{}'\ntimeout = '100ms'\nexpect-timeout = true\n[env.add]\nsleep = \"10\"\n",
+                bin.display(),
+            ),
+        )
+        .unwrap();
+        let timeout_recorded = run(Mode::Record(recordings.clone()));
+        let timeout_replayed = run(Mode::Replay(recordings.clone()));
+
+        assert!(missing.contains("No recording at"), "{}", missing);
+        assert_eq!(recorded, "");
+        assert_eq!(replayed, "");
+        assert!(mismatched.contains("Goodbye"), "{}", mismatched);
+        assert!(changed.contains("Step changed since"), "{}", changed);
+        assert!(
+            harness_changed.contains("Step changed since"),
+            "{}",
+            harness_changed
+        );
+        assert_eq!(timeout_recorded, "");
+        assert_eq!(timeout_replayed, "");
+    }
+
+    #[test]
+    fn fail_context() {
+        let logged = FailContext {
+            path: "app.log".into(),
+            lines: 2,
+            tail: ContextTail::Lines("two\nthree".to_owned()),
+        };
+        let silent = FailContext {
+            tail: ContextTail::Missing,
+            ..logged.clone()
+        };
+
+        assert_eq!(
+            crate::report::strip_ansi(&logged.to_string()),
+            "context (last 2 lines of app.log):\ntwo\nthree\n"
+        );
+        assert_eq!(
+            crate::report::strip_ansi(&silent.to_string()),
+            "context: app.log does not exist\n"
+        );
+    }
+
+    #[test]
+    fn fail_context_redacted() {
+        let root = TempDir::new("fail-context-redacted");
+        std::fs::write(root.join("app.log"), "one\ntoken hunter2\nthree\n").unwrap();
+        let context = FailContext::read(
+            std::path::Path::new("app.log"),
+            Some(&root),
+            2,
+            &["hunter2".to_owned()],
+        );
+        assert_eq!(
+            context.tail,
+            ContextTail::Lines("token [REDACTED]\nthree".to_owned())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "filesystem")]
+    fn repeat() {
+        let root = TempDir::new("repeat");
+        let path = root.join("case.trycmd");
+        // The first run leaves `state.txt` behind for the second to read
+        std::fs::write(
+            &path,
+            format!(
+                "---\ncwd = '{}'\nrepeat = 3\n---\n\n```\n$ cat=state.txt bin-fixture\n? failed\n...\n$ write=state.txt=ready bin-fixture\n```\n",
+                root.display()
+            ),
+        )
+        .unwrap();
+        let case = case_at(&path);
+        let report = run_case(&case, &Mode::Fail);
+        assert!(
+            report.contains("Run 2 of 3 differs from run 1:"),
+            "{}",
+            report
+        );
+        assert!(report.contains("+ Exit: 0"), "{}", report);
+    }
+
+    #[test]
+    #[cfg(feature = "filesystem")]
+    fn repeat_ignores_wildcards() {
+        let root = TempDir::new("repeat-wildcards");
+        std::fs::write(root.join("state.txt"), "one").unwrap();
+        let path = root.join("case.trycmd");
+        // Each run sees what the one before it wrote, which the expected output hides
+        std::fs::write(
+            &path,
+            format!(
+                "---\ncwd = '{}'\nrepeat = 2\n---\n\n```\n$ cat=state.txt bin-fixture\n[..]\n\n$ write=state.txt=two bin-fixture\n```\n",
+                root.display()
+            ),
+        )
+        .unwrap();
+        let case = case_at(&path);
+        let report = run_case(&case, &Mode::Fail);
+        assert!(
+            report.contains("Run 2 of 2 differs from run 1:"),
+            "{}",
+            report
+        );
+        assert!(report.contains("+ two"), "{}", report);
+    }
+
+    #[test]
+    fn max_output_bytes() {
+        let mut case = Case::with_error("case.toml".into(), "unused".into());
+        case.max_output_bytes = Some(1024);
+        let mut env = crate::schema::Env::default();
+        env.add.insert("echo_large".into(), "1".into());
+        let step = crate::schema::Step {
+            bin: Some(crate::schema::Bin::Path(crate::cargo::cargo_bin(
+                "bin-fixture",
+            ))),
+            env,
+            ..Default::default()
+        };
+
+        let actual = run_step(&case, step);
+        assert!(
+            actual.contains("Failed: stdout exceeded the limit of 1024 bytes"),
+            "{}",
+            actual
+        );
+    }
+
+    #[test]
+    fn abort_on_panic() {
+        let mut case = Case::with_error("case.toml".into(), "unused".into());
+        case.abort_on_panic = true;
+        let mut env = crate::schema::Env::default();
+        env.add.insert("exit".into(), "panic".into());
+        let step = crate::schema::Step {
+            bin: Some(crate::schema::Bin::Path(crate::cargo::cargo_bin(
+                "bin-fixture",
+            ))),
+            env,
+            expected_status: Some(crate::schema::CommandStatus::Failed),
+            ..Default::default()
+        };
+
+        let actual = run_step(&case, step);
+        assert!(
+            actual.contains("Failed: Child process panicked:\nthread 'main'"),
+            "{}",
+            actual
+        );
+        assert!(actual.contains("Panic requested"), "{}", actual);
+    }
+
+    #[test]
+    fn reproduce_timeout() {
+        let case = Case::with_error("case.toml".into(), "unused".into());
+        let mut env = crate::schema::Env::default();
+        env.add.insert("sleep".into(), "30".into());
+        env.add.insert("stdout".into(), "started".into());
+        let step = crate::schema::Step {
+            bin: Some(crate::schema::Bin::Path(crate::cargo::cargo_bin(
+                "bin-fixture",
+            ))),
+            env,
+            stdin_source: Some("case.stdin".into()),
+            stdin: Some(crate::Data::text("")),
+            // Reported by `snapbox`, so this doesn't depend on how long the command ran
+            timeout: Some(std::time::Duration::from_millis(10)),
+            ..Default::default()
+        };
+
+        let actual = run_step(&case, step);
+        assert!(
+            actual.contains("Timed out after 10ms, having written "),
+            "{}",
+            actual
+        );
+        assert!(
+            actual.contains("  env:   sleep=30 stdout=started\n"),
+            "{}",
+            actual
+        );
+        assert!(actual.contains("  stdin: case.stdin\n"), "{}", actual);
+        assert!(actual.contains("bin-fixture < case.stdin\n"), "{}", actual);
+    }
+
+    #[test]
+    #[cfg(feature = "filesystem")]
+    fn absent() {
+        let root = TempDir::new("absent");
+        let base = root.join("case.in");
+        let sandbox = root.join("sandbox");
+        for dir in [&base, &sandbox] {
+            std::fs::create_dir_all(dir.join("build")).unwrap();
+            std::fs::write(dir.join("build/cache.bin"), "cache").unwrap();
+        }
+        std::fs::write(sandbox.join("cache.new"), "x").unwrap();
+        let spec = crate::schema::Filesystem {
+            base: Some(base),
+            absent: vec![
+                "build/cache.bin".into(),
+                "*.new".into(),
+                "missing/**".into(),
+            ],
+            ..Default::default()
+        };
+        let case = Case::with_error(root.join("case.toml"), "unused".into());
+        let fs = case
+            .validate_fs(
+                &sandbox,
+                &spec,
+                Filesystem::default(),
+                &Mode::Fail,
+                &snapbox::Substitutions::new(),
+            )
+            .unwrap_err();
+        let failed = crate::report::strip_ansi(&fs.to_string());
+
+        assert!(
+            failed.contains(
+                "build/cache.bin: expected path to be absent but it exists (copied from `*.in/`)"
+            ),
+            "{}",
+            failed
+        );
+        assert!(
+            failed.contains(
+                "cache.new: expected path to be absent but it exists (created by the command)"
+            ),
+            "{}",
+            failed
+        );
+        assert!(!failed.contains("missing"), "{}", failed);
+    }
+
+    #[test]
+    #[cfg(feature = "filesystem")]
+    fn comparator() {
+        let root = TempDir::new("comparator");
+        std::fs::create_dir_all(root.join("case.out")).unwrap();
+        let expected_path = root.join("case.out/image.png");
+        std::fs::write(&expected_path, "abc").unwrap();
+        let sandbox = root.join("sandbox");
+        std::fs::create_dir_all(&sandbox).unwrap();
+        std::fs::write(sandbox.join("image.png"), "xyz").unwrap();
+
+        let validate = |mode: &Mode, same_len: bool| {
+            let mut case = Case::with_error(root.join("case.toml"), "unused".into());
+            case.comparators.register(
+                "png".into(),
+                std::sync::Arc::new(move |expected: &[u8], actual: &[u8]| {
+                    (expected.len() == actual.len()) == same_len
+                }),
+            );
+            case.validate_fs(
+                &sandbox,
+                &crate::schema::Filesystem::default(),
+                Filesystem::default(),
+                mode,
+                &snapbox::Substitutions::new(),
+            )
+            .map_err(|fs| crate::report::strip_ansi(&fs.to_string()))
+        };
+        let rejected = validate(&Mode::Fail, false);
+        let overwritten = validate(&Mode::Overwrite, false);
+        let content = std::fs::read_to_string(&expected_path).unwrap();
+
+        let rejected = rejected.unwrap_err();
+        assert!(
+            rejected.contains("image.png: custom comparator rejected"),
+            "{}",
+            rejected
+        );
+        assert!(overwritten.is_ok());
+        assert_eq!(content, "xyz");
+    }
+
+    #[test]
+    fn sorted_lines_by_bytes() {
+        assert_eq!(
+            sorted_lines(&crate::Data::text("b\na\nc\n")),
+            crate::Data::text("a\nb\nc\n")
+        );
+        assert_eq!(
+            sorted_lines(&crate::Data::text("b\na")),
+            crate::Data::text("a\nb")
+        );
+        assert_eq!(
+            sorted_lines(&crate::Data::binary(&b"\xff\n\x01\n"[..])),
+            crate::Data::binary(&b"\x01\n\xff\n"[..])
+        );
+    }
+
+    #[test]
+    fn change_dir_within_root() {
+        let root = TempDir::new("change-dir");
+        std::fs::create_dir_all(root.join("project/src")).unwrap();
+        let cd = |cwd: &std::path::Path, dir: &str| {
+            change_dir(Some(cwd), Some(&root), std::path::Path::new(dir)).map_err(|e| e.to_string())
+        };
+        let nested = cd(&root, "project/./src");
+        let parent = cd(&root.join("project/src"), "..");
+        let absolute = cd(&root, &root.join("project").display().to_string());
+        let escaped = cd(&root.join("project"), "../..");
+        let missing = cd(&root, "missing");
+
+        assert_eq!(nested.unwrap(), root.join("project/src"));
+        assert_eq!(parent.unwrap(), root.join("project"));
+        assert_eq!(absolute.unwrap(), root.join("project"));
+        assert!(escaped.unwrap_err().contains("is outside of"));
+        assert!(missing.unwrap_err().contains("doesn't exist"));
+    }
+}
@@ -9,59 +9,170 @@ use std::collections::VecDeque;
 #[derive(Clone, Default, Debug, PartialEq, Eq)]
 pub(crate) struct TryCmd {
     pub(crate) steps: Vec<Step>,
+    pub(crate) tags: Vec<String>,
+    pub(crate) lock: Option<String>,
+    pub(crate) cwd: Option<std::path::PathBuf>,
     pub(crate) fs: Filesystem,
+    pub(crate) isolate_home: Option<IsolateHome>,
+    pub(crate) repeat: Option<usize>,
+    pub(crate) externalize_over: Option<usize>,
+    pub(crate) substitutions: SubstitutionOverrides,
+    /// Where snapshots were read from and are written to, see [`snapshot_path`]
+    pub(crate) snapshot_dir: Option<std::path::PathBuf>,
 }
 
 impl TryCmd {
-    pub(crate) fn load(path: &std::path::Path) -> Result<Self, crate::Error> {
+    pub(crate) fn load(
+        path: &std::path::Path,
+        snapshot_dir: Option<&std::path::Path>,
+    ) -> Result<Self, crate::Error> {
+        Self::parse(path, snapshot_dir).map_err(Into::into)
+    }
+
+    /// Like [`TryCmd::load`] but reporting where in `path` a problem was found
+    pub(crate) fn parse(
+        path: &std::path::Path,
+        snapshot_dir: Option<&std::path::Path>,
+    ) -> Result<Self, crate::ParseError> {
+        let mut sequence = Self::parse_inner(path, snapshot_dir).map_err(|e| e.with_path(path))?;
+        if let Some(config) = DirConfig::find(path)? {
+            config.apply(&mut sequence);
+        }
+        sequence.snapshot_dir = snapshot_dir.map(ToOwned::to_owned);
+        Ok(sequence)
+    }
+
+    fn parse_inner(
+        path: &std::path::Path,
+        snapshot_dir: Option<&std::path::Path>,
+    ) -> Result<Self, crate::ParseError> {
         let mut sequence = if let Some(ext) = path.extension() {
             if ext == std::ffi::OsStr::new("toml") {
                 let raw = std::fs::read_to_string(path)
                     .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
                 let one_shot = OneShot::parse_toml(&raw)?;
+                if one_shot.comment_prefix.is_some() {
+                    return Err("`comment-prefix` is only supported in `.trycmd` files".into());
+                }
+                if one_shot.shell_syntax {
+                    return Err("`shell-syntax` is only supported in `.trycmd` files".into());
+                }
                 let mut sequence: Self = one_shot.into();
-                let is_binary = match sequence.steps[0].binary {
+                let encoding = sequence.steps[0].encoding;
+                let is_binary = match sequence.steps[0].binary || encoding.is_some() {
                     true => snapbox::DataFormat::Binary,
                     false => snapbox::DataFormat::Text,
                 };
 
-                if sequence.steps[0].stdin.is_none() {
+                if sequence.steps[0].stdin.is_none() && sequence.steps[0].stdin_file.is_none() {
                     let stdin_path = path.with_extension("stdin");
-                    let stdin = if stdin_path.exists() {
+                    if stdin_path.exists() {
                         // No `map_text` as we will trust what the user inputted
-                        Some(crate::Data::read_from(&stdin_path, Some(is_binary))?)
-                    } else {
-                        None
-                    };
-                    sequence.steps[0].stdin = stdin;
+                        let stdin = crate::Data::read_from(&stdin_path, Some(is_binary))?;
+                        sequence.steps[0].stdin = Some(stdin);
+                        sequence.steps[0].stdin_source = Some(stdin_path);
+                    }
+                }
+                if sequence.steps[0].null_stdin
+                    && (sequence.steps[0].stdin.is_some() || sequence.steps[0].stdin_file.is_some())
+                {
+                    return Err(
+                        "`stdin-pipe = false` can't be combined with `stdin` or `*.stdin`".into(),
+                    );
+                }
+
+                let dir = path.parent().unwrap_or_else(|| std::path::Path::new("."));
+                let step = &mut sequence.steps[0];
+
+                if step.expect_timeout && step.expected_status.is_some() {
+                    return Err("`expect-timeout` can't be combined with `status`".into());
+                }
+                if sequence.repeat == Some(0) {
+                    return Err("`repeat` must be at least 1".into());
+                }
+                if step.arg0.is_some() && matches!(step.bin, Some(Bin::Script { .. })) {
+                    return Err("`arg0` can't be combined with `bin.script`".into());
+                }
+                for extract in &step.extracts {
+                    extract.check()?;
+                    if extract.stream == ExtractStream::Stderr
+                        && (step.stderr_to_stdout || step.interleaved)
+                    {
+                        return Err(
+                            "`extract` can't read `stderr` when it is captured with `stdout`"
+                                .into(),
+                        );
+                    }
                 }
 
-                if sequence.steps[0].expected_stdout.is_none() {
-                    let stdout_path = path.with_extension("stdout");
+                if let Some(files) = &mut step.expected_stdout_files {
+                    files.resolve(dir, "stdout")?;
+                    step.expected_stdout = Some(files.read(is_binary, encoding)?);
+                } else if let Some(same_as) = &mut step.expected_stdout_same_as {
+                    *same_as = dir.join(&*same_as);
+                    if same_as == path {
+                        return Err("`stdout.same-as` can't be the case itself".into());
+                    }
+                } else if step.expected_stdout.is_none() {
+                    let stdout_path = snapshot_path(snapshot_dir, path, "stdout");
                     let stdout = if stdout_path.exists() {
-                        Some(
-                            crate::Data::read_from(&stdout_path, Some(is_binary))?
-                                .normalize(NormalizePaths)
-                                .normalize(NormalizeNewlines),
-                        )
+                        Some(read_expected(&stdout_path, is_binary, encoding)?)
                     } else {
                         None
                     };
-                    sequence.steps[0].expected_stdout = stdout;
+                    step.expected_stdout = stdout;
                 }
 
-                if sequence.steps[0].expected_stderr.is_none() {
-                    let stderr_path = path.with_extension("stderr");
+                if let Some(files) = &mut step.expected_stderr_files {
+                    files.resolve(dir, "stderr")?;
+                    step.expected_stderr = Some(files.read(is_binary, encoding)?);
+                } else if let Some(same_as) = &mut step.expected_stderr_same_as {
+                    *same_as = dir.join(&*same_as);
+                    if same_as == path {
+                        return Err("`stderr.same-as` can't be the case itself".into());
+                    }
+                } else if step.expected_stderr.is_none() {
+                    let stderr_path = snapshot_path(snapshot_dir, path, "stderr");
                     let stderr = if stderr_path.exists() {
-                        Some(
-                            crate::Data::read_from(&stderr_path, Some(is_binary))?
-                                .normalize(NormalizePaths)
-                                .normalize(NormalizeNewlines),
-                        )
+                        Some(read_expected(&stderr_path, is_binary, encoding)?)
                     } else {
                         None
                     };
-                    sequence.steps[0].expected_stderr = stderr;
+                    step.expected_stderr = stderr;
+                }
+
+                if step.interleaved {
+                    let conflicts = [
+                        (
+                            "stdout",
+                            step.expected_stdout.is_some()
+                                || step.expected_stdout_same_as.is_some(),
+                        ),
+                        (
+                            "stderr",
+                            step.expected_stderr.is_some()
+                                || step.expected_stderr_same_as.is_some(),
+                        ),
+                        ("stderr-to-stdout", step.stderr_to_stdout),
+                        ("stderr-unordered", step.stderr_unordered),
+                        (
+                            "stderr-match-anchor",
+                            step.stderr_match_anchor != MatchAnchor::Full,
+                        ),
+                    ];
+                    if let Some((key, _)) = conflicts.iter().find(|(_, is_set)| *is_set) {
+                        return Err(format!(
+                            "`{}` can't be used with `output = \"interleaved\"`, which is compared against `*.interleaved`",
+                            key
+                        )
+                        .into());
+                    }
+
+                    let interleaved_path = snapshot_path(snapshot_dir, path, "interleaved");
+                    if interleaved_path.exists() {
+                        step.expected_stdout =
+                            Some(read_expected(&interleaved_path, is_binary, encoding)?);
+                    }
                 }
 
                 sequence
@@ -87,6 +198,12 @@ impl TryCmd {
                 .unwrap_or_else(|| std::path::Path::new("."))
                 .join(cwd)
         });
+        if let Some(IsolateHome::Seeded(home)) = &mut sequence.isolate_home {
+            home.seed = path
+                .parent()
+                .unwrap_or_else(|| std::path::Path::new("."))
+                .join(&home.seed);
+        }
 
         if sequence.fs.base.is_none() {
             let base_path = path.with_extension("in");
@@ -99,8 +216,13 @@ impl TryCmd {
         if sequence.fs.cwd.is_none() {
             sequence.fs.cwd = sequence.fs.base.clone();
         }
+        for pattern in &sequence.fs.absent {
+            glob::Pattern::new(pattern)
+                .map_err(|e| format!("Invalid `fs.absent` pattern `{}`: {}", pattern, e))?;
+        }
         if sequence.fs.sandbox.is_none() {
-            sequence.fs.sandbox = Some(path.with_extension("out").exists());
+            sequence.fs.sandbox =
+                Some(path.with_extension("out").exists() || !sequence.fs.absent.is_empty());
         }
 
         sequence.fs.base = sequence
@@ -116,9 +238,81 @@ impl TryCmd {
             .map(|p| snapbox::path::resolve_dir(p).map_err(|e| e.to_string()))
             .transpose()?;
 
+        sequence.substitutions.check()?;
+
         Ok(sequence)
     }
 
+    /// Tags declared in the case file, ignoring any problems
+    pub(crate) fn load_tags(path: &std::path::Path) -> Vec<String> {
+        load_one_shot(path)
+            .map(|one_shot| one_shot.tags)
+            .unwrap_or_default()
+    }
+
+    /// Cases whose output this case's output is compared against, ignoring any problems
+    pub(crate) fn load_same_as(path: &std::path::Path) -> Vec<std::path::PathBuf> {
+        let dir = path.parent().unwrap_or_else(|| std::path::Path::new("."));
+        load_one_shot(path)
+            .map(|one_shot| {
+                [one_shot.stdout, one_shot.stderr]
+                    .into_iter()
+                    .filter_map(|expected| match expected {
+                        Some(Expected::SameAs(same_as)) => Some(dir.join(same_as.same_as)),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Files listed in `stdout.paths` / `stderr.paths`, ignoring any problems
+    pub(crate) fn load_expected_paths(path: &std::path::Path) -> Vec<std::path::PathBuf> {
+        let dir = path.parent().unwrap_or_else(|| std::path::Path::new("."));
+        load_one_shot(path)
+            .map(|one_shot| {
+                [one_shot.stdout, one_shot.stderr]
+                    .into_iter()
+                    .flat_map(|expected| match expected {
+                        Some(Expected::Files(files)) => files.paths,
+                        _ => Vec::new(),
+                    })
+                    .map(|path| dir.join(path))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether the case file is meant for the current platform, ignoring any problems
+    pub(crate) fn supports_platform(path: &std::path::Path) -> bool {
+        load_one_shot(path)
+            .and_then(|one_shot| one_shot.platforms)
+            .map(|platforms| {
+                platforms
+                    .iter()
+                    .any(|p| p == std::env::consts::FAMILY || p == std::env::consts::OS)
+            })
+            .unwrap_or(true)
+    }
+
+    /// The program of each command in `trycmd` and `console` blocks, with its line, including
+    /// in blocks that are ignored
+    pub(crate) fn load_programs(
+        path: &std::path::Path,
+    ) -> Result<Vec<(usize, String)>, crate::Error> {
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let normalized = snapbox::utils::normalize_lines(&raw);
+        programs(&normalized).map_err(|e| e.with_path(path).into())
+    }
+
+    /// Whether the case file participates in [`TestCases::matrix_bins`][crate::TestCases::matrix_bins], ignoring any problems
+    pub(crate) fn load_matrix(path: &std::path::Path) -> bool {
+        load_one_shot(path)
+            .and_then(|one_shot| one_shot.matrix)
+            .unwrap_or(true)
+    }
+
     pub(crate) fn overwrite(
         &self,
         path: &std::path::Path,
@@ -126,13 +320,53 @@ impl TryCmd {
         stdout: Option<&crate::Data>,
         stderr: Option<&crate::Data>,
         exit: Option<std::process::ExitStatus>,
+        externalize_over: Option<usize>,
     ) -> Result<(), crate::Error> {
         if let Some(ext) = path.extension() {
             if ext == std::ffi::OsStr::new("toml") {
                 assert_eq!(id, None);
 
-                overwrite_toml_output(path, id, stdout, "stdout", "stdout")?;
-                overwrite_toml_output(path, id, stderr, "stderr", "stderr")?;
+                let externalize_over = self.externalize_over.or(externalize_over);
+                let step = &self.steps[0];
+                let encoding = step.encoding;
+                // Another case's output is the expectation, so there is nothing to write
+                let stdout = stdout.filter(|_| step.expected_stdout_same_as.is_none());
+                let stderr = stderr.filter(|_| step.expected_stderr_same_as.is_none());
+                if step.interleaved {
+                    if let Some(output) = stdout {
+                        let output_path =
+                            snapshot_path(self.snapshot_dir.as_deref(), path, "interleaved");
+                        match encoding {
+                            Some(encoding) => encoding.encode(output).write_to(&output_path)?,
+                            None => output.write_to(&output_path)?,
+                        }
+                    }
+                } else {
+                    match &step.expected_stdout_files {
+                        Some(files) => files.overwrite(stdout, "stdout", step.binary, encoding)?,
+                        None => overwrite_toml_output(
+                            path,
+                            id,
+                            stdout,
+                            &snapshot_path(self.snapshot_dir.as_deref(), path, "stdout"),
+                            "stdout",
+                            externalize_over,
+                            encoding,
+                        )?,
+                    }
+                    match &step.expected_stderr_files {
+                        Some(files) => files.overwrite(stderr, "stderr", step.binary, encoding)?,
+                        None => overwrite_toml_output(
+                            path,
+                            id,
+                            stderr,
+                            &snapshot_path(self.snapshot_dir.as_deref(), path, "stderr"),
+                            "stderr",
+                            externalize_over,
+                            encoding,
+                        )?,
+                    }
+                }
 
                 if let Some(status) = exit {
                     let raw = std::fs::read_to_string(path)
@@ -181,15 +415,37 @@ impl TryCmd {
         Ok(())
     }
 
-    fn parse_trycmd(s: &str) -> Result<Self, crate::Error> {
+    fn parse_trycmd(s: &str) -> Result<Self, crate::ParseError> {
         let mut steps = Vec::new();
 
         let mut lines: VecDeque<_> = snapbox::utils::LinesWithTerminator::new(s)
             .enumerate()
             .map(|(i, l)| (i + 1, l))
             .collect();
+        let front_matter = parse_front_matter(s)?;
+        if let Some((_, line_count)) = &front_matter {
+            lines.drain(..*line_count);
+        }
+        // Comments are opt-in so existing transcripts with output like `// ...` keep it
+        let comment_prefix = front_matter
+            .as_ref()
+            .and_then(|(front_matter, _)| front_matter.comment_prefix.as_deref());
+        if comment_prefix == Some("") {
+            return Err(crate::ParseError::new(
+                Some(1),
+                "`comment-prefix` can't be empty",
+            ));
+        }
+        let is_comment = |line: &str| {
+            comment_prefix.map_or(false, |prefix| line.starts_with(prefix))
+                && !crate::ignore::is_marker_line(line)
+        };
+        let all_shell_syntax = front_matter
+            .as_ref()
+            .map_or(false, |(front_matter, _)| front_matter.shell_syntax);
         'outer: loop {
             let mut fence_pattern = "```".to_owned();
+            let mut shell_syntax = all_shell_syntax;
             while let Some((_, line)) = lines.pop_front() {
                 let tick_end = line
                     .char_indices()
@@ -206,9 +462,11 @@ impl TryCmd {
                         let lang = info.next().unwrap();
                         match lang {
                             "trycmd" | "console" => {
-                                if info.any(|i| i == "ignore") {
-                                    snapbox::debug!("ignore from infostring: {:?}", info);
+                                let flags: Vec<_> = info.collect();
+                                if flags.contains(&"ignore") {
+                                    snapbox::debug!("ignore from infostring: {:?}", flags);
                                 } else {
+                                    shell_syntax |= flags.contains(&"shell-syntax");
                                     break;
                                 }
                             }
@@ -228,9 +486,9 @@ impl TryCmd {
             }
 
             'code: loop {
-                let mut cmdline = Vec::new();
+                let mut raw_cmdline = String::new();
                 let mut expected_status_source = None;
-                let mut expected_status = Some(CommandStatus::Success);
+                let mut expected_status = None;
                 let mut stdout = String::new();
                 let cmd_start;
                 let mut stdout_start;
@@ -238,21 +496,24 @@ impl TryCmd {
                 if let Some((line_num, line)) = lines.pop_front() {
                     if line.starts_with(&fence_pattern) {
                         break;
+                    } else if is_comment(line) {
+                        continue;
                     } else if let Some(raw) = line.strip_prefix("$ ") {
-                        cmdline.extend(shlex::Shlex::new(raw.trim()));
+                        raw_cmdline.push_str(raw.trim());
                         cmd_start = line_num;
                         stdout_start = line_num + 1;
                     } else {
-                        return Err(
-                            format!("Expected `$` on line {}, got `{}`", line_num, line).into()
-                        );
+                        return Err(crate::ParseError::new(
+                            Some(line_num),
+                            format!("Expected `$` on line {}, got `{}`", line_num, line),
+                        ));
                     }
                 } else {
                     break 'outer;
                 }
                 while let Some((line_num, line)) = lines.pop_front() {
                     if let Some(raw) = line.strip_prefix("> ") {
-                        cmdline.extend(shlex::Shlex::new(raw.trim()));
+                        continue_line(&mut raw_cmdline, raw);
                         stdout_start = line_num + 1;
                     } else {
                         lines.push_front((line_num, line));
@@ -262,7 +523,11 @@ impl TryCmd {
                 if let Some((line_num, line)) = lines.pop_front() {
                     if let Some(raw) = line.strip_prefix("? ") {
                         expected_status_source = Some(line_num);
-                        expected_status = Some(raw.trim().parse::<CommandStatus>()?);
+                        expected_status = Some(
+                            raw.trim()
+                                .parse::<CommandStatus>()
+                                .map_err(|e| crate::ParseError::new(Some(line_num), e))?,
+                        );
                         stdout_start = line_num + 1;
                     } else {
                         lines.push_front((line_num, line));
@@ -279,6 +544,19 @@ impl TryCmd {
                         block_done = true;
                         post_stdout_start = line_num;
                         break;
+                    } else if is_comment(line)
+                        && lines
+                            .iter()
+                            .map(|(_, line)| *line)
+                            .find(|line| !is_comment(line))
+                            .map_or(true, |line| {
+                                line.starts_with("$ ") || line.starts_with(&fence_pattern)
+                            })
+                    {
+                        // Comments leading into the next command, rather than output
+                        lines.push_front((line_num, line));
+                        post_stdout_start = line_num;
+                        break;
                     } else {
                         stdout.push_str(line);
                         post_stdout_start = line_num + 1;
@@ -289,35 +567,118 @@ impl TryCmd {
                     // trailing newline.
                     stdout.pop();
                 }
-
-                let mut env = Env::default();
-
-                let bin = loop {
-                    if cmdline.is_empty() {
-                        return Err(format!("No bin specified on line {}", cmd_start).into());
+                crate::ignore::check(&stdout).map_err(|(offset, message)| {
+                    crate::ParseError::new(Some(stdout_start + offset), message)
+                })?;
+                crate::conditional::check(&stdout).map_err(|(offset, message)| {
+                    crate::ParseError::new(Some(stdout_start + offset), message)
+                })?;
+
+                let mut stdin_file = None;
+                let mut commands = Vec::new();
+                for (i, raw) in split_pipeline(&raw_cmdline, shell_syntax)
+                    .into_iter()
+                    .enumerate()
+                {
+                    let (raw, redirect) = match shell_syntax {
+                        true => split_redirect(raw, cmd_start)?,
+                        false => (raw, None),
+                    };
+                    if let Some(redirect) = redirect {
+                        if i != 0 {
+                            return Err(crate::ParseError::new(
+                                Some(cmd_start),
+                                "Only the first command of a pipeline can read `stdin` from a file with `<`",
+                            ));
+                        }
+                        stdin_file = Some(redirect);
                     }
-                    let next = cmdline.remove(0);
-                    if let Some((key, value)) = next.split_once('=') {
-                        env.add.insert(key.to_owned(), value.to_owned());
-                    } else {
-                        break next;
+                    commands.push(
+                        split_words(raw).map_err(|e| crate::ParseError::new(Some(cmd_start), e))?,
+                    );
+                }
+                let mut commands = commands.into_iter();
+                let mut cmdline = commands.next().unwrap_or_default();
+                let (env, bin) = split_env(&mut cmdline, cmd_start)?;
+                let first_env = &env;
+                let pipeline = commands
+                    .map(|mut args| {
+                        let (env, bin) = split_env(&mut args, cmd_start)?;
+                        Ok(Piped {
+                            bin: Bin::Name(bin),
+                            args,
+                            env,
+                            unset: first_env.add.keys().cloned().collect(),
+                        })
+                    })
+                    .collect::<Result<Vec<_>, crate::ParseError>>()?;
+                // Built-in, changing the working directory of later steps
+                let cd = if bin == "cd" {
+                    if !pipeline.is_empty() {
+                        return Err(crate::ParseError::new(
+                            Some(cmd_start),
+                            "`cd` can't be part of a pipeline",
+                        ));
                     }
+                    if cmdline.len() != 1 {
+                        return Err(crate::ParseError::new(
+                            Some(cmd_start),
+                            "`cd` takes exactly one directory",
+                        ));
+                    }
+                    if !env.add.is_empty()
+                        || expected_status.is_some()
+                        || !stdout.is_empty()
+                        || stdin_file.is_some()
+                    {
+                        return Err(crate::ParseError::new(
+                            Some(cmd_start),
+                            "`cd` can't have environment variables, a status, output, or `<`",
+                        ));
+                    }
+                    Some(std::path::PathBuf::from(cmdline.remove(0)))
+                } else {
+                    None
                 };
                 let step = Step {
                     id: Some(cmd_start.to_string()),
-                    bin: Some(Bin::Name(bin)),
+                    bin: cd.is_none().then_some(Bin::Name(bin)),
+                    cd,
                     args: cmdline,
+                    args_override: None,
+                    arg0: None,
+                    pipeline,
                     env,
                     stdin: None,
+                    stdin_source: None,
+                    stdin_file,
+                    null_stdin: false,
+                    inherit_stdin: false,
+                    max_output_bytes: None,
                     stderr_to_stdout: true,
+                    interleaved: false,
                     expected_status_source,
                     expected_status,
                     expected_stdout_source: Some(stdout_start..post_stdout_start),
                     expected_stdout: Some(crate::Data::text(stdout)),
+                    expected_stdout_files: None,
+                    expected_stdout_same_as: None,
+                    expected_stdout_lines: None,
+                    extracts: Vec::new(),
                     expected_stderr_source: None,
                     expected_stderr: None,
+                    expected_stderr_files: None,
+                    expected_stderr_same_as: None,
+                    stderr_unordered: false,
+                    sort_lines: false,
+                    stdout_match_anchor: Default::default(),
+                    stderr_match_anchor: Default::default(),
+                    encoding: None,
                     binary: false,
                     timeout: None,
+                    expect_timeout: false,
+                    umask: None,
+                    locale: None,
                 };
                 steps.push(step);
                 if block_done {
@@ -326,46 +687,452 @@ impl TryCmd {
             }
         }
 
-        Ok(Self {
+        let mut sequence = Self {
             steps,
             ..Default::default()
-        })
+        };
+        if let Some((front_matter, _)) = front_matter {
+            sequence.apply_front_matter(front_matter)?;
+        }
+        Ok(sequence)
+    }
+
+    /// Apply settings from a `.trycmd` file's front matter to all of its steps
+    fn apply_front_matter(&mut self, front_matter: OneShot) -> Result<(), crate::ParseError> {
+        let OneShot {
+            bin,
+            args,
+            args_override,
+            arg0,
+            env,
+            stdin,
+            stdin_pipe,
+            stdout,
+            stdout_lines,
+            extract,
+            stderr,
+            stderr_unordered,
+            sort_output_lines,
+            stdout_match_anchor,
+            stderr_match_anchor,
+            stderr_to_stdout,
+            output,
+            status,
+            binary,
+            encoding,
+            timeout,
+            expect_timeout,
+            umask,
+            locale,
+            tags,
+            platforms,
+            matrix,
+            lock,
+            cwd,
+            mut fs,
+            files_absent,
+            isolate_home,
+            repeat,
+            externalize_over,
+            substitutions,
+            comment_prefix: _,
+            shell_syntax: _,
+        } = front_matter;
+
+        let unsupported = [
+            ("bin", bin.is_some()),
+            ("args", !args.is_empty()),
+            ("args-override", args_override.is_some()),
+            ("arg0", arg0.is_some()),
+            ("stdin", stdin.is_some()),
+            ("stdout", stdout.is_some()),
+            ("stdout-lines", stdout_lines.is_some()),
+            ("extract", !extract.is_empty()),
+            ("stderr", stderr.is_some()),
+            ("stderr-unordered", stderr_unordered),
+            ("stderr-to-stdout", stderr_to_stdout),
+            (
+                "stdout-match-anchor",
+                stdout_match_anchor != MatchAnchor::Full,
+            ),
+            (
+                "stderr-match-anchor",
+                stderr_match_anchor != MatchAnchor::Full,
+            ),
+            ("status", status.is_some()),
+            ("expect-timeout", expect_timeout),
+            ("output", output != OutputCapture::Split),
+            ("platforms", platforms.is_some()),
+            ("matrix", matrix.is_some()),
+        ];
+        if let Some((key, _)) = unsupported.iter().find(|(_, is_set)| *is_set) {
+            return Err(crate::ParseError::new(
+                Some(1),
+                format!("`{}` is not supported in front matter", key),
+            ));
+        }
+        if repeat == Some(0) {
+            return Err(crate::ParseError::new(
+                Some(1),
+                "`repeat` must be at least 1".to_owned(),
+            ));
+        }
+
+        for step in &mut self.steps {
+            let mut step_env = env.clone();
+            step_env.update(&step.env);
+            step.env = step_env;
+            step.binary |= binary;
+            step.null_stdin |= stdin_pipe == Some(false);
+            step.sort_lines |= sort_output_lines;
+            if step.encoding.is_none() {
+                step.encoding = encoding;
+            }
+            if step.timeout.is_none() {
+                step.timeout = timeout;
+            }
+            if step.umask.is_none() {
+                step.umask = umask.map(|u| u.0);
+            }
+            if step.locale.is_none() {
+                step.locale = locale.clone();
+            }
+        }
+        self.tags = tags;
+        self.lock = lock;
+        self.cwd = cwd;
+        fs.absent
+            .extend(files_absent.into_iter().map(|absent| absent.path));
+        self.fs = fs;
+        self.isolate_home = isolate_home;
+        self.repeat = repeat;
+        self.externalize_over = externalize_over;
+        self.substitutions = substitutions;
+        Ok(())
+    }
+}
+
+/// Append a `> ` continuation line, dropping the shell's trailing `\` from the line before
+fn continue_line(cmdline: &mut String, raw: &str) {
+    if cmdline.ends_with('\\') && !cmdline.ends_with("\\\\") {
+        cmdline.pop();
+    }
+    cmdline.push(' ');
+    cmdline.push_str(raw.trim());
+}
+
+/// Split a command line into the commands of its pipeline, with `shell-syntax`
+fn split_pipeline(raw: &str, shell_syntax: bool) -> Vec<&str> {
+    if shell_syntax {
+        split_unquoted(raw, '|')
+    } else {
+        vec![raw]
+    }
+}
+
+/// Split a command line on the `separator`s outside of quotes, like `|`
+fn split_unquoted(raw: &str, separator: char) -> Vec<&str> {
+    let mut commands = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in raw.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, c) if c == separator => {
+                commands.push(raw[start..i].trim());
+                start = i + 1;
+            }
+            (None, _) => {}
+        }
+    }
+    commands.push(raw[start..].trim());
+    commands
+}
+
+/// Split off a trailing `< file`, outside of quotes
+fn split_redirect(
+    raw: &str,
+    line_num: usize,
+) -> Result<(&str, Option<std::path::PathBuf>), crate::ParseError> {
+    match split_unquoted(raw, '<').as_slice() {
+        [command] => Ok((command, None)),
+        [command, file] => {
+            let mut words =
+                split_words(file).map_err(|e| crate::ParseError::new(Some(line_num), e))?;
+            if words.len() != 1 {
+                return Err(crate::ParseError::new(
+                    Some(line_num),
+                    "`<` takes exactly one file",
+                ));
+            }
+            Ok((command, Some(words.remove(0).into())))
+        }
+        _ => Err(crate::ParseError::new(
+            Some(line_num),
+            "`stdin` can only be read from one file",
+        )),
+    }
+}
+
+/// See [`TryCmd::load_programs`]
+fn programs(s: &str) -> Result<Vec<(usize, String)>, crate::ParseError> {
+    let mut lines: VecDeque<_> = snapbox::utils::LinesWithTerminator::new(s)
+        .enumerate()
+        .map(|(i, l)| (i + 1, l))
+        .collect();
+    let mut all_shell_syntax = false;
+    if let Some((front_matter, line_count)) = parse_front_matter(s)? {
+        lines.drain(..line_count);
+        all_shell_syntax = front_matter.shell_syntax;
+    }
+
+    let mut programs = Vec::new();
+    while let Some((_, line)) = lines.pop_front() {
+        let tick_end = line
+            .char_indices()
+            .find_map(|(i, c)| (c != '`').then_some(i))
+            .unwrap_or(line.len());
+        if tick_end < 3 {
+            continue;
+        }
+        let fence_pattern = &line[..tick_end];
+        let mut info = line[tick_end..].trim().split(',');
+        let lang = info.next().unwrap_or_default();
+        let checked = matches!(lang, "" | "trycmd" | "console");
+        let shell_syntax = all_shell_syntax || info.any(|flag| flag == "shell-syntax");
+
+        let mut command: Option<(usize, String)> = None;
+        while let Some((line_num, line)) = lines.pop_front() {
+            if line.starts_with(fence_pattern) {
+                break;
+            } else if !checked {
+                continue;
+            }
+            if let Some(raw) = line.strip_prefix("$ ") {
+                if let Some((line_num, raw)) = command.take() {
+                    push_programs(&mut programs, line_num, &raw, shell_syntax)?;
+                }
+                command = Some((line_num, raw.trim().to_owned()));
+            } else if let (Some(raw), Some((_, cmdline))) = (line.strip_prefix("> "), &mut command)
+            {
+                continue_line(cmdline, raw);
+            } else if let Some((line_num, raw)) = command.take() {
+                push_programs(&mut programs, line_num, &raw, shell_syntax)?;
+            }
+        }
+        if let Some((line_num, raw)) = command.take() {
+            push_programs(&mut programs, line_num, &raw, shell_syntax)?;
+        }
+    }
+    Ok(programs)
+}
+
+fn push_programs(
+    programs: &mut Vec<(usize, String)>,
+    line_num: usize,
+    raw: &str,
+    shell_syntax: bool,
+) -> Result<(), crate::ParseError> {
+    for raw in split_pipeline(raw, shell_syntax) {
+        let (raw, _) = match shell_syntax {
+            true => split_redirect(raw, line_num)?,
+            false => (raw, None),
+        };
+        let mut cmdline =
+            split_words(raw).map_err(|e| crate::ParseError::new(Some(line_num), e))?;
+        let (_, bin) = split_env(&mut cmdline, line_num)?;
+        programs.push((line_num, bin));
+    }
+    Ok(())
+}
+
+/// Split a command line into words, with shell quoting
+///
+/// Shared by `.trycmd` command lines and `args = "..."` in `.toml` so both quote the same way.
+fn split_words(raw: &str) -> Result<Vec<String>, String> {
+    let mut lexer = shlex::Shlex::new(raw);
+    let words = lexer.by_ref().collect();
+    if lexer.had_error {
+        return Err(format!(
+            "Unterminated quote or trailing `\\` in `{}`",
+            raw.trim()
+        ));
+    }
+    Ok(words)
+}
+
+/// Take the leading `KEY=value` assignments and the bin from a command line
+fn split_env(
+    cmdline: &mut Vec<String>,
+    line_num: usize,
+) -> Result<(Env, String), crate::ParseError> {
+    let mut env = Env::default();
+    loop {
+        if cmdline.is_empty() {
+            return Err(crate::ParseError::new(
+                Some(line_num),
+                format!("No bin specified on line {}", line_num),
+            ));
+        }
+        let next = cmdline.remove(0);
+        if let Some((key, value)) = next.split_once('=') {
+            env.add.insert(key.to_owned(), value.to_owned());
+        } else {
+            return Ok((env, next));
+        }
+    }
+}
+
+/// Parse a leading block of settings, fenced by `---` or ```` ```toml ````, in `.trycmd` files
+///
+/// A `---` block that isn't valid settings, like YAML front matter in a `.md` file, is left
+/// alone.
+///
+/// Returns the settings and how many lines the block spans
+fn parse_front_matter(s: &str) -> Result<Option<(OneShot, usize)>, crate::ParseError> {
+    let mut lines = snapbox::utils::LinesWithTerminator::new(s);
+    let (closing, strict) = match lines.next().map(str::trim_end) {
+        Some("---") => ("---", false),
+        Some("```toml") => ("```", true),
+        _ => return Ok(None),
+    };
+
+    let mut raw = String::new();
+    let mut line_count = 1;
+    for line in lines {
+        line_count += 1;
+        if line.trim_end() == closing {
+            return match OneShot::parse_toml(&raw) {
+                Ok(one_shot) => Ok(Some((one_shot, line_count))),
+                Err(_) if !strict => Ok(None),
+                Err(e) => Err(crate::ParseError::new(
+                    // Offset by the opening fence
+                    e.line().map(|line| line + 1),
+                    e.message(),
+                )),
+            };
+        }
+        raw.push_str(line);
+    }
+
+    if !strict {
+        return Ok(None);
+    }
+    Err(crate::ParseError::new(
+        Some(1),
+        format!("Front matter is missing its closing `{}`", closing),
+    ))
+}
+
+fn load_one_shot(path: &std::path::Path) -> Option<OneShot> {
+    let ext = path.extension()?;
+    if ext == std::ffi::OsStr::new("toml") {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|raw| OneShot::parse_toml(&raw).ok())
+    } else if ext == std::ffi::OsStr::new("trycmd") || ext == std::ffi::OsStr::new("md") {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|raw| parse_front_matter(&snapbox::utils::normalize_lines(&raw)).ok())
+            .flatten()
+            .map(|(one_shot, _)| one_shot)
+    } else {
+        None
     }
 }
 
+/// Where a case's `*.<ext>` snapshot, like `*.stdout`, is kept
+///
+/// With a `dir`, from `TRYCMD_SNAPSHOT_DIR`, snapshots are kept in that directory, under the
+/// case's path relative to the current directory, rather than next to the case.
+pub(crate) fn snapshot_path(
+    dir: Option<&std::path::Path>,
+    path: &std::path::Path,
+    ext: &str,
+) -> std::path::PathBuf {
+    let dir = match dir {
+        Some(dir) => dir,
+        None => return path.with_extension(ext),
+    };
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let relative = path.strip_prefix(&cwd).unwrap_or(path);
+    // Keep snapshots for cases outside of the current directory within `dir`, without mixing
+    // them up with those of cases inside it
+    let relative: std::path::PathBuf = relative
+        .components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(c) => Some(c),
+            std::path::Component::ParentDir => Some(std::ffi::OsStr::new(PARENT_DIR)),
+            _ => None,
+        })
+        .collect();
+    dir.join(relative).with_extension(ext)
+}
+
+/// Stands in for `..` under `TRYCMD_SNAPSHOT_DIR`
+const PARENT_DIR: &str = "_parent_";
+
+/// Write `output` inline or to `output_path`
+///
+/// With `externalize_over`, expectations longer than that many lines are moved to `output_path`
+/// and shorter ones are moved inline.  Otherwise, the expectation stays where it is, except for
+/// binary data which can only be written to `output_path`.
 fn overwrite_toml_output(
     path: &std::path::Path,
     _id: Option<&str>,
     output: Option<&crate::Data>,
-    output_ext: &str,
+    output_path: &std::path::Path,
     output_field: &str,
+    externalize_over: Option<usize>,
+    encoding: Option<Encoding>,
 ) -> Result<(), crate::Error> {
     if let Some(output) = output {
-        let output_path = path.with_extension(output_ext);
-        if output_path.exists() {
-            output.write_to(&output_path)?;
-        } else if let Some(output) = output.render() {
-            let raw = std::fs::read_to_string(path)
-                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
-            let mut doc = raw
-                .parse::<toml_edit::Document>()
-                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
-            if let Some(output_value) = doc.get_mut(output_field) {
-                *output_value = toml_edit::value(output);
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut doc = raw
+            .parse::<toml_edit::Document>()
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let is_inline = doc.contains_key(output_field);
+        let is_external = output_path.exists();
+
+        let rendered = output.render();
+        let externalize = match (&rendered, externalize_over) {
+            (None, _) => true,
+            (Some(rendered), Some(max_lines)) if is_inline || is_external => {
+                max_lines < rendered.lines().count()
             }
-            std::fs::write(path, doc.to_string())
-                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
-        } else {
-            output.write_to(&output_path)?;
+            (Some(_), _) => is_external,
+        };
 
-            let raw = std::fs::read_to_string(path)
-                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
-            let mut doc = raw
-                .parse::<toml_edit::Document>()
-                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
-            doc[output_field] = toml_edit::Item::None;
-            std::fs::write(path, doc.to_string())
-                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        if externalize {
+            match encoding {
+                Some(encoding) => encoding.encode(output).write_to(output_path)?,
+                None => output.write_to(output_path)?,
+            }
+            if is_inline {
+                doc.remove(output_field);
+                std::fs::write(path, doc.to_string())
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            }
+        } else if let Some(rendered) = rendered {
+            if is_external {
+                std::fs::remove_file(output_path)
+                    .map_err(|e| format!("Failed to remove {}: {}", output_path.display(), e))?;
+            }
+            if is_inline || is_external {
+                doc[output_field] = toml_edit::value(rendered);
+                std::fs::write(path, doc.to_string())
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            }
         }
     }
 
@@ -464,7 +1231,7 @@ fn overwrite_trycmd_status(
             None
         } else {
             match step.expected_status {
-                Some(CommandStatus::Success | CommandStatus::Interrupted) => {
+                None | Some(CommandStatus::Success | CommandStatus::Interrupted) => {
                     Some(format!("? {code}"))
                 }
                 Some(CommandStatus::Code(expected)) if expected != code => {
@@ -525,7 +1292,7 @@ impl std::str::FromStr for TryCmd {
     type Err = crate::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Self::parse_trycmd(s)
+        Self::parse_trycmd(s).map_err(Into::into)
     }
 }
 
@@ -534,34 +1301,101 @@ impl From<OneShot> for TryCmd {
         let OneShot {
             bin,
             args,
+            args_override,
+            arg0,
             env,
             stdin,
+            stdin_pipe,
             stdout,
+            stdout_lines,
+            extract,
             stderr,
+            stderr_unordered,
+            sort_output_lines,
+            stdout_match_anchor,
+            stderr_match_anchor,
             stderr_to_stdout,
+            output,
             status,
             binary,
+            encoding,
             timeout,
-            fs,
+            expect_timeout,
+            umask,
+            locale,
+            tags,
+            platforms: _,
+            matrix: _,
+            lock,
+            cwd,
+            mut fs,
+            files_absent,
+            isolate_home,
+            repeat,
+            externalize_over,
+            substitutions,
+            comment_prefix: _,
+            shell_syntax: _,
         } = other;
+        fs.absent
+            .extend(files_absent.into_iter().map(|absent| absent.path));
+        let (stdout, stdout_files, stdout_same_as) = Expected::into_parts(stdout);
+        let (stderr, stderr_files, stderr_same_as) = Expected::into_parts(stderr);
+        let (stdin, stdin_file) = match stdin {
+            Some(Stdin::Inline(text)) => (Some(crate::Data::text(text)), None),
+            Some(Stdin::File(file)) => (None, Some(file.file)),
+            None => (None, None),
+        };
         Self {
             steps: vec![Step {
                 id: None,
                 bin,
                 args: args.into_vec(),
+                args_override: args_override.map(Args::into_vec),
+                arg0,
+                cd: None,
+                pipeline: Vec::new(),
                 env,
-                stdin: stdin.map(crate::Data::text),
+                stdin,
+                stdin_source: None,
+                stdin_file,
+                null_stdin: stdin_pipe == Some(false),
+                inherit_stdin: false,
+                max_output_bytes: None,
                 stderr_to_stdout,
+                interleaved: output == OutputCapture::Interleaved,
                 expected_status_source: None,
                 expected_status: status,
                 expected_stdout_source: None,
-                expected_stdout: stdout.map(crate::Data::text),
+                expected_stdout: stdout,
+                expected_stdout_files: stdout_files,
+                expected_stdout_same_as: stdout_same_as,
+                expected_stdout_lines: stdout_lines,
+                extracts: extract,
                 expected_stderr_source: None,
-                expected_stderr: stderr.map(crate::Data::text),
+                expected_stderr: stderr,
+                expected_stderr_files: stderr_files,
+                expected_stderr_same_as: stderr_same_as,
+                stderr_unordered,
+                sort_lines: sort_output_lines,
+                stdout_match_anchor,
+                stderr_match_anchor,
+                encoding,
                 binary,
                 timeout,
+                expect_timeout,
+                umask: umask.map(|u| u.0),
+                locale,
             }],
+            tags,
+            lock,
+            cwd,
             fs,
+            isolate_home,
+            repeat,
+            externalize_over,
+            substitutions,
+            snapshot_dir: None,
         }
     }
 }
@@ -571,20 +1405,100 @@ pub(crate) struct Step {
     pub(crate) id: Option<String>,
     pub(crate) bin: Option<Bin>,
     pub(crate) args: Vec<String>,
+    /// Replaces the default args, see `args-override` in [`OneShot`]
+    pub(crate) args_override: Option<Vec<String>>,
+    /// See `arg0` in [`OneShot`]
+    pub(crate) arg0: Option<String>,
     pub(crate) env: Env,
     pub(crate) stdin: Option<crate::Data>,
+    /// File `stdin` was read from
+    pub(crate) stdin_source: Option<std::path::PathBuf>,
+    /// File to read `stdin` from when the step runs, relative to its working directory, like
+    /// with `< file`
+    pub(crate) stdin_file: Option<std::path::PathBuf>,
+    /// See `stdin-pipe` in [`OneShot`]
+    pub(crate) null_stdin: bool,
+    /// See [`TestCases::inherit_stdin`][crate::TestCases::inherit_stdin]
+    pub(crate) inherit_stdin: bool,
+    /// See [`TestCases::max_output_bytes`][crate::TestCases::max_output_bytes]
+    pub(crate) max_output_bytes: Option<usize>,
     pub(crate) stderr_to_stdout: bool,
+    /// `$ cd <dir>`, run as a built-in that changes the working directory of later steps
+    pub(crate) cd: Option<std::path::PathBuf>,
+    /// Commands `stdout` is piped through with `|`, only the last of which has its output compared
+    pub(crate) pipeline: Vec<Piped>,
+    /// Capture `stdout` and `stderr` as one stream, in `expected_stdout`, with `stderr` lines tagged
+    pub(crate) interleaved: bool,
     pub(crate) expected_status_source: Option<usize>,
     pub(crate) expected_status: Option<CommandStatus>,
     pub(crate) expected_stdout_source: Option<std::ops::Range<usize>>,
     pub(crate) expected_stdout: Option<crate::Data>,
+    pub(crate) expected_stdout_files: Option<ExpectedFiles>,
+    /// Case whose `stdout` is expected, see [`SameAs`]
+    pub(crate) expected_stdout_same_as: Option<std::path::PathBuf>,
+    pub(crate) expected_stdout_lines: Option<usize>,
+    /// See `extract` in [`OneShot`]
+    pub(crate) extracts: Vec<Extract>,
     pub(crate) expected_stderr_source: Option<std::ops::Range<usize>>,
     pub(crate) expected_stderr: Option<crate::Data>,
+    pub(crate) expected_stderr_files: Option<ExpectedFiles>,
+    /// Case whose `stderr` is expected, see [`SameAs`]
+    pub(crate) expected_stderr_same_as: Option<std::path::PathBuf>,
+    pub(crate) stderr_unordered: bool,
+    /// See `sort-output-lines` in [`OneShot`]
+    pub(crate) sort_lines: bool,
+    pub(crate) stdout_match_anchor: MatchAnchor,
+    pub(crate) stderr_match_anchor: MatchAnchor,
+    pub(crate) encoding: Option<Encoding>,
     pub(crate) binary: bool,
     pub(crate) timeout: Option<std::time::Duration>,
+    /// See `expect-timeout` in [`OneShot`]
+    pub(crate) expect_timeout: bool,
+    /// See `umask` in [`OneShot`]
+    pub(crate) umask: Option<u32>,
+    /// See `locale` in [`OneShot`]
+    pub(crate) locale: Option<String>,
+}
+
+/// Command after a `|` in a [`Step`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Piped {
+    pub(crate) bin: Bin,
+    pub(crate) args: Vec<String>,
+    /// In addition to the [`Step`]'s
+    pub(crate) env: Env,
+    /// Variables assigned on the first command's line, which only it sees
+    pub(crate) unset: Vec<String>,
 }
 
 impl Step {
+    /// Build the command for the `index`th [`Piped`] command, sharing this step's settings
+    pub(crate) fn piped_command(
+        &self,
+        index: usize,
+        cwd: Option<&std::path::Path>,
+    ) -> Result<snapbox::cmd::Command, crate::Error> {
+        let piped = &self.pipeline[index];
+        let mut env = self.env.clone();
+        env.add.retain(|k, _| !piped.unset.contains(k));
+        env.update(&piped.env);
+        let step = Step {
+            bin: Some(piped.bin.clone()),
+            args: piped.args.clone(),
+            arg0: None,
+            env,
+            stdin: None,
+            stdin_source: None,
+            stdin_file: None,
+            null_stdin: false,
+            inherit_stdin: false,
+            max_output_bytes: None,
+            pipeline: Vec::new(),
+            ..self.clone()
+        };
+        step.to_command(cwd)
+    }
+
     pub(crate) fn to_command(
         &self,
         cwd: Option<&std::path::Path>,
@@ -592,20 +1506,40 @@ impl Step {
         let bin = match &self.bin {
             Some(Bin::Path(path)) => Ok(path.clone()),
             Some(Bin::Name(name)) => Err(format!("Unknown bin.name = {}", name).into()),
+            Some(Bin::Script { shell, .. }) => Ok(std::path::PathBuf::from(shell)),
             Some(Bin::Ignore) => Err("Internal error: tried to run an ignored bin".into()),
+            Some(Bin::CargoWorkspace { package, .. }) => {
+                Err(format!("Internal error: bin for `{}` was not built", package).into())
+            }
             Some(Bin::Error(err)) => Err(err.clone()),
             None => Err("No bin specified".into()),
         }?;
-        if !bin.exists() {
-            return Err(format!("Bin doesn't exist: {}", bin.display()).into());
-        }
 
-        let mut cmd = snapbox::cmd::Command::new(bin).args(&self.args);
+        let mut cmd = if let Some(Bin::Script { shell, script }) = &self.bin {
+            // `shell` is looked up in `PATH`.  Pass it as `$0` so `args` are `$1`, `$2`, etc
+            snapbox::cmd::Command::new(bin)
+                .arg("-c")
+                .arg(script)
+                .arg(shell)
+        } else {
+            if !bin.exists() {
+                return Err(format!("Bin doesn't exist: {}", bin.display()).into());
+            }
+            snapbox::cmd::Command::new(bin)
+        };
+        if let Some(arg0) = &self.arg0 {
+            cmd = with_arg0(cmd, arg0)?;
+        }
+        cmd = cmd.args(&self.args);
         if let Some(cwd) = cwd {
             cmd = cmd.current_dir(cwd);
         }
-        if let Some(stdin) = &self.stdin {
+        if self.null_stdin {
+            cmd = cmd.null_stdin();
+        } else if let Some(stdin) = &self.stdin {
             cmd = cmd.stdin(stdin);
+        } else if self.inherit_stdin {
+            cmd = cmd.stdin_from(std::process::Stdio::inherit());
         }
         if self.stderr_to_stdout {
             cmd = cmd.stderr_to_stdout();
@@ -613,16 +1547,119 @@ impl Step {
         if let Some(timeout) = self.timeout {
             cmd = cmd.timeout(timeout)
         }
+        if let Some(limit) = self.max_output_bytes {
+            cmd = cmd.max_output_bytes(limit);
+        }
+        if let Some(mask) = self.umask {
+            cmd = with_umask(cmd, mask);
+        }
         cmd = self.env.apply(cmd);
 
         Ok(cmd)
     }
 
+    /// Arguments the command runs with, including the bin, as split from the command line
+    pub(crate) fn argv(&self) -> Vec<String> {
+        let mut argv = match &self.bin {
+            Some(Bin::Path(path)) => vec![path.display().to_string()],
+            Some(Bin::Name(name)) => vec![name.clone()],
+            Some(Bin::Script { shell, script }) => {
+                vec![
+                    shell.clone(),
+                    "-c".to_owned(),
+                    script.clone(),
+                    shell.clone(),
+                ]
+            }
+            Some(Bin::CargoWorkspace { package, bin, .. }) => {
+                vec![bin.clone().unwrap_or_else(|| package.clone())]
+            }
+            Some(Bin::Ignore) | Some(Bin::Error(_)) | None => vec![],
+        };
+        if let (Some(arg0), Some(first)) = (&self.arg0, argv.first_mut()) {
+            *first = arg0.clone();
+        }
+        argv.extend(self.args.iter().cloned());
+        argv
+    }
+
+    /// Render the command for display, like in a shell
+    pub(crate) fn to_command_line(&self) -> String {
+        let bin = match &self.bin {
+            Some(Bin::Path(path)) => path.display().to_string(),
+            Some(Bin::Name(name)) => name.clone(),
+            Some(Bin::CargoWorkspace { package, bin, .. }) => {
+                bin.clone().unwrap_or_else(|| package.clone())
+            }
+            Some(Bin::Script { shell, .. }) => shell.clone(),
+            Some(Bin::Ignore) | Some(Bin::Error(_)) | None => String::new(),
+        };
+        // Quote the values separately so the shell still sees assignments
+        let mut words: Vec<_> = self
+            .env
+            .add
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, shlex::quote(v)))
+            .collect();
+        words.push(shlex::quote(&bin).into_owned());
+        if let Some(Bin::Script { shell, script }) = &self.bin {
+            words.push("-c".to_owned());
+            words.push(shlex::quote(script).into_owned());
+            words.push(shlex::quote(shell).into_owned());
+        }
+        words.extend(self.args.iter().map(|a| shlex::quote(a).into_owned()));
+        if let Some(file) = &self.stdin_file {
+            words.push("<".to_owned());
+            words.push(shlex::quote(&file.display().to_string()).into_owned());
+        }
+        for piped in &self.pipeline {
+            words.push("|".to_owned());
+            let step = Step {
+                bin: Some(piped.bin.clone()),
+                args: piped.args.clone(),
+                env: piped.env.clone(),
+                pipeline: Vec::new(),
+                ..Default::default()
+            };
+            words.push(step.to_command_line());
+        }
+        words.join(" ")
+    }
+
     pub(crate) fn expected_status(&self) -> CommandStatus {
         self.expected_status.unwrap_or_default()
     }
 }
 
+#[cfg(unix)]
+fn with_umask(cmd: snapbox::cmd::Command, mask: u32) -> snapbox::cmd::Command {
+    cmd.umask(mask)
+}
+
+// Windows has no umask
+#[cfg(not(unix))]
+fn with_umask(cmd: snapbox::cmd::Command, _mask: u32) -> snapbox::cmd::Command {
+    cmd
+}
+
+#[cfg(unix)]
+fn with_arg0(
+    mut cmd: snapbox::cmd::Command,
+    arg0: &str,
+) -> Result<snapbox::cmd::Command, crate::Error> {
+    use std::os::unix::process::CommandExt;
+    cmd.as_std_mut().arg0(arg0);
+    Ok(cmd)
+}
+
+#[cfg(not(unix))]
+fn with_arg0(
+    _cmd: snapbox::cmd::Command,
+    _arg0: &str,
+) -> Result<snapbox::cmd::Command, crate::Error> {
+    Err("`arg0` is only supported on Unix".into())
+}
+
 /// Top-level data in `cmd.toml` files
 #[derive(Clone, Default, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "kebab-case")]
@@ -631,43 +1668,668 @@ pub struct OneShot {
     pub(crate) bin: Option<Bin>,
     #[serde(default)]
     pub(crate) args: Args,
+    /// Arguments to use in place of
+    /// [`TestCases::default_args`][crate::TestCases::default_args], before `args`
+    #[serde(default, alias = "args_override")]
+    pub(crate) args_override: Option<Args>,
+    /// Name the command sees as `argv[0]` in place of the bin's path, for multi-call binaries
+    /// that act on the name they were run as (Unix only)
+    #[serde(default)]
+    pub(crate) arg0: Option<String>,
     #[serde(default)]
     pub(crate) env: Env,
     #[serde(default)]
-    pub(crate) stdin: Option<String>,
+    pub(crate) stdin: Option<Stdin>,
+    /// Give the command a pipe for `stdin` (default: `true`); when `false`, `stdin` is null, for
+    /// testing how a command acts when `stdin` isn't a pipe
+    #[serde(default, alias = "stdin_pipe")]
+    pub(crate) stdin_pipe: Option<bool>,
+    #[serde(default)]
+    pub(crate) stdout: Option<Expected>,
+    /// Number of lines expected in `stdout`, after normalization
+    #[serde(default)]
+    pub(crate) stdout_lines: Option<usize>,
+    /// Numbers in the output that must be within bounds
+    #[serde(default)]
+    pub(crate) extract: Vec<Extract>,
     #[serde(default)]
-    pub(crate) stdout: Option<String>,
+    pub(crate) stderr: Option<Expected>,
+    /// Compare `stderr` lines without regard to their order
     #[serde(default)]
-    pub(crate) stderr: Option<String>,
+    pub(crate) stderr_unordered: bool,
+    /// Sort `stdout` and `stderr` lines, each on their own, before comparing, for commands that
+    /// write lines in a nondeterministic order
+    #[serde(default, alias = "sort_output_lines")]
+    pub(crate) sort_output_lines: bool,
+    /// Which part of `stdout` to compare against the expected output
+    #[serde(default)]
+    pub(crate) stdout_match_anchor: MatchAnchor,
+    /// Which part of `stderr` to compare against the expected output
+    #[serde(default)]
+    pub(crate) stderr_match_anchor: MatchAnchor,
     #[serde(default)]
     pub(crate) stderr_to_stdout: bool,
+    /// How `stdout` and `stderr` are captured
+    #[serde(default)]
+    pub(crate) output: OutputCapture,
     pub(crate) status: Option<CommandStatus>,
     #[serde(default)]
     pub(crate) binary: bool,
+    /// Encoding of `stdout`, `stderr`, `*.stdout`, and `*.stderr`, like `iso-8859-1` (default: `utf-8`)
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub(crate) encoding: Option<Encoding>,
     #[serde(default)]
     #[serde(deserialize_with = "humantime_serde::deserialize")]
     pub(crate) timeout: Option<std::time::Duration>,
+    /// Expect the command to still be running when `timeout` fires, failing if it exits
+    #[serde(default, alias = "expect_timeout")]
+    pub(crate) expect_timeout: bool,
+    /// File mode creation mask, in octal like `"022"`, see
+    /// [`TestCases::umask`][crate::TestCases::umask]
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub(crate) umask: Option<Umask>,
+    /// Locale, like `C`, see [`TestCases::locale`][crate::TestCases::locale]
+    #[serde(default)]
+    pub(crate) locale: Option<String>,
+    /// Labels for selecting which cases to run
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
+    /// Only run on these platforms, like `unix`, `windows`, `linux`, or `macos`
+    #[serde(default)]
+    pub(crate) platforms: Option<Vec<String>>,
+    /// Run against each of the matrix bins (default: `true`)
+    #[serde(default)]
+    pub(crate) matrix: Option<bool>,
+    /// Serialize with other cases using the same lock name
+    #[serde(default)]
+    pub(crate) lock: Option<String>,
+    /// Working directory, relative to `CARGO_MANIFEST_DIR`, when not sandboxed
+    #[serde(default)]
+    pub(crate) cwd: Option<std::path::PathBuf>,
     #[serde(default)]
     pub(crate) fs: Filesystem,
+    /// Paths, relative to the sandbox, that must not exist after the command runs, like
+    /// `fs.absent`
+    #[serde(default, alias = "files_absent")]
+    pub(crate) files_absent: Vec<FileAbsent>,
+    /// Point `HOME` and the XDG directories at empty directories in a temp directory, see
+    /// [`TestCases::isolate_home`][crate::TestCases::isolate_home]
+    #[serde(default, alias = "isolate_home")]
+    pub(crate) isolate_home: Option<IsolateHome>,
+    /// Run the case this many times, each in a fresh sandbox, failing unless every run passes and
+    /// their outputs are the same after substitutions
+    ///
+    /// With `TRYCMD=overwrite`, the first run's output is written and the other runs are checked
+    /// against it.
+    #[serde(default)]
+    pub(crate) repeat: Option<usize>,
+    /// With `TRYCMD=overwrite`, write `stdout` / `stderr` to `*.stdout` / `*.stderr` when longer
+    /// than this many lines and inline otherwise
+    #[serde(default)]
+    pub(crate) externalize_over: Option<usize>,
+    /// Variables to leave unreplaced when comparing this case's output
+    #[serde(default)]
+    pub(crate) substitutions: SubstitutionOverrides,
+    /// In `.trycmd` front matter, what comment lines start with, like `//` (default: no comments)
+    #[serde(default, alias = "comment_prefix")]
+    pub(crate) comment_prefix: Option<String>,
+    /// In `.trycmd` front matter, run commands with an unquoted `|` as pipelines and read `stdin`
+    /// from the file after an unquoted `<`
+    #[serde(default, alias = "shell_syntax")]
+    pub(crate) shell_syntax: bool,
 }
 
 impl OneShot {
-    fn parse_toml(s: &str) -> Result<Self, crate::Error> {
-        toml_edit::de::from_str(s).map_err(|e| e.to_string().into())
+    fn parse_toml(s: &str) -> Result<Self, crate::ParseError> {
+        parse_toml(s)
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
-#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
-#[serde(untagged)]
-pub(crate) enum Args {
-    Joined(JoinedArgs),
-    Split(Vec<String>),
+fn parse_toml<T: serde::de::DeserializeOwned>(s: &str) -> Result<T, crate::ParseError> {
+    toml_edit::de::from_str(s).map_err(|e| {
+        let line = e
+            .span()
+            .map(|span| s[..span.start].matches('\n').count() + 1);
+        crate::ParseError::new(line, e)
+    })
 }
 
-impl Args {
-    fn new() -> Self {
-        Self::Split(Default::default())
+/// Name of the file with [`DirConfig`]
+const DIR_CONFIG: &str = "trycmd.toml";
+
+/// Defaults for the cases in a directory and below, from `trycmd.toml`
+///
+/// Only the nearest `trycmd.toml`, up to the package root, is used.  Settings in the case file
+/// take precedence.
+#[derive(Clone, Default, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct DirConfig {
+    /// Timeout for cases without a `timeout`, in milliseconds
+    #[serde(default)]
+    pub(crate) timeout_ms: Option<u64>,
+    /// Command for cases without a `bin`
+    #[serde(default)]
+    pub(crate) default_bin: Option<Bin>,
+    /// Environment for all cases, which they can add to or override
+    #[serde(default)]
+    pub(crate) env: Env,
+}
+
+impl DirConfig {
+    pub(crate) fn is_config(path: &std::path::Path) -> bool {
+        path.file_name() == Some(std::ffi::OsStr::new(DIR_CONFIG))
+    }
+
+    /// Look for `trycmd.toml` next to `case`, then in each parent up to the package root
+    pub(crate) fn find(case: &std::path::Path) -> Result<Option<Self>, crate::ParseError> {
+        let mut dir = case.parent();
+        while let Some(current) = dir {
+            let path = current.join(DIR_CONFIG);
+            if path.exists() {
+                return Self::load(&path).map(Some);
+            }
+            if current.join("Cargo.toml").exists() {
+                break;
+            }
+            dir = current.parent();
+        }
+        Ok(None)
+    }
+
+    fn load(path: &std::path::Path) -> Result<Self, crate::ParseError> {
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| crate::ParseError::new(None, e).with_path(path))?;
+        parse_toml(&raw).map_err(|e| e.with_path(path))
+    }
+
+    fn apply(&self, sequence: &mut TryCmd) {
+        for step in &mut sequence.steps {
+            if step.timeout.is_none() {
+                step.timeout = self.timeout_ms.map(std::time::Duration::from_millis);
+            }
+            if step.bin.is_none() {
+                step.bin = self.default_bin.clone();
+            }
+            let removed = step.env.removed_only();
+            let mut env = self.env.clone();
+            env.add.retain(|k, _| !removed.contains(k));
+            env.update(&step.env);
+            env.inherit = step.env.inherit.or(self.env.inherit);
+            env.case_vars = step.env.case_vars.or(self.env.case_vars);
+            step.env = env;
+        }
+    }
+}
+
+/// Name of the file with [`SuiteConfig`]
+const SUITE_CONFIG: &str = "_suite.toml";
+
+/// Name for the suite of cases in a directory, from `_suite.toml`
+///
+/// Without one, the suite is named after the directory, relative to the glob it was found with.
+#[derive(Clone, Default, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct SuiteConfig {
+    pub(crate) name: String,
+}
+
+impl SuiteConfig {
+    pub(crate) fn is_config(path: &std::path::Path) -> bool {
+        path.file_name() == Some(std::ffi::OsStr::new(SUITE_CONFIG))
+    }
+
+    /// Look for `_suite.toml` in `dir`
+    pub(crate) fn find(dir: &std::path::Path) -> Result<Option<Self>, crate::ParseError> {
+        let path = dir.join(SUITE_CONFIG);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let raw = std::fs::read_to_string(&path)
+            .map_err(|e| crate::ParseError::new(None, e).with_path(&path))?;
+        parse_toml(&raw).map(Some).map_err(|e| e.with_path(&path))
+    }
+}
+
+/// Cases listed in a manifest, see
+/// [`TestCases::load_cases_from_manifest`][crate::TestCases::load_cases_from_manifest]
+#[derive(Clone, Default, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct Manifest {
+    #[serde(default, rename = "case")]
+    pub(crate) cases: Vec<ManifestCase>,
+}
+
+impl Manifest {
+    pub(crate) fn load(path: &std::path::Path) -> Result<Self, crate::ParseError> {
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| crate::ParseError::new(None, e).with_path(path))?;
+        let mut manifest: Self = parse_toml(&raw).map_err(|e| e.with_path(path))?;
+        let dir = path.parent().unwrap_or_else(|| std::path::Path::new("."));
+        for case in &mut manifest.cases {
+            case.glob = dir.join(&case.glob);
+        }
+        Ok(manifest)
+    }
+}
+
+/// `[[case]]` in a [`Manifest`]
+///
+/// Only settings that apply to a whole glob of cases.  Everything else in [`OneShot`] belongs in
+/// the case files and is rejected as an unknown field.
+#[derive(Clone, Default, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct ManifestCase {
+    /// Case files, relative to the manifest
+    pub(crate) glob: std::path::PathBuf,
+    /// Expected status of every command, overriding the cases' own
+    #[serde(default)]
+    pub(crate) status: Option<CommandStatus>,
+    /// Timeout for commands without a `timeout`
+    #[serde(default)]
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    pub(crate) timeout: Option<std::time::Duration>,
+    /// Environment for the cases, which takes precedence over their own
+    #[serde(default)]
+    pub(crate) env: Env,
+    /// Labels for selecting which cases to run
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
+}
+
+/// `stdin` for the command
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub(crate) enum Stdin {
+    Inline(String),
+    File(StdinFile),
+}
+
+/// `stdin` read from a file when the command runs
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub(crate) struct StdinFile {
+    /// Relative to the working directory, like the sandbox, so it can be written by `*.in/` or
+    /// an earlier command
+    pub(crate) file: std::path::PathBuf,
+}
+
+/// A path that must not exist after the command runs, see `files-absent` in `*.toml`
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub(crate) struct FileAbsent {
+    /// Relative to the sandbox, like `report.txt` or `build/*.tmp`
+    pub(crate) path: String,
+}
+
+/// A number captured from the output that must be within `min` and `max`, see `extract` in
+/// `*.toml`
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub(crate) struct Extract {
+    /// Regex with one capture group for the number, checked against the first match
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub(crate) pattern: ExtractPattern,
+    #[serde(default)]
+    pub(crate) stream: ExtractStream,
+    /// Smallest value allowed, inclusive
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<f64>"))]
+    pub(crate) min: Option<Bound>,
+    /// Largest value allowed, inclusive
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<f64>"))]
+    pub(crate) max: Option<Bound>,
+}
+
+impl Extract {
+    fn check(&self) -> Result<(), crate::Error> {
+        if self.pattern.0.captures_len() != 2 {
+            return Err(format!(
+                "`extract` pattern `{}` must have exactly one capture group",
+                self.pattern
+            )
+            .into());
+        }
+        if self.min.is_none() && self.max.is_none() {
+            return Err(format!(
+                "`extract` pattern `{}` needs a `min` or `max`",
+                self.pattern
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Check the number captured from `output` against the bounds
+    pub(crate) fn validate(&self, output: &str) -> Result<(), crate::Error> {
+        let captured = self
+            .pattern
+            .0
+            .captures(output)
+            .and_then(|c| c.get(1))
+            .ok_or_else(|| format!("`extract` pattern `{}` didn't match", self.pattern))?
+            .as_str();
+        let value = captured.trim().parse::<f64>().map_err(|_| {
+            format!(
+                "`extract` pattern `{}` captured `{}`, which isn't a number",
+                self.pattern, captured
+            )
+        })?;
+        let in_bounds = self.min.map_or(true, |min| min.0 <= value)
+            && self.max.map_or(true, |max| value <= max.0);
+        if in_bounds {
+            Ok(())
+        } else {
+            Err(format!(
+                "`extract` pattern `{}` captured {}, expected {}",
+                self.pattern,
+                captured,
+                self.bounds()
+            )
+            .into())
+        }
+    }
+
+    fn bounds(&self) -> String {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => format!("between {} and {}", min, max),
+            (Some(min), None) => format!("at least {}", min),
+            (None, Some(max)) => format!("at most {}", max),
+            (None, None) => "anything".to_owned(),
+        }
+    }
+}
+
+/// [`Extract`]'s regex, compiled when the case is parsed
+#[derive(Clone, Debug)]
+pub(crate) struct ExtractPattern(regex::Regex);
+
+impl PartialEq for ExtractPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for ExtractPattern {}
+
+impl std::fmt::Display for ExtractPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.as_str().fmt(f)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ExtractPattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        regex::Regex::new(&pattern).map(Self).map_err(|e| {
+            serde::de::Error::custom(format!("invalid `extract` pattern `{}`: {}", pattern, e))
+        })
+    }
+}
+
+impl serde::Serialize for ExtractPattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+/// [`Extract`]'s `min` or `max`, which is never NaN
+#[derive(Copy, Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(transparent)]
+pub(crate) struct Bound(f64);
+
+// NaN, the only value not equal to itself, is rejected when deserializing
+impl Eq for Bound {}
+
+impl std::fmt::Display for Bound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Bound {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = f64::deserialize(deserializer)?;
+        if value.is_nan() {
+            return Err(serde::de::Error::custom("`extract` bounds can't be `nan`"));
+        }
+        Ok(Self(value))
+    }
+}
+
+/// Output stream an [`Extract`] reads
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub(crate) enum ExtractStream {
+    #[default]
+    Stdout,
+    Stderr,
+}
+
+/// `isolate-home` for the command
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub(crate) enum IsolateHome {
+    Enabled(bool),
+    Seeded(HomeSeed),
+}
+
+impl IsolateHome {
+    pub(crate) fn is_enabled(&self) -> bool {
+        match self {
+            Self::Enabled(enabled) => *enabled,
+            Self::Seeded(_) => true,
+        }
+    }
+
+    /// Directory to copy into the fake home
+    pub(crate) fn seed(&self) -> Option<&std::path::Path> {
+        match self {
+            Self::Enabled(_) => None,
+            Self::Seeded(home) => Some(&home.seed),
+        }
+    }
+}
+
+/// Fake home filled in from a fixture
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub(crate) struct HomeSeed {
+    /// Directory, relative to the case file, copied into the fake home, like `home.in`
+    pub(crate) seed: std::path::PathBuf,
+}
+
+/// Expected `stdout` / `stderr`
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub(crate) enum Expected {
+    Inline(String),
+    Files(ExpectedFiles),
+    SameAs(SameAs),
+}
+
+impl Expected {
+    fn into_parts(
+        expected: Option<Self>,
+    ) -> (
+        Option<crate::Data>,
+        Option<ExpectedFiles>,
+        Option<std::path::PathBuf>,
+    ) {
+        match expected {
+            Some(Self::Inline(text)) => (Some(crate::Data::text(text)), None, None),
+            Some(Self::Files(files)) => (None, Some(files), None),
+            Some(Self::SameAs(same_as)) => (None, None, Some(same_as.same_as)),
+            None => (None, None, None),
+        }
+    }
+}
+
+impl From<String> for Expected {
+    fn from(text: String) -> Self {
+        Self::Inline(text)
+    }
+}
+
+/// Expected output is whatever another case's output was, like for idempotency checks
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub(crate) struct SameAs {
+    /// Case file, relative to the `*.toml` file
+    #[serde(alias = "same_as")]
+    pub(crate) same_as: std::path::PathBuf,
+}
+
+/// Expected output kept in several files that are concatenated, in order
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub(crate) struct ExpectedFiles {
+    /// Relative to the `*.toml` file
+    pub(crate) paths: Vec<std::path::PathBuf>,
+    /// Which of `paths` `TRYCMD=overwrite` writes the changes to
+    #[serde(default)]
+    pub(crate) overwrite: Option<std::path::PathBuf>,
+}
+
+impl ExpectedFiles {
+    fn resolve(&mut self, dir: &std::path::Path, field: &str) -> Result<(), crate::Error> {
+        if self.paths.is_empty() {
+            return Err(format!("`{}.paths` is empty", field).into());
+        }
+        if let Some(overwrite) = &self.overwrite {
+            if !self.paths.contains(overwrite) {
+                return Err(format!(
+                    "`{}.overwrite` must be one of `{}.paths`, not {}",
+                    field,
+                    field,
+                    overwrite.display()
+                )
+                .into());
+            }
+            self.overwrite = Some(dir.join(overwrite));
+        }
+        for path in &mut self.paths {
+            *path = dir.join(&*path);
+        }
+        Ok(())
+    }
+
+    fn read(
+        &self,
+        is_binary: snapbox::DataFormat,
+        encoding: Option<Encoding>,
+    ) -> Result<crate::Data, crate::Error> {
+        let parts = self
+            .paths
+            .iter()
+            .map(|path| read_expected(path, is_binary, encoding))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(concat(&parts))
+    }
+
+    /// Write `output` to the `overwrite` file, if the other files still match around it
+    fn overwrite(
+        &self,
+        output: Option<&crate::Data>,
+        field: &str,
+        binary: bool,
+        encoding: Option<Encoding>,
+    ) -> Result<(), crate::Error> {
+        let output = match output {
+            Some(output) => output,
+            None => return Ok(()),
+        };
+        let target = self.overwrite.as_ref().ok_or_else(|| {
+            format!(
+                "`{}` is split across files, so set `{}.overwrite` to the one that should take the changes",
+                field, field
+            )
+        })?;
+        let index = self
+            .paths
+            .iter()
+            .position(|p| p == target)
+            .expect("validated when loading");
+
+        let is_binary = match binary || encoding.is_some() {
+            true => snapbox::DataFormat::Binary,
+            false => snapbox::DataFormat::Text,
+        };
+        let read_all = |paths: &[std::path::PathBuf]| {
+            paths
+                .iter()
+                .map(|path| read_expected(path, is_binary, encoding))
+                .collect::<Result<Vec<_>, crate::Error>>()
+                .map(|parts| concat(&parts).to_bytes())
+        };
+        let before = read_all(&self.paths[..index])?;
+        let after = read_all(&self.paths[index + 1..])?;
+
+        let actual = output.to_bytes();
+        if actual.len() < before.len() + after.len()
+            || !actual.starts_with(&before)
+            || !actual.ends_with(&after)
+        {
+            return Err(format!(
+                "`{}` changed outside of {}; update the other files by hand",
+                field,
+                target.display()
+            )
+            .into());
+        }
+        let middle = crate::Data::binary(&actual[before.len()..actual.len() - after.len()])
+            .try_coerce(snapbox::DataFormat::Text);
+        match encoding {
+            Some(encoding) => encoding.encode(&middle).write_to(target),
+            None => middle.write_to(target),
+        }
+    }
+}
+
+/// Read an expected `stdout` / `stderr` file
+fn read_expected(
+    path: &std::path::Path,
+    is_binary: snapbox::DataFormat,
+    encoding: Option<Encoding>,
+) -> Result<crate::Data, crate::Error> {
+    Ok(
+        Encoding::decode_all(encoding, crate::Data::read_from(path, Some(is_binary))?)
+            .normalize(NormalizePaths)
+            .normalize(NormalizeNewlines),
+    )
+}
+
+fn concat(parts: &[crate::Data]) -> crate::Data {
+    let rendered = parts.iter().map(|p| p.render()).collect::<Option<Vec<_>>>();
+    match rendered {
+        Some(rendered) => crate::Data::text(rendered.concat()),
+        None => crate::Data::binary(parts.iter().flat_map(|p| p.to_bytes()).collect::<Vec<_>>()),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub(crate) enum Args {
+    Joined(JoinedArgs),
+    Split(Vec<String>),
+}
+
+impl Args {
+    fn new() -> Self {
+        Self::Split(Default::default())
     }
 
     fn as_slice(&self) -> &[String] {
@@ -691,6 +2353,40 @@ impl Default for Args {
     }
 }
 
+// Not `untagged` so quoting errors in the string form are reported
+impl<'de> serde::de::Deserialize<'de> for Args {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        struct ArgsVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for ArgsVisitor {
+            type Value = Args;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("a command line or an array of args")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                s.parse().map(Args::Joined).map_err(E::custom)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                serde::de::Deserialize::deserialize(serde::de::value::SeqAccessDeserializer::new(
+                    seq,
+                ))
+                .map(Args::Split)
+            }
+        }
+
+        deserializer.deserialize_any(ArgsVisitor)
+    }
+}
+
 impl std::ops::Deref for Args {
     type Target = [String];
 
@@ -718,10 +2414,10 @@ impl JoinedArgs {
 }
 
 impl std::str::FromStr for JoinedArgs {
-    type Err = std::convert::Infallible;
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let inner = shlex::Shlex::new(s).collect();
+        let inner = split_words(s)?;
         Ok(Self { inner })
     }
 }
@@ -751,6 +2447,43 @@ impl serde::ser::Serialize for JoinedArgs {
     }
 }
 
+/// Variables set by `locale`
+pub(crate) const LOCALE_VARS: &[&str] = &["LC_ALL", "LANG", "LANGUAGE"];
+
+/// File mode creation mask, written in octal like `"022"`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Umask(pub(crate) u32);
+
+impl std::str::FromStr for Umask {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match u32::from_str_radix(s, 8) {
+            Ok(mask) if mask <= 0o777 => Ok(Self(mask)),
+            _ => Err(format!("`umask` must be in octal, like `022`, not `{}`", s)),
+        }
+    }
+}
+
+impl<'de> serde::de::Deserialize<'de> for Umask {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl serde::ser::Serialize for Umask {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        serializer.serialize_str(&format!("{:03o}", self.0))
+    }
+}
+
 /// Describe the command's filesystem context
 #[derive(Clone, Default, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "kebab-case")]
@@ -760,6 +2493,10 @@ pub struct Filesystem {
     /// Sandbox base
     pub(crate) base: Option<std::path::PathBuf>,
     pub(crate) sandbox: Option<bool>,
+    /// Paths, relative to the sandbox, that must not exist after the command runs, like
+    /// `build/cache.bin` or `tmp/**`
+    #[serde(default)]
+    pub(crate) absent: Vec<String>,
 }
 
 impl Filesystem {
@@ -783,6 +2520,133 @@ impl Filesystem {
     }
 }
 
+/// Per-case opt-outs from the [`TestCases`][crate::TestCases] substitutions, see
+/// `substitutions` in `*.toml`
+#[derive(Clone, Default, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub(crate) struct SubstitutionOverrides {
+    /// Variables to leave unreplaced, like `[CWD]`
+    #[serde(default)]
+    pub(crate) disable: Vec<String>,
+    /// Leave all variables unreplaced, including the built-in ones like `[EXE]`
+    #[serde(default, alias = "disable_all")]
+    pub(crate) disable_all: bool,
+}
+
+impl SubstitutionOverrides {
+    fn check(&self) -> Result<(), crate::ParseError> {
+        for key in &self.disable {
+            if !(key.len() > 2 && key.starts_with('[') && key.ends_with(']')) {
+                return Err(format!(
+                    "`substitutions.disable` entries must be variables like `[CWD]`, not `{}`",
+                    key
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn disables(&self, key: &str) -> bool {
+        self.disable_all || self.disable.iter().any(|k| k == key)
+    }
+
+    /// Drop the disabled variables from `substitutions`
+    pub(crate) fn apply(
+        &self,
+        substitutions: &mut snapbox::Substitutions,
+    ) -> Result<(), crate::Error> {
+        for key in &self.disable {
+            // `[ROOT]` is only set when sandboxed
+            if !substitutions.remove(key) && key != "[ROOT]" {
+                return Err(
+                    format!("`substitutions.disable` names unknown variable `{}`", key).into(),
+                );
+            }
+        }
+        if self.disable_all {
+            *substitutions = snapbox::Substitutions::new();
+        }
+        Ok(())
+    }
+}
+
+/// How a command's output is captured, see `output` in `*.toml`
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum OutputCapture {
+    /// `stdout` and `stderr` are compared separately
+    #[default]
+    Split,
+    /// `stdout` and `stderr` lines are compared together, in the order they were written, against
+    /// `*.interleaved`
+    ///
+    /// `stderr` lines are prefixed with `err: `.  Ordering is best-effort: lines written at nearly
+    /// the same time may be captured in either order.
+    Interleaved,
+}
+
+/// Which part of the actual output is compared against the expected output
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum MatchAnchor {
+    /// All of the output
+    #[default]
+    Full,
+    /// As many lines from the start of the output as are expected
+    Prefix,
+    /// As many lines from the end of the output as are expected
+    Suffix,
+}
+
+/// Character encoding of a command's output, see `encoding` in `*.toml`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Encoding(&'static encoding_rs::Encoding);
+
+impl Encoding {
+    /// Transcode `data` to UTF-8 text, leaving it be without an encoding
+    pub(crate) fn decode_all(encoding: Option<Self>, data: crate::Data) -> crate::Data {
+        match encoding {
+            Some(encoding) => encoding.decode(&data),
+            None => data,
+        }
+    }
+
+    pub(crate) fn decode(&self, data: &crate::Data) -> crate::Data {
+        let bytes = data.to_bytes();
+        let (text, _) = self.0.decode_without_bom_handling(&bytes);
+        crate::Data::text(text)
+    }
+
+    pub(crate) fn encode(&self, data: &crate::Data) -> crate::Data {
+        match data.render() {
+            Some(text) => {
+                let (bytes, _, _) = self.0.encode(&text);
+                crate::Data::binary(bytes)
+            }
+            None => data.clone(),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Encoding {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let label = String::deserialize(deserializer)?;
+        encoding_rs::Encoding::for_label(label.as_bytes())
+            .map(Self)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown encoding `{}`", label)))
+    }
+}
+
+impl serde::Serialize for Encoding {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.name())
+    }
+}
+
 /// Describe command's environment
 #[derive(Clone, Default, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "kebab-case")]
@@ -794,6 +2658,13 @@ pub struct Env {
     pub(crate) add: BTreeMap<String, String>,
     #[serde(default)]
     pub(crate) remove: Vec<String>,
+    /// Set `TRYCMD_CASE`, `TRYCMD_STEP`, and `TRYCMD_SANDBOX` (default: `true`)
+    #[serde(default)]
+    pub(crate) case_vars: Option<bool>,
+    /// Variables still inherited when `inherit` is `false`, see
+    /// [`TestCases::env_inherit_list`][crate::TestCases::env_inherit_list]
+    #[serde(skip)]
+    pub(crate) inherit_only: Vec<String>,
 }
 
 impl Env {
@@ -801,14 +2672,36 @@ impl Env {
         if self.inherit.is_none() {
             self.inherit = other.inherit;
         }
+        if self.case_vars.is_none() {
+            self.case_vars = other.case_vars;
+        }
         self.add
             .extend(other.add.iter().map(|(k, v)| (k.clone(), v.clone())));
         self.remove.extend(other.remove.iter().cloned());
+        self.inherit_only.extend(other.inherit_only.iter().cloned());
+    }
+
+    /// Variables this removes without also adding them
+    ///
+    /// These are dropped from any additions layered on top of this, e.g. by
+    /// [`TestCases::env`][crate::TestCases::env], while a variable both added and removed stays
+    /// set.
+    pub(crate) fn removed_only(&self) -> Vec<String> {
+        self.remove
+            .iter()
+            .filter(|k| !self.add.contains_key(*k))
+            .cloned()
+            .collect()
     }
 
     pub(crate) fn apply(&self, mut command: snapbox::cmd::Command) -> snapbox::cmd::Command {
         if !self.inherit() {
             command = command.env_clear();
+            for key in &self.inherit_only {
+                if let Some(value) = std::env::var_os(key) {
+                    command = command.env(key, value);
+                }
+            }
         }
         for remove in &self.remove {
             command = command.env_remove(&remove);
@@ -819,16 +2712,113 @@ impl Env {
     pub(crate) fn inherit(&self) -> bool {
         self.inherit.unwrap_or(true)
     }
+
+    /// Whether the command will see `key`, whether set here or inherited
+    pub(crate) fn is_set(&self, key: &str) -> bool {
+        if self.add.contains_key(key) {
+            true
+        } else if self.remove.iter().any(|k| k == key) {
+            false
+        } else if self.inherit() || self.inherit_only.iter().any(|k| k == key) {
+            std::env::var_os(key).is_some()
+        } else {
+            false
+        }
+    }
+
+    pub(crate) fn case_vars(&self) -> bool {
+        self.case_vars.unwrap_or(true)
+    }
+
+    /// Describe the case to the command, see [`Env::case_vars`]
+    pub(crate) fn add_case_vars(
+        &mut self,
+        case: &std::path::Path,
+        step: usize,
+        sandbox: Option<&std::path::Path>,
+    ) {
+        if !self.case_vars() {
+            return;
+        }
+        let root = std::env::var_os("CARGO_MANIFEST_DIR").map(std::path::PathBuf::from);
+        let case = root
+            .as_deref()
+            .and_then(|root| case.strip_prefix(root).ok())
+            .unwrap_or(case);
+        let case = case
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        self.add.entry("TRYCMD_CASE".to_owned()).or_insert(case);
+        self.add
+            .entry("TRYCMD_STEP".to_owned())
+            .or_insert_with(|| step.to_string());
+        if let Some(sandbox) = sandbox {
+            self.add
+                .entry("TRYCMD_SANDBOX".to_owned())
+                .or_insert_with(|| sandbox.display().to_string());
+        }
+    }
+
+    /// Point the home directory variables at `home`, see `isolate-home` in [`OneShot`]
+    ///
+    /// These replace any set for the case, even in `env.add`.
+    pub(crate) fn isolate_home(&mut self, home: &std::path::Path) {
+        for (key, dir) in home_dirs(home) {
+            self.add.insert(key.to_owned(), dir.display().to_string());
+        }
+    }
+}
+
+/// Home directory variables and where they point within the fake `home`
+pub(crate) fn home_dirs(home: &std::path::Path) -> Vec<(&'static str, std::path::PathBuf)> {
+    let mut dirs = vec![
+        ("HOME", home.to_owned()),
+        ("XDG_CONFIG_HOME", home.join(".config")),
+        ("XDG_CACHE_HOME", home.join(".cache")),
+        ("XDG_DATA_HOME", home.join(".local").join("share")),
+    ];
+    if cfg!(windows) {
+        dirs.extend([
+            ("USERPROFILE", home.to_owned()),
+            ("APPDATA", home.join("AppData").join("Roaming")),
+            ("LOCALAPPDATA", home.join("AppData").join("Local")),
+        ]);
+    }
+    dirs
 }
 
 /// Target under test
+///
+/// More ways of providing a bin may be added, so matching on this needs a wildcard arm.
 #[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "kebab-case")]
 #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
 pub enum Bin {
     Path(std::path::PathBuf),
     Name(String),
     Ignore,
+    /// Run `script` with `shell -c`, like `sh` or `bash`
+    ///
+    /// `args` are passed to the script as `$1`, `$2`, etc.
+    Script {
+        shell: String,
+        script: String,
+    },
+    /// Build a bin from a package in the current workspace
+    ///
+    /// See [`TestCases::default_bin_workspace`][crate::TestCases::default_bin_workspace]
+    #[serde(skip)]
+    CargoWorkspace {
+        package: String,
+        /// Defaults to the name of `package`
+        bin: Option<String>,
+        features: Vec<String>,
+        /// Cargo profile, like `dev` or `release`
+        profile: String,
+    },
     #[serde(skip)]
     Error(crate::Error),
 }
@@ -932,13 +2922,103 @@ mod test {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn parse_toml_interleaved_conflict() {
+        let root = std::env::temp_dir().join(format!(
+            "trycmd-interleaved-conflict-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("case.toml");
+        std::fs::write(&path, "output = \"interleaved\"\nstderr-to-stdout = true\n").unwrap();
+        let actual = TryCmd::parse_inner(&path, None).unwrap_err();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            actual.message(),
+            "`stderr-to-stdout` can't be used with `output = \"interleaved\"`, which is compared against `*.interleaved`"
+        );
+    }
+
+    #[test]
+    fn parse_toml_stdin_pipe_conflict() {
+        let root =
+            std::env::temp_dir().join(format!("trycmd-stdin-pipe-conflict-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("case.toml");
+        std::fs::write(&path, "stdin-pipe = false\n").unwrap();
+        let null = TryCmd::parse_inner(&path, None).unwrap();
+        std::fs::write(root.join("case.stdin"), "Hello").unwrap();
+        let actual = TryCmd::parse_inner(&path, None).unwrap_err();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(null.steps[0].null_stdin);
+        assert_eq!(
+            actual.message(),
+            "`stdin-pipe = false` can't be combined with `stdin` or `*.stdin`"
+        );
+    }
+
+    #[test]
+    fn extract_bounds() {
+        let extract = Extract {
+            pattern: ExtractPattern(regex::Regex::new(r"processed (\d+) files").unwrap()),
+            stream: ExtractStream::Stdout,
+            min: Some(Bound(1.0)),
+            max: Some(Bound(100.0)),
+        };
+        extract.check().unwrap();
+        assert_eq!(extract.validate("processed 42 files\n"), Ok(()));
+        assert_eq!(
+            extract.validate("processed 150 files\n"),
+            Err(crate::Error::from(
+                "`extract` pattern `processed (\\d+) files` captured 150, expected between 1 and 100"
+            ))
+        );
+        assert_eq!(
+            extract.validate("nothing to do\n"),
+            Err(crate::Error::from(
+                "`extract` pattern `processed (\\d+) files` didn't match"
+            ))
+        );
+
+        let no_capture = Extract {
+            pattern: ExtractPattern(regex::Regex::new(r"processed \d+ files").unwrap()),
+            ..extract
+        };
+        assert_eq!(
+            no_capture.check(),
+            Err(crate::Error::from(
+                "`extract` pattern `processed \\d+ files` must have exactly one capture group"
+            ))
+        );
+    }
+
+    #[test]
+    fn extract_rejected_when_parsed() {
+        let nan = OneShot::parse_toml("[[extract]]\npattern = '(\\d+)'\nmin = nan\n").unwrap_err();
+        assert!(
+            nan.to_string().contains("`extract` bounds can't be `nan`"),
+            "{}",
+            nan
+        );
+        let invalid = OneShot::parse_toml("[[extract]]\npattern = '(\\d+'\nmax = 1\n").unwrap_err();
+        assert!(
+            invalid
+                .to_string()
+                .contains("invalid `extract` pattern `(\\d+`"),
+            "{}",
+            invalid
+        );
+    }
+
     #[test]
     fn parse_trycmd_command() {
         let expected = TryCmd {
             steps: vec![Step {
                 id: Some("3".into()),
                 bin: Some(Bin::Name("cmd".into())),
-                expected_status: Some(CommandStatus::Success),
+                expected_status: None,
                 stderr_to_stdout: true,
                 expected_stdout_source: Some(4..4),
                 expected_stdout: Some(crate::Data::new()),
@@ -965,7 +3045,7 @@ $ cmd
                 id: Some("3".into()),
                 bin: Some(Bin::Name("cmd".into())),
                 args: vec!["arg1".into(), "arg with space".into()],
-                expected_status: Some(CommandStatus::Success),
+                expected_status: None,
                 stderr_to_stdout: true,
                 expected_stdout_source: Some(4..4),
                 expected_stdout: Some(crate::Data::new()),
@@ -992,7 +3072,90 @@ $ cmd arg1 'arg with space'
                 id: Some("3".into()),
                 bin: Some(Bin::Name("cmd".into())),
                 args: vec!["arg1".into(), "arg with space".into()],
-                expected_status: Some(CommandStatus::Success),
+                expected_status: None,
+                stderr_to_stdout: true,
+                expected_stdout_source: Some(5..5),
+                expected_stdout: Some(crate::Data::new()),
+                expected_stderr: None,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let actual = TryCmd::parse_trycmd(
+            "
+```
+$ cmd arg1
+> 'arg with space'
+```
+",
+        )
+        .unwrap();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn parse_trycmd_env() {
+        let expected = TryCmd {
+            steps: vec![Step {
+                id: Some("3".into()),
+                bin: Some(Bin::Name("cmd".into())),
+                env: Env {
+                    add: IntoIterator::into_iter([
+                        ("KEY1".into(), "VALUE1".into()),
+                        ("KEY2".into(), "VALUE2 with space".into()),
+                    ])
+                    .collect(),
+                    ..Default::default()
+                },
+                expected_status: None,
+                stderr_to_stdout: true,
+                expected_stdout_source: Some(4..4),
+                expected_stdout: Some(crate::Data::new()),
+                expected_stderr: None,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let actual = TryCmd::parse_trycmd(
+            "
+```
+$ KEY1=VALUE1 KEY2='VALUE2 with space' cmd
+```
+",
+        )
+        .unwrap();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn parse_trycmd_pipeline() {
+        let expected = TryCmd {
+            steps: vec![Step {
+                id: Some("3".into()),
+                bin: Some(Bin::Name("cmd".into())),
+                args: vec!["a|b".into()],
+                env: Env {
+                    add: IntoIterator::into_iter([("KEY".into(), "VALUE".into())]).collect(),
+                    ..Default::default()
+                },
+                pipeline: vec![
+                    Piped {
+                        bin: Bin::Name("sort".into()),
+                        args: vec!["-r".into()],
+                        env: Env::default(),
+                        unset: vec!["KEY".into()],
+                    },
+                    Piped {
+                        bin: Bin::Name("head".into()),
+                        args: vec![],
+                        env: Env {
+                            add: IntoIterator::into_iter([("LINES".into(), "1".into())]).collect(),
+                            ..Default::default()
+                        },
+                        unset: vec!["KEY".into()],
+                    },
+                ],
+                expected_status: None,
                 stderr_to_stdout: true,
                 expected_stdout_source: Some(5..5),
                 expected_stdout: Some(crate::Data::new()),
@@ -1003,48 +3166,148 @@ $ cmd arg1 'arg with space'
         };
         let actual = TryCmd::parse_trycmd(
             "
-```
-$ cmd arg1
-> 'arg with space'
+```trycmd,shell-syntax
+$ KEY=VALUE cmd 'a|b' | sort -r
+> | LINES=1 head
 ```
 ",
         )
         .unwrap();
         assert_eq!(expected, actual);
+
+        for (cmd, error) in [
+            ("$ cmd |\n", "No bin specified on line 2"),
+            ("$ cd dir | cmd\n", "`cd` can't be part of a pipeline"),
+        ] {
+            let actual =
+                TryCmd::parse_trycmd(&format!("```trycmd,shell-syntax\n{}```\n", cmd)).unwrap_err();
+            assert!(actual.to_string().contains(error), "{}", actual);
+        }
     }
 
     #[test]
-    fn parse_trycmd_env() {
+    fn parse_trycmd_stdin_file() {
+        let actual = TryCmd::parse_trycmd(
+            "---\nshell-syntax = true\n---\n```\n$ cmd '<' \"a < b\" < 'plan file.json'\n```\n",
+        )
+        .unwrap();
+        let step = &actual.steps[0];
+        assert_eq!(step.args, ["<", "a < b"]);
+        assert_eq!(
+            step.stdin_file.as_deref(),
+            Some(std::path::Path::new("plan file.json"))
+        );
+        assert_eq!(
+            step.to_command_line(),
+            "cmd \"<\" \"a < b\" < \"plan file.json\""
+        );
+
+        for (cmd, error) in [
+            ("$ cmd <\n", "`<` takes exactly one file"),
+            ("$ cmd < a < b\n", "`stdin` can only be read from one file"),
+            ("$ cmd | cmd < a\n", "Only the first command of a pipeline"),
+        ] {
+            let actual =
+                TryCmd::parse_trycmd(&format!("```trycmd,shell-syntax\n{}```\n", cmd)).unwrap_err();
+            assert!(actual.to_string().contains(error), "{}", actual);
+        }
+    }
+
+    #[test]
+    fn parse_trycmd_no_shell_syntax_by_default() {
+        let actual = TryCmd::parse_trycmd("```\n$ cmd a | b < c\n```\n").unwrap();
+        let step = &actual.steps[0];
+        assert_eq!(step.args, ["a", "|", "b", "<", "c"]);
+        assert!(step.pipeline.is_empty());
+        assert_eq!(step.stdin_file, None);
+    }
+
+    #[test]
+    fn parse_trycmd_front_matter() {
         let expected = TryCmd {
             steps: vec![Step {
-                id: Some("3".into()),
+                id: Some("7".into()),
                 bin: Some(Bin::Name("cmd".into())),
                 env: Env {
                     add: IntoIterator::into_iter([
                         ("KEY1".into(), "VALUE1".into()),
-                        ("KEY2".into(), "VALUE2 with space".into()),
+                        ("KEY2".into(), "override".into()),
                     ])
                     .collect(),
                     ..Default::default()
                 },
-                expected_status: Some(CommandStatus::Success),
+                expected_status: None,
                 stderr_to_stdout: true,
-                expected_stdout_source: Some(4..4),
+                expected_stdout_source: Some(8..8),
                 expected_stdout: Some(crate::Data::new()),
-                expected_stderr: None,
+                timeout: Some(std::time::Duration::from_secs(5)),
                 ..Default::default()
             }],
+            tags: vec!["slow".into()],
             ..Default::default()
         };
+        for (open, close) in [("---", "---"), ("```toml", "```")] {
+            let actual = TryCmd::parse_trycmd(&format!(
+                "{}
+timeout = \"5s\"
+tags = [\"slow\"]
+env.add = {{ KEY1 = \"VALUE1\", KEY2 = \"VALUE2\" }}
+{}
+```
+$ KEY2=override cmd
+```
+",
+                open, close
+            ))
+            .unwrap();
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn parse_trycmd_front_matter_error_line() {
+        let err = TryCmd::parse_trycmd(
+            "```toml
+timeout = \"5s\"
+tags = slow
+```
+```
+$ cmd
+```
+",
+        )
+        .unwrap_err();
+        assert_eq!(err.line(), Some(3));
+
+        for (key, raw) in [
+            ("bin", "bin.name = \"cmd\""),
+            ("stderr-to-stdout", "stderr-to-stdout = true"),
+            ("platforms", "platforms = [\"unix\"]"),
+            ("matrix", "matrix = false"),
+        ] {
+            let err = TryCmd::parse_trycmd(&format!("---\n{}\n---\n", raw)).unwrap_err();
+            assert_eq!(
+                err.message(),
+                format!("`{}` is not supported in front matter", key)
+            );
+        }
+    }
+
+    #[test]
+    fn parse_trycmd_yaml_front_matter() {
         let actual = TryCmd::parse_trycmd(
-            "
+            "---
+title: Usage
+---
 ```
-$ KEY1=VALUE1 KEY2='VALUE2 with space' cmd
+$ cmd
 ```
 ",
         )
         .unwrap();
-        assert_eq!(expected, actual);
+        assert_eq!(actual.steps.len(), 1);
+        assert_eq!(actual.steps[0].id.as_deref(), Some("5"));
+        assert_eq!(actual.steps[0].timeout, None);
     }
 
     #[test]
@@ -1109,7 +3372,7 @@ $ cmd
             steps: vec![Step {
                 id: Some("3".into()),
                 bin: Some(Bin::Name("cmd".into())),
-                expected_status: Some(CommandStatus::Success),
+                expected_status: None,
                 stderr_to_stdout: true,
                 expected_stdout_source: Some(4..6),
                 expected_stdout: Some(crate::Data::text("Hello World\n")),
@@ -1136,7 +3399,7 @@ Hello World
             steps: vec![Step {
                 id: Some("3".into()),
                 bin: Some(Bin::Name("cmd".into())),
-                expected_status: Some(CommandStatus::Success),
+                expected_status: None,
                 stderr_to_stdout: true,
                 expected_stdout_source: Some(4..7),
                 expected_stdout: Some(crate::Data::text("```\nHello World\n```")),
@@ -1176,7 +3439,7 @@ Hello World
                 Step {
                     id: Some("5".into()),
                     bin: Some(Bin::Name("cmd2".into())),
-                    expected_status: Some(CommandStatus::Success),
+                    expected_status: None,
                     stderr_to_stdout: true,
                     expected_stdout_source: Some(6..6),
                     expected_stdout: Some(crate::Data::new()),
@@ -1199,6 +3462,127 @@ $ cmd2
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn parse_trycmd_comments() {
+        let expected = TryCmd {
+            steps: vec![
+                Step {
+                    id: Some("6".into()),
+                    bin: Some(Bin::Name("cmd1".into())),
+                    stderr_to_stdout: true,
+                    expected_stdout_source: Some(7..10),
+                    expected_stdout: Some(crate::Data::text("Hello\n// not a comment\nBye")),
+                    ..Default::default()
+                },
+                Step {
+                    id: Some("11".into()),
+                    bin: Some(Bin::Name("cmd2".into())),
+                    stderr_to_stdout: true,
+                    expected_stdout_source: Some(12..13),
+                    expected_stdout: Some(crate::Data::text("World")),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let actual = TryCmd::parse_trycmd(
+            "---
+comment-prefix = \"//\"
+---
+```
+// Says hello
+$ cmd1
+Hello
+// not a comment
+Bye
+// Says world
+$ cmd2
+World
+// trailing
+```
+",
+        )
+        .unwrap();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn parse_trycmd_no_comments_by_default() {
+        let actual = TryCmd::parse_trycmd(
+            "
+```
+$ cmd1
+Hello
+// World
+$ cmd2
+```
+",
+        )
+        .unwrap();
+        assert_eq!(
+            actual.steps[0].expected_stdout,
+            Some(crate::Data::text("Hello\n// World"))
+        );
+    }
+
+    #[test]
+    fn parse_trycmd_comment_prefix() {
+        let actual = TryCmd::parse_trycmd(
+            "---
+comment-prefix = \"# \"
+---
+```
+# Says hello
+$ cmd1
+# [IGNORE START]
+# [IGNORE END]
+# Says world
+$ cmd2
+```
+",
+        )
+        .unwrap();
+        let stdout: Vec<_> = actual
+            .steps
+            .iter()
+            .map(|step| step.expected_stdout.as_ref().unwrap().to_string())
+            .collect();
+        assert_eq!(stdout, ["# [IGNORE START]\n# [IGNORE END]", ""]);
+    }
+
+    #[test]
+    fn programs_in_all_blocks() {
+        let actual = programs(
+            "Intro
+
+```console,shell-syntax
+$ FOO=1 cmd1 --flag | sort
+output
+$ cmd2 \\
+> < input.txt
+```
+
+```console,ignore
+$ removed deploy
+```
+
+```rust
+$ not-a-command
+```
+",
+        )
+        .unwrap();
+        assert_eq!(
+            actual,
+            [
+                (4, "cmd1".to_owned()),
+                (4, "sort".to_owned()),
+                (6, "cmd2".to_owned()),
+                (11, "removed".to_owned()),
+            ]
+        );
+    }
+
     #[test]
     fn parse_trycmd_info_string() {
         let expected = TryCmd {
@@ -1281,6 +3665,38 @@ $ rust-cmd1
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn parse_trycmd_missing_cmd_line() {
+        let err = TryCmd::parse_trycmd(
+            "
+```
+$ cmd
+? 1
+```
+
+```
+cmd
+```
+",
+        )
+        .unwrap_err();
+        assert_eq!(err.line(), Some(8));
+    }
+
+    #[test]
+    fn parse_trycmd_bad_status_line() {
+        let err = TryCmd::parse_trycmd(
+            "
+```
+$ cmd
+? sucess
+```
+",
+        )
+        .unwrap_err();
+        assert_eq!(err.line(), Some(4));
+    }
+
     #[test]
     fn parse_toml_minimal() {
         let expected = OneShot {
@@ -1342,6 +3758,79 @@ $ rust-cmd1
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn parse_toml_args_both_forms() {
+        let err = OneShot::parse_toml(
+            r#"args = "arg1"
+args = ["arg1"]"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("duplicate key"), "{}", err);
+    }
+
+    #[test]
+    fn args_quoting() {
+        let cases: &[(&str, &[&str])] = &[
+            ("build --quiet", &["build", "--quiet"]),
+            ("--features 'foo bar'", &["--features", "foo bar"]),
+            (r#"--features "foo bar""#, &["--features", "foo bar"]),
+            (r#"--msg "it's""#, &["--msg", "it's"]),
+            (r#"--msg 'say "hi"'"#, &["--msg", r#"say "hi""#]),
+            (r#"a\ b"#, &["a b"]),
+            ("--name=''", &["--name="]),
+            ("'' x", &["", "x"]),
+            ("  spaced   out  ", &["spaced", "out"]),
+            (r#"pre'fix'"suf"fix"#, &["prefixsuffix"]),
+            (r#""\$HOME""#, &["$HOME"]),
+        ];
+        for (joined, expected) in cases {
+            let expected: Vec<_> = expected.iter().map(|s| s.to_string()).collect();
+
+            let toml = format!("args = {}", toml_edit::Value::from(*joined));
+            let joined_args = OneShot::parse_toml(&toml).unwrap().args.into_vec();
+            assert_eq!(joined_args, expected, "joined: {}", toml);
+
+            let array: toml_edit::Array = expected.iter().map(String::as_str).collect();
+            let toml = format!("args = {}", array);
+            let split_args = OneShot::parse_toml(&toml).unwrap().args.into_vec();
+            assert_eq!(split_args, expected, "split: {}", toml);
+
+            let trycmd = format!("```\n$ cmd {}\n```\n", joined);
+            let step = TryCmd::parse_trycmd(&trycmd).unwrap().steps.remove(0);
+            assert_eq!(step.args, expected, "trycmd: {}", joined);
+        }
+    }
+
+    #[test]
+    fn args_unterminated_quote() {
+        let err = OneShot::parse_toml(r#"args = "--features 'foo bar""#).unwrap_err();
+        assert!(err.to_string().contains("Unterminated quote"), "{}", err);
+
+        let err = TryCmd::parse_trycmd("```\n$ cmd --features 'foo bar\n```\n").unwrap_err();
+        assert!(err.to_string().contains("Unterminated quote"), "{}", err);
+    }
+
+    #[test]
+    fn arg0_in_argv() {
+        let step = Step {
+            bin: Some(Bin::Path("/usr/bin/busybox".into())),
+            args: vec!["-d".into()],
+            arg0: Some("gzip".into()),
+            ..Default::default()
+        };
+        assert_eq!(step.argv(), ["gzip", "-d"]);
+    }
+
+    #[test]
+    fn parse_toml_umask() {
+        let actual = OneShot::parse_toml("umask = '027'").unwrap();
+        assert_eq!(actual.umask, Some(Umask(0o027)));
+        for invalid in ["'999'", "'1000'", "'-22'", "22"] {
+            let err = OneShot::parse_toml(&format!("umask = {}", invalid)).unwrap_err();
+            assert!(err.to_string().contains("umask"), "{}: {}", invalid, err);
+        }
+    }
+
     #[test]
     fn parse_toml_status_success() {
         let expected = OneShot {
@@ -1362,6 +3851,95 @@ $ rust-cmd1
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn parse_toml_error_line() {
+        let err = OneShot::parse_toml(
+            r#"bin.name = "cmd"
+args = ["arg1"]
+status = "sucess"
+"#,
+        )
+        .unwrap_err();
+        assert_eq!(err.line(), Some(3));
+    }
+
+    #[test]
+    fn parse_toml_encoding() {
+        let actual = OneShot::parse_toml(r#"encoding = "iso-8859-1""#).unwrap();
+        assert_eq!(actual.encoding, Some(Encoding(encoding_rs::WINDOWS_1252)),);
+
+        let err = OneShot::parse_toml(
+            r#"bin.name = "cmd"
+encoding = "klingon"
+"#,
+        )
+        .unwrap_err();
+        assert_eq!(err.line(), Some(2));
+        assert!(
+            err.to_string().contains("unknown encoding `klingon`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn parse_toml_tags() {
+        let expected = OneShot {
+            tags: vec!["slow".into(), "network".into()],
+            ..Default::default()
+        };
+        let actual = OneShot::parse_toml(r#"tags = ["slow", "network"]"#).unwrap();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn parse_toml_lock() {
+        let expected = OneShot {
+            lock: Some("repo-root".into()),
+            ..Default::default()
+        };
+        let actual = OneShot::parse_toml(r#"lock = "repo-root""#).unwrap();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn parse_toml_files_absent() {
+        let expected = OneShot {
+            fs: Filesystem {
+                absent: vec!["build/cache.bin".into()],
+                ..Default::default()
+            },
+            files_absent: vec![
+                FileAbsent {
+                    path: "report.txt".into(),
+                },
+                FileAbsent {
+                    path: "build/*.tmp".into(),
+                },
+            ],
+            ..Default::default()
+        };
+        let actual = OneShot::parse_toml(
+            r#"
+fs.absent = ["build/cache.bin"]
+
+[[files-absent]]
+path = "report.txt"
+
+[[files-absent]]
+path = "build/*.tmp"
+"#,
+        )
+        .unwrap();
+        assert_eq!(expected, actual);
+
+        let sequence = TryCmd::from(actual);
+        assert_eq!(
+            sequence.fs.absent,
+            ["build/cache.bin", "report.txt", "build/*.tmp"]
+        );
+    }
+
     #[test]
     fn replace_lines_same_line_count() {
         let input = "One\nTwo\nThree";
@@ -1422,6 +4000,299 @@ $ rust-cmd1
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn snapshot_dir() {
+        let case = std::path::Path::new("tests/cmd/help.toml");
+        assert_eq!(
+            snapshot_path(None, case, "stdout"),
+            std::path::Path::new("tests/cmd/help.stdout")
+        );
+        let dir = std::path::Path::new("tests/snapshots");
+        assert_eq!(
+            snapshot_path(Some(dir), case, "stdout"),
+            std::path::Path::new("tests/snapshots/tests/cmd/help.stdout")
+        );
+        let absolute = std::env::current_dir().unwrap().join(case);
+        assert_eq!(
+            snapshot_path(Some(dir), &absolute, "stderr"),
+            std::path::Path::new("tests/snapshots/tests/cmd/help.stderr")
+        );
+        assert_eq!(
+            snapshot_path(
+                Some(dir),
+                std::path::Path::new("../other/help.toml"),
+                "stdout"
+            ),
+            std::path::Path::new("tests/snapshots/_parent_/other/help.stdout")
+        );
+        assert_eq!(
+            snapshot_path(Some(dir), std::path::Path::new("other/help.toml"), "stdout"),
+            std::path::Path::new("tests/snapshots/other/help.stdout")
+        );
+    }
+
+    #[test]
+    fn overwrite_toml_output_externalize() {
+        let root = std::env::temp_dir().join(format!("trycmd-externalize-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("case.toml");
+        let stdout_path = path.with_extension("stdout");
+        std::fs::write(
+            &path,
+            "# Comment\nbin.name = \"cmd\"\nstdout = \"\"\n\n[env.add]\nKEY = \"value\"\n",
+        )
+        .unwrap();
+
+        let long = crate::Data::text("1\n2\n3\n");
+        overwrite_toml_output(
+            &path,
+            None,
+            Some(&long),
+            &stdout_path,
+            "stdout",
+            Some(2),
+            None,
+        )
+        .unwrap();
+        let externalized = std::fs::read_to_string(&path).unwrap();
+        let externalized_stdout = std::fs::read_to_string(&stdout_path).unwrap();
+
+        let short = crate::Data::text("1\n2\n");
+        overwrite_toml_output(
+            &path,
+            None,
+            Some(&short),
+            &stdout_path,
+            "stdout",
+            Some(2),
+            None,
+        )
+        .unwrap();
+        let inlined = std::fs::read_to_string(&path).unwrap();
+        let inlined_stdout = stdout_path.exists();
+
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            externalized,
+            "# Comment\nbin.name = \"cmd\"\n\n[env.add]\nKEY = \"value\"\n"
+        );
+        assert_eq!(externalized_stdout, "1\n2\n3\n");
+        assert_eq!(
+            inlined,
+            "# Comment\nbin.name = \"cmd\"\nstdout = \"\"\"\n1\n2\n\"\"\"\n\n[env.add]\nKEY = \"value\"\n"
+        );
+        assert!(!inlined_stdout);
+    }
+
+    #[test]
+    fn overwrite_toml_output_encoding() {
+        let root = std::env::temp_dir().join(format!("trycmd-encoding-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("case.toml");
+        let stdout_path = path.with_extension("stdout");
+        std::fs::write(&path, "bin.name = \"cmd\"\nencoding = \"iso-8859-1\"\n").unwrap();
+        std::fs::write(&stdout_path, "").unwrap();
+
+        let encoding = Some(Encoding(encoding_rs::WINDOWS_1252));
+        let stdout = crate::Data::text("caf\u{e9}\n");
+        overwrite_toml_output(
+            &path,
+            None,
+            Some(&stdout),
+            &stdout_path,
+            "stdout",
+            None,
+            encoding,
+        )
+        .unwrap();
+        let actual = std::fs::read(&stdout_path).unwrap();
+
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(actual, b"caf\xe9\n");
+    }
+
+    #[test]
+    fn overwrite_toml_interleaved() {
+        let root = std::env::temp_dir().join(format!("trycmd-interleaved-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("case.toml");
+        let raw = "bin.name = \"cmd\"\noutput = \"interleaved\"\n";
+        std::fs::write(&path, raw).unwrap();
+
+        let sequence = TryCmd::load(&path, None).unwrap();
+        let output = crate::Data::text("one\nerr: two\n");
+        sequence
+            .overwrite(&path, None, Some(&output), None, None, None)
+            .unwrap();
+        let actual = std::fs::read_to_string(path.with_extension("interleaved")).unwrap();
+        let case = std::fs::read_to_string(&path).unwrap();
+
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(actual, "one\nerr: two\n");
+        assert_eq!(case, raw);
+    }
+
+    #[test]
+    fn parse_toml_stdout_files() {
+        let expected = OneShot {
+            stdout: Some(Expected::Files(ExpectedFiles {
+                paths: vec!["head.out".into(), "body.out".into()],
+                overwrite: Some("body.out".into()),
+            })),
+            ..Default::default()
+        };
+        let actual = OneShot::parse_toml(
+            r#"stdout = { paths = ["head.out", "body.out"], overwrite = "body.out" }"#,
+        )
+        .unwrap();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn expected_files_overwrite() {
+        let root = std::env::temp_dir().join(format!("trycmd-split-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("head.out"), "head\n").unwrap();
+        std::fs::write(root.join("body.out"), "body\n").unwrap();
+        std::fs::write(root.join("tail.out"), "tail\n").unwrap();
+        let mut files = ExpectedFiles {
+            paths: vec!["head.out".into(), "body.out".into(), "tail.out".into()],
+            overwrite: Some("body.out".into()),
+        };
+        files.resolve(&root, "stdout").unwrap();
+
+        let changed = crate::Data::text("head\nnew body\ntail\n");
+        files
+            .overwrite(Some(&changed), "stdout", false, None)
+            .unwrap();
+        let body = std::fs::read_to_string(root.join("body.out")).unwrap();
+        let reread = files.read(snapbox::DataFormat::Text, None).unwrap();
+
+        let outside = crate::Data::text("new head\nnew body\ntail\n");
+        let err = files
+            .overwrite(Some(&outside), "stdout", false, None)
+            .unwrap_err();
+
+        files.overwrite = None;
+        let unmarked = files
+            .overwrite(Some(&changed), "stdout", false, None)
+            .unwrap_err();
+
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(body, "new body\n");
+        assert_eq!(reread, changed);
+        assert!(err.to_string().contains("changed outside of"), "{}", err);
+        assert!(
+            unmarked.to_string().contains("set `stdout.overwrite`"),
+            "{}",
+            unmarked
+        );
+    }
+
+    #[test]
+    fn expected_files_resolve_unknown_overwrite() {
+        let mut files = ExpectedFiles {
+            paths: vec!["head.out".into()],
+            overwrite: Some("body.out".into()),
+        };
+        let err = files
+            .resolve(std::path::Path::new("tests"), "stdout")
+            .unwrap_err();
+        assert_eq!(
+            err,
+            crate::Error::from("`stdout.overwrite` must be one of `stdout.paths`, not body.out")
+        );
+    }
+
+    #[test]
+    fn dir_config_defaults() {
+        let config: DirConfig = parse_toml(
+            r#"timeout-ms = 1500
+default-bin.name = "cmd"
+
+[env]
+inherit = false
+add.SHARED = "config"
+add.CONFIG = "config"
+"#,
+        )
+        .unwrap();
+        let mut sequence = TryCmd {
+            steps: vec![
+                Step::default(),
+                Step {
+                    bin: Some(Bin::Name("other".into())),
+                    timeout: Some(std::time::Duration::from_secs(1)),
+                    env: Env {
+                        add: [("SHARED".to_owned(), "case".to_owned())]
+                            .into_iter()
+                            .collect(),
+                        inherit: Some(true),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        config.apply(&mut sequence);
+
+        let defaulted = &sequence.steps[0];
+        assert_eq!(
+            defaulted.timeout,
+            Some(std::time::Duration::from_millis(1500))
+        );
+        assert_eq!(defaulted.bin, Some(Bin::Name("cmd".into())));
+        assert!(!defaulted.env.inherit());
+        assert_eq!(defaulted.env.add["SHARED"], "config");
+
+        let overridden = &sequence.steps[1];
+        assert_eq!(overridden.timeout, Some(std::time::Duration::from_secs(1)));
+        assert_eq!(overridden.bin, Some(Bin::Name("other".into())));
+        assert!(overridden.env.inherit());
+        assert_eq!(overridden.env.add["SHARED"], "case");
+        assert_eq!(overridden.env.add["CONFIG"], "config");
+    }
+
+    #[test]
+    fn dir_config_env_remove() {
+        let config: DirConfig = parse_toml(
+            r#"[env]
+add.REMOVED = "config"
+add.READDED = "config"
+"#,
+        )
+        .unwrap();
+        let mut sequence = TryCmd {
+            steps: vec![Step {
+                env: Env {
+                    add: [("READDED".to_owned(), "case".to_owned())]
+                        .into_iter()
+                        .collect(),
+                    remove: vec!["REMOVED".to_owned(), "READDED".to_owned()],
+                    ..Default::default()
+                },
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        config.apply(&mut sequence);
+
+        let env = &sequence.steps[0].env;
+        assert!(!env.add.contains_key("REMOVED"));
+        assert_eq!(env.add["READDED"], "case");
+    }
+
+    #[test]
+    fn dir_config_unknown_key() {
+        let err = parse_toml::<DirConfig>("timeout = \"5s\"\n").unwrap_err();
+        assert_eq!(err.line(), Some(1));
+    }
+
     #[test]
     fn overwrite_toml_status_success() {
         let expected = r#"
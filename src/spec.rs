@@ -1,22 +1,149 @@
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+/// Precedence of the default bins, by the [`TestCases`][crate::TestCases] method that set them
+const DEFAULT_BIN_ORDER: &[&str] = &[
+    "default_bin_path",
+    "default_bin_workspace",
+    "default_bin_script",
+    "default_bin_name",
+];
+
+/// Locale set by [`TestCases::hermetic`][crate::TestCases::hermetic]
+const HERMETIC_LOCALE: &str = "C";
+/// Umask set by [`TestCases::hermetic`][crate::TestCases::hermetic]
+const HERMETIC_UMASK: u32 = 0o022;
+/// Terminal size set by [`TestCases::hermetic`][crate::TestCases::hermetic]
+pub(crate) const HERMETIC_TERMINAL: &[(&str, &str)] = &[("COLUMNS", "80"), ("LINES", "24")];
+/// Variables still inherited with [`TestCases::hermetic`][crate::TestCases::hermetic], to be
+/// able to find and run programs
+const HERMETIC_INHERIT: &[&str] = &["PATH", "SYSTEMROOT"];
 
 #[derive(Debug)]
 pub(crate) struct RunnerSpec {
     cases: Vec<CaseSpec>,
+    /// Problems registering cases, reported as failed cases
+    errors: Vec<(std::path::PathBuf, crate::Error)>,
+    tags: Vec<TagSpec>,
+    fixtures: Vec<FixtureSpec>,
+    ordering: Vec<OrderSpec>,
+    sort_lines: Vec<std::path::PathBuf>,
     include: Option<Vec<String>>,
-    default_bin: Option<crate::schema::Bin>,
+    include_tags: Option<Vec<String>>,
+    only_tags: Option<Vec<String>>,
+    skip_tags: Vec<String>,
+    filters: Vec<CaseFilter>,
+    require_matches: bool,
+    require_binary_exists: bool,
+    allow_empty: Vec<std::path::PathBuf>,
+    case_count: Option<usize>,
+    check_artifacts: bool,
+    strict_artifacts: bool,
+    default_bins: Vec<(&'static str, crate::schema::Bin)>,
+    bin_fallback: Vec<crate::schema::Bin>,
+    bins: Vec<BinSpec>,
+    per_line: Vec<PerLineSpec>,
+    weights: Vec<WeightSpec>,
+    matrix: Vec<(String, crate::schema::Bin)>,
     timeout: Option<std::time::Duration>,
+    externalize_over: Option<usize>,
+    snapshot_dir: Option<std::path::PathBuf>,
+    default_args: Option<Vec<String>>,
+    default_stdin: Option<crate::Data>,
+    inherit_stdin: bool,
+    max_output_bytes: Option<usize>,
+    verify_commands: bool,
+    allowed_commands: Option<Vec<String>>,
+    abort_on_panic: bool,
+    locale: Option<String>,
+    umask: Option<u32>,
+    hermetic: bool,
+    default_status: Option<crate::schema::CommandStatus>,
+    default_cwd: Option<std::path::PathBuf>,
     env: crate::schema::Env,
+    /// Variables set from a command's output, see [`RunnerSpec::resolve_env_commands`]
+    env_commands: Vec<(String, Vec<String>)>,
+    seeds: BTreeMap<String, String>,
+    secrets: Vec<String>,
+    strip_trailing_whitespace: bool,
+    collapse_repeated: Option<usize>,
+    trailing_newline: Option<crate::TrailingNewline>,
+    truncate_output: Option<usize>,
+    verbose: u8,
+    working_copy: bool,
+    isolate_home: bool,
+    share_fixtures: bool,
+    dump_on_failure: Option<std::path::PathBuf>,
+    fail_context: Option<(std::path::PathBuf, usize)>,
+    no_new_snapshots: bool,
+    quiet: bool,
+    abort_after: Option<usize>,
+    comparators: crate::comparator::Comparators,
+    configure_command: Option<crate::runner::CommandHook>,
+    format: crate::OutputFormat,
 }
 
 impl RunnerSpec {
     pub(crate) fn new() -> Self {
         Self {
             cases: Default::default(),
+            errors: Default::default(),
+            tags: Default::default(),
+            fixtures: Default::default(),
+            ordering: Default::default(),
+            sort_lines: Default::default(),
             include: None,
-            default_bin: None,
+            include_tags: None,
+            only_tags: None,
+            skip_tags: Default::default(),
+            filters: Default::default(),
+            require_matches: false,
+            require_binary_exists: false,
+            allow_empty: Default::default(),
+            case_count: None,
+            check_artifacts: true,
+            strict_artifacts: false,
+            default_bins: Vec::new(),
+            bin_fallback: Vec::new(),
+            bins: Default::default(),
+            per_line: Default::default(),
+            weights: Default::default(),
+            matrix: Default::default(),
             timeout: Default::default(),
+            externalize_over: None,
+            snapshot_dir: None,
+            default_args: None,
+            default_stdin: None,
+            inherit_stdin: false,
+            max_output_bytes: None,
+            verify_commands: false,
+            allowed_commands: None,
+            abort_on_panic: false,
+            locale: None,
+            umask: None,
+            hermetic: false,
+            default_status: None,
+            default_cwd: None,
             env: Default::default(),
+            env_commands: Default::default(),
+            seeds: Default::default(),
+            secrets: Default::default(),
+            strip_trailing_whitespace: false,
+            collapse_repeated: None,
+            trailing_newline: None,
+            truncate_output: None,
+            verbose: 0,
+            working_copy: false,
+            isolate_home: false,
+            share_fixtures: false,
+            dump_on_failure: None,
+            fail_context: None,
+            no_new_snapshots: false,
+            quiet: false,
+            abort_after: None,
+            comparators: Default::default(),
+            configure_command: None,
+            format: Default::default(),
         }
     }
 
@@ -31,49 +158,477 @@ impl RunnerSpec {
             expected,
             #[cfg(miri)]
             expected: Some(crate::schema::CommandStatus::Skipped),
+            timeout: None,
+            env: Default::default(),
+        });
+    }
+
+    pub(crate) fn manifest(&mut self, path: &std::path::Path) {
+        let manifest = match crate::schema::Manifest::load(path) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                self.errors.push((path.into(), err.into()));
+                return;
+            }
+        };
+        for case in manifest.cases {
+            self.case(&case.glob, case.status);
+            if let Some(spec) = self.cases.last_mut() {
+                spec.timeout = case.timeout;
+                spec.env = case.env;
+            }
+            for tag in case.tags {
+                self.case_tag(&case.glob, tag);
+            }
+        }
+    }
+
+    pub(crate) fn case_tag(&mut self, glob: &std::path::Path, tag: String) {
+        self.tags.push(TagSpec {
+            glob: glob.into(),
+            tag,
         });
     }
 
+    pub(crate) fn case_fixtures(&mut self, glob: &std::path::Path, dir: &std::path::Path) {
+        self.fixtures.push(FixtureSpec {
+            glob: glob.into(),
+            dir: dir.into(),
+        });
+    }
+
+    pub(crate) fn case_ordered_before(
+        &mut self,
+        before: &std::path::Path,
+        after: &std::path::Path,
+    ) {
+        self.ordering.push(OrderSpec {
+            before: before.into(),
+            after: after.into(),
+        });
+    }
+
+    pub(crate) fn sort_lines(&mut self, glob: &std::path::Path) {
+        self.sort_lines.push(glob.into());
+    }
+
+    pub(crate) fn require_matches(&mut self, yes: bool) {
+        self.require_matches = yes;
+    }
+
+    pub(crate) fn require_binary_exists(&mut self, yes: bool) {
+        self.require_binary_exists = yes;
+    }
+
+    pub(crate) fn allow_empty(&mut self, glob: &std::path::Path) {
+        self.allow_empty.push(glob.into());
+    }
+
+    pub(crate) fn case_count(&mut self, count: usize) {
+        self.case_count = Some(count);
+    }
+
+    pub(crate) fn check_artifacts(&mut self, yes: bool) {
+        self.check_artifacts = yes;
+    }
+
+    pub(crate) fn strict_artifacts(&mut self, yes: bool) {
+        self.strict_artifacts = yes;
+    }
+
     pub(crate) fn include(&mut self, include: Option<Vec<String>>) {
         self.include = include;
     }
 
-    pub(crate) fn default_bin(&mut self, bin: Option<crate::schema::Bin>) {
-        self.default_bin = bin;
+    pub(crate) fn include_tags(&mut self, tags: Option<Vec<String>>) {
+        self.include_tags = tags;
+    }
+
+    pub(crate) fn only_tags(&mut self, tags: Option<Vec<String>>) {
+        self.only_tags = tags;
+    }
+
+    pub(crate) fn skip_tags(&mut self, tags: Vec<String>) {
+        self.skip_tags = tags;
+    }
+
+    pub(crate) fn filter(&mut self, filter: CaseFilter) {
+        self.filters.push(filter);
+    }
+
+    /// Set the default bin of the kind set by the [`TestCases`][crate::TestCases] method `source`
+    pub(crate) fn default_bin(&mut self, source: &'static str, bin: crate::schema::Bin) {
+        self.default_bins.retain(|(s, _)| *s != source);
+        self.default_bins.push((source, bin));
+        self.default_bins
+            .sort_by_key(|(s, _)| DEFAULT_BIN_ORDER.iter().position(|o| o == s));
+    }
+
+    pub(crate) fn bin_fallback(&mut self, bins: Vec<crate::schema::Bin>) {
+        self.bin_fallback = bins;
+    }
+
+    pub(crate) fn case_bin(&mut self, glob: &std::path::Path, bin: crate::schema::Bin) {
+        self.bins.push(BinSpec {
+            glob: glob.into(),
+            bin,
+        });
+    }
+
+    pub(crate) fn case_per_line(&mut self, input: &std::path::Path, template: &std::path::Path) {
+        self.per_line.push(PerLineSpec {
+            input: input.into(),
+            template: template.into(),
+        });
+    }
+
+    pub(crate) fn case_weight(&mut self, glob: &std::path::Path, weight: u32) {
+        self.weights.push(WeightSpec {
+            glob: glob.into(),
+            weight,
+        });
+    }
+
+    pub(crate) fn matrix_bins(&mut self, bins: Vec<(String, crate::schema::Bin)>) {
+        self.matrix = bins;
     }
 
     pub(crate) fn timeout(&mut self, time: Option<std::time::Duration>) {
         self.timeout = time;
     }
 
+    pub(crate) fn externalize_over(&mut self, lines: Option<usize>) {
+        self.externalize_over = lines;
+    }
+
+    pub(crate) fn snapshot_dir(&mut self, dir: Option<std::path::PathBuf>) {
+        self.snapshot_dir = dir;
+    }
+
+    pub(crate) fn default_args(&mut self, args: Option<Vec<String>>) {
+        self.default_args = args;
+    }
+
+    pub(crate) fn default_stdin(&mut self, stdin: Option<crate::Data>) {
+        self.default_stdin = stdin;
+    }
+
+    pub(crate) fn inherit_stdin(&mut self, yes: bool) {
+        self.inherit_stdin = yes;
+    }
+
+    pub(crate) fn max_output_bytes(&mut self, limit: Option<usize>) {
+        self.max_output_bytes = limit;
+    }
+
+    pub(crate) fn verify_commands(&mut self, yes: bool) {
+        self.verify_commands = yes;
+    }
+
+    pub(crate) fn allowed_commands(&mut self, names: Option<Vec<String>>) {
+        self.allowed_commands = names;
+    }
+
+    pub(crate) fn abort_on_panic(&mut self, yes: bool) {
+        self.abort_on_panic = yes;
+    }
+
+    pub(crate) fn locale(&mut self, locale: String) {
+        self.locale = Some(locale);
+    }
+
+    pub(crate) fn umask(&mut self, mask: u32) {
+        self.umask = Some(mask);
+    }
+
+    pub(crate) fn hermetic(&mut self, yes: bool) {
+        self.hermetic = yes;
+    }
+
+    pub(crate) fn default_status(&mut self, status: Option<crate::schema::CommandStatus>) {
+        self.default_status = status;
+    }
+
+    pub(crate) fn default_cwd(&mut self, cwd: Option<std::path::PathBuf>) {
+        self.default_cwd = cwd;
+    }
+
     pub(crate) fn env(&mut self, key: impl Into<String>, value: impl Into<String>) {
         self.env.add.insert(key.into(), value.into());
     }
 
+    pub(crate) fn env_from_command(&mut self, key: String, command: Vec<String>) {
+        self.env_commands.push((key, command));
+    }
+
+    pub(crate) fn env_inherit_list(&mut self, keys: Vec<String>) {
+        self.env.inherit = Some(false);
+        self.env.inherit_only = keys;
+    }
+
+    pub(crate) fn env_remove(&mut self, key: impl Into<String>) {
+        self.env.remove.push(key.into());
+    }
+
+    pub(crate) fn env_secret(&mut self, key: String, value: String) {
+        self.env(key, value.clone());
+        if !value.is_empty() {
+            self.secrets.push(value);
+        }
+    }
+
+    pub(crate) fn seed_env(&mut self, key: String, value: String) {
+        self.env(key.clone(), value.clone());
+        self.seeds.insert(key, value);
+    }
+
+    pub(crate) fn strip_trailing_whitespace(&mut self, yes: bool) {
+        self.strip_trailing_whitespace = yes;
+    }
+
+    pub(crate) fn collapse_repeated(&mut self, threshold: usize) {
+        self.collapse_repeated = Some(threshold);
+    }
+
+    pub(crate) fn trailing_newline(&mut self, policy: crate::TrailingNewline) {
+        self.trailing_newline = Some(policy);
+    }
+
+    pub(crate) fn truncate_output(&mut self, bytes: usize) {
+        self.truncate_output = Some(bytes);
+    }
+
+    pub(crate) fn verbose(&mut self, yes: bool) {
+        self.verbose = if yes { self.verbose.max(1) } else { 0 };
+    }
+
+    pub(crate) fn verbosity(&mut self, level: u8) {
+        self.verbose = level;
+    }
+
+    pub(crate) fn working_copy(&mut self, yes: bool) {
+        self.working_copy = yes;
+    }
+
+    pub(crate) fn isolate_home(&mut self, yes: bool) {
+        self.isolate_home = yes;
+    }
+
+    pub(crate) fn share_fixtures(&mut self, yes: bool) {
+        self.share_fixtures = yes;
+    }
+
+    pub(crate) fn dump_on_failure(&mut self, dir: Option<std::path::PathBuf>) {
+        self.dump_on_failure = dir;
+    }
+
+    pub(crate) fn fail_context(&mut self, context: Option<(std::path::PathBuf, usize)>) {
+        self.fail_context = context;
+    }
+
+    pub(crate) fn no_new_snapshots(&mut self, yes: bool) {
+        self.no_new_snapshots = yes;
+    }
+
+    pub(crate) fn quiet(&mut self, yes: bool) {
+        self.quiet = yes;
+    }
+
+    pub(crate) fn abort_after(&mut self, failures: usize) {
+        self.abort_after = Some(failures);
+    }
+
+    pub(crate) fn comparator(
+        &mut self,
+        extension: String,
+        comparator: crate::comparator::Comparator,
+    ) {
+        self.comparators.register(extension, comparator);
+    }
+
+    pub(crate) fn configure_command(&mut self, hook: crate::runner::CommandHook) {
+        self.configure_command = Some(hook);
+    }
+
+    pub(crate) fn output_format(&mut self, format: crate::OutputFormat) {
+        self.format = format;
+    }
+
+    /// Merge in `other`
+    ///
+    /// Cases and tags from `other` are added after ours, so they take precedence for overlapping
+    /// globs.  Settings explicitly set in `other` replace ours.
+    pub(crate) fn update(&mut self, other: &Self) {
+        self.cases.extend(other.cases.iter().cloned());
+        self.errors.extend(other.errors.iter().cloned());
+        self.tags.extend(other.tags.iter().cloned());
+        self.fixtures.extend(other.fixtures.iter().cloned());
+        self.ordering.extend(other.ordering.iter().cloned());
+        self.sort_lines.extend(other.sort_lines.iter().cloned());
+        if other.only_tags.is_some() {
+            self.only_tags = other.only_tags.clone();
+        }
+        self.skip_tags.extend(other.skip_tags.iter().cloned());
+        self.filters.extend(other.filters.iter().cloned());
+        self.require_matches |= other.require_matches;
+        self.require_binary_exists |= other.require_binary_exists;
+        self.allow_empty.extend(other.allow_empty.iter().cloned());
+        if other.case_count.is_some() {
+            self.case_count = other.case_count;
+        }
+        self.check_artifacts &= other.check_artifacts;
+        self.strict_artifacts |= other.strict_artifacts;
+        for (source, bin) in &other.default_bins {
+            self.default_bin(source, bin.clone());
+        }
+        if !other.bin_fallback.is_empty() {
+            self.bin_fallback = other.bin_fallback.clone();
+        }
+        self.bins.extend(other.bins.iter().cloned());
+        self.per_line.extend(other.per_line.iter().cloned());
+        self.weights.extend(other.weights.iter().cloned());
+        if !other.matrix.is_empty() {
+            self.matrix = other.matrix.clone();
+        }
+        if other.timeout.is_some() {
+            self.timeout = other.timeout;
+        }
+        if other.externalize_over.is_some() {
+            self.externalize_over = other.externalize_over;
+        }
+        if other.snapshot_dir.is_some() {
+            self.snapshot_dir = other.snapshot_dir.clone();
+        }
+        if other.default_args.is_some() {
+            self.default_args = other.default_args.clone();
+        }
+        if other.default_stdin.is_some() {
+            self.default_stdin = other.default_stdin.clone();
+        }
+        self.inherit_stdin |= other.inherit_stdin;
+        if other.max_output_bytes.is_some() {
+            self.max_output_bytes = other.max_output_bytes;
+        }
+        self.verify_commands |= other.verify_commands;
+        self.abort_on_panic |= other.abort_on_panic;
+        if other.locale.is_some() {
+            self.locale = other.locale.clone();
+        }
+        if other.umask.is_some() {
+            self.umask = other.umask;
+        }
+        self.hermetic |= other.hermetic;
+        if other.allowed_commands.is_some() {
+            self.allowed_commands = other.allowed_commands.clone();
+        }
+        if other.default_status.is_some() {
+            self.default_status = other.default_status;
+        }
+        if other.default_cwd.is_some() {
+            self.default_cwd = other.default_cwd.clone();
+        }
+        self.env.update(&other.env);
+        self.env_commands.extend(other.env_commands.iter().cloned());
+        self.seeds
+            .extend(other.seeds.iter().map(|(k, v)| (k.clone(), v.clone())));
+        self.secrets.extend(other.secrets.iter().cloned());
+        self.strip_trailing_whitespace |= other.strip_trailing_whitespace;
+        if other.collapse_repeated.is_some() {
+            self.collapse_repeated = other.collapse_repeated;
+        }
+        if other.trailing_newline.is_some() {
+            self.trailing_newline = other.trailing_newline;
+        }
+        if other.truncate_output.is_some() {
+            self.truncate_output = other.truncate_output;
+        }
+        self.verbose = self.verbose.max(other.verbose);
+        self.working_copy |= other.working_copy;
+        self.isolate_home |= other.isolate_home;
+        self.share_fixtures |= other.share_fixtures;
+        self.no_new_snapshots |= other.no_new_snapshots;
+        self.quiet |= other.quiet;
+        if other.abort_after.is_some() {
+            self.abort_after = other.abort_after;
+        }
+        self.comparators.update(&other.comparators);
+        if other.configure_command.is_some() {
+            self.configure_command = other.configure_command.clone();
+        }
+        if other.dump_on_failure.is_some() {
+            self.dump_on_failure = other.dump_on_failure.clone();
+        }
+        if other.fail_context.is_some() {
+            self.fail_context = other.fail_context.clone();
+        }
+        if other.format != crate::OutputFormat::Text {
+            self.format = other.format.clone();
+        }
+    }
+
     pub(crate) fn prepare(&mut self) -> crate::Runner {
         let mut runner = crate::Runner::new();
+        runner.output_format(self.format.clone());
+        runner.no_new_snapshots(self.no_new_snapshots);
+        runner.quiet(self.quiet);
+        runner.abort_after(self.abort_after);
+
+        for bin in self
+            .default_bins
+            .iter_mut()
+            .map(|(_, bin)| bin)
+            .chain(self.bin_fallback.iter_mut())
+            .chain(self.bins.iter_mut().map(|spec| &mut spec.bin))
+            .chain(self.matrix.iter_mut().map(|(_, bin)| bin))
+        {
+            if matches!(bin, crate::schema::Bin::CargoWorkspace { .. }) {
+                *bin = crate::cargo::build_workspace_bin(bin);
+            }
+        }
+        let failed = self.resolve_env_commands();
+        if !failed.is_empty() {
+            for (command, err) in failed {
+                runner.case(crate::Case::with_error(command, err));
+            }
+            return runner;
+        }
+        if self.require_binary_exists {
+            let missing = self.missing_bins();
+            if !missing.is_empty() {
+                for path in missing {
+                    let err = crate::Error::new(format!("binary not found: {}", path.display()));
+                    runner.case(crate::Case::with_error(path, err));
+                }
+                return runner;
+            }
+        }
 
         // Both sort and let the last writer win to allow overriding specific cases within a glob
         let mut cases: BTreeMap<std::path::PathBuf, crate::Case> = BTreeMap::new();
+        for (path, err) in &self.errors {
+            cases.insert(
+                path.clone(),
+                crate::Case::with_error(path.clone(), err.clone()),
+            );
+        }
 
         for spec in &self.cases {
             if let Some(glob) = get_glob(&spec.glob) {
                 match ::glob::glob(glob) {
                     Ok(paths) => {
+                        let mut matched = false;
                         for path in paths {
+                            // Settings for the cases, rather than a case itself
+                            if matches!(&path, Ok(path) if crate::schema::DirConfig::is_config(path) || crate::schema::SuiteConfig::is_config(path))
+                            {
+                                continue;
+                            }
+                            matched = true;
                             match path {
                                 Ok(path) => {
-                                    cases.insert(
-                                        path.clone(),
-                                        crate::Case {
-                                            path,
-                                            expected: spec.expected,
-                                            default_bin: self.default_bin.clone(),
-                                            timeout: self.timeout,
-                                            env: self.env.clone(),
-                                            error: None,
-                                        },
-                                    );
+                                    let mut case = self.new_case(path.clone(), spec.expected);
+                                    spec.apply(&mut case);
+                                    cases.insert(path, case);
                                 }
                                 Err(err) => {
                                     let path = err.path().to_owned();
@@ -82,6 +637,20 @@ impl RunnerSpec {
                                 }
                             }
                         }
+                        if !matched
+                            && self.require_matches
+                            && !self.allow_empty.contains(&spec.glob)
+                        {
+                            let err = crate::Error::new(format!(
+                                "No test cases matched `{}` in {}",
+                                glob,
+                                glob_root(&spec.glob).display()
+                            ));
+                            cases.insert(
+                                spec.glob.clone(),
+                                crate::Case::with_error(spec.glob.clone(), err),
+                            );
+                        }
                     }
                     Err(err) => {
                         let err = crate::Error::new(err.to_string());
@@ -93,37 +662,577 @@ impl RunnerSpec {
                 }
             } else {
                 let path = spec.glob.as_path();
-                cases.insert(
-                    path.into(),
-                    crate::Case {
-                        path: path.into(),
-                        expected: spec.expected,
-                        default_bin: self.default_bin.clone(),
-                        timeout: self.timeout,
-                        env: self.env.clone(),
-                        error: None,
-                    },
-                );
+                let mut case = self.new_case(path.into(), spec.expected);
+                spec.apply(&mut case);
+                cases.insert(path.into(), case);
             }
         }
 
-        for case in cases.into_values() {
-            if self.is_included(&case) {
+        for spec in &self.per_line {
+            self.per_line_cases(spec, &mut cases);
+        }
+
+        self.check_shared_artifacts(&mut cases);
+        self.check_case_count(&mut cases);
+        self.check_orphaned_artifacts(&mut cases);
+
+        // Cases compared against with `same-as` hand their output to the cases comparing
+        let compared: BTreeSet<_> = cases
+            .values()
+            .flat_map(|case| crate::schema::TryCmd::load_same_as(case.file()))
+            .collect();
+        let captured = crate::CapturedOutputs::default();
+
+        // `BTreeMap` orders paths by component, which varies with the separator
+        let mut cases: Vec<_> = cases.into_values().collect();
+        let cwd = std::env::current_dir().unwrap_or_default();
+        cases.sort_by_cached_key(|case| sort_key(&cwd, &case.path));
+
+        let roots = self.suite_roots();
+        for mut case in cases {
+            match case_suite(&roots, &case.path) {
+                Ok(suite) => case.suite = suite,
+                Err(err) => {
+                    if case.error.is_none() {
+                        case.error = Some(crate::runner::SpawnStatus::Failure(err.into()));
+                    }
+                }
+            }
+            case.capture_output = compared.contains(&case.path);
+            case.captured = captured.clone();
+            if case.error.is_none()
+                && case.expected != Some(crate::schema::CommandStatus::Skipped)
+                && !crate::schema::TryCmd::supports_platform(case.file())
+            {
+                case.expected = Some(crate::schema::CommandStatus::Skipped);
+                case.skip_reason = Some(format!("not supported on {}", std::env::consts::OS));
+            }
+
+            // Always report problems with the test setup
+            if case.error.is_some() {
                 runner.case(case);
+            } else if self.is_included(&case) {
+                if !self.matrix.is_empty() && crate::schema::TryCmd::load_matrix(case.file()) {
+                    for (i, (label, bin)) in self.matrix.iter().enumerate() {
+                        let mut case = case.clone();
+                        case.matrix_leg = Some(crate::MatrixLeg {
+                            label: label.clone(),
+                            bin: bin.clone(),
+                            canonical: i == 0,
+                        });
+                        runner.case(case);
+                    }
+                } else {
+                    runner.case(case);
+                }
+            }
+        }
+
+        let weights = runner
+            .cases()
+            .iter()
+            .map(|case| self.case_weight_of(&case.path))
+            .collect();
+        runner.weights(weights);
+        match self.case_stages(runner.cases()) {
+            Ok(stages) => runner.stages(stages),
+            Err((path, err)) => {
+                runner.case(crate::Case::with_error(path, err));
             }
         }
 
         runner
     }
 
+    /// A case running `spec.template` for each non-blank line of `spec.input`
+    fn per_line_cases(
+        &self,
+        spec: &PerLineSpec,
+        cases: &mut BTreeMap<std::path::PathBuf, crate::Case>,
+    ) {
+        let input = match std::fs::read_to_string(&spec.input) {
+            Ok(input) => input,
+            Err(e) => {
+                let err =
+                    crate::Error::new(format!("Failed to read {}: {}", spec.input.display(), e));
+                cases.insert(
+                    spec.input.clone(),
+                    crate::Case::with_error(spec.input.clone(), err),
+                );
+                return;
+            }
+        };
+        for (i, line) in input.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let line_num = i + 1;
+            let mut case = self.new_case(spec.template.clone(), None);
+            case.default_stdin = Some(crate::Data::text(format!("{}\n", line)));
+            case.input_line = Some(line_num);
+            case.path = crate::runner::input_line_path(&spec.template, line_num);
+            case.template = Some(spec.template.clone());
+            cases.insert(case.path.clone(), case);
+        }
+    }
+
+    /// Report an error when [`RunnerSpec::case_count`] doesn't match what was discovered
+    ///
+    /// Cases are counted before filtering, so this is unaffected by `trycmd=` and tags.
+    fn check_case_count(&self, cases: &mut BTreeMap<std::path::PathBuf, crate::Case>) {
+        let expected = match self.case_count {
+            Some(expected) => expected,
+            None => return,
+        };
+        if cases.len() == expected {
+            return;
+        }
+        let mut message = format!("Expected {} cases, found {}:", expected, cases.len());
+        for path in cases.keys() {
+            message.push_str(&format!("\n  {}", path.display()));
+        }
+        let path = std::path::PathBuf::from("assert_case_count");
+        cases.insert(
+            path.clone(),
+            crate::Case::with_error(path, crate::Error::new(message)),
+        );
+    }
+
+    /// Warn about `*.stdout`, `*.stderr`, `*.in/`, and `*.out/` next to the cases that no case
+    /// uses, or report an error with [`RunnerSpec::strict_artifacts`]
+    ///
+    /// Like with [`RunnerSpec::check_case_count`], this is before filtering, so the artifacts of
+    /// cases left out by `trycmd=` and tags are still in use.
+    fn check_orphaned_artifacts(&self, cases: &mut BTreeMap<std::path::PathBuf, crate::Case>) {
+        if !self.check_artifacts {
+            return;
+        }
+        let orphans = self.orphaned_artifacts(cases.values().map(|case| case.file()));
+        if orphans.is_empty() {
+            return;
+        }
+        let mut message = format!("{} artifacts aren't used by any case:", orphans.len());
+        for path in &orphans {
+            message.push_str(&format!("\n  {}", path.display()));
+        }
+        if self.strict_artifacts {
+            let path = std::path::PathBuf::from("orphaned_artifacts");
+            cases.insert(
+                path.clone(),
+                crate::Case::with_error(path, crate::Error::new(message)),
+            );
+        } else {
+            let palette = snapbox::report::Palette::color();
+            eprintln!("{}", palette.warn(message));
+            eprintln!(
+                "{}",
+                palette.hint("Fail on these with `TRYCMD_STRICT_ARTIFACTS=1`")
+            );
+        }
+    }
+
+    fn orphaned_artifacts<'p>(
+        &self,
+        cases: impl Iterator<Item = &'p std::path::Path>,
+    ) -> BTreeSet<std::path::PathBuf> {
+        let used: BTreeSet<_> = cases
+            .flat_map(|path| {
+                let mut used = artifact_paths(path, self.snapshot_dir.as_deref());
+                used.extend(crate::schema::TryCmd::load_expected_paths(path));
+                used
+            })
+            .collect();
+        let mut orphans = BTreeSet::new();
+        for spec in &self.cases {
+            let dir = match get_glob(&spec.glob).and_then(|_| spec.glob.parent()) {
+                Some(dir) => dir,
+                None => continue,
+            };
+            let pattern = dir.join("*");
+            let paths = match pattern.to_str().map(::glob::glob) {
+                Some(Ok(paths)) => paths,
+                _ => continue,
+            };
+            for path in paths.flatten() {
+                let is_artifact = match path.extension().and_then(|e| e.to_str()) {
+                    Some("stdout") | Some("stderr") => path.is_file(),
+                    Some("in") | Some("out") => path.is_dir(),
+                    _ => false,
+                };
+                if is_artifact && !used.contains(&path) {
+                    orphans.insert(path);
+                }
+            }
+        }
+        orphans
+    }
+
+    /// Like [`RunnerSpec::prepare`] but for the case at `path` alone, regardless of filters
+    ///
+    /// Settings for globs matching `path` still apply.
+    pub(crate) fn prepare_one(&self, path: &std::path::Path) -> crate::Runner {
+        let mut spec = Self::new();
+        spec.update(self);
+        spec.only_tags = None;
+        spec.skip_tags.clear();
+        spec.filters.clear();
+        spec.require_matches = false;
+        spec.case_count = None;
+        spec.check_artifacts = false;
+        if !spec.cases.iter().any(|case| glob_matches(&case.glob, path)) {
+            spec.case(path, None);
+        }
+        spec.prepare()
+    }
+
+    /// Run each command for [`TestCases::env_var_from_command`][crate::TestCases::env_var_from_command]
+    /// and set its variable to the trimmed `stdout`
+    ///
+    /// Commands only run once; those that failed are returned, named by their command line, and
+    /// are tried again on the next run.
+    fn resolve_env_commands(&mut self) -> Vec<(std::path::PathBuf, crate::Error)> {
+        let mut failed = Vec::new();
+        for (key, command) in std::mem::take(&mut self.env_commands) {
+            match env_from_command(&key, &command) {
+                Ok(value) => self.env(key, value),
+                Err(err) => {
+                    failed.push((std::path::PathBuf::from(command.join(" ")), err));
+                    self.env_commands.push((key, command));
+                }
+            }
+        }
+        failed
+    }
+
+    /// Paths of the default, [`BinSpec`], and matrix bins that don't exist
+    ///
+    /// Missing default bins are fine as long as another bin in the chain, including
+    /// [`RunnerSpec::bin_fallback`], might be found.
+    fn missing_bins(&self) -> Vec<std::path::PathBuf> {
+        let is_missing = |bin: &crate::schema::Bin| match bin {
+            crate::schema::Bin::Path(path) => !path.exists(),
+            _ => false,
+        };
+        let chain: Vec<_> = self
+            .default_bins
+            .iter()
+            .map(|(_, bin)| bin)
+            .chain(self.bin_fallback.iter())
+            .collect();
+        let default_bins = if chain.iter().all(|bin| is_missing(bin)) {
+            chain
+        } else {
+            Vec::new()
+        };
+
+        let mut missing = Vec::new();
+        for bin in default_bins
+            .into_iter()
+            .chain(self.bins.iter().map(|spec| &spec.bin))
+            .chain(self.matrix.iter().map(|(_, b)| b))
+        {
+            if let crate::schema::Bin::Path(path) = bin {
+                if !path.exists() && !missing.contains(path) {
+                    missing.push(path.clone());
+                }
+            }
+        }
+        missing
+    }
+
+    /// Group cases so that each [`OrderSpec`] has its `before` cases in earlier stages than its
+    /// `after` cases, and cases compared against with `same-as` run before those comparing
+    ///
+    /// On failure, returns a case in the cycle to report the error against
+    fn case_stages(
+        &self,
+        cases: &[crate::Case],
+    ) -> Result<Vec<usize>, (std::path::PathBuf, crate::Error)> {
+        let mut successors = vec![Vec::new(); cases.len()];
+        let mut predecessors = vec![0; cases.len()];
+        let mut rules: Vec<_> = self
+            .ordering
+            .iter()
+            .map(|o| format!("{} before {}", o.before.display(), o.after.display()))
+            .collect();
+        for (a, after) in cases.iter().enumerate() {
+            for same_as in crate::schema::TryCmd::load_same_as(&after.path) {
+                for (b, before) in cases.iter().enumerate() {
+                    if b != a && before.path == same_as {
+                        successors[b].push(a);
+                        predecessors[a] += 1;
+                    }
+                }
+                let rule = format!("{} same-as {}", after.path.display(), same_as.display());
+                if !rules.contains(&rule) {
+                    rules.push(rule);
+                }
+            }
+        }
+        for order in &self.ordering {
+            for (b, before) in cases.iter().enumerate() {
+                if !glob_matches(&order.before, &before.path) {
+                    continue;
+                }
+                for (a, after) in cases.iter().enumerate() {
+                    if b != a && glob_matches(&order.after, &after.path) {
+                        successors[b].push(a);
+                        predecessors[a] += 1;
+                    }
+                }
+            }
+        }
+
+        let mut stages = vec![0; cases.len()];
+        let mut ready: Vec<_> = (0..cases.len()).filter(|i| predecessors[*i] == 0).collect();
+        let mut staged = 0;
+        while let Some(i) = ready.pop() {
+            staged += 1;
+            for &next in &successors[i] {
+                stages[next] = stages[next].max(stages[i] + 1);
+                predecessors[next] -= 1;
+                if predecessors[next] == 0 {
+                    ready.push(next);
+                }
+            }
+        }
+        if staged < cases.len() {
+            let unstaged = (0..cases.len())
+                .find(|i| predecessors[*i] != 0)
+                .expect("some case is left over");
+            return Err((
+                cases[unstaged].path.clone(),
+                format!(
+                    "Case ordering can't be satisfied as it is circular: {}",
+                    rules.join(", ")
+                )
+                .into(),
+            ));
+        }
+        Ok(stages)
+    }
+
+    /// Report cases that would read or write each other's sidecars, `*.in/`, or `*.out/`
+    fn check_shared_artifacts(&self, cases: &mut BTreeMap<std::path::PathBuf, crate::Case>) {
+        let mut owners: BTreeMap<std::path::PathBuf, std::path::PathBuf> = BTreeMap::new();
+        let mut collisions = Vec::new();
+        for case in cases.values() {
+            if case.error.is_some() || case.expected == Some(crate::schema::CommandStatus::Skipped)
+            {
+                continue;
+            }
+            for artifact in artifact_paths(case.file(), self.snapshot_dir.as_deref()) {
+                if !artifact.exists() {
+                    continue;
+                }
+                match owners.get(&artifact) {
+                    // Lines of a `case_per_line` input share their template's artifacts
+                    Some(owner) if cases[owner].file() != case.file() => {
+                        collisions.push((owner.clone(), case.path.clone(), artifact));
+                    }
+                    Some(_) => {}
+                    None => {
+                        owners.insert(artifact, case.path.clone());
+                    }
+                }
+            }
+        }
+
+        for (first, second, artifact) in collisions {
+            for (path, other) in [(&first, &second), (&second, &first)] {
+                let case = cases
+                    .get_mut(path)
+                    .expect("collisions are between known cases");
+                if case.error.is_some() {
+                    continue;
+                }
+                if self.share_fixtures {
+                    case.shared_with = Some(other.clone());
+                } else {
+                    let err = crate::Error::new(format!(
+                        "{} and {} both use {}; rename one of them or, if they only read it, use `TestCases::share_fixtures`",
+                        first.display(),
+                        second.display(),
+                        artifact.display()
+                    ));
+                    case.error = Some(crate::runner::SpawnStatus::Failure(err));
+                }
+            }
+        }
+    }
+
+    fn new_case(
+        &self,
+        path: std::path::PathBuf,
+        expected: Option<crate::schema::CommandStatus>,
+    ) -> crate::Case {
+        crate::Case {
+            fixture_dir: self.case_fixture_dir(&path),
+            sort_lines: self.sort_lines.iter().any(|glob| glob_matches(glob, &path)),
+            default_bins: self.case_default_bins(&path),
+            path,
+            expected,
+            timeout: self.timeout,
+            externalize_over: self.externalize_over,
+            snapshot_dir: self.snapshot_dir.clone(),
+            default_args: self.default_args.clone(),
+            default_stdin: self.default_stdin.clone(),
+            inherit_stdin: self.inherit_stdin,
+            max_output_bytes: self.max_output_bytes,
+            verify_commands: self.verify_commands,
+            allowed_commands: self.allowed_commands.clone(),
+            abort_on_panic: self.abort_on_panic,
+            locale: self
+                .locale
+                .clone()
+                .or_else(|| self.hermetic.then(|| HERMETIC_LOCALE.to_owned())),
+            umask: self.umask.or(self.hermetic.then_some(HERMETIC_UMASK)),
+            hermetic: self.hermetic,
+            default_status: self.default_status,
+            default_cwd: self.default_cwd.clone(),
+            env: self.case_env(),
+            seeds: self.seeds.clone(),
+            secrets: self.secrets.clone(),
+            strip_trailing_whitespace: self.strip_trailing_whitespace,
+            collapse_repeated: self.collapse_repeated,
+            trailing_newline: self.trailing_newline,
+            truncate_output: self.truncate_output,
+            verbose: self.verbose,
+            working_copy: self.working_copy,
+            isolate_home: self.isolate_home,
+            dump_on_failure: self.dump_on_failure.clone(),
+            fail_context: self.fail_context.clone(),
+            shared_with: None,
+            matrix_leg: None,
+            input_line: None,
+            template: None,
+            skip_reason: None,
+            capture_output: false,
+            captured: Default::default(),
+            #[cfg(feature = "filesystem")]
+            comparators: self.comparators.clone(),
+            configure_command: self.configure_command.clone(),
+            suite: Default::default(),
+            error: None,
+        }
+    }
+
+    /// Environment for cases, cleared down to what's needed to run commands with
+    /// [`TestCases::hermetic`][crate::TestCases::hermetic] unless inheritance was configured
+    fn case_env(&self) -> crate::schema::Env {
+        let mut env = self.env.clone();
+        if self.hermetic && env.inherit.is_none() {
+            env.inherit = Some(false);
+            env.inherit_only = HERMETIC_INHERIT.iter().map(|k| (*k).to_owned()).collect();
+        }
+        env
+    }
+
     fn is_included(&self, case: &crate::Case) -> bool {
+        if !self.filters.is_empty() {
+            let path = std::fs::canonicalize(&case.path).unwrap_or_else(|_| case.path.clone());
+            if !self.filters.iter().all(|filter| filter.matches(&path)) {
+                return false;
+            }
+        }
+
+        if self.include.is_none()
+            && self.include_tags.is_none()
+            && self.only_tags.is_none()
+            && self.skip_tags.is_empty()
+        {
+            return true;
+        }
+
+        let tags = self.case_tags(case);
+        let has_any = |filter: &[String]| filter.iter().any(|t| tags.contains(t));
+
         if let Some(include) = self.include.as_deref() {
-            include
-                .iter()
-                .any(|i| case.path.to_string_lossy().contains(i))
-        } else {
-            true
+            let matches_path = include.iter().any(|i| {
+                case.path.to_string_lossy().contains(i) || i.trim_end_matches('/') == case.suite
+            });
+            if !matches_path && !has_any(include) {
+                return false;
+            }
+        }
+        if let Some(include_tags) = self.include_tags.as_deref() {
+            if !has_any(include_tags) {
+                return false;
+            }
+        }
+        if let Some(only_tags) = self.only_tags.as_deref() {
+            if !has_any(only_tags) {
+                return false;
+            }
         }
+        !has_any(&self.skip_tags)
+    }
+
+    /// Bins for steps without their own, in the order they're tried
+    ///
+    /// Of the [`BinSpec`]s, the last matching bin wins, like with [`RunnerSpec::case`].
+    fn case_default_bins(&self, path: &std::path::Path) -> Vec<(&'static str, crate::schema::Bin)> {
+        let case_bin = self
+            .bins
+            .iter()
+            .rev()
+            .find(|spec| glob_matches(&spec.glob, path))
+            .map(|spec| ("case_bin", spec.bin.clone()));
+        case_bin
+            .into_iter()
+            .chain(self.default_bins.iter().cloned())
+            .chain(
+                self.bin_fallback
+                    .iter()
+                    .map(|bin| (crate::runner::FALLBACK_SOURCE, bin.clone())),
+            )
+            .collect()
+    }
+
+    /// Directories suites are named relative to, one per case glob
+    fn suite_roots(&self) -> Vec<std::path::PathBuf> {
+        let mut roots: Vec<_> = self
+            .cases
+            .iter()
+            .map(|spec| match get_glob(&spec.glob) {
+                Some(_) => glob_prefix(&spec.glob),
+                None => spec.glob.parent().map(Into::into).unwrap_or_default(),
+            })
+            .collect();
+        // Shallowest first, so it's found first
+        roots.sort_by_key(|root| root.components().count());
+        roots
+    }
+
+    /// The last matching weight wins, like with [`RunnerSpec::case`]
+    fn case_weight_of(&self, path: &std::path::Path) -> u32 {
+        self.weights
+            .iter()
+            .rev()
+            .find(|spec| glob_matches(&spec.glob, path))
+            .map_or(1, |spec| spec.weight)
+    }
+
+    /// The last matching fixture wins, like with [`RunnerSpec::case`]
+    fn case_fixture_dir(&self, path: &std::path::Path) -> Option<std::path::PathBuf> {
+        self.fixtures
+            .iter()
+            .rev()
+            .find(|spec| glob_matches(&spec.glob, path))
+            .map(|spec| spec.dir.clone())
+    }
+
+    fn case_tags(&self, case: &crate::Case) -> std::collections::BTreeSet<String> {
+        let mut tags: std::collections::BTreeSet<_> = self
+            .tags
+            .iter()
+            .filter(|spec| {
+                glob_matches(&spec.glob, &case.path) || glob_matches(&spec.glob, case.file())
+            })
+            .map(|spec| spec.tag.clone())
+            .collect();
+        tags.extend(crate::schema::TryCmd::load_tags(case.file()));
+        tags
     }
 }
 
@@ -133,10 +1242,162 @@ impl Default for RunnerSpec {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct CaseSpec {
     glob: std::path::PathBuf,
     expected: Option<crate::schema::CommandStatus>,
+    /// From a manifest's `[[case]]`, see [`RunnerSpec::manifest`]
+    timeout: Option<std::time::Duration>,
+    env: crate::schema::Env,
+}
+
+impl CaseSpec {
+    fn apply(&self, case: &mut crate::Case) {
+        if self.timeout.is_some() {
+            case.timeout = self.timeout;
+        }
+        case.env.update(&self.env);
+    }
+}
+
+#[derive(Clone, Debug)]
+struct TagSpec {
+    glob: std::path::PathBuf,
+    tag: String,
+}
+
+/// Files, other than the case itself, that a case reads or writes
+fn artifact_paths(
+    path: &std::path::Path,
+    snapshot_dir: Option<&std::path::Path>,
+) -> Vec<std::path::PathBuf> {
+    let mut artifacts = Vec::new();
+    if path.extension() == Some(std::ffi::OsStr::new("toml")) {
+        artifacts.push(path.with_extension("stdin"));
+        artifacts.push(crate::schema::snapshot_path(snapshot_dir, path, "stdout"));
+        artifacts.push(crate::schema::snapshot_path(snapshot_dir, path, "stderr"));
+    }
+    artifacts.push(path.with_extension("in"));
+    artifacts.push(path.with_extension("out"));
+    artifacts
+}
+
+#[derive(Clone, Debug)]
+struct FixtureSpec {
+    glob: std::path::PathBuf,
+    dir: std::path::PathBuf,
+}
+
+/// See [`TestCases::case_bin`][crate::TestCases::case_bin]
+#[derive(Clone, Debug)]
+struct BinSpec {
+    glob: std::path::PathBuf,
+    bin: crate::schema::Bin,
+}
+
+/// See [`TestCases::case_per_line`][crate::TestCases::case_per_line]
+#[derive(Clone, Debug)]
+struct PerLineSpec {
+    input: std::path::PathBuf,
+    template: std::path::PathBuf,
+}
+
+/// See [`TestCases::case_weight`][crate::TestCases::case_weight]
+#[derive(Clone, Debug)]
+struct WeightSpec {
+    glob: std::path::PathBuf,
+    weight: u32,
+}
+
+/// See [`TestCases::case_ordered_before`][crate::TestCases::case_ordered_before]
+#[derive(Clone, Debug)]
+struct OrderSpec {
+    before: std::path::PathBuf,
+    after: std::path::PathBuf,
+}
+
+/// See [`TestCases::run_cases_matching`][crate::TestCases::run_cases_matching]
+#[derive(Clone)]
+pub(crate) struct CaseFilter(std::sync::Arc<dyn Fn(&std::path::Path) -> bool + Send + Sync>);
+
+impl CaseFilter {
+    pub(crate) fn new(filter: impl Fn(&std::path::Path) -> bool + Send + Sync + 'static) -> Self {
+        Self(std::sync::Arc::new(filter))
+    }
+
+    fn matches(&self, path: &std::path::Path) -> bool {
+        (self.0)(path)
+    }
+}
+
+impl std::fmt::Debug for CaseFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CaseFilter")
+    }
+}
+
+fn glob_matches(glob: &std::path::Path, path: &std::path::Path) -> bool {
+    if let Some(pattern) = get_glob(glob) {
+        ::glob::Pattern::new(pattern)
+            .map(|p| p.matches_path(path))
+            .unwrap_or(false)
+    } else {
+        glob == path
+    }
+}
+
+/// Order cases byte-wise by their path relative to `cwd`, with `/` separators, to run and report
+/// them in the same order on every platform
+fn sort_key(cwd: &std::path::Path, path: &std::path::Path) -> String {
+    path.strip_prefix(cwd)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Name of the suite `path` is reported in, see [`crate::schema::SuiteConfig`]
+///
+/// This is its directory relative to the shallowest of `roots` containing it, or `trycmd` for
+/// cases directly in the root.
+fn case_suite(
+    roots: &[std::path::PathBuf],
+    path: &std::path::Path,
+) -> Result<String, crate::ParseError> {
+    let dir = path.parent().unwrap_or_else(|| std::path::Path::new(""));
+    if let Some(config) = crate::schema::SuiteConfig::find(dir)? {
+        return Ok(config.name);
+    }
+    let relative = roots
+        .iter()
+        .find_map(|root| dir.strip_prefix(root).ok())
+        .unwrap_or(dir);
+    let suite = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    if suite.is_empty() {
+        Ok("trycmd".to_owned())
+    } else {
+        Ok(suite)
+    }
+}
+
+/// Non-glob leading components of a glob
+fn glob_prefix(glob: &std::path::Path) -> std::path::PathBuf {
+    glob.components()
+        .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .collect()
+}
+
+/// Directory a glob searches within
+fn glob_root(glob: &std::path::Path) -> std::path::PathBuf {
+    let root = glob_prefix(glob);
+    std::env::current_dir()
+        .map(|cwd| cwd.join(&root))
+        .unwrap_or(root)
 }
 
 fn get_glob(path: &std::path::Path) -> Option<&str> {
@@ -148,3 +1409,724 @@ fn get_glob(path: &std::path::Path) -> Option<&str> {
 
     None
 }
+
+/// Trimmed `stdout` of `command`, for the variable `key`
+fn env_from_command(key: &str, command: &[String]) -> Result<String, crate::Error> {
+    let line = command.join(" ");
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| format!("No command given for `{}`", key))?;
+    let output = std::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run `{}` for `{}`: {}", line, key, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "`{}` for `{}` failed with {}:\n{}",
+            line,
+            key,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        )
+        .into());
+    }
+    let value = String::from_utf8(output.stdout)
+        .map_err(|_| format!("`{}` for `{}` printed invalid UTF-8", line, key))?;
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(format!("`{}` for `{}` printed nothing", line, key).into());
+    }
+    Ok(value.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn require_matches_empty_glob() {
+        let mut spec = RunnerSpec::new();
+        spec.require_matches(true);
+        spec.case(std::path::Path::new("tests/does-not-exist/*.toml"), None);
+        let runner = spec.prepare();
+        assert_eq!(runner.cases().len(), 1);
+        assert!(runner.cases()[0].error.is_some());
+    }
+
+    #[test]
+    fn require_matches_allow_empty() {
+        let mut spec = RunnerSpec::new();
+        spec.require_matches(true);
+        spec.case(std::path::Path::new("tests/does-not-exist/*.toml"), None);
+        spec.allow_empty(std::path::Path::new("tests/does-not-exist/*.toml"));
+        let runner = spec.prepare();
+        assert_eq!(runner.cases().len(), 0);
+    }
+
+    #[test]
+    fn require_matches_filtered_out() {
+        let mut spec = RunnerSpec::new();
+        spec.require_matches(true);
+        spec.include(Some(vec!["does-not-exist".into()]));
+        spec.case(std::path::Path::new("tests/cmd/*.toml"), None);
+        let runner = spec.prepare();
+        assert_eq!(runner.cases().len(), 0);
+    }
+
+    #[test]
+    fn sorted_cases() {
+        let mut spec = RunnerSpec::new();
+        spec.case(std::path::Path::new("tests/sorted/a/b.toml"), None);
+        spec.case(std::path::Path::new("tests/sorted/a-b.toml"), None);
+        spec.case(std::path::Path::new("tests/sorted/A.toml"), None);
+        let runner = spec.prepare();
+        let paths: Vec<_> = runner.cases().iter().map(|c| c.path.clone()).collect();
+        assert_eq!(
+            paths,
+            [
+                std::path::Path::new("tests/sorted/A.toml"),
+                std::path::Path::new("tests/sorted/a-b.toml"),
+                std::path::Path::new("tests/sorted/a/b.toml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn orphaned_artifacts() {
+        let root = std::env::temp_dir().join(format!("trycmd-orphans-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("cmd/used.out")).unwrap();
+        std::fs::create_dir_all(root.join("cmd/gone.in")).unwrap();
+        for file in [
+            "used.toml",
+            "used.stdout",
+            "filtered.toml",
+            "filtered.stderr",
+            "parts.toml",
+            "header.stdout",
+            "gone.stdout",
+        ] {
+            std::fs::write(root.join("cmd").join(file), "").unwrap();
+        }
+        std::fs::write(
+            root.join("cmd/parts.toml"),
+            "stdout = { paths = [\"header.stdout\"] }\n",
+        )
+        .unwrap();
+        let mut spec = RunnerSpec::new();
+        spec.case(&root.join("cmd/*.toml"), None);
+        spec.include(Some(vec!["used".into()]));
+        spec.strict_artifacts(true);
+        let runner = spec.prepare();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let error = runner
+            .cases()
+            .iter()
+            .find(|c| c.path == std::path::Path::new("orphaned_artifacts"))
+            .and_then(|c| c.error.clone());
+        let expected = format!(
+            "2 artifacts aren't used by any case:\n  {}\n  {}",
+            root.join("cmd/gone.in").display(),
+            root.join("cmd/gone.stdout").display()
+        );
+        assert_eq!(
+            error,
+            Some(crate::runner::SpawnStatus::Failure(expected.into()))
+        );
+    }
+
+    #[test]
+    fn case_bin() {
+        let mut spec = RunnerSpec::new();
+        spec.default_bin(
+            "default_bin_name",
+            crate::schema::Bin::Name("default".into()),
+        );
+        spec.case_bin(
+            std::path::Path::new("tests/bin/*.toml"),
+            crate::schema::Bin::Name("first".into()),
+        );
+        spec.case_bin(
+            std::path::Path::new("tests/bin/b.toml"),
+            crate::schema::Bin::Name("second".into()),
+        );
+        spec.case(std::path::Path::new("tests/bin/a.toml"), None);
+        spec.case(std::path::Path::new("tests/bin/b.toml"), None);
+        spec.case(std::path::Path::new("tests/other.toml"), None);
+        let runner = spec.prepare();
+        let bins: Vec<_> = runner
+            .cases()
+            .iter()
+            .map(|c| c.default_bins.first().map(|(_, bin)| bin.clone()))
+            .collect();
+        assert_eq!(
+            bins,
+            [
+                Some(crate::schema::Bin::Name("first".into())),
+                Some(crate::schema::Bin::Name("second".into())),
+                Some(crate::schema::Bin::Name("default".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn default_bin_order() {
+        let mut spec = RunnerSpec::new();
+        spec.default_bin(
+            "default_bin_path",
+            crate::schema::Bin::Path("my-cmd".into()),
+        );
+        // Doesn't replace the path set before it
+        spec.default_bin("default_bin_name", crate::schema::Bin::Name("other".into()));
+        spec.bin_fallback(vec![crate::schema::Bin::Name("fallback".into())]);
+        spec.case_bin(
+            std::path::Path::new("tests/bin/*.toml"),
+            crate::schema::Bin::Name("case".into()),
+        );
+        spec.case(std::path::Path::new("tests/bin/a.toml"), None);
+        let runner = spec.prepare();
+        assert_eq!(
+            runner.cases()[0].default_bins,
+            [
+                ("case_bin", crate::schema::Bin::Name("case".into())),
+                (
+                    "default_bin_path",
+                    crate::schema::Bin::Path("my-cmd".into())
+                ),
+                ("default_bin_name", crate::schema::Bin::Name("other".into())),
+                ("bin_fallback", crate::schema::Bin::Name("fallback".into())),
+            ]
+        );
+
+        let mut other = RunnerSpec::new();
+        other.default_bin(
+            "default_bin_name",
+            crate::schema::Bin::Name("shared".into()),
+        );
+        spec.update(&other);
+        spec.case(std::path::Path::new("tests/bin/a.toml"), None);
+        let runner = spec.prepare();
+        assert_eq!(
+            runner.cases()[0].default_bins[1..3],
+            [
+                (
+                    "default_bin_path",
+                    crate::schema::Bin::Path("my-cmd".into())
+                ),
+                (
+                    "default_bin_name",
+                    crate::schema::Bin::Name("shared".into())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn case_per_line() {
+        let root = std::env::temp_dir().join(format!("trycmd-per-line-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let input = root.join("seeds.txt");
+        std::fs::write(&input, "alpha\n\n  \nbeta\r\n").unwrap();
+        let template = root.join("parse.trycmd");
+        let mut spec = RunnerSpec::new();
+        spec.case_per_line(&input, &template);
+        spec.case_per_line(&root.join("missing.txt"), &template);
+        let runner = spec.prepare();
+        spec.include(Some(vec!["parse_4".into()]));
+        let included = spec.prepare();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let cases: Vec<_> = runner
+            .cases()
+            .iter()
+            .map(|c| (c.path.clone(), c.input_line, c.default_stdin.clone()))
+            .collect();
+        assert_eq!(cases.len(), 3);
+        assert_eq!(cases[0].0, root.join("missing.txt"));
+        assert_eq!(
+            cases[1..],
+            [
+                (
+                    root.join("parse_1.trycmd"),
+                    Some(1),
+                    Some(crate::Data::text("alpha\n"))
+                ),
+                (
+                    root.join("parse_4.trycmd"),
+                    Some(4),
+                    Some(crate::Data::text("beta\n"))
+                ),
+            ]
+        );
+        assert!(runner.cases()[1..].iter().all(|c| c.file() == template));
+        let included: Vec<_> = included.cases().iter().map(|c| c.path.clone()).collect();
+        assert_eq!(
+            included,
+            [root.join("missing.txt"), root.join("parse_4.trycmd")]
+        );
+    }
+
+    #[test]
+    fn case_weight() {
+        let mut spec = RunnerSpec::new();
+        spec.case_weight(std::path::Path::new("tests/slow/*.toml"), 10);
+        spec.case_weight(std::path::Path::new("tests/slow/b.toml"), 5);
+        spec.case(std::path::Path::new("tests/slow/a.toml"), None);
+        spec.case(std::path::Path::new("tests/slow/b.toml"), None);
+        spec.case(std::path::Path::new("tests/fast.toml"), None);
+        let runner = spec.prepare();
+        let scheduled: Vec<_> = runner
+            .schedule(0)
+            .into_iter()
+            .map(|i| runner.cases()[i].path.display().to_string())
+            .collect();
+        assert_eq!(
+            scheduled,
+            ["tests/slow/a.toml", "tests/slow/b.toml", "tests/fast.toml"]
+        );
+    }
+
+    #[test]
+    fn case_suites() {
+        let root = std::env::temp_dir().join(format!("trycmd-suites-{}", std::process::id()));
+        for dir in ["init", "build/release", "publish"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        std::fs::write(root.join("publish/_suite.toml"), "name = \"release\"\n").unwrap();
+        let roots = vec![root.clone(), root.join("build")];
+        let suite = |path: &str| case_suite(&roots, &root.join(path)).unwrap();
+        assert_eq!(suite("top.toml"), "trycmd");
+        assert_eq!(suite("init/a.toml"), "init");
+        assert_eq!(suite("build/release/a.toml"), "build/release");
+        assert_eq!(suite("publish/a.toml"), "release");
+
+        std::fs::write(root.join("init/_suite.toml"), "title = \"init\"\n").unwrap();
+        assert!(case_suite(&roots, &root.join("init/a.toml")).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn manifest() {
+        let root = std::env::temp_dir().join(format!("trycmd-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("cmd")).unwrap();
+        std::fs::write(root.join("cmd/a.toml"), "").unwrap();
+        let path = root.join("trycmd-manifest.toml");
+        std::fs::write(
+            &path,
+            "[[case]]
+glob = \"cmd/*.toml\"
+status = \"failed\"
+timeout = \"3s\"
+tags = [\"slow\"]
+env.add = { FOO = \"bar\" }
+",
+        )
+        .unwrap();
+        let mut spec = RunnerSpec::new();
+        spec.manifest(&path);
+        spec.only_tags(Some(vec!["slow".into()]));
+        let runner = spec.prepare();
+
+        let bad_path = root.join("bad-manifest.toml");
+        std::fs::write(&bad_path, "[[case]]\nglob = 1\n").unwrap();
+        let mut bad = RunnerSpec::new();
+        bad.manifest(&bad_path);
+        let bad_runner = bad.prepare();
+
+        let case_setting_path = root.join("case-setting-manifest.toml");
+        std::fs::write(
+            &case_setting_path,
+            "[[case]]\nglob = \"cmd/*.toml\"\nargs = [\"--verbose\"]\n",
+        )
+        .unwrap();
+        let mut case_setting = RunnerSpec::new();
+        case_setting.manifest(&case_setting_path);
+        let case_setting_runner = case_setting.prepare();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(runner.cases().len(), 1);
+        let case = &runner.cases()[0];
+        assert_eq!(case.path, root.join("cmd/a.toml"));
+        assert_eq!(case.expected, Some(crate::schema::CommandStatus::Failed));
+        assert_eq!(case.timeout, Some(std::time::Duration::from_secs(3)));
+        assert_eq!(case.env.add.get("FOO").map(String::as_str), Some("bar"));
+
+        assert_eq!(bad_runner.cases().len(), 1);
+        assert_eq!(bad_runner.cases()[0].path, bad_path);
+        assert!(bad_runner.cases()[0].error.is_some());
+
+        match &case_setting_runner.cases()[0].error {
+            Some(crate::runner::SpawnStatus::Failure(err)) => {
+                assert!(err.to_string().contains("unknown field `args`"), "{}", err);
+            }
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn case_count() {
+        let mut spec = RunnerSpec::new();
+        spec.case(std::path::Path::new("tests/cmd/basic.toml"), None);
+        spec.case(std::path::Path::new("tests/cmd/basic.trycmd"), None);
+        spec.case_count(2);
+        let runner = spec.prepare();
+        assert!(runner.cases().iter().all(|c| c.error.is_none()));
+
+        spec.case_count(3);
+        let runner = spec.prepare();
+        let error = runner.cases().iter().find_map(|c| c.error.clone()).unwrap();
+        assert_eq!(
+            error,
+            crate::runner::SpawnStatus::Failure(
+                "Expected 3 cases, found 2:
+  tests/cmd/basic.toml
+  tests/cmd/basic.trycmd"
+                    .into()
+            )
+        );
+    }
+
+    fn colliding_cases(name: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+        let root = std::env::temp_dir().join(format!("trycmd-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(root.join("init.out")).unwrap();
+        std::fs::write(root.join("init.trycmd"), "```\n$ cmd\n```\n").unwrap();
+        std::fs::write(root.join("init.toml"), "bin.name = \"cmd\"\n").unwrap();
+        std::fs::write(root.join("init.stdout"), "").unwrap();
+        (root.join("init.toml"), root.join("init.trycmd"))
+    }
+
+    #[test]
+    fn shared_artifacts_error() {
+        let (toml, trycmd) = colliding_cases("shared-artifacts-error");
+        let mut spec = RunnerSpec::new();
+        spec.case(&toml, None);
+        spec.case(&trycmd, None);
+        let runner = spec.prepare();
+        std::fs::remove_dir_all(toml.parent().unwrap()).unwrap();
+
+        let expected = format!(
+            "{} and {} both use {}; rename one of them or, if they only read it, use `TestCases::share_fixtures`",
+            toml.display(),
+            trycmd.display(),
+            toml.with_extension("out").display()
+        );
+        assert_eq!(runner.cases().len(), 2);
+        for case in runner.cases() {
+            let err = case.validate().unwrap_err().to_string();
+            assert!(err.contains(&expected), "{}", err);
+        }
+    }
+
+    #[test]
+    fn shared_artifacts_opt_in() {
+        let (toml, trycmd) = colliding_cases("shared-artifacts-opt-in");
+        let mut spec = RunnerSpec::new();
+        spec.share_fixtures(true);
+        spec.case(&toml, None);
+        spec.case(&trycmd, None);
+        let runner = spec.prepare();
+        std::fs::remove_dir_all(toml.parent().unwrap()).unwrap();
+
+        assert_eq!(runner.cases().len(), 2);
+        assert!(runner.cases().iter().all(|case| case.error.is_none()));
+        assert_eq!(
+            runner.cases()[0].shared_with.as_deref(),
+            Some(trycmd.as_path())
+        );
+        assert_eq!(
+            runner.cases()[1].shared_with.as_deref(),
+            Some(toml.as_path())
+        );
+    }
+
+    #[test]
+    fn case_fixtures_last_wins() {
+        let mut spec = RunnerSpec::new();
+        spec.case(std::path::Path::new("tests/cmd/*.toml"), None);
+        spec.case_fixtures(
+            std::path::Path::new("tests/cmd/*.toml"),
+            std::path::Path::new("first"),
+        );
+        spec.case_fixtures(
+            std::path::Path::new("tests/cmd/timeout.toml"),
+            std::path::Path::new("second"),
+        );
+        let runner = spec.prepare();
+        for case in runner.cases() {
+            let expected = if case.path == std::path::Path::new("tests/cmd/timeout.toml") {
+                "second"
+            } else {
+                "first"
+            };
+            assert_eq!(
+                case.fixture_dir.as_deref(),
+                Some(std::path::Path::new(expected)),
+                "{}",
+                case.path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn case_ordering_stages() {
+        let mut spec = RunnerSpec::new();
+        spec.case_ordered_before(
+            std::path::Path::new("tests/cmd/basic.*"),
+            std::path::Path::new("tests/cmd/stdout.*"),
+        );
+        spec.case_ordered_before(
+            std::path::Path::new("tests/cmd/stdout.toml"),
+            std::path::Path::new("tests/cmd/stderr.toml"),
+        );
+        let cases: Vec<_> = [
+            "tests/cmd/stderr.toml",
+            "tests/cmd/stdout.toml",
+            "tests/cmd/stdout.trycmd",
+            "tests/cmd/basic.toml",
+            "tests/cmd/code.toml",
+        ]
+        .into_iter()
+        .map(|path| crate::Case::with_error(path.into(), "unused".into()))
+        .collect();
+        assert_eq!(spec.case_stages(&cases).unwrap(), vec![2, 1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn same_as_stages() {
+        let mut spec = RunnerSpec::new();
+        spec.case(std::path::Path::new("tests/cmd/same-as/*.toml"), None);
+        let runner = spec.prepare();
+        let capturing: Vec<_> = runner
+            .cases()
+            .iter()
+            .map(|case| (case.path.clone(), case.capture_output))
+            .collect();
+        assert_eq!(
+            capturing,
+            vec![
+                ("tests/cmd/same-as/format.toml".into(), true),
+                ("tests/cmd/same-as/reformat.toml".into(), false),
+            ]
+        );
+        assert_eq!(spec.case_stages(runner.cases()).unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn case_ordering_cycle() {
+        let mut spec = RunnerSpec::new();
+        spec.case(std::path::Path::new("tests/cmd/*.toml"), None);
+        spec.case_ordered_before(
+            std::path::Path::new("tests/cmd/basic.toml"),
+            std::path::Path::new("tests/cmd/stdout.toml"),
+        );
+        spec.case_ordered_before(
+            std::path::Path::new("tests/cmd/stdout.toml"),
+            std::path::Path::new("tests/cmd/basic.toml"),
+        );
+        let runner = spec.prepare();
+        let errors: Vec<_> = runner
+            .cases()
+            .iter()
+            .filter_map(|case| match &case.error {
+                Some(crate::runner::SpawnStatus::Failure(err)) => Some(err.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].contains("circular"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn require_binary_exists() {
+        let mut spec = RunnerSpec::new();
+        spec.require_binary_exists(true);
+        spec.default_bin(
+            "default_bin_path",
+            crate::schema::Bin::Path("target/does-not-exist".into()),
+        );
+        spec.case(std::path::Path::new("tests/cmd/*.toml"), None);
+        let runner = spec.prepare();
+        let errors: Vec<_> = runner
+            .cases()
+            .iter()
+            .map(|case| match &case.error {
+                Some(crate::runner::SpawnStatus::Failure(err)) => err.clone(),
+                _ => panic!("{} should not run", case.path.display()),
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [crate::Error::from(
+                "binary not found: target/does-not-exist"
+            )]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn env_var_from_command() {
+        let mut spec = RunnerSpec::new();
+        spec.env_from_command("TOKEN".into(), vec!["echo".into(), " secret ".into()]);
+        spec.case(std::path::Path::new("tests/cmd/basic.toml"), None);
+        let runner = spec.prepare();
+        assert_eq!(runner.cases()[0].env.add["TOKEN"], "secret");
+
+        let errors = |command: &[&str]| {
+            let mut spec = RunnerSpec::new();
+            spec.env_from_command(
+                "TOKEN".into(),
+                command.iter().map(|arg| (*arg).to_owned()).collect(),
+            );
+            spec.case(std::path::Path::new("tests/cmd/basic.toml"), None);
+            let runner = spec.prepare();
+            runner
+                .cases()
+                .iter()
+                .map(|case| match &case.error {
+                    Some(crate::runner::SpawnStatus::Failure(err)) => err.clone(),
+                    _ => panic!("{} should not run", case.path.display()),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errors(&["true"]),
+            [crate::Error::from("`true` for `TOKEN` printed nothing")]
+        );
+        assert_eq!(
+            errors(&["false"]),
+            [crate::Error::from(
+                "`false` for `TOKEN` failed with exit status: 1:\n"
+            )]
+        );
+    }
+
+    #[test]
+    fn require_binary_exists_found() {
+        let mut spec = RunnerSpec::new();
+        spec.require_binary_exists(true);
+        spec.default_bin(
+            "default_bin_path",
+            crate::schema::Bin::Path(crate::cargo::cargo_bin("bin-fixture")),
+        );
+        spec.case(std::path::Path::new("tests/cmd/basic.toml"), None);
+        let runner = spec.prepare();
+        assert_eq!(runner.cases().len(), 1);
+        assert!(runner.cases()[0].error.is_none());
+    }
+
+    #[test]
+    fn require_binary_exists_fallback() {
+        let mut spec = RunnerSpec::new();
+        spec.require_binary_exists(true);
+        spec.default_bin(
+            "default_bin_path",
+            crate::schema::Bin::Path("target/does-not-exist".into()),
+        );
+        spec.bin_fallback(vec![crate::schema::Bin::Path(crate::cargo::cargo_bin(
+            "bin-fixture",
+        ))]);
+        spec.case(std::path::Path::new("tests/cmd/basic.toml"), None);
+        let runner = spec.prepare();
+        assert_eq!(runner.cases().len(), 1);
+        assert!(runner.cases()[0].error.is_none());
+    }
+
+    #[test]
+    fn include_tags_union() {
+        let mut spec = RunnerSpec::new();
+        spec.include_tags(Some(vec!["first".into(), "second".into()]));
+        spec.case(std::path::Path::new("tests/cmd/*.toml"), None);
+        spec.case_tag(std::path::Path::new("tests/cmd/lock.toml"), "first".into());
+        spec.case_tag(
+            std::path::Path::new("tests/cmd/timeout.toml"),
+            "second".into(),
+        );
+        let runner = spec.prepare();
+        let paths: Vec<_> = runner.cases().iter().map(|c| c.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                std::path::PathBuf::from("tests/cmd/lock.toml"),
+                std::path::PathBuf::from("tests/cmd/timeout.toml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn filter_by_content() {
+        let mut spec = RunnerSpec::new();
+        spec.case(std::path::Path::new("tests/cmd/*.toml"), None);
+        spec.filter(CaseFilter::new(|path| {
+            assert!(path.is_absolute(), "{}", path.display());
+            std::fs::read_to_string(path)
+                .unwrap()
+                .contains("expect-timeout")
+        }));
+        let runner = spec.prepare();
+        let paths: Vec<_> = runner.cases().iter().map(|c| c.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![std::path::PathBuf::from("tests/cmd/expect-timeout.toml")]
+        );
+    }
+
+    #[test]
+    fn update_later_case_wins() {
+        let mut shared = RunnerSpec::new();
+        shared.case(std::path::Path::new("tests/cmd/*.toml"), None);
+        shared.timeout(Some(std::time::Duration::from_secs(1)));
+        let mut spec = RunnerSpec::new();
+        spec.case(
+            std::path::Path::new("tests/cmd/lock.toml"),
+            Some(crate::schema::CommandStatus::Skipped),
+        );
+        spec.update(&shared);
+        let mut local = RunnerSpec::new();
+        local.case(
+            std::path::Path::new("tests/cmd/lock.toml"),
+            Some(crate::schema::CommandStatus::Skipped),
+        );
+        spec.update(&local);
+
+        let runner = spec.prepare();
+        let lock = runner
+            .cases()
+            .iter()
+            .find(|c| c.path == std::path::Path::new("tests/cmd/lock.toml"))
+            .unwrap();
+        assert_eq!(lock.expected, Some(crate::schema::CommandStatus::Skipped));
+        assert_eq!(lock.timeout, Some(std::time::Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn matrix_bins_per_case() {
+        let mut spec = RunnerSpec::new();
+        spec.matrix_bins(vec![
+            ("a".into(), crate::schema::Bin::Name("a".into())),
+            ("b".into(), crate::schema::Bin::Name("b".into())),
+        ]);
+        spec.case(std::path::Path::new("tests/cmd/matrix/*.toml"), None);
+        let runner = spec.prepare();
+        let legs: Vec<_> = runner
+            .cases()
+            .iter()
+            .map(|c| {
+                (
+                    c.path.file_name().unwrap().to_str().unwrap(),
+                    c.matrix_leg
+                        .as_ref()
+                        .map(|l| (l.label.as_str(), l.canonical)),
+                )
+            })
+            .collect();
+        assert_eq!(
+            legs,
+            vec![
+                ("each.toml", Some(("a", true))),
+                ("each.toml", Some(("b", false))),
+                ("once.toml", None),
+            ]
+        );
+    }
+}
@@ -1,7 +1,9 @@
 #[test]
 fn cli_tests() {
     let t = trycmd::TestCases::new();
-    t.case("tests/cmd/*.trycmd").case("tests/cmd/*.toml");
+    t.require_matches(true)
+        .case("tests/cmd/*.trycmd")
+        .case("tests/cmd/*.toml");
     #[cfg(not(feature = "schema"))]
     {
         t.skip("tests/cmd/schema.toml");
@@ -14,12 +16,247 @@ fn cli_tests() {
         t.skip("tests/cmd/normalize.toml");
         t.skip("tests/cmd/symlink.toml");
         t.skip("tests/cmd/subst-cwd.toml");
+        t.skip("tests/cmd/cd.trycmd");
+        t.skip("tests/cmd/cd-cwd.trycmd");
+        t.skip("tests/cmd/stdin-file.trycmd");
+        t.skip("tests/cmd/repeat.toml");
+        t.skip("tests/cmd/absent.toml");
+        t.skip("tests/cmd/files-absent.toml");
     }
     #[cfg(target_os = "windows")]
     {
         // On windows, crashes are returned as code=1
         t.skip("tests/cmd/timeout.toml");
+        t.skip("tests/cmd/expect-timeout.toml");
     }
     t.extend_vars([("[EXAMPLE]", "example")]).unwrap();
     t.register_bin("ignored-bin", trycmd::schema::Bin::Ignore);
+    t.validate().unwrap();
+}
+
+#[test]
+fn default_stdin_tests() {
+    trycmd::TestCases::new()
+        .default_stdin("Hello\nWorld!\n")
+        .case("tests/cmd/default-stdin/*.toml");
+}
+
+#[test]
+fn strip_trailing_whitespace_tests() {
+    trycmd::TestCases::new()
+        .strip_trailing_whitespace()
+        .case("tests/cmd/trailing-whitespace/*.toml");
+}
+
+#[test]
+fn trailing_newline_tests() {
+    trycmd::TestCases::new()
+        .trailing_newline(trycmd::TrailingNewline::Ignore)
+        .case("tests/cmd/trailing-newline/*.toml");
+}
+
+#[test]
+fn collapse_repeated_lines_tests() {
+    trycmd::TestCases::new()
+        .collapse_repeated_lines(3)
+        .case("tests/cmd/collapse/*.toml");
+}
+
+#[test]
+fn matrix_bins_tests() {
+    trycmd::TestCases::new()
+        .matrix_bins([
+            ("name", trycmd::schema::Bin::Name("bin-fixture".into())),
+            (
+                "path",
+                trycmd::schema::Bin::Path(trycmd::cargo::cargo_bin("bin-fixture")),
+            ),
+        ])
+        .case("tests/cmd/matrix/*.toml");
+}
+
+#[test]
+fn platforms_tests() {
+    trycmd::TestCases::new().case("tests/cmd/platforms/*.toml");
+}
+
+#[test]
+#[cfg(unix)]
+fn script_tests() {
+    trycmd::TestCases::new()
+        .default_bin_script("sh", r#"echo "hello $1" | tr a-z A-Z"#)
+        .case("tests/cmd/script/*.toml");
+}
+
+#[test]
+#[cfg(feature = "filesystem")]
+fn case_with_fixtures_tests() {
+    trycmd::TestCases::new()
+        .case_with_fixtures("tests/cmd/fixtures/*.toml", "tests/cmd/fixtures/template");
+}
+
+#[test]
+fn redact_tests() {
+    trycmd::TestCases::new()
+        .redact(
+            r"(?P<name>worker-\d+) took \d+ms",
+            "${name} took [DURATION]ms",
+        )
+        .unwrap()
+        .case("tests/cmd/redact/*.toml");
+}
+
+#[test]
+fn normalize_thread_ids_tests() {
+    trycmd::TestCases::new()
+        .normalize_thread_ids()
+        .unwrap()
+        .case("tests/cmd/thread-ids/*.toml");
+}
+
+#[test]
+#[cfg(feature = "filesystem")]
+fn working_copy_tests() {
+    trycmd::TestCases::new()
+        .working_copy(true)
+        .case("tests/cmd/working-copy/*.toml")
+        .run();
+    assert!(!std::path::Path::new("stray-write.txt").exists());
+}
+
+#[test]
+#[cfg(feature = "filesystem")]
+fn isolate_home_tests() {
+    trycmd::TestCases::new()
+        .isolate_home(true)
+        .case("tests/cmd/isolate-home/*.toml");
+}
+
+#[test]
+fn dir_config_tests() {
+    trycmd::TestCases::new()
+        .case("tests/cmd/dir-config/*.toml")
+        .case("tests/cmd/dir-config/*.trycmd")
+        .case("tests/cmd/dir-config/nested/*.toml");
+}
+
+#[test]
+fn case_vars_tests() {
+    let t = trycmd::TestCases::new();
+    t.case("tests/cmd/case-vars/*.toml")
+        .case("tests/cmd/case-vars/*.trycmd");
+    #[cfg(not(feature = "filesystem"))]
+    {
+        t.skip("tests/cmd/case-vars/sandboxed.toml");
+    }
+}
+
+#[test]
+fn default_status_tests() {
+    trycmd::TestCases::new()
+        .default_status(trycmd::schema::CommandStatus::Failed)
+        .case("tests/cmd/default-status/*.toml")
+        .pass("tests/cmd/default-status/glob.toml")
+        .case("tests/cmd/default-status/*.trycmd");
+}
+
+#[test]
+fn dump_on_failure_tests() {
+    let dump = std::env::temp_dir().join(format!("trycmd-dump-on-failure-{}", std::process::id()));
+    trycmd::TestCases::new()
+        .dump_on_failure(&dump)
+        .case("tests/cmd/dump-on-failure/*.toml")
+        .run();
+    // Only failures are written
+    assert!(!dump.exists());
+}
+
+#[test]
+#[cfg(feature = "filesystem")]
+fn comparator_tests() {
+    trycmd::TestCases::new()
+        .comparator("png", |expected, actual| expected.len() == actual.len())
+        .case("tests/cmd/comparator/*.toml");
+}
+
+#[test]
+fn same_as_tests() {
+    trycmd::TestCases::new().case("tests/cmd/same-as/*.toml");
+}
+
+#[test]
+fn env_remove_tests() {
+    trycmd::TestCases::new()
+        .env("HARNESS_VAR", "harness")
+        .env_remove("REMOVED_VAR")
+        .case("tests/cmd/env-remove/*.toml");
+}
+
+#[test]
+fn env_inherit_list_tests() {
+    trycmd::TestCases::new()
+        .env_inherit_list(&["CARGO_PKG_NAME"])
+        .case("tests/cmd/env-inherit-list/*.toml");
+}
+
+#[test]
+fn run_one_tests() {
+    let t = trycmd::TestCases::new();
+    let results = t.run_one("tests/cmd/stdout.trycmd");
+    let names: Vec<_> = results.iter().map(|r| r.name()).collect();
+    assert_eq!(names, ["tests/cmd/stdout.trycmd:2"]);
+    assert_eq!(results[0].status(), trycmd::report::CaseStatus::Passed);
+
+    // Settings for matching globs still apply
+    let t = trycmd::TestCases::new();
+    t.skip("tests/cmd/*.trycmd");
+    let results = t.run_one("tests/cmd/stdout.trycmd");
+    assert_eq!(results[0].status(), trycmd::report::CaseStatus::Ignored);
+}
+
+#[test]
+fn configure_command_tests() {
+    trycmd::TestCases::new()
+        .configure_command(|path, cmd| {
+            let name = path.file_name().unwrap_or_default();
+            cmd.env("CONFIGURED", name);
+        })
+        .case("tests/cmd/configure-command/*.toml");
+}
+
+#[test]
+fn verify_commands_tests() {
+    trycmd::TestCases::new()
+        .verify_commands(true)
+        .allowed_commands(&["bin-fixture"])
+        .case("tests/cmd/verify-commands/*.md");
+}
+
+#[test]
+fn normalize_socket_addrs_tests() {
+    trycmd::TestCases::new()
+        .normalize_socket_addrs()
+        .case("tests/cmd/socket-addrs/*.toml");
+}
+
+#[test]
+fn env_secret_tests() {
+    trycmd::TestCases::new()
+        .env_secret("API_TOKEN", "s3cr3t-t0k3n")
+        .case("tests/cmd/env-secret/*.toml");
+}
+
+#[test]
+fn hermetic_tests() {
+    trycmd::TestCases::new()
+        .hermetic(true)
+        .case("tests/cmd/hermetic/*.toml");
+}
+
+#[test]
+fn case_per_line_tests() {
+    trycmd::TestCases::new().case_per_line(
+        "tests/cmd/per-line/seeds.txt",
+        "tests/cmd/per-line/echo.trycmd",
+    );
 }